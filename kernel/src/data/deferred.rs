@@ -0,0 +1,151 @@
+#![warn(missing_docs)]
+//! Verzögerte Interruptbearbeitung.
+//!
+//! Serviceroutinen sollen kurz sein: sie quittieren das Gerät, retten die Daten und tragen
+//! alles Weitere als Arbeitsauftrag (`WorkItem`) ein. Es gibt zwei Warteschlangen:
+//!
+//! - _Bottom halves_ (`defer`) werden direkt nach der Bearbeitung aller anliegenden Interrupts
+//!   ausgeführt, bevor zum unterbrochenen Code zurückgekehrt wird. Interrupts sind dabei
+//!   zugelassen.
+//! - _Arbeiten_ (`schedule_work`) für längere Aufgaben werden von einem Kernel-Worker
+//!   ausgeführt (`run_work`). Solange es keine Prozesse gibt, ist das die Leerlaufschleife
+//!   des Kernels.
+//!
+//! Beide Schlangen haben eine feste Größe, damit in Serviceroutinen kein Speicher
+//! angefordert werden muss. Ist eine Schlange voll, wird der Auftrag verworfen und gezählt.
+use hal::cpu::Cpu;
+use sync::IrqSpinLock;
+
+/// Anzahl der Aufträge pro Warteschlange
+pub const WORK_QUEUE_SIZE: usize = 32;
+
+/// Funktion eines Arbeitsauftrages, bekommt das beim Eintragen übergebene Argument
+pub type WorkFn = fn(u32);
+
+/// Arbeitsauftrag
+#[derive(Copy,Clone)]
+pub struct WorkItem {
+    func: WorkFn,
+    arg:  u32,
+}
+
+/// Fehler beim Eintragen eines Auftrags
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum DeferError {
+    /// Die Warteschlange ist voll
+    QueueFull,
+}
+
+/// Ringpuffer für Arbeitsaufträge
+struct WorkQueue {
+    items:   [Option<WorkItem>; WORK_QUEUE_SIZE],
+    head:    usize,
+    len:     usize,
+    dropped: usize,
+    running: bool,
+}
+
+impl WorkQueue {
+    const fn new() -> WorkQueue {
+        WorkQueue {
+            items:   [None; WORK_QUEUE_SIZE],
+            head:    0,
+            len:     0,
+            dropped: 0,
+            running: false,
+        }
+    }
+
+    fn push(&mut self, item: WorkItem) -> Result<(),DeferError> {
+        if self.len < WORK_QUEUE_SIZE {
+            self.items[(self.head + self.len) % WORK_QUEUE_SIZE] = Some(item);
+            self.len += 1;
+            Ok(())
+        } else {
+            self.dropped += 1;
+            Err(DeferError::QueueFull)
+        }
+    }
+
+    fn pop(&mut self) -> Option<WorkItem> {
+        if self.len > 0 {
+            let item = self.items[self.head].take();
+            self.head = (self.head + 1) % WORK_QUEUE_SIZE;
+            self.len -= 1;
+            item
+        } else {
+            None
+        }
+    }
+}
+
+/// Führt alle Aufträge der Schlange aus, auch die, die währenddessen eingetragen werden.
+///
+/// Läuft die Schlange bereits (weil ein Interrupt die Abarbeitung unterbrochen hat), kehrt
+/// die Funktion sofort zurück; die neuen Aufträge werden dann von der unterbrochenen
+/// Abarbeitung erledigt. Die Aufträge selbst laufen ohne gehaltenen Lock.
+fn run_queue(queue: &IrqSpinLock<WorkQueue>) -> usize {
+    {
+        let mut q = queue.lock();
+        if q.running {
+            return 0;
+        }
+        q.running = true;
+    }
+    let mut count = 0;
+    loop {
+        // `running` wird im selben Lock zurückgesetzt, in dem die Schlange leer ist. Sonst
+        // könnte ein Interrupt dazwischen einen Auftrag eintragen, den niemand mehr ausführt.
+        let item = {
+            let mut q = queue.lock();
+            match q.pop() {
+                Some(item) => item,
+                None       => {
+                    q.running = false;
+                    break;
+                }
+            }
+        };
+        (item.func)(item.arg);
+        count += 1;
+    }
+    count
+}
+
+static BOTTOM_HALVES: IrqSpinLock<WorkQueue> = IrqSpinLock::new(WorkQueue::new());
+static WORK: IrqSpinLock<WorkQueue> = IrqSpinLock::new(WorkQueue::new());
+
+/// Trägt `func(arg)` zur Ausführung nach dem Ende der Interruptbearbeitung ein.
+pub fn defer(func: WorkFn, arg: u32) -> Result<(),DeferError> {
+    BOTTOM_HALVES.lock().push(WorkItem { func: func, arg: arg })
+}
+
+/// Trägt `func(arg)` zur Ausführung durch einen Kernel-Worker ein.
+pub fn schedule_work(func: WorkFn, arg: u32) -> Result<(),DeferError> {
+    WORK.lock().push(WorkItem { func: func, arg: arg })
+}
+
+/// Führt alle anstehenden _bottom halves_ aus.
+///
+/// Wird am Ende der äußersten Interruptbearbeitung gerufen (siehe `IsrTable::dispatch`).
+/// Interrupts werden dafür zugelassen und danach wieder gesperrt.
+pub fn run_bottom_halves() {
+    if BOTTOM_HALVES.lock().len == 0 {
+        return;
+    }
+    Cpu::enable_interrupts();
+    run_queue(&BOTTOM_HALVES);
+    Cpu::disable_interrupts();
+}
+
+/// Führt alle anstehenden Arbeiten aus und gibt ihre Anzahl zurück.
+///
+/// Wird von Kernel-Workern in ihrer Schleife gerufen.
+pub fn run_work() -> usize {
+    run_queue(&WORK)
+}
+
+/// Anzahl der verworfenen Aufträge (_bottom halves_, Arbeiten)
+pub fn dropped() -> (usize, usize) {
+    (BOTTOM_HALVES.lock().dropped, WORK.lock().dropped)
+}
@@ -0,0 +1,118 @@
+#![warn(missing_docs)]
+//! Schneller Interrupt (FIQ).
+//!
+//! Genau eine Interruptquelle kann als FIQ ausgewählt werden (siehe
+//! `IrqController::set_and_enable_fiq`). Dafür gibt es genau eine Serviceroutine, die ohne den
+//! Umweg über die `IsrTable` gerufen wird.
+//!
+//! Im FIQ-Modus sind die Register r8 bis r12 gebankt. Ihr Inhalt bleibt zwischen zwei FIQs
+//! erhalten und kann von der Serviceroutine als Zustand genutzt werden (z.B. als Zähler oder
+//! Pufferzeiger). Der Einsprung (`dispatch_fast_interrupt`) legt die Register auf den FIQ-Stack
+//! und übergibt sie als `FiqRegisters`; Änderungen werden beim Rücksprung zurückgeschrieben.
+//!
+//! Die Serviceroutine läuft mit gesperrten IRQs und FIQs. Sie sollte kurz sein und darf weder
+//! Speicher anfordern noch `kprint!` nutzen.
+use hal::bmc2835::{Bmc2835,Interrupt,IrqController};
+use hal::cpu::Cpu;
+use sync::no_concurrency::NoConcurrency;
+
+/// Gebankte Register des FIQ-Modus
+#[repr(C)]
+#[derive(Copy,Clone,Debug,Default)]
+#[allow(missing_docs)]
+pub struct FiqRegisters {
+    pub r8:  u32,
+    pub r9:  u32,
+    pub r10: u32,
+    pub r11: u32,
+    pub r12: u32,
+}
+
+/// Serviceroutine für den FIQ
+pub type FiqHandler = fn(&mut FiqRegisters);
+
+/// Fehler bei der Anmeldung eines FIQ
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum FiqError {
+    /// Es ist bereits eine Serviceroutine angemeldet
+    AlreadyRegistered,
+    /// Die Interruptquelle kann nicht als FIQ genutzt werden (Sammelinterrupts)
+    InvalidInterrupt,
+}
+
+static FIQ_HANDLER: NoConcurrency<Option<FiqHandler>> = NoConcurrency::new(None);
+static FIQ_SOURCE:  NoConcurrency<Option<usize>>      = NoConcurrency::new(None);
+
+/// Verwaltung des schnellen Interrupts
+pub struct Fiq {}
+
+impl Fiq {
+    /// Meldet `handler` als Serviceroutine für den Interrupt `int` an und aktiviert den FIQ.
+    ///
+    /// Die gebankten Register werden mit `regs` vorbelegt. Der Interrupt wird als normaler
+    /// Interrupt (IRQ) deaktiviert, da er sonst zusätzlich die `IsrTable` auslösen würde.
+    pub fn register<T: Interrupt + Copy>(int: T, handler: FiqHandler, regs: FiqRegisters) -> Result<(),FiqError> {
+        if FIQ_HANDLER.get().is_some() {
+            return Err(FiqError::AlreadyRegistered);
+        }
+        if int.uid().is_none() {
+            return Err(FiqError::InvalidInterrupt);
+        }
+        Cpu::disable_fast_interrupts();
+        FIQ_HANDLER.set(Some(handler));
+        FIQ_SOURCE.set(int.uid());
+        Fiq::set_registers(&regs);
+        IrqController::get()
+            .disable(int)
+            .set_and_enable_fiq(int);
+        Cpu::enable_fast_interrupts();
+        Ok(())
+    }
+
+    /// Deaktiviert den FIQ und meldet die Serviceroutine ab.
+    pub fn unregister() {
+        Cpu::disable_fast_interrupts();
+        IrqController::get().disable_fiq();
+        FIQ_HANDLER.set(None);
+        FIQ_SOURCE.set(None);
+    }
+
+    /// Gibt an, ob eine Serviceroutine angemeldet ist.
+    pub fn is_registered() -> bool {
+        FIQ_HANDLER.get().is_some()
+    }
+
+    /// Gibt die UID der Interruptquelle zurück, die als FIQ dient, oder `None`, wenn keine
+    /// Serviceroutine angemeldet ist.
+    pub fn source() -> Option<usize> {
+        *FIQ_SOURCE.get()
+    }
+
+    /// Ruft die angemeldete Serviceroutine.
+    ///
+    /// Ohne Serviceroutine wird der FIQ abgeschaltet, da er sonst sofort wieder auslösen würde.
+    pub fn dispatch(regs: &mut FiqRegisters) {
+        match *FIQ_HANDLER.get() {
+            Some(handler) => handler(regs),
+            None          => { IrqController::get().disable_fiq(); }
+        }
+    }
+
+    /// Setzt die gebankten Register r8 bis r12 des FIQ-Modus.
+    ///
+    /// Dazu wird kurz in den FIQ-Modus gewechselt; FIQs müssen gesperrt sein.
+    fn set_registers(regs: &FiqRegisters) {
+        unsafe{
+            asm!("mrs r5, cpsr
+                  cps 0x11
+                  mov r8, r0
+                  mov r9, r1
+                  mov r10, r2
+                  mov r11, r3
+                  mov r12, r4
+                  msr cpsr_c, r5"
+                 ::"{r0}"(regs.r8),"{r1}"(regs.r9),"{r2}"(regs.r10),"{r3}"(regs.r11),"{r4}"(regs.r12)
+                 :"r5","memory":"volatile");
+        }
+    }
+}
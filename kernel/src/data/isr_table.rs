@@ -1,78 +1,298 @@
-use hal::bmc2835::{Bmc2835,Interrupt,IrqController,NUM_INTERRUPTS};
-use alloc::boxed::Box;
+use hal::bmc2835::{Bmc2835,Interrupt,IrqController,IrqMask,NUM_INTERRUPTS,FIRST_BASIC_INTERRUPT};
+use hal::bmc2835::{BasicInterrupt,GeneralInterrupt,SystemTimer};
+use hal::cpu::Cpu;
+use data::deferred;
 
-#[derive(Debug,Clone)]
-pub struct Isr {
-    function:     fn(),
-    next:         Option<Box<Isr>>
+/// Priorität eines Interrupts, 0 ist die niedrigste.
+pub type IrqPriority = u8;
+
+/// Anzahl der Prioritätsstufen
+pub const NUM_PRIORITIES: usize = 8;
+
+/// Standardpriorität
+pub const DEFAULT_PRIORITY: IrqPriority = 0;
+
+/// Anzahl aufeinanderfolgender Interrupts, die keine Serviceroutine bearbeitet hat, nach
+/// der der Interrupt gesperrt wird.
+pub const UNCLAIMED_LIMIT: u32 = 100;
+
+/// Maximale Anzahl der Serviceroutinen pro Interrupt
+pub const MAX_ISRS_PER_INTERRUPT: usize = 4;
+
+/// Ergebnis einer Serviceroutine
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum IsrResult {
+    /// Die Serviceroutine hat den Interrupt bearbeitet.
+    Handled,
+    /// Der Interrupt stammt nicht vom Gerät der Serviceroutine (geteilte Interrupts).
+    NotHandled,
 }
 
-impl Isr {
-    pub fn new(func:fn()) -> Isr {
-        Isr {
-            function:  func,
-            next:      None,    
+/// Serviceroutine; das Argument ist der bei der Anmeldung übergebene Kontext
+/// (z.B. die Adresse der Gerätedaten).
+pub type IsrFn = fn(usize) -> IsrResult;
+
+/// Statistik eines Interrupts.
+///
+/// Die Zeiten werden mit dem Systemtimer (1 MHz) gemessen, sind also in µs angegeben. Sie
+/// umfassen alle Serviceroutinen des Interrupts, einschließlich der Zeit für Unterbrechungen
+/// durch Interrupts höherer Priorität.
+#[repr(C)]
+#[derive(Copy,Clone,Debug)]
+pub struct IrqStats {
+    /// Anzahl der aufgetretenen Interrupts
+    pub fired:      u32,
+    /// Anzahl der Interrupts, die eine Serviceroutine bearbeitet hat
+    pub handled:    u32,
+    /// Anzahl der Interrupts ohne Serviceroutine oder ohne bearbeitende Serviceroutine
+    pub spurious:   u32,
+    /// Anzahl der Zeitmessungen
+    pub measured:   u32,
+    /// Kürzeste Bearbeitungszeit
+    pub min_time:   u32,
+    /// Längste Bearbeitungszeit
+    pub max_time:   u32,
+    /// Summe der Bearbeitungszeiten
+    pub total_time: u64,
+}
+
+impl IrqStats {
+    pub const fn new() -> IrqStats {
+        IrqStats {
+            fired:      0,
+            handled:    0,
+            spurious:   0,
+            measured:   0,
+            min_time:   !0,
+            max_time:   0,
+            total_time: 0,
         }
     }
 
-    fn iter(&self) -> IsrIterator {
-        IsrIterator{
-            isr: Some(self.clone())
+    /// Mittlere Bearbeitungszeit
+    pub fn avg_time(&self) -> u32 {
+        if self.measured == 0 {
+            0
+        } else {
+            (self.total_time / self.measured as u64) as u32
+        }
+    }
+
+    fn record_time(&mut self, time: u32) {
+        self.measured = self.measured.wrapping_add(1);
+        self.total_time += time as u64;
+        if time < self.min_time {
+            self.min_time = time;
+        }
+        if time > self.max_time {
+            self.max_time = time;
         }
     }
 }
 
-struct IsrIterator {
-    isr: Option<Isr>
+/// Fehler bei der Anmeldung einer Serviceroutine
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum IsrError {
+    /// Für den Interrupt sind bereits `MAX_ISRS_PER_INTERRUPT` Serviceroutinen angemeldet.
+    TableFull,
+    /// Der Interrupt hat keine UID (Sammelinterrupt `General1` oder `General2`).
+    InvalidInterrupt,
 }
 
+/// Kennung einer angemeldeten Serviceroutine, siehe `IsrTable::remove_isr`.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct IsrHandle {
+    uid: usize,
+    id:  usize,
+}
 
-impl Iterator for IsrIterator {
-    type Item= fn();
-    
-    fn next(&mut self) -> Option<fn()> {
-        let ret = 
-            if let Some(ref isr) = self.isr {
-                Some(isr.function)
-            } else {
-                None
-            };
-        if ret.is_some() {
-            let new_isr = self.isr.clone();
-            self.isr = new_isr.unwrap().next.map_or(None, |obj| Some(*obj));
+#[derive(Debug,Clone,Copy)]
+pub struct Isr {
+    function:     IsrFn,
+    context:      usize,
+    id:           usize,
+}
+
+impl Isr {
+    pub fn new(func: IsrFn, context: usize) -> Isr {
+        Isr {
+            function:  func,
+            context:   context,
+            id:        0,
         }
-        ret
+    }
+
+    /// Ruft die Serviceroutine.
+    fn call(&self) -> IsrResult {
+        (self.function)(self.context)
     }
 }
 
+/// Serviceroutinen eines Interrupts in der Reihenfolge ihrer Anmeldung.
+///
+/// Die Kette hat eine feste Größe, damit bei der Interruptbearbeitung kein Speicher
+/// angefordert werden muss.
+type IsrChain = [Option<Isr>; MAX_ISRS_PER_INTERRUPT];
 
+/// Tabelle der Serviceroutinen.
+///
+/// # Prioritäten
+/// Jeder Interrupt hat eine Priorität. Während die Serviceroutinen eines Interrupts laufen, sind
+/// alle Interrupts gleicher oder niedrigerer Priorität im `IrqController` gesperrt, die übrigen
+/// Interrupts sind zugelassen. Ein Interrupt höherer Priorität kann also eine laufende
+/// Serviceroutine unterbrechen. Da zu Beginn alle Interrupts die gleiche Priorität haben, gibt
+/// es ohne Aufruf von `set_priority` keine Verschachtelung.
+///
+/// Anliegende Interrupts werden in der Reihenfolge ihrer Priorität bearbeitet.
+///
+/// # Unbearbeitete Interrupts
+/// Liegt ein Interrupt an, für den keine Serviceroutine angemeldet ist, wird er sofort im
+/// `IrqController` gesperrt. Melden alle Serviceroutinen eines Interrupts `UNCLAIMED_LIMIT` mal
+/// hintereinander `IsrResult::NotHandled`, wird er ebenfalls gesperrt. Beides wird gezählt.
+///
+/// Die Bearbeitung (`dispatch`) fordert keinen Speicher an.
 pub struct IsrTable {
-    table: [Option<Isr>; NUM_INTERRUPTS]
+    table:     [IsrChain; NUM_INTERRUPTS],
+    priority:  [IrqPriority; NUM_INTERRUPTS],
+    /// Für jede Stufe die Interrupts mit gleicher oder niedrigerer Priorität
+    masks:     [IrqMask; NUM_PRIORITIES],
+    /// Schachtelungstiefe der Interruptbearbeitung
+    depth:     usize,
+    /// Nächste Kennung für eine Serviceroutine
+    next_id:   usize,
+    /// Statistik pro Interrupt
+    stats:     [IrqStats; NUM_INTERRUPTS],
+    /// Anzahl unbearbeiteter Interrupts seit dem letzten bearbeiteten
+    unclaimed: [u32; NUM_INTERRUPTS],
 }
 
 impl IsrTable {
-    /// 
+    ///
     pub fn new() -> IsrTable {
         IsrTable {
-            // Diese umständliche Art der Initialisierung ist nötig, das Isr nicht "Copy" ist und "Default"
-            // nur bis maximal 32 Elemente funktioniert.
-            table: 
-            [None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,
-             None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,
-             None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,None,
-             None,None,None,None,None,None,None,None,None,None,None,None]
+            table:     [[None; MAX_ISRS_PER_INTERRUPT]; NUM_INTERRUPTS],
+            priority:  [DEFAULT_PRIORITY; NUM_INTERRUPTS],
+            masks:     [IsrTable::all_interrupts(); NUM_PRIORITIES],
+            depth:     0,
+            next_id:   1,
+            stats:     [IrqStats::new(); NUM_INTERRUPTS],
+            unclaimed: [0; NUM_INTERRUPTS],
         }
     }
 
+    fn all_interrupts() -> IrqMask {
+        let mut mask = IrqMask::empty();
+        for uid in 0..NUM_INTERRUPTS {
+            mask.insert(uid);
+        }
+        mask
+    }
+
+    /// Setzt die Priorität des Interrupts `int`.
+    ///
+    /// Prioritäten über `NUM_PRIORITIES - 1` werden auf die höchste Stufe begrenzt. Für
+    /// Interrupts ohne UID (Sammelinterrupts) passiert nichts.
+    pub fn set_priority<T: Interrupt + Sized>(&mut self, int: T, prio: IrqPriority) {
+        let uid = match int.uid() {
+            Some(uid) => uid,
+            None      => return,
+        };
+        let prio = if prio as usize >= NUM_PRIORITIES { (NUM_PRIORITIES - 1) as IrqPriority } else { prio };
+        self.priority[uid] = prio;
+        for level in 0..NUM_PRIORITIES {
+            let mut mask = IrqMask::empty();
+            for (uid, p) in self.priority.iter().enumerate() {
+                if *p as usize <= level {
+                    mask.insert(uid);
+                }
+            }
+            self.masks[level] = mask;
+        }
+    }
+
+    /// Gibt die Priorität des Interrupts `int` zurück.
+    pub fn priority<T: Interrupt + Sized>(&self, int: T) -> IrqPriority {
+        int.uid().map_or(0, |uid| self.priority[uid])
+    }
+
     /// Füge für Interrupt `int` eine Serviceroutine hinzu.
-    pub fn add_isr<T: Interrupt + Sized>(&mut self, int: T, func: fn()) {
-        let mut isr: Isr = Isr::new(func);
-        let ndx = int.uid();
+    ///
+    /// Die Serviceroutinen eines Interrupts werden in der Reihenfolge ihrer Anmeldung gerufen.
+    /// Über das Ergebnis kann die Serviceroutine mit `remove_isr` wieder entfernt werden.
+    pub fn add_isr<T: Interrupt + Sized>(&mut self, int: T, func: IsrFn, context: usize) -> Result<IsrHandle,IsrError> {
+        let mut isr: Isr = Isr::new(func, context);
+        let ndx = int.uid().ok_or(IsrError::InvalidInterrupt)?;
         kprint!("Add ISF for Interrupt {}.\n",ndx; BLUE);
-        if self.table[ndx].is_some() {
-            isr.next = Some(Box::new(self.table[ndx].clone().unwrap()));
-        } 
-        self.table[ndx] = Some(isr);
+        let state = Cpu::save_and_disable_interrupts();
+        let res = match self.table[ndx].iter().position(|entry| entry.is_none()) {
+            Some(slot) => {
+                isr.id = self.next_id;
+                self.next_id += 1;
+                self.table[ndx][slot] = Some(isr);
+                self.unclaimed[ndx] = 0;
+                Ok(IsrHandle {
+                    uid: ndx,
+                    id:  isr.id,
+                })
+            },
+            None => Err(IsrError::TableFull)
+        };
+        Cpu::restore_interrupts(state);
+        res
+    }
+
+    /// Entfernt die Serviceroutine mit der Kennung `handle`.
+    ///
+    /// Gibt `false` zurück, wenn die Serviceroutine nicht (mehr) angemeldet ist. Der Interrupt
+    /// bleibt aktiv; liegt er ohne Serviceroutine an, wird er bei der Bearbeitung gesperrt.
+    pub fn remove_isr(&mut self, handle: IsrHandle) -> bool {
+        let state = Cpu::save_and_disable_interrupts();
+        let chain = &mut self.table[handle.uid];
+        let found = chain.iter().position(|entry| entry.map_or(false, |isr| isr.id == handle.id));
+        if let Some(slot) = found {
+            // Die folgenden Einträge rücken nach, damit die Reihenfolge erhalten bleibt.
+            for i in slot .. MAX_ISRS_PER_INTERRUPT - 1 {
+                chain[i] = chain[i + 1];
+            }
+            chain[MAX_ISRS_PER_INTERRUPT - 1] = None;
+        }
+        Cpu::restore_interrupts(state);
+        found.is_some()
+    }
+
+    /// Anzahl der Interrupts `int`, die keine Serviceroutine bearbeitet hat.
+    pub fn unhandled_count<T: Interrupt + Sized>(&self, int: T) -> u32 {
+        int.uid().map_or(0, |uid| self.stats[uid].spurious)
+    }
+
+    /// Statistik des Interrupts mit der UID `uid`
+    pub fn stats(&self, uid: usize) -> Option<IrqStats> {
+        if uid < NUM_INTERRUPTS {
+            Some(self.stats[uid])
+        } else {
+            None
+        }
+    }
+
+    /// Gibt die Statistik aller bisher aufgetretenen Interrupts aus (ähnlich `/proc/interrupts`).
+    ///
+    /// Die Statistik wird kopiert, damit während der Ausgabe Interrupts zugelassen sind.
+    pub fn dump_stats() {
+        use data::kernel::KernelData;
+        let stats = KernelData::isr_table().stats;
+        kprint!("  IRQ  ausgelöst bearbeitet unbearbeitet    min    avg    max (µs)\n"; WHITE);
+        for (uid, s) in stats.iter().enumerate() {
+            if s.fired == 0 {
+                continue;
+            }
+            if s.measured == 0 {
+                kprint!("  {:3} {:10} {:10} {:12}      -      -      -\n",
+                        uid, s.fired, s.handled, s.spurious; WHITE);
+            } else {
+                kprint!("  {:3} {:10} {:10} {:12} {:6} {:6} {:6}\n",
+                        uid, s.fired, s.handled, s.spurious, s.min_time, s.avg_time(), s.max_time; WHITE);
+            }
+        }
     }
 
 #[inline(never)]
@@ -80,24 +300,124 @@ impl IsrTable {
 #[allow(private_no_mangle_fns)]
 #[linkage="weak"] // Verhindert, dass der Optimierer die Funktion eliminiert
     /// Rufe für alle anliegenden Interrupts alle Serviceroutinen auf.
+    ///
+    /// Die Interrupts werden nach absteigender Priorität bearbeitet. Wird im SVC-Modus mit
+    /// gesperrten Interrupts gerufen (siehe `dispatch_interrupt`).
+    ///
+    /// Am Ende der äußersten Interruptbearbeitung werden die verzögerten Arbeiten
+    /// (_bottom halves_) ausgeführt, siehe `data::deferred`.
     pub fn dispatch() {
         use data::kernel::KernelData;
-        let isr_table = KernelData::isr_table();
+        KernelData::isr_table().depth += 1;
+        IsrTable::dispatch_pending();
+        let depth = {
+            let mut isr_table = KernelData::isr_table();
+            isr_table.depth -= 1;
+            isr_table.depth
+        };
+        if depth == 0 {
+            deferred::run_bottom_halves();
+        }
+    }
+
+    /// Bearbeitet alle anliegenden Interrupts.
+    ///
+    /// Die Tabelle ist nur belegt, während Kette und Maske kopiert und die Statistik
+    /// nachgetragen werden. Die Serviceroutinen laufen ohne gehaltenen Lock, damit sie
+    /// selbst Serviceroutinen anmelden und von Interrupts höherer Priorität unterbrochen
+    /// werden können.
+    fn dispatch_pending() {
+        use data::kernel::KernelData;
         let irq_controller = IrqController::get();
-        //let next: Isr;
-        for int in irq_controller.get_all_pending() {
-            //kprint!("Suche ISR for int #{}...",int;WHITE);
-            if let Some(ref mut isr) = isr_table.table[int] {
-                for func in isr.iter() {
-                    //kprint!("gefunden.\n";WHITE);
-                    func();
+        let pending = irq_controller.pending();
+        for level in (0..NUM_PRIORITIES).rev() {
+            for int in pending.iter() {
+                let (chain, mask) = {
+                    let mut isr_table = KernelData::isr_table();
+                    if isr_table.priority[int] as usize != level {
+                        continue;
+                    }
+                    isr_table.stats[int].fired = isr_table.stats[int].fired.wrapping_add(1);
+                    (isr_table.table[int], isr_table.masks[level])
+                };
+                let result = if chain[0].is_some() {
+                    let timer = SystemTimer::get();
+                    let start = timer.get_long_counter();
+                    let result = IsrTable::run(&chain, mask);
+                    let time = timer.get_long_counter() - start;
+                    Some((result, time as u32))
+                } else {
+                    None
+                };
+                let mut isr_table = KernelData::isr_table();
+                match result {
+                    Some((IsrResult::Handled, time)) => {
+                        isr_table.stats[int].record_time(time);
+                        isr_table.stats[int].handled = isr_table.stats[int].handled.wrapping_add(1);
+                        isr_table.unclaimed[int] = 0;
+                    },
+                    Some((IsrResult::NotHandled, time)) => {
+                        isr_table.stats[int].record_time(time);
+                        isr_table.stats[int].spurious = isr_table.stats[int].spurious.wrapping_add(1);
+                        isr_table.unclaimed[int] += 1;
+                        if isr_table.unclaimed[int] >= UNCLAIMED_LIMIT {
+                            IsrTable::disable_unhandled(int);
+                        }
+                    },
+                    None => {
+                        // Ohne Serviceroutine würde der Interrupt sofort erneut auslösen.
+                        isr_table.stats[int].spurious = isr_table.stats[int].spurious.wrapping_add(1);
+                        IsrTable::disable_unhandled(int);
+                    }
                 }
-            } else {
-                //kprint!("NICHT gefunden.\n";WHITE);
-                // ToDo: Wenn keine ISR definiert ist, sperre den Interrupt.
-                //irq_controller.
             }
         }
-                
     }
+
+    /// Ruft die Serviceroutinen `chain` eines Interrupts; `mask` sind die Interrupts gleicher
+    /// oder niedrigerer Priorität.
+    ///
+    /// Während der Ausführung sind nur Interrupts höherer Priorität zugelassen. Danach werden
+    /// genau die Interrupts wieder aktiviert, die vorher aktiv waren und nicht währenddessen
+    /// (z.B. von einer Serviceroutine) gesperrt wurden.
+    /// Gibt `IsrResult::Handled` zurück, wenn mindestens eine Serviceroutine den Interrupt
+    /// bearbeitet hat.
+    fn run(chain: &IsrChain, mask: IrqMask) -> IsrResult {
+        let irq_controller = IrqController::get();
+        let masked = mask.intersect(&irq_controller.enabled());
+        let mut result = IsrResult::NotHandled;
+        // Die Kette ist eine Kopie, damit eine Serviceroutine sich selbst (oder andere)
+        // abmelden kann, ohne die laufende Bearbeitung zu stören.
+        irq_controller.disable_mask(masked).forget_disabled(masked);
+        Cpu::data_synchronization_barrier();
+        Cpu::enable_interrupts();
+        for isr in chain.iter().filter_map(|entry| entry.as_ref()) {
+            if isr.call() == IsrResult::Handled {
+                result = IsrResult::Handled;
+            }
+        }
+        Cpu::disable_interrupts();
+        // Was eine Serviceroutine währenddessen gesperrt hat, bleibt gesperrt.
+        let restore = irq_controller.without_disabled(masked);
+        irq_controller.enable_mask(restore);
+        result
+    }
+
+    /// Sperrt den Interrupt mit der UID `uid` und meldet dies nach der Interruptbearbeitung.
+    fn disable_unhandled(uid: usize) {
+        let irq_controller = IrqController::get();
+        if uid < FIRST_BASIC_INTERRUPT {
+            if let Some(int) = GeneralInterrupt::from_uid(uid) {
+                irq_controller.disable(int);
+            }
+        } else if let Some(int) = BasicInterrupt::from_uid(uid) {
+            irq_controller.disable(int);
+        }
+        let _ = deferred::defer(report_disabled, uid as u32);
+    }
+}
+
+/// Verzögerte Meldung eines gesperrten Interrupts
+fn report_disabled(uid: u32) {
+    kprint!("Interrupt {} ohne Bearbeitung, gesperrt.\n",uid; RED);
 }
@@ -1,5 +1,6 @@
-use memory::{PageTable, FrameManager,PageDirectory};
-use sync::no_concurrency::NoConcurrency;
+use core::ops::{Deref, DerefMut};
+use memory::{PageTable, FrameManager,PageDirectory,StackLayout};
+use sync::{IrqSpinLock, IrqSpinLockGuard};
 
 use data::isr_table::IsrTable;
 
@@ -29,54 +30,101 @@ impl KernelData {
     }
 }
 
-static KERNEL_DATA: NoConcurrency<KernelData> = NoConcurrency::new(KernelData::new());
+static KERNEL_DATA: IrqSpinLock<KernelData> = IrqSpinLock::new(KernelData::new());
+
+/// Belegte Seitentabellen des Kernels, siehe `KernelData::page_tables`
+pub struct KernelPageTables {
+    guard: IrqSpinLockGuard<'static, KernelData>,
+}
+
+impl KernelPageTables {
+    /// Seitentabellen für Code, Daten und Heap (`kpages`) und für die Stacks (`spages`)
+    pub fn split(&mut self) -> (&mut PageTable, &mut PageTable) {
+        let data = &mut *self.guard;
+        (&mut data.kpages, &mut data.spages)
+    }
+}
+
+/// Belegte Interrupttabelle, siehe `KernelData::isr_table`
+pub struct IsrTableGuard {
+    guard: IrqSpinLockGuard<'static, KernelData>,
+}
+
+impl Deref for IsrTableGuard {
+    type Target = IsrTable;
+
+    fn deref(&self) -> &IsrTable {
+        self.guard.isr_table.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for IsrTableGuard {
+    fn deref_mut(&mut self) -> &mut IsrTable {
+        self.guard.isr_table.as_mut().unwrap()
+    }
+}
 
 impl KernelData {
     #[allow(dead_code)]
     pub fn get_pid() -> PidType {
-        KERNEL_DATA.get().pid
+        KERNEL_DATA.lock().pid
     }
 
     #[allow(dead_code)]
     pub fn set_pid(pid: PidType) {
-        KERNEL_DATA.get().pid = pid
+        KERNEL_DATA.lock().pid = pid
     }
 
     #[allow(dead_code)]
     pub fn get_toss() -> Option<usize> {
-        KERNEL_DATA.get().toss
+        KERNEL_DATA.lock().toss
     }
 
     #[allow(dead_code)]
     pub fn set_toss(tos: usize) {
-        KERNEL_DATA.get().toss = Some(tos);
+        KERNEL_DATA.lock().toss = Some(tos);
     }
 
+    /// Lage der Kernel-Stacks, sofern das obere Ende bereits bestimmt wurde.
+    ///
+    /// Wird auch im Abort-Modus gerufen; ist die Kerneldatenstruktur gerade belegt, wird
+    /// `None` zurückgegeben.
     #[allow(dead_code)]
-    pub fn kpages<'a>() -> &'a mut PageTable {
-        &mut KERNEL_DATA.get().kpages
+    pub fn stack_layout() -> Option<StackLayout> {
+        KERNEL_DATA.try_lock().and_then(|data| data.toss).map(|tos| StackLayout::new(tos))
     }
 
+    /// Belegt die Kerneldaten und gibt die Seitentabellen des Kernels zurück.
     #[allow(dead_code)]
-    pub fn spages<'a>() -> &'a mut PageTable {
-        &mut KERNEL_DATA.get().spages
+    pub fn page_tables() -> KernelPageTables {
+        KernelPageTables {
+            guard: KERNEL_DATA.lock(),
+        }
     }
 
     #[allow(dead_code)]
-    pub fn frame_allocator<'a>() -> &'a mut FrameManager {
+    pub fn frame_allocator() -> IrqSpinLockGuard<'static, FrameManager> {
         FrameManager::get()
     }
 
     #[allow(dead_code)]
-    pub fn page_directory<'a>() -> &'a mut PageDirectory {
+    pub fn page_directory() -> IrqSpinLockGuard<'static, PageDirectory> {
         PageDirectory::get()
     }
 
+    /// Belegt die Kerneldaten und gibt die Interrupttabelle zurück; sie wird beim ersten
+    /// Zugriff angelegt.
+    ///
+    /// Solange der Guard lebt, sind Interrupts gesperrt. Serviceroutinen laufen daher ohne
+    /// gehaltenen Guard, siehe `IsrTable::dispatch`.
     #[allow(dead_code)]
-    pub fn isr_table<'a>() -> &'a mut IsrTable {
-        if !KERNEL_DATA.get().isr_table.is_some() {
-            KERNEL_DATA.get().isr_table = Some(IsrTable::new());
+    pub fn isr_table() -> IsrTableGuard {
+        let mut guard = KERNEL_DATA.lock();
+        if guard.isr_table.is_none() {
+            guard.isr_table = Some(IsrTable::new());
+        }
+        IsrTableGuard {
+            guard: guard,
         }
-        KERNEL_DATA.get().isr_table.as_mut().unwrap()
     }
 }
@@ -2,3 +2,6 @@ pub mod kernel;
 pub mod isr_table;
 #[macro_use]
 pub mod bit_pos_enum;
+pub mod fiq;
+pub mod deferred;
+pub mod ring_buffer;
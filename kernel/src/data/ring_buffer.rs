@@ -0,0 +1,101 @@
+#![warn(missing_docs)]
+//! Ringpuffer fester Größe für Bytes.
+//!
+//! Wird von Gerätetreibern genutzt, um Daten zwischen Serviceroutine und Aufrufer
+//! auszutauschen. Der Puffer selbst ist nicht synchronisiert, er muss z.B. in einem
+//! `IrqSpinLock` liegen.
+
+/// Kapazität eines `RingBuffer`
+pub const RING_BUFFER_SIZE: usize = 256;
+
+/// Ringpuffer für Bytes
+pub struct RingBuffer {
+    buf:  [u8; RING_BUFFER_SIZE],
+    head: usize,
+    len:  usize,
+}
+
+impl RingBuffer {
+    /// Leerer Puffer
+    pub const fn new() -> RingBuffer {
+        RingBuffer {
+            buf:  [0; RING_BUFFER_SIZE],
+            head: 0,
+            len:  0,
+        }
+    }
+
+    /// Hängt ein Byte an; `false`, wenn der Puffer voll ist.
+    pub fn push(&mut self, b: u8) -> bool {
+        if self.is_full() {
+            false
+        } else {
+            self.buf[(self.head + self.len) % RING_BUFFER_SIZE] = b;
+            self.len += 1;
+            true
+        }
+    }
+
+    /// Entnimmt das älteste Byte.
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            None
+        } else {
+            let b = self.buf[self.head];
+            self.head = (self.head + 1) % RING_BUFFER_SIZE;
+            self.len -= 1;
+            Some(b)
+        }
+    }
+
+    /// Hängt so viele Bytes aus `data` an, wie Platz ist, und gibt deren Anzahl zurück.
+    pub fn push_slice(&mut self, data: &[u8]) -> usize {
+        let mut n = 0;
+        for &b in data {
+            if !self.push(b) {
+                break;
+            }
+            n += 1;
+        }
+        n
+    }
+
+    /// Entnimmt so viele Bytes, wie in `data` passen, und gibt deren Anzahl zurück.
+    pub fn pop_slice(&mut self, data: &mut [u8]) -> usize {
+        let mut n = 0;
+        for d in data.iter_mut() {
+            match self.pop() {
+                Some(b) => *d = b,
+                None    => break
+            }
+            n += 1;
+        }
+        n
+    }
+
+    /// Verwirft alle Bytes.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Anzahl der enthaltenen Bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Anzahl der freien Plätze
+    pub fn free(&self) -> usize {
+        RING_BUFFER_SIZE - self.len
+    }
+
+    /// Ist der Puffer leer?
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Ist der Puffer voll?
+    pub fn is_full(&self) -> bool {
+        self.len == RING_BUFFER_SIZE
+    }
+}
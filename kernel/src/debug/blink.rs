@@ -43,9 +43,16 @@ fn sleep(value: u32) {
     }
 }
 
+/// Belegt die LED für die Blinkzeichen im Verzeichnis der Pins; beim Start rufen.
+pub fn init() {
+    Led::init(LedType::Green);
+}
+
 /// Gibt eine Blinksequenz am LED aus
+///
+/// Läuft auch in Fehlerpfaden und greift daher nicht auf das Verzeichnis der Pins zu.
 pub fn blink_once(s: BlinkSeq) {
-    let mut led = Led::init(LedType::Green);
+    let mut led = Led::raw(LedType::Green);
     //let led_on  = GPSET1;
     //let led_off = GPCLR1; 
 
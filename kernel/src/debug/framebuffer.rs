@@ -1,7 +1,7 @@
 use core::{fmt,cmp,slice};
 //use core::fmt::Write;
 //use core::ops::{DerefMut, Deref};
-use ::hal::bmc2835::{mailbox, Channel,Tag,PropertyTagBuffer};
+use ::hal::bmc2835::{Tag,PropertyTagBuffer};
 use debug::font::{Font,SystemFont};
 use blink;
 
@@ -46,9 +46,7 @@ impl<'a> Framebuffer<'a> {
         prob_tag_buf.add_tag_with_param(Tag::SetDepth,Some(&[FB_COLOR_DEP]));
         prob_tag_buf.add_tag_with_param(Tag::AllocateFrameBuffer,Some(&[16]));
         prob_tag_buf.add_tag_with_param(Tag::GetPitch,None);
-        let mb = mailbox(0);
-        mb.write(Channel::ATags, prob_tag_buf.data_addr() as u32);
-        mb.read(Channel::ATags);
+        prob_tag_buf.exchange();
         // Die Antwort enthält die Speicheradresse des Framebuffers
         let ret = prob_tag_buf.get_answer(Tag::AllocateFrameBuffer);
         let adr: &'a mut[u32];
@@ -112,9 +110,7 @@ impl<'a> Framebuffer<'a> {
         let mut prob_tag_buf: PropertyTagBuffer = PropertyTagBuffer::new();
         prob_tag_buf.init();
         prob_tag_buf.add_tag_with_param(Tag::SetVirtualOffset,Some(&[0,0]));
-        let mb = mailbox(0);
-        mb.write(Channel::ATags, prob_tag_buf.data_addr() as u32);
-        mb.read(Channel::ATags);
+        prob_tag_buf.exchange();
     }
     /// Gibt alle Zeichen einer Zeichenkette aus
     pub fn print(&mut self, s: &str) {
@@ -200,11 +196,9 @@ impl<'a> Framebuffer<'a> {
             }
         }
         let mut prob_tag_buf: PropertyTagBuffer = PropertyTagBuffer::new();
-        let mb = mailbox(0);
         prob_tag_buf.init();
         prob_tag_buf.add_tag_with_param(Tag::SetVirtualOffset,Some(&[0,self.y_offset]));
-        mb.write(Channel::ATags, prob_tag_buf.data_addr() as u32);
-        mb.read(Channel::ATags);
+        prob_tag_buf.exchange();
     }
 
     /// Getter für aktuelle Farbe
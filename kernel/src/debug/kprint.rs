@@ -1,5 +1,5 @@
 use core::fmt::{write,Arguments};
-use sync::no_concurrency::NoConcurrency;
+use sync::IrqSpinLock;
 use debug::framebuffer::Framebuffer;
 
 #[allow(dead_code)]
@@ -36,57 +36,54 @@ pub const GRAY:u32 =       0x007f7f7f;
 pub const BLACK:u32 =      0x00000000;
 
 #[doc(hidden)]
-static _KPRINT_FB: NoConcurrency<Option<Framebuffer<'static>>> = NoConcurrency::new(None);
+static _KPRINT_FB: IrqSpinLock<Option<Framebuffer<'static>>> = IrqSpinLock::new(None);
 
-#[doc(hidden)]
-pub fn fkprintc(arg: Arguments,color: u32) {
-    let fbo = _KPRINT_FB.get();
-    match *fbo {
-        Some(ref mut fb) => {
-            let c_old = fb.get_color();
-            fb.set_color(color);
-            write(fb,arg).expect("");
-            fb.set_color(c_old);
-        },
-        None => {
-            kprint_init();
-            fkprintc(arg, color);
+/// Ruft `f` mit dem Framebuffer der Konsole und legt ihn beim ersten Mal an.
+///
+/// Ist die Konsole bereits belegt (Ausgabe während einer Ausgabe, z.B. durch eine Panik beim
+/// Schreiben), wird die Ausgabe verworfen, statt sich selbst zu blockieren.
+fn with_console<F: FnOnce(&mut Framebuffer<'static>)>(f: F) {
+    if let Some(mut fbo) = _KPRINT_FB.try_lock() {
+        if fbo.is_none() {
+            let mut fb = Framebuffer::new();
+            fb.clear();
+            *fbo = Some(fb);
+        }
+        if let Some(ref mut fb) = *fbo {
+            f(fb);
         }
     }
 }
 
+#[doc(hidden)]
+pub fn fkprintc(arg: Arguments,color: u32) {
+    with_console(|fb| {
+        let c_old = fb.get_color();
+        fb.set_color(color);
+        write(fb,arg).expect("");
+        fb.set_color(c_old);
+    });
+}
+
 #[doc(hidden)]
 pub fn fkprint(arg: Arguments) {
-    let fbo = _KPRINT_FB.get();
-    match *fbo {
-        Some(ref mut fb) => {
-            write(fb,arg).expect("");
-        },
-        None => {
-            kprint_init();
-            fkprint(arg);
-        }
-    }
+    with_console(|fb| {
+        write(fb,arg).expect("");
+    });
 }
 
 #[doc(hidden)]
 pub fn kprint_init() {
-    _KPRINT_FB.set(Some(::debug::framebuffer::Framebuffer::new()));
+    let fb = Framebuffer::new();
+    if let Some(mut fbo) = _KPRINT_FB.try_lock() {
+        *fbo = Some(fb);
+    }
     kprint_clear()
 }
 
 
 pub fn kprint_clear() {
-    let fbo = _KPRINT_FB.get();
-    match *fbo {
-        Some(ref mut fb) => {
-            fb.clear();
-        },
-        None => {
-            kprint_init();
-            kprint_clear();
-        }
-    }
+    with_console(|fb| fb.clear());
 }
 
 #[macro_export]
@@ -101,6 +98,7 @@ macro_rules! kprint {
 //#[cfg(feature="debug")]
 #[doc(hidden)]
 pub fn deb_info() {
-    let addr =  _KPRINT_FB.get().as_ref().unwrap().info_addr();
+    let mut addr = 0;
+    with_console(|fb| addr = fb.info_addr());
     kprint!("0x{:08x} ({:10}): Framebuffer\n",addr,addr;WHITE);
 }
@@ -1,10 +1,12 @@
 #![allow(dead_code)]
-use hal::cpu::Cpu;
+use hal::cpu::{Cpu,MMU};
 use hal::bmc2835::Bmc2835;
 use hal::bmc2835::ArmTimer;
 use syscall_interface::{SysCall};
 use ::kernel_start;
 use data::isr_table::IsrTable;
+use data::fiq::{Fiq,FiqRegisters};
+use data::kernel::KernelData;
 
 //use debug::blink;
 
@@ -53,7 +55,7 @@ pub struct ServiceRoutine{
     abort:      fn(*const u32),
     data_abort: fn(*const u32),
     irq:        fn(),
-    fiq:        fn(),
+    fiq:        fn(&mut FiqRegisters),
 }
 
 #[allow(non_upper_case_globals)]
@@ -63,7 +65,7 @@ pub static service_routine: ServiceRoutine =  ServiceRoutine{
             abort:      abort_service_routine,
             data_abort: data_abort_service_routine,
             irq:        IsrTable::dispatch,
-            fiq:        IsrTable::dispatch,
+            fiq:        Fiq::dispatch,
 };
 
 #[naked]
@@ -91,7 +93,9 @@ pub extern "C" fn dispatch_svc(nr: u32, arg1: u32, arg2: u32, arg3: u32){
          //asm!("sub sp, sp, r5":::"memory");
          //asm!("push {r0,r5}":::"memory");
          Cpu::data_memory_barrier();
-         asm!("bl svc_service_routine":::"memory","r0","r1");
+         // Der Rückgabewert ersetzt das gesicherte r0, sonst würde ihn `ldmfd` überschreiben.
+         asm!("bl svc_service_routine
+               str r0, [sp]":::"memory","r0","r1","r2","r3","r12","lr");
          Cpu::data_memory_barrier();
          //asm!("pop {r0,r5}":::"memory");
          //asm!("add sp, sp, r5":::"memory");
@@ -112,7 +116,9 @@ pub extern "C" fn dispatch_prefetch_abort() {
 pub extern "C" fn dispatch_data_abort() {
     //Cpu::save_context();
     unsafe {
-        asm!("mov lr, r0":::"memory");
+        // Das Linkregister zeigt 8 Bytes hinter den Befehl, der den Fehler ausgelöst hat,
+        // siehe ARM ARM A2.6.6. Diese Adresse wird als Argument übergeben.
+        asm!("sub r0, lr, #8":::"memory");
         asm!("blx $0"::"r"(service_routine.data_abort):"r0","r1","r2","r3","r4","r5",
              "r6","r7","r8","r9","r10","r11","memory":"alignstack","volatile");
     }
@@ -127,18 +133,19 @@ pub extern "C" fn dispatch_interrupt() {
         // siehe ARM ARM A2.6.8 (Seite A2-24).
         // Daher wird es um eine Befehlsgröße dekrementiert.
         asm!("sub lr, lr, #4":::"memory");
-        // Linkregister und SPSR werden auf den Svc(!)-Stack gelegt.
-        asm!("push {lr}":::"memory");
-        asm!("mrs lr, spsr":::"memory");
-        asm!("push {lr}":::"memory");
+        // Linkregister und SPSR werden auf den Svc(!)-Stack gelegt (srs = store return state).
+        // Damit können die Serviceroutinen im SVC-Modus unterbrochen werden: ein weiterer
+        // Interrupt überschreibt zwar lr und SPSR des IRQ-Modus, diese sind aber bereits gesichert.
+        asm!("srsdb sp!, #0x13":::"memory");
         // Wechsel in den SVC-Modus, Interrupt gesperrt
-        //asm!("cpsid i, 0x13");
-        // Rette alle allgemeinen Register
+        asm!("cps 0x13":::"memory");
+        // Rette alle allgemeinen Register und das Linkregister des SVC-Modus, das bei einer
+        // Unterbrechung des Kernels noch gebraucht wird.
         //
         // # Anmerkung
         // Sobald Prozesse existieren, solle der Stack des unterbrochenen
         // Prozesses (m.H.d. Sys-Modes) genutzt werden
-        asm!("push {r0-r12}":::"memory");
+        asm!("push {r0-r12, lr}":::"memory");
         // Externe Funktionen dürfen nur mit einem Stackalignment von 8 gerufen werden,
         // siehe 5.2.1.2 (Seite 17) des "Procedure Call Standard for the ARM® Architecture"
         // (http://infocenter.arm.com/help/topic/com.arm.doc.ihi0042e/IHI0042E_aapcs.pdf)
@@ -155,7 +162,8 @@ pub extern "C" fn dispatch_interrupt() {
         // Stelle sicher, dass vor dem Ruf der "normalen" Service-Funktion alle Speicher-
         // operationen beendet sind.
         Cpu::data_memory_barrier();
-        // Rufe eigentliche ISR.
+        // Rufe eigentliche ISR. Sie lässt für die Dauer der Serviceroutinen Interrupts höherer
+        // Priorität zu, siehe `IsrTable::dispatch`.
         IsrTable::dispatch();
         //asm!("bl interrupt_service":::"memory");
         Cpu::data_memory_barrier();
@@ -163,20 +171,30 @@ pub extern "C" fn dispatch_interrupt() {
         asm!("pop {r0,r5}":::"memory");
         asm!("add sp, sp, r5":::"memory");
         // Hole gesicherte Register
-        asm!("pop {r0-r12}":::"memory");
-        //Cpu::enable_interrupts();
-        // Hole SPSR und PC => Rücksprung.
-        asm!("pop {lr}":::"memory");
-        asm!("msr spsr, lr":::"memory");
-        asm!("pop {lr}":::"memory");
-        //asm!("rfeia sp!");
-        asm!("movs pc, lr":::"memory");
+        asm!("pop {r0-r12, lr}":::"memory");
+        // Hole PC und SPSR => Rücksprung (rfe = return from exception).
+        asm!("rfeia sp!":::"memory");
     }
 }
 
 #[naked]
-#[allow(unreachable_code)] // remove after debug!!
 pub extern "C" fn dispatch_fast_interrupt() {
+    unsafe {
+        // r8 bis r12 und lr sind im FIQ-Modus gebankt, r0 bis r3 werden von der Serviceroutine
+        // ggf. verändert (r4 bis r7 sichert sie nach AAPCS selbst). Die gebankten Register werden
+        // mitgesichert, damit die Serviceroutine sie als `FiqRegisters` lesen und ändern kann.
+        // Es werden 10 Register gesichert, das Stackalignment von 8 bleibt also erhalten.
+        asm!("push {r0-r3, r8-r12, lr}":::"memory");
+        // Zeiger auf die gesicherten r8 bis r12
+        asm!("add r0, sp, #16":::"memory");
+        Cpu::data_memory_barrier();
+        asm!("bl fiq_service_routine":::"memory","r0","r1","r2","r3","r12","lr");
+        Cpu::data_memory_barrier();
+        asm!("pop {r0-r3, r8-r12, lr}":::"memory");
+        // Das Linkregister zeigt (wie beim IRQ) auf den übernächsten Befehl,
+        // siehe ARM ARM A2.6.9. `subs` stellt zugleich das CPSR aus dem SPSR wieder her.
+        asm!("subs pc, lr, #4":::"memory");
+    }
 }
 
 /*
@@ -190,6 +208,14 @@ pub fn irq_service_routine()
 }
 */
 
+#[inline(never)]
+#[no_mangle]
+#[allow(private_no_mangle_fns)]
+#[linkage="weak"] // Verhindert, dass der Optimierer die Funktion eliminiert
+pub extern "C" fn fiq_service_routine(regs: &mut FiqRegisters) {
+    (service_routine.fiq)(regs);
+}
+
 #[inline(never)]
 #[no_mangle]
 #[allow(private_no_mangle_fns)]
@@ -218,9 +244,22 @@ pub fn abort_service_routine(adr: *const u32) {
 #[allow(private_no_mangle_fns)]
 #[linkage="weak"] // Verhindert, dass der Optimierer die Funktion eliminiert
 pub fn data_abort_service_routine(adr: *const u32) {
+    let status = MMU::data_fault_status();
+    let fault_addr = MMU::data_fault_address();
+    // Ein Zugriff auf eine Schutzseite bedeutet, dass der darüberliegende Stack übergelaufen ist.
+    // Der Abort-Modus hat einen eigenen Stack, daher kann die Meldung gefahrlos ausgegeben werden
+    // (es sei denn, der Abort-Stack selbst ist übergelaufen).
+    if MMU::is_translation_fault(status) {
+        if let Some(stack) = KernelData::stack_layout().and_then(|l| l.guard_hit(fault_addr)) {
+            kprint!("Stacküberlauf: {}-Stack, Zugriff auf Schutzseite 0x{:08x} durch Befehl @ {:?}\n",
+                    stack.name(),fault_addr,adr; RED);
+            panic!("Stacküberlauf");
+        }
+    }
     // Im Moment wird das Windows-3.X-Verhalten simuliert.
     // Sobald eine Prozessabstraktion existiert, sollte dies angepasst werden.
-    kprint!("Allgemeine Schutzverletzung bei Datenzugriff @ {:?}\n",adr);
+    kprint!("Allgemeine Schutzverletzung bei Datenzugriff auf 0x{:08x} (Status {:#x}) @ {:?}\n",
+            fault_addr,status,adr);
     panic!("Unbehandelt");
 }
 
@@ -1,7 +1,11 @@
 #![allow(dead_code)] 
 use bit_field::BitField;
+use core::ptr::{read_volatile, write_volatile};
+use hal::cpu::Cpu;
 use hal::bmc2835::uart::*;
+use hal::bmc2835::spi::{SpiMode, SpiError};
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum AuxDevice {
     MiniUART = 0,
     SPI1,
@@ -18,6 +22,7 @@ impl Into<u8> for AuxDevice {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum AuxInterrupt {
     UartReceive,
     UartTransmit
@@ -41,11 +46,20 @@ impl Bmc2835 for Aux {
 
 impl Aux {
     pub fn enable(&mut self, dev: AuxDevice, a: bool) {
+        Cpu::data_memory_barrier();
         self.enables.set_bit(dev as u8, a);
+        Cpu::data_memory_barrier();
+    }
+
+    pub fn is_enabled(&self, dev: AuxDevice) -> bool {
+        Cpu::data_memory_barrier();
+        self.enables.get_bit(dev as u8)
     }
 
     pub fn is_pending(&self, dev: AuxDevice) -> bool {
-        self.irq.get_bit(dev as u8)
+        Cpu::data_memory_barrier();
+        let irq = unsafe{ read_volatile(&self.irq) };
+        irq.get_bit(dev as u8)
     }
 }
 
@@ -56,6 +70,13 @@ pub enum MiniUartError {
     Overrun,
 }
 
+/// Mini-UART (UART1)
+///
+/// Der Takt ist der Kerntakt des VideoCore (`ClockId::Core`), die Baudrate ergibt sich zu
+/// `Takt / (8 * (baud + 1))`. Parität wird nicht unterstützt, es gibt 7 oder 8 Datenbits und
+/// immer ein Stop-Bit. Die FIFOs sind 8 Zeichen tief.
+///
+/// Vgl. BMC2835 Manual, S. 8ff. und die Errata unter http://elinux.org/BCM2835_datasheet_errata
 #[repr(C)]
 pub struct MiniUart {
         io:         u32,
@@ -80,6 +101,27 @@ impl Bmc2835 for MiniUart {
     
 }
 
+/// Bits im Line Status Register
+const LSR_DATA_READY: u8 = 0;
+const LSR_OVERRUN:    u8 = 1;
+const LSR_TX_EMPTY:   u8 = 5;
+const LSR_TX_IDLE:    u8 = 6;
+
+/// Berechnet den Wert des Baudratenregisters für die Rate `rate` beim Takt `clock` und die
+/// damit erreichte Rate.
+pub fn mini_uart_baud_divisor(clock: u32, rate: u32) -> Result<(u32,UartBaud),UartError> {
+    if rate == 0 || clock == 0 {
+        return Err(UartError::Invalid);
+    }
+    // Gerundet: clock / (8 * rate) - 1
+    let div = (clock as u64 + 4 * rate as u64) / (8 * rate as u64);
+    if div == 0 || div > 0x10000 {
+        return Err(UartError::NoSupported);
+    }
+    let actual = (clock as u64 / (8 * div)) as u32;
+    Ok(((div - 1) as u32, UartBaud { requested: rate, actual: actual }))
+}
+
 impl MiniUart {
 
 
@@ -88,46 +130,166 @@ impl MiniUart {
     }
 
     pub fn set_baudrate(&mut self, rate: u16) {
+        Cpu::data_memory_barrier();
         self.baud = rate as u32;
+        Cpu::data_memory_barrier();
     }
 
     pub fn get_baudrate(&self) -> u16 {
+        Cpu::data_memory_barrier();
         self.baud as u16
     }
 
+    /// Setzt die Baudrate bezogen auf den Takt `clock` und gibt die erreichte Rate zurück.
+    pub fn set_baud_rate(&mut self, clock: u32, rate: u32) -> Result<UartBaud,UartError> {
+        let (reg, baud) = mini_uart_baud_divisor(clock, rate)?;
+        self.set_baudrate(reg as u16);
+        Ok(baud)
+    }
+
+    /// Wendet die Konfiguration an und gibt die erreichte Baudrate zurück.
+    ///
+    /// Die Baudrate bezieht sich auf den Kerntakt `clock`. Parität, zwei Stop-Bits und
+    /// FIFO-Schwellen gibt es nicht; bei einem Fehler bleibt die UART unverändert. Die
+    /// GPIO-Pins werden nicht verändert, sie belegt `UartPins::claim`. Die UART ist danach
+    /// abgeschaltet, siehe `enable`.
+    pub fn configure(&mut self, clock: u32, config: &UartConfig) -> Result<UartBaud,UartError> {
+        let (reg, baud) = mini_uart_baud_divisor(clock, config.baud_rate)?;
+        if config.data_width != 7 && config.data_width != 8 {
+            return Err(UartError::NoSupported);
+        }
+        if config.parity != UartParity::None || config.stop_bits != 1 {
+            return Err(UartError::NoSupported);
+        }
+        let flow = config.flow_control == UartFlowControl::RtsCts;
+
+        // Register sind erst nach Aktivierung im AUX zugreifbar.
+        Aux::get().enable(AuxDevice::MiniUART, true);
+        Cpu::data_memory_barrier();
+        self.ctrl = 0;
+        self.int_enable = 0;
+        // Laut Errata sind für 8 Bit beide unteren Bits zu setzen.
+        self.line_ctl = if config.data_width == 8 { 0b11 } else { 0b00 };
+        self.modem_ctl = 0;
+        self.flush_fifos();
+        self.baud = reg;
+
+        if flow {
+            // Automatische Flusssteuerung für RTS (bei 3 freien Plätzen) und CTS
+            self.ctrl.set_bits(2..4, 0b11);
+        }
+        Cpu::data_memory_barrier();
+        Ok(baud)
+    }
+
+    // Laut Errata sind die Bits 0 und 1 gegenüber dem Manual vertauscht, außerdem müssen die
+    // Bits 2 und 3 gesetzt sein, damit überhaupt Interrupts ausgelöst werden.
     pub fn enable_interrupt(&mut self, intr: AuxInterrupt) {
+        Cpu::data_memory_barrier();
         match intr {
-            AuxInterrupt::UartReceive => { self.int_enable.set_bit(1,true); },
-            AuxInterrupt::UartTransmit => { self.int_enable.set_bit(0,true); },
+            AuxInterrupt::UartReceive => { self.int_enable.set_bit(0,true); },
+            AuxInterrupt::UartTransmit => { self.int_enable.set_bit(1,true); },
         }
+        self.int_enable.set_bits(2..4,0b11);
+        Cpu::data_memory_barrier();
     }
 
     pub fn disable_interrupt(&mut self, intr: AuxInterrupt) {
+        Cpu::data_memory_barrier();
         match intr {
-            AuxInterrupt::UartReceive => { self.int_enable.set_bit(1,false); },
-            AuxInterrupt::UartTransmit => { self.int_enable.set_bit(0,false); },
+            AuxInterrupt::UartReceive => { self.int_enable.set_bit(0,false); },
+            AuxInterrupt::UartTransmit => { self.int_enable.set_bit(1,false); },
         }
+        Cpu::data_memory_barrier();
     }
 
-    pub fn reset_interrupt(&mut self, intr: AuxInterrupt) {
+    /// Gibt an, ob der Interrupt aktiviert ist.
+    pub fn is_interrupt_enabled(&self, intr: AuxInterrupt) -> bool {
+        Cpu::data_memory_barrier();
         match intr {
-            AuxInterrupt::UartReceive => { self.int_ident.set_bit(1,false); },
-            AuxInterrupt::UartTransmit => { self.int_ident.set_bit(2,false); },
+            AuxInterrupt::UartReceive  => self.int_enable.get_bit(0),
+            AuxInterrupt::UartTransmit => self.int_enable.get_bit(1),
         }
     }
 
-    pub fn read(&self) -> Result<u8,()> {
-        Ok(0)
+    /// Gibt den anliegenden Interrupt zurück.
+    ///
+    /// Interrupts werden nicht explizit bestätigt: der Empfangsinterrupt endet, wenn die
+    /// Empfangs-FIFO leer ist, der Sendeinterrupt, wenn die Sende-FIFO wieder gefüllt wird.
+    pub fn pending_interrupt(&self) -> Option<AuxInterrupt> {
+        Cpu::data_memory_barrier();
+        let iir = unsafe{ read_volatile(&self.int_ident) };
+        if iir.get_bit(0) {
+            None
+        } else {
+            match iir.get_bits(1..3) {
+                0b01 => Some(AuxInterrupt::UartTransmit),
+                0b10 => Some(AuxInterrupt::UartReceive),
+                _    => None
+            }
+        }
+    }
+
+    /// Leert beide FIFOs.
+    pub fn flush_fifos(&mut self) {
+        Cpu::data_memory_barrier();
+        self.int_ident = 0b110;
+        Cpu::data_memory_barrier();
+    }
+
+    /// Liest den Line Status.
+    fn line_status(&self) -> u32 {
+        Cpu::data_memory_barrier();
+        unsafe{ read_volatile(&self.line_stat) }
+    }
+
+    /// Ist die Empfangs-FIFO leer?
+    pub fn rx_is_empty(&self) -> bool {
+        !self.line_status().get_bit(LSR_DATA_READY)
+    }
+
+    /// Kann die Sende-FIFO noch ein Zeichen aufnehmen?
+    pub fn tx_has_space(&self) -> bool {
+        self.line_status().get_bit(LSR_TX_EMPTY)
+    }
+
+    /// Ist alles gesendet?
+    pub fn tx_is_idle(&self) -> bool {
+        self.line_status().get_bit(LSR_TX_IDLE)
+    }
+
+    /// Liest ein Zeichen und gibt zusätzlich an, ob vorher Zeichen verloren gingen
+    /// (Überlauf). Das Überlaufbit wird durch das Lesen gelöscht.
+    pub fn read_raw(&self) -> Option<(u8,bool)> {
+        let lsr = self.line_status();
+        if lsr.get_bit(LSR_DATA_READY) {
+            let data = unsafe{ read_volatile(&self.io) } as u8;
+            Some((data, lsr.get_bit(LSR_OVERRUN)))
+        } else {
+            None
+        }
+    }
+
+    /// Schreibt ein Zeichen; `false`, wenn die Sende-FIFO voll ist.
+    pub fn try_write(&mut self, b: u8) -> bool {
+        if self.tx_has_space() {
+            unsafe{ write_volatile(&mut self.io, b as u32); }
+            Cpu::data_memory_barrier();
+            true
+        } else {
+            false
+        }
     }
 
 }
 
 impl Uart for MiniUart {
     fn enable(&mut self, e:UartEnable) {
+        Cpu::data_memory_barrier();
         match e {
             UartEnable::None => {
-                Aux::get().enable(AuxDevice::MiniUART,false);
                 self.ctrl.set_bits(0..2,0b00);
+                Aux::get().enable(AuxDevice::MiniUART,false);
             },
             UartEnable::Transmitter => {
                 Aux::get().enable(AuxDevice::MiniUART,true);
@@ -142,54 +304,220 @@ impl Uart for MiniUart {
                 self.ctrl.set_bits(0..2,0b11);
             }
         }
+        Cpu::data_memory_barrier();
     }
     
-    #[allow(unused_variables)]
     fn set_data_width(&mut self, width: u8) -> Result<(),UartError> {
-        unimplemented!();
+        Cpu::data_memory_barrier();
+        match width {
+            7 => self.line_ctl.set_bits(0..2,0b00),
+            8 => self.line_ctl.set_bits(0..2,0b11),
+            _ => return Err(UartError::NoSupported)
+        };
+        Cpu::data_memory_barrier();
+        Ok(())
     }
     
-    #[allow(unused_variables)]
+    /// Die Mini-UART kennt keine Parität.
     fn set_parity(&mut self, parity: UartParity) -> Result<(),UartError>{
-        unimplemented!();
+        if parity == UartParity::None {
+            Ok(())
+        } else {
+            Err(UartError::NoSupported)
+        }
     }
     
-    #[allow(unused_variables)]
+    /// Die Mini-UART sendet immer genau ein Stop-Bit.
     fn set_stop_bits(&mut self, number: u8) -> Result<(),UartError>{
-        unimplemented!();
+        if number == 1 {
+            Ok(())
+        } else {
+            Err(UartError::NoSupported)
+        }
     }
     
-    #[allow(unused_variables)]
     fn read(&self) -> Result<u8,UartError>{
-        unimplemented!();
+        match self.read_raw() {
+            Some((b, false)) => Ok(b),
+            Some((_, true))  => Err(UartError::OverrunError),
+            None             => Err(UartError::NoData),
+        }
     }
     
-    #[allow(unused_variables)]
     fn write(&mut self, data: u8) -> Result<u8,UartError>{
-        unimplemented!();
+        if self.try_write(data) {
+            Ok(data)
+        } else {
+            Err(UartError::FIFOfull)
+        }
     }
 }
 
-pub struct SPI {
-    ctl0:        u32,
-    ctl1:        u32,
-    stat:        u32,
-    io:          u32,
-    peek:        u32,
-}
+/// Bits in CNTL0 der AUX-SPI
+const SPI_CNTL0_MSB_OUT:      u8 = 6;
+const SPI_CNTL0_CPOL:         u8 = 7;
+const SPI_CNTL0_OUT_RISING:   u8 = 8;
+const SPI_CNTL0_CLEAR_FIFOS:  u8 = 9;
+const SPI_CNTL0_IN_RISING:    u8 = 10;
+const SPI_CNTL0_ENABLE:       u8 = 11;
+const SPI_CNTL0_VAR_WIDTH:    u8 = 14;
 
-/*
-impl Bmc2835 for SPI {
+/// Bits in CNTL1 der AUX-SPI
+const SPI_CNTL1_MSB_IN:       u8 = 1;
+const SPI_CNTL1_IRQ_DONE:     u8 = 6;
+const SPI_CNTL1_IRQ_TXEMPTY:  u8 = 7;
 
-    fn base_offset() -> usize {
-        0x215080
+/// Bits im Statusregister der AUX-SPI
+const SPI_STAT_BUSY:          u8 = 6;
+const SPI_STAT_RX_EMPTY:      u8 = 7;
+const SPI_STAT_TX_EMPTY:      u8 = 9;
+const SPI_STAT_TX_FULL:       u8 = 10;
+
+/// Anzahl der Chip-Select-Leitungen einer AUX-SPI
+pub const AUX_SPI_NUM_CS: u8 = 3;
+
+/// Tiefe der FIFOs einer AUX-SPI (Einträge)
+pub const AUX_SPI_FIFO_SIZE: usize = 4;
+
+/// SPI1 bzw. SPI2 im AUX ("Mini-SPI", ohne DMA)
+///
+/// Die Lage der Register weicht vom Manual ab, vgl. die Errata: IO und TXHOLD gibt es
+/// jeweils viermal ab 0x20 bzw. 0x30. Der Takt ist der Kerntakt des VideoCore, die Rate
+/// ergibt sich zu `Takt / (2 * (speed + 1))`.
+#[repr(C)]
+pub struct AuxSpi {
+    cntl0:       u32,       // Offset 0x00
+    cntl1:       u32,       // Offset 0x04
+    stat:        u32,       // Offset 0x08
+    peek:        u32,       // Offset 0x0C
+    _reserved:   [u32; 4],  // Offset 0x10
+    io:          [u32; 4],  // Offset 0x20
+    txhold:      [u32; 4],  // Offset 0x30
+}
+
+/// Berechnet den Wert des Speed-Feldes für die Rate `hz` beim Takt `clock` und die damit
+/// erreichte Rate (höchstens `hz`).
+pub fn aux_spi_speed(clock: u32, hz: u32) -> Result<(u32,u32),SpiError> {
+    if hz == 0 || clock == 0 {
+        return Err(SpiError::InvalidClock);
     }
-    
+    let div = (clock as u64 + 2 * hz as u64 - 1) / (2 * hz as u64);
+    let speed = if div == 0 { 0 } else { div - 1 };
+    if speed > 0xfff {
+        return Err(SpiError::InvalidClock);
+    }
+    Ok((speed as u32, clock / (2 * (speed as u32 + 1))))
 }
-impl Bmc2835 for SPI2 {
 
-    fn base_offset() -> usize {
-        0x215000
+impl AuxSpi {
+    /// Register von SPI1 (`AuxDevice::SPI1`) oder SPI2 (`AuxDevice::SPI2`)
+    pub fn get(dev: AuxDevice) -> &'static mut AuxSpi {
+        let offset = match dev {
+            AuxDevice::SPI2 => 0xC0,
+            _               => 0x80,
+        };
+        unsafe {
+            &mut *((Aux::base() + offset) as *mut AuxSpi)
+        }
     }
-    
-}*/
+
+    fn read_stat(&self) -> u32 {
+        Cpu::data_memory_barrier();
+        unsafe{ read_volatile(&self.stat) }
+    }
+
+    /// Stellt Takt (`speed`, siehe `aux_spi_speed`), Modus und Chip-Select `cs` (aktiv low)
+    /// ein, leert die FIFOs und aktiviert das Gerät. Gesendet wird immer MSB zuerst.
+    pub fn configure(&mut self, speed: u32, mode: SpiMode, cs: u8) {
+        // Bei gleicher Polarität und Phase (Mode 0 und 3) wird mit der fallenden Flanke
+        // ausgegeben und mit der steigenden eingelesen, sonst umgekehrt.
+        let out_rising = mode.cpol() != mode.cpha();
+        let mut cntl0: u32 = 0;
+        cntl0.set_bits(20..32, speed & 0xfff);
+        cntl0.set_bits(17..20, 0b111 & !(1 << cs));
+        cntl0.set_bit(SPI_CNTL0_VAR_WIDTH, true);
+        cntl0.set_bit(SPI_CNTL0_ENABLE, true);
+        cntl0.set_bit(SPI_CNTL0_IN_RISING, !out_rising);
+        cntl0.set_bit(SPI_CNTL0_OUT_RISING, out_rising);
+        cntl0.set_bit(SPI_CNTL0_CPOL, mode.cpol());
+        cntl0.set_bit(SPI_CNTL0_MSB_OUT, true);
+        let mut cntl1: u32 = 0;
+        cntl1.set_bit(SPI_CNTL1_MSB_IN, true);
+        Cpu::data_memory_barrier();
+        unsafe{
+            write_volatile(&mut self.cntl1, cntl1);
+            write_volatile(&mut self.cntl0, cntl0 | 1 << SPI_CNTL0_CLEAR_FIFOS);
+            write_volatile(&mut self.cntl0, cntl0);
+        }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Schaltet das Gerät ab.
+    pub fn disable(&mut self) {
+        Cpu::data_memory_barrier();
+        unsafe{
+            write_volatile(&mut self.cntl1, 0);
+            write_volatile(&mut self.cntl0, 1 << SPI_CNTL0_CLEAR_FIFOS);
+        }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Schaltet die Interrupts für "Sende-FIFO leer" und "fertig" (nichts mehr zu tun).
+    ///
+    /// Beide sind pegelgesteuert und liegen an, solange die Bedingung gilt.
+    pub fn enable_interrupts(&mut self, tx_empty: bool, done: bool) {
+        Cpu::data_memory_barrier();
+        let mut cntl1 = unsafe{ read_volatile(&self.cntl1) };
+        cntl1.set_bit(SPI_CNTL1_IRQ_TXEMPTY, tx_empty);
+        cntl1.set_bit(SPI_CNTL1_IRQ_DONE, done);
+        unsafe{ write_volatile(&mut self.cntl1, cntl1); }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Ist mindestens einer der Interrupts aktiv?
+    pub fn interrupts_enabled(&self) -> bool {
+        Cpu::data_memory_barrier();
+        let cntl1 = unsafe{ read_volatile(&self.cntl1) };
+        cntl1.get_bit(SPI_CNTL1_IRQ_TXEMPTY) || cntl1.get_bit(SPI_CNTL1_IRQ_DONE)
+    }
+
+    /// Wird gerade übertragen?
+    pub fn is_busy(&self) -> bool {
+        self.read_stat().get_bit(SPI_STAT_BUSY)
+    }
+
+    /// Ist die Empfangs-FIFO leer?
+    pub fn rx_is_empty(&self) -> bool {
+        self.read_stat().get_bit(SPI_STAT_RX_EMPTY)
+    }
+
+    /// Ist die Sende-FIFO leer?
+    pub fn tx_is_empty(&self) -> bool {
+        self.read_stat().get_bit(SPI_STAT_TX_EMPTY)
+    }
+
+    /// Ist die Sende-FIFO voll?
+    pub fn tx_is_full(&self) -> bool {
+        self.read_stat().get_bit(SPI_STAT_TX_FULL)
+    }
+
+    /// Schreibt ein Byte in die Sende-FIFO.
+    ///
+    /// Über TXHOLD bleibt Chip-Select nach dem Byte aktiv, über IO wird es danach
+    /// zurückgenommen; `last` kennzeichnet daher das letzte Byte einer Übertragung.
+    pub fn write_byte(&mut self, b: u8, last: bool) {
+        let data = 8 << 24 | (b as u32) << 16;
+        unsafe{
+            if last {
+                write_volatile(&mut self.io[0], data);
+            } else {
+                write_volatile(&mut self.txhold[0], data);
+            }
+        }
+    }
+
+    /// Liest ein Byte aus der Empfangs-FIFO.
+    pub fn read_byte(&self) -> u8 {
+        unsafe{ read_volatile(&self.io[0]) as u8 }
+    }
+}
@@ -0,0 +1,176 @@
+#![warn(missing_docs)]
+//! Treiber für die SPI-Master im AUX (SPI1 und SPI2).
+//!
+//! Wie `Spi0Driver` ein `SpiBus` mit drei Chip-Select-Leitungen, aber ohne DMA und mit
+//! FIFOs von nur vier Einträgen. Chip-Select ist immer aktiv low.
+//!
+//! Der AUX-Interrupt wird mit der Mini-UART geteilt. Jeder Treiber meldet eine eigene
+//! Serviceroutine an, die `NotHandled` meldet, wenn das Gerät keinen Interrupt anmeldet.
+use hal::cpu::Cpu;
+use data::isr_table::IsrResult;
+use data::kernel::KernelData;
+use super::{Bmc2835, Aux, AuxDevice, AuxSpi, IrqController, GeneralInterrupt, GpioPins};
+use super::{SpiBus, SpiSettings, SpiTransfer, SpiError};
+use super::spi::SpiTransferState;
+use super::spi_driver::SpiDriverCore;
+use super::{ClockId, report_clock_rate, aux_spi_speed, AUX_SPI_NUM_CS, AUX_SPI_FIFO_SIZE};
+
+/// Treiber für SPI1 oder SPI2
+pub struct AuxSpiDriver {
+    dev:  AuxDevice,
+    core: SpiDriverCore,
+}
+
+static SPI1_DRIVER: AuxSpiDriver = AuxSpiDriver::new(AuxDevice::SPI1);
+static SPI2_DRIVER: AuxSpiDriver = AuxSpiDriver::new(AuxDevice::SPI2);
+
+impl AuxSpiDriver {
+    const fn new(dev: AuxDevice) -> AuxSpiDriver {
+        AuxSpiDriver {
+            dev:  dev,
+            core: SpiDriverCore::new(),
+        }
+    }
+
+    /// Der Treiber für SPI1
+    pub fn spi1() -> &'static AuxSpiDriver {
+        &SPI1_DRIVER
+    }
+
+    /// Der Treiber für SPI2
+    pub fn spi2() -> &'static AuxSpiDriver {
+        &SPI2_DRIVER
+    }
+
+    /// Aktiviert das Gerät im AUX, belegt die GPIO-Pins (alt4; SPI1: 16 bis 21, SPI2: 40
+    /// bis 45), meldet die Serviceroutine an und aktiviert den Interrupt.
+    pub fn init(&self) -> Result<(),SpiError> {
+        use super::GpioPull;
+        use super::gpio_config::{Device,SPI};
+
+        Aux::get().enable(self.dev, true);
+        AuxSpi::get(self.dev).disable();
+
+        // Bei erneuter Initialisierung zuerst die eigenen Pins freigeben
+        self.core.release_pins();
+        let registry = GpioPins::get();
+        let (pins, miso, context) = match self.dev {
+            AuxDevice::SPI2 => {
+                (registry.claim_all(&[(40, Device::Spi2(SPI::MiSo)),
+                                      (41, Device::Spi2(SPI::MoSi)),
+                                      (42, Device::Spi2(SPI::SClk)),
+                                      (43, Device::Spi2(SPI::CE0)),
+                                      (44, Device::Spi2(SPI::CE1)),
+                                      (45, Device::Spi2(SPI::CE2))], "SPI2"), 0, 2)
+            },
+            _ => {
+                (registry.claim_all(&[(16, Device::Spi1(SPI::CE2)),
+                                      (17, Device::Spi1(SPI::CE1)),
+                                      (18, Device::Spi1(SPI::CE0)),
+                                      (19, Device::Spi1(SPI::MiSo)),
+                                      (20, Device::Spi1(SPI::MoSi)),
+                                      (21, Device::Spi1(SPI::SClk))], "SPI1"), 3, 1)
+            }
+        };
+        let pins = pins.map_err(SpiError::Gpio)?;
+        pins[miso].set_pull(GpioPull::Down);
+
+        if !self.core.is_initialized() {
+            KernelData::isr_table().add_isr(GeneralInterrupt::AUX, AuxSpiDriver::isr, context)
+                .map_err(|_| SpiError::Failed)?;
+            IrqController::get().enable(GeneralInterrupt::AUX);
+        }
+        self.core.set_initialized(pins);
+        Ok(())
+    }
+
+    /// Serviceroutine; `context` ist 1 für SPI1 und 2 für SPI2.
+    pub fn isr(context: usize) -> IsrResult {
+        match context {
+            1 => SPI1_DRIVER.handle_interrupt(),
+            2 => SPI2_DRIVER.handle_interrupt(),
+            _ => IsrResult::NotHandled
+        }
+    }
+
+    fn handle_interrupt(&self) -> IsrResult {
+        let spi = AuxSpi::get(self.dev);
+        if !Aux::get().is_pending(self.dev) || !spi.interrupts_enabled() {
+            return IsrResult::NotHandled;
+        }
+        let (done, tx_pending) = self.core.with_transfer((true, false), |transfer| {
+            (AuxSpiDriver::service(spi, transfer), transfer.tx_pending())
+        });
+        // Der Sendeinterrupt wird nur gebraucht, solange es etwas zu senden gibt; danach
+        // wird auf das Ende der Übertragung gewartet.
+        spi.enable_interrupts(tx_pending, !done);
+        if done {
+            self.core.wake();
+        }
+        IsrResult::Handled
+    }
+
+    /// Leert die Empfangs-FIFO und füllt die Sende-FIFO nach. Gibt `true` zurück, wenn alle
+    /// Bytes übertragen wurden.
+    fn service(spi: &mut AuxSpi, transfer: &mut SpiTransferState) -> bool {
+        while transfer.rx_pending() && !spi.rx_is_empty() {
+            transfer.store_rx(spi.read_byte());
+        }
+        while transfer.in_flight() < AUX_SPI_FIFO_SIZE && !spi.tx_is_full() {
+            match transfer.next_tx() {
+                Some(b) => {
+                    let last = !transfer.tx_pending();
+                    spi.write_byte(b, last);
+                },
+                None => break
+            }
+        }
+        transfer.is_done()
+    }
+
+    fn transfer_polled(&self, spi: &mut AuxSpi, mut transfer: SpiTransferState) {
+        while !AuxSpiDriver::service(spi, &mut transfer) {
+            Cpu::data_memory_barrier();
+        }
+        while spi.is_busy() {
+            Cpu::data_memory_barrier();
+        }
+    }
+
+    fn transfer_interrupt(&self, spi: &mut AuxSpi, transfer: SpiTransferState) {
+        // Die Sende-FIFO ist leer, der Sendeinterrupt kommt also sofort.
+        self.core.transfer_interrupt(transfer, || spi.enable_interrupts(true, true));
+        while spi.is_busy() {
+            Cpu::data_memory_barrier();
+        }
+    }
+}
+
+impl SpiBus for AuxSpiDriver {
+    fn transfer(&self, cs: u8, settings: &SpiSettings, tx: &[u8], rx: &mut [u8]) -> Result<(),SpiError> {
+        if cs >= AUX_SPI_NUM_CS {
+            return Err(SpiError::InvalidChipSelect);
+        }
+        if settings.transfer == SpiTransfer::Dma || settings.cs_active_high {
+            return Err(SpiError::NotSupported);
+        }
+        let (speed, _) = aux_spi_speed(report_clock_rate(ClockId::Core), settings.clock_hz)?;
+        // Die Übertragung endet, bevor `transfer` zurückkehrt.
+        let transfer = unsafe{ SpiTransferState::new(tx, rx) };
+        if transfer.len() == 0 {
+            return Ok(());
+        }
+        let _claim = self.core.claim()?;
+        let spi = AuxSpi::get(self.dev);
+        spi.configure(speed, settings.mode, cs);
+        match settings.transfer {
+            SpiTransfer::Interrupt => self.transfer_interrupt(spi, transfer),
+            _                      => self.transfer_polled(spi, transfer)
+        }
+        Ok(())
+    }
+
+    fn effective_clock(&self, hz: u32) -> Result<u32,SpiError> {
+        aux_spi_speed(report_clock_rate(ClockId::Core), hz).map(|(_, actual)| actual)
+    }
+}
@@ -0,0 +1,55 @@
+//! Gemeinsame Schnittstelle für blockorientierte Geräte (z.B. SD-Karten).
+
+/// Fehler eines Blockgerätes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BlockError {
+    /// Der Treiber wurde noch nicht initialisiert.
+    NotInitialized,
+    /// Es ist kein Medium (keine Karte) vorhanden oder es antwortet nicht.
+    NoMedium,
+    /// Das Gerät ist mit einer anderen Übertragung beschäftigt.
+    Busy,
+    /// Die Blocknummern liegen außerhalb des Gerätes.
+    OutOfRange,
+    /// Die Puffergröße ist kein Vielfaches der Blockgröße.
+    InvalidLength,
+    /// Das Gerät hat nicht rechtzeitig geantwortet.
+    Timeout,
+    /// Prüfsummenfehler bei Kommando oder Daten
+    Crc,
+    /// Die Operation wird vom Gerät nicht unterstützt.
+    NotSupported,
+    /// Sonstiger Fehler
+    Failed,
+}
+
+/// Ein Gerät, das in Blöcken fester Größe gelesen und geschrieben wird.
+///
+/// Blöcke werden ab 0 gezählt. Die Puffer müssen ein Vielfaches der Blockgröße lang sein;
+/// es werden so viele aufeinanderfolgende Blöcke übertragen, wie in den Puffer passen.
+pub trait BlockDevice {
+    /// Größe eines Blocks in Bytes
+    fn block_size(&self) -> usize;
+
+    /// Anzahl der Blöcke (0, wenn kein Medium vorhanden ist)
+    fn block_count(&self) -> u64;
+
+    /// Liest Blöcke ab `start` nach `buf`.
+    fn read_blocks(&self, start: u64, buf: &mut [u8]) -> Result<(),BlockError>;
+
+    /// Schreibt `buf` in die Blöcke ab `start`.
+    fn write_blocks(&self, start: u64, buf: &[u8]) -> Result<(),BlockError>;
+
+    /// Prüft Pufferlänge und Bereich und gibt die Anzahl der Blöcke zurück.
+    fn check_range(&self, start: u64, len: usize) -> Result<u64,BlockError> {
+        let size = self.block_size();
+        if len == 0 || len % size != 0 {
+            return Err(BlockError::InvalidLength);
+        }
+        let blocks = (len / size) as u64;
+        match start.checked_add(blocks) {
+            Some(end) if end <= self.block_count() => Ok(blocks),
+            _                                      => Err(BlockError::OutOfRange)
+        }
+    }
+}
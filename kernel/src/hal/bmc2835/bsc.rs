@@ -0,0 +1,237 @@
+#![allow(dead_code)]
+use core::ptr::{read_volatile, write_volatile};
+use bit_field::BitField;
+use hal::cpu::Cpu;
+use super::I2cError;
+
+/// Bits des Steuerregisters
+const C_READ:  u8 = 0;
+const C_CLEAR: u8 = 4;
+const C_ST:    u8 = 7;
+const C_INTD:  u8 = 8;
+const C_INTT:  u8 = 9;
+const C_INTR:  u8 = 10;
+const C_I2CEN: u8 = 15;
+
+/// Bits des Statusregisters
+const S_TA:    u8 = 0;
+const S_DONE:  u8 = 1;
+const S_TXW:   u8 = 2;
+const S_RXR:   u8 = 3;
+const S_TXD:   u8 = 4;
+const S_RXD:   u8 = 5;
+const S_TXE:   u8 = 6;
+const S_RXF:   u8 = 7;
+const S_ERR:   u8 = 8;
+const S_CLKT:  u8 = 9;
+
+/// Tiefe der FIFO in Bytes
+pub const BSC_FIFO_SIZE: usize = 16;
+
+/// Die BSC-Master
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BscMaster {
+    /// BSC0 (GPIO 0 und 1 bzw. 28 und 29)
+    Bsc0,
+    /// BSC1 (GPIO 2 und 3, am Header)
+    Bsc1,
+}
+
+/// Ergebnis der Abfrage des Status
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BscStatus {
+    /// Die Übertragung läuft
+    Active,
+    /// Die Übertragung ist beendet
+    Done,
+    /// Der Slave hat nicht bestätigt
+    Nack,
+    /// Der Slave hat den Takt zu lange gehalten
+    ClockStretchTimeout,
+}
+
+/// Broadcom Serial Controller (I2C-Master)
+///
+/// Vgl. BMC2835 Manual, S. 28ff.
+#[repr(C)]
+pub struct Bsc {
+    /// Steuerung
+    c:     u32,    // Offset 0x00
+    /// Status
+    s:     u32,    // Offset 0x04
+    /// Datenlänge
+    dlen:  u32,    // Offset 0x08
+    /// Slave-Adresse
+    a:     u32,    // Offset 0x0C
+    /// FIFO
+    fifo:  u32,    // Offset 0x10
+    /// Taktteiler
+    div:   u32,    // Offset 0x14
+    /// Verzögerung der Datenübernahme
+    del:   u32,    // Offset 0x18
+    /// Timeout für das Halten des Takts
+    clkt:  u32,    // Offset 0x1C
+}
+
+use super::Bmc2835;
+impl Bmc2835 for Bsc {
+
+    /// BSC0; die anderen Master liefert `Bsc::master`.
+    fn base_offset() -> usize {
+        0x205000
+    }
+}
+
+/// Berechnet den Taktteiler für die Rate `hz` beim Kerntakt `core` und die erreichte Rate.
+///
+/// Der Teiler wird von der Hardware auf eine gerade Zahl abgerundet, 0 steht für 32768.
+pub fn bsc_clock_divider(core: u32, hz: u32) -> Result<(u32,u32),I2cError> {
+    if hz == 0 || core == 0 {
+        return Err(I2cError::InvalidClock);
+    }
+    // Aufrunden, damit die Rate höchstens `hz` ist
+    let mut div = (core + hz - 1) / hz;
+    div += div & 1;
+    if div < 2 {
+        div = 2;
+    }
+    if div > 32768 {
+        return Err(I2cError::InvalidClock);
+    }
+    Ok((div & 0x7fff, core / div))
+}
+
+impl Bsc {
+    /// Register des Masters `master`
+    pub fn master(master: BscMaster) -> &'static mut Bsc {
+        let offset = match master {
+            BscMaster::Bsc0 => 0x205000,
+            BscMaster::Bsc1 => 0x804000,
+        };
+        unsafe {
+            &mut *((Bsc::device_base() + offset) as *mut Bsc)
+        }
+    }
+
+    fn read_status(&self) -> u32 {
+        Cpu::data_memory_barrier();
+        unsafe{ read_volatile(&self.s) }
+    }
+
+    fn write_control(&mut self, value: u32) {
+        Cpu::data_memory_barrier();
+        unsafe{ write_volatile(&mut self.c, value); }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Setzt den Taktteiler (siehe `bsc_clock_divider`) und den Timeout für das Halten des
+    /// Takts in Takten (0: kein Timeout).
+    pub fn set_clock(&mut self, div: u32, stretch_timeout: u16) {
+        Cpu::data_memory_barrier();
+        unsafe{
+            write_volatile(&mut self.div, div & 0xffff);
+            write_volatile(&mut self.clkt, stretch_timeout as u32);
+        }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Bricht eine laufende Übertragung ab, leert die FIFO und löscht den Status.
+    pub fn reset(&mut self) {
+        let mut c: u32 = 0;
+        c.set_bit(C_I2CEN, true);
+        c.set_bits(C_CLEAR..C_CLEAR + 2, 0b11);
+        self.write_control(c);
+        let mut s: u32 = 0;
+        s.set_bit(S_CLKT, true);
+        s.set_bit(S_ERR, true);
+        s.set_bit(S_DONE, true);
+        unsafe{ write_volatile(&mut self.s, s); }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Setzt Slave-Adresse und Datenlänge der nächsten Übertragung.
+    pub fn set_transfer(&mut self, addr: u8, len: u16) {
+        Cpu::data_memory_barrier();
+        unsafe{
+            write_volatile(&mut self.a, addr as u32 & 0x7f);
+            write_volatile(&mut self.dlen, len as u32);
+        }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Startet eine Übertragung zum (`read == false`) oder vom Slave.
+    ///
+    /// Läuft bereits eine Übertragung, folgt die neue nach einem wiederholten Start. Mit
+    /// `interrupts` werden der Interrupt für das Ende und der für die FIFO in
+    /// Übertragungsrichtung aktiviert.
+    pub fn start(&mut self, read: bool, interrupts: bool) {
+        let mut c: u32 = 0;
+        c.set_bit(C_I2CEN, true);
+        c.set_bit(C_ST, true);
+        c.set_bit(C_READ, read);
+        c.set_bit(C_INTD, interrupts);
+        c.set_bit(C_INTR, interrupts && read);
+        c.set_bit(C_INTT, interrupts && !read);
+        self.write_control(c);
+    }
+
+    /// Schaltet die Interrupts für "fertig", "Sende-FIFO braucht Daten" und "Empfangs-FIFO
+    /// muss gelesen werden".
+    pub fn enable_interrupts(&mut self, done: bool, tx: bool, rx: bool) {
+        Cpu::data_memory_barrier();
+        let mut c = unsafe{ read_volatile(&self.c) };
+        c.set_bit(C_ST, false);
+        c.set_bits(C_CLEAR..C_CLEAR + 2, 0);
+        c.set_bit(C_INTD, done);
+        c.set_bit(C_INTT, tx);
+        c.set_bit(C_INTR, rx);
+        self.write_control(c);
+    }
+
+    /// Ist mindestens ein Interrupt aktiv?
+    pub fn interrupts_enabled(&self) -> bool {
+        Cpu::data_memory_barrier();
+        let c = unsafe{ read_volatile(&self.c) };
+        c.get_bit(C_INTD) || c.get_bit(C_INTT) || c.get_bit(C_INTR)
+    }
+
+    /// Zustand der Übertragung; Fehler haben Vorrang vor dem Ende.
+    pub fn status(&self) -> BscStatus {
+        let s = self.read_status();
+        if s.get_bit(S_ERR) {
+            BscStatus::Nack
+        } else if s.get_bit(S_CLKT) {
+            BscStatus::ClockStretchTimeout
+        } else if s.get_bit(S_DONE) {
+            BscStatus::Done
+        } else {
+            BscStatus::Active
+        }
+    }
+
+    /// Hat die Übertragung begonnen oder ist sie schon beendet?
+    pub fn is_started(&self) -> bool {
+        let s = self.read_status();
+        s.get_bit(S_TA) || s.get_bit(S_DONE) || s.get_bit(S_ERR) || s.get_bit(S_CLKT)
+    }
+
+    /// Kann die FIFO ein weiteres Byte aufnehmen?
+    pub fn tx_has_space(&self) -> bool {
+        self.read_status().get_bit(S_TXD)
+    }
+
+    /// Enthält die FIFO empfangene Daten?
+    pub fn rx_has_data(&self) -> bool {
+        self.read_status().get_bit(S_RXD)
+    }
+
+    /// Schreibt ein Byte in die FIFO.
+    pub fn write_fifo(&mut self, b: u8) {
+        unsafe{ write_volatile(&mut self.fifo, b as u32); }
+    }
+
+    /// Liest ein Byte aus der FIFO.
+    pub fn read_fifo(&self) -> u8 {
+        unsafe{ read_volatile(&self.fifo) as u8 }
+    }
+}
@@ -0,0 +1,351 @@
+#![warn(missing_docs)]
+//! Treiber für die I2C-Master (BSC0 und BSC1).
+//!
+//! Die FIFO wird je nach Konfiguration vom Aufrufer (Polling) oder von der Serviceroutine
+//! bedient. NACK und zu langes Halten des Takts erkennt die Hardware; bleibt eine
+//! Transaktion trotzdem hängen, bricht der Treiber sie nach einer aus Taktrate und Länge
+//! berechneten Zeit ab.
+//!
+//! Die Hardware kennt keinen expliziten wiederholten Start. Für eine Transaktion mit Senden
+//! und Empfangen wird daher das Lesen angestoßen, sobald das Schreiben begonnen hat; der
+//! Controller setzt es nach dem letzten Byte mit einem wiederholten Start fort. Dafür müssen
+//! die Sendedaten vorher vollständig in der FIFO liegen, sie sind also auf 16 Bytes
+//! begrenzt (einschließlich des Adressbytes bei 10-Bit-Adressen).
+use alloc::vec::Vec;
+use hal::cpu::Cpu;
+use data::isr_table::IsrResult;
+use data::kernel::KernelData;
+use sync::{IrqSpinLock, WaitQueue};
+use super::{Bmc2835, Bsc, BscMaster, BscStatus, IrqController, BasicInterrupt, GpioPins, GpioPin, SystemTimer};
+use super::{I2cBus, I2cAddress, I2cError};
+use super::{ClockId, report_clock_rate, bsc_clock_divider, BSC_FIFO_SIZE};
+
+/// Konfiguration eines I2C-Masters
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BscConfig {
+    /// Gewünschte Taktrate in Hz; es wird die nächste nicht größere Rate eingestellt.
+    pub clock_hz:        u32,
+    /// Höchstzahl an Takten, die ein Slave den Takt halten darf (0: unbegrenzt)
+    pub stretch_timeout: u16,
+    /// FIFO in der Serviceroutine bedienen (statt Polling)
+    pub interrupt:       bool,
+}
+
+impl BscConfig {
+    /// Timeout wie nach dem Reset (64 Takte), Polling
+    pub const fn new(clock_hz: u32) -> BscConfig {
+        BscConfig {
+            clock_hz:        clock_hz,
+            stretch_timeout: 0x40,
+            interrupt:       false,
+        }
+    }
+}
+
+/// Zustand einer laufenden Transaktion
+struct BscTransfer {
+    prefix:   Option<u8>,
+    tx:       *const u8,
+    tx_len:   usize,
+    sent:     usize,
+    rx:       *mut u8,
+    rx_len:   usize,
+    received: usize,
+    result:   Option<Result<(),I2cError>>,
+}
+
+unsafe impl Send for BscTransfer {}
+
+impl BscTransfer {
+    fn new(prefix: Option<u8>, tx: &[u8], rx: &mut [u8]) -> BscTransfer {
+        BscTransfer {
+            prefix:   prefix,
+            tx:       tx.as_ptr(),
+            tx_len:   tx.len() + if prefix.is_some() { 1 } else { 0 },
+            sent:     0,
+            rx:       rx.as_mut_ptr(),
+            rx_len:   rx.len(),
+            received: 0,
+            result:   None,
+        }
+    }
+
+    fn tx_pending(&self) -> bool {
+        self.sent < self.tx_len
+    }
+
+    fn next_tx(&mut self) -> u8 {
+        let b = match self.prefix {
+            Some(prefix) if self.sent == 0 => prefix,
+            Some(_)                        => unsafe{ *self.tx.offset(self.sent as isize - 1) },
+            None                           => unsafe{ *self.tx.offset(self.sent as isize) },
+        };
+        self.sent += 1;
+        b
+    }
+
+    fn is_reading(&self) -> bool {
+        self.rx_len > 0
+    }
+
+    fn store_rx(&mut self, b: u8) {
+        if self.received < self.rx_len {
+            unsafe{ *self.rx.offset(self.received as isize) = b; }
+            self.received += 1;
+        }
+    }
+}
+
+struct BscState {
+    initialized: bool,
+    busy:        bool,
+    interrupt:   bool,
+    clock_hz:    u32,
+    transfer:    Option<BscTransfer>,
+    pins:        Option<Vec<GpioPin>>,
+}
+
+/// Treiber für einen I2C-Master
+pub struct BscDriver {
+    master: BscMaster,
+    state:  IrqSpinLock<BscState>,
+    queue:  WaitQueue,
+}
+
+static BSC0_DRIVER: BscDriver = BscDriver::new(BscMaster::Bsc0);
+static BSC1_DRIVER: BscDriver = BscDriver::new(BscMaster::Bsc1);
+
+impl BscDriver {
+    const fn new(master: BscMaster) -> BscDriver {
+        BscDriver {
+            master: master,
+            state:  IrqSpinLock::new(BscState {
+                initialized: false,
+                busy:        false,
+                interrupt:   false,
+                clock_hz:    0,
+                transfer:    None,
+                pins:        None,
+            }),
+            queue:  WaitQueue::new(),
+        }
+    }
+
+    /// Der Treiber für BSC0
+    pub fn bsc0() -> &'static BscDriver {
+        &BSC0_DRIVER
+    }
+
+    /// Der Treiber für BSC1
+    pub fn bsc1() -> &'static BscDriver {
+        &BSC1_DRIVER
+    }
+
+    /// Stellt Takt und Timeout ein, belegt die GPIO-Pins (alt0 mit Pull-up; BSC0: 0 und 1,
+    /// BSC1: 2 und 3), meldet die Serviceroutine an und aktiviert den Interrupt.
+    ///
+    /// Gibt die erreichte Taktrate zurück.
+    pub fn init(&self, config: &BscConfig) -> Result<u32,I2cError> {
+        use super::GpioPull;
+        use super::gpio_config::{Device,BSC};
+
+        let (div, actual) = bsc_clock_divider(report_clock_rate(ClockId::Core), config.clock_hz)?;
+        let bsc = Bsc::master(self.master);
+        bsc.reset();
+        bsc.set_clock(div, config.stretch_timeout);
+
+        // Bei erneuter Initialisierung zuerst die eigenen Pins freigeben
+        self.state.lock().pins = None;
+        let registry = GpioPins::get();
+        let (pins, context) = match self.master {
+            BscMaster::Bsc0 => (registry.claim_all(&[(0, Device::BscMaster0(BSC::Data)),
+                                                     (1, Device::BscMaster0(BSC::Clock))], "I2C0"), 0),
+            BscMaster::Bsc1 => (registry.claim_all(&[(2, Device::BscMaster1(BSC::Data)),
+                                                     (3, Device::BscMaster1(BSC::Clock))], "I2C1"), 1),
+        };
+        let pins = pins.map_err(I2cError::Gpio)?;
+        for pin in pins.iter() {
+            pin.set_pull(GpioPull::Up);
+        }
+
+        if !self.state.lock().initialized {
+            KernelData::isr_table().add_isr(BasicInterrupt::I2C, BscDriver::isr, context)
+                .map_err(|_| I2cError::Failed)?;
+            IrqController::get().enable(BasicInterrupt::I2C);
+        }
+        let mut state = self.state.lock();
+        state.initialized = true;
+        state.pins = Some(pins);
+        state.interrupt = config.interrupt;
+        state.clock_hz = actual;
+        Ok(actual)
+    }
+
+    /// Serviceroutine; `context` ist 0 für BSC0 und 1 für BSC1.
+    pub fn isr(context: usize) -> IsrResult {
+        match context {
+            0 => BSC0_DRIVER.handle_interrupt(),
+            1 => BSC1_DRIVER.handle_interrupt(),
+            _ => IsrResult::NotHandled
+        }
+    }
+
+    fn handle_interrupt(&self) -> IsrResult {
+        let bsc = Bsc::master(self.master);
+        if !bsc.interrupts_enabled() {
+            return IsrResult::NotHandled;
+        }
+        let finished = {
+            let mut state = self.state.lock();
+            match state.transfer {
+                Some(ref mut transfer) => {
+                    BscDriver::service(bsc, transfer);
+                    if transfer.result.is_none() {
+                        bsc.enable_interrupts(true, transfer.tx_pending(), transfer.is_reading());
+                        false
+                    } else {
+                        true
+                    }
+                },
+                None => true
+            }
+        };
+        if finished {
+            bsc.enable_interrupts(false, false, false);
+            self.queue.wake_all();
+        }
+        IsrResult::Handled
+    }
+
+    /// Füllt die FIFO aus den Sendedaten.
+    fn fill(bsc: &mut Bsc, transfer: &mut BscTransfer) {
+        while transfer.tx_pending() && bsc.tx_has_space() {
+            let b = transfer.next_tx();
+            bsc.write_fifo(b);
+        }
+    }
+
+    /// Leert und füllt die FIFO und prüft, ob die Transaktion beendet ist.
+    fn service(bsc: &mut Bsc, transfer: &mut BscTransfer) {
+        if transfer.result.is_some() {
+            return;
+        }
+        if transfer.is_reading() {
+            while bsc.rx_has_data() {
+                transfer.store_rx(bsc.read_fifo());
+            }
+        }
+        BscDriver::fill(bsc, transfer);
+        transfer.result = match bsc.status() {
+            BscStatus::Active              => None,
+            BscStatus::Nack                => Some(Err(I2cError::Nack)),
+            BscStatus::ClockStretchTimeout => Some(Err(I2cError::ClockStretchTimeout)),
+            BscStatus::Done                => {
+                while bsc.rx_has_data() {
+                    transfer.store_rx(bsc.read_fifo());
+                }
+                Some(Ok(()))
+            }
+        };
+    }
+
+    /// Startet die Transaktion; die Sendedaten liegen, soweit möglich, schon in der FIFO.
+    fn start(bsc: &mut Bsc, addr: u8, transfer: &mut BscTransfer, interrupt: bool) {
+        if transfer.tx_len == 0 && transfer.is_reading() {
+            bsc.set_transfer(addr, transfer.rx_len as u16);
+            bsc.start(true, interrupt);
+            return;
+        }
+        let combined = transfer.is_reading();
+        bsc.set_transfer(addr, transfer.tx_len as u16);
+        BscDriver::fill(bsc, transfer);
+        bsc.start(false, interrupt && !combined);
+        if combined {
+            // Lesen anstoßen, sobald das Schreiben läuft; es folgt mit wiederholtem Start.
+            while !bsc.is_started() {
+                Cpu::data_memory_barrier();
+            }
+            bsc.set_transfer(addr, transfer.rx_len as u16);
+            bsc.start(true, interrupt);
+        }
+    }
+
+    /// Höchstdauer einer Transaktion in µs: doppelte Übertragungszeit (9 Takte je Byte,
+    /// einschließlich Adressen) plus 10 ms.
+    fn timeout_us(clock_hz: u32, transfer: &BscTransfer) -> u32 {
+        let bits = 9 * (transfer.tx_len + transfer.rx_len + 2) as u64;
+        (10_000 + 2 * bits * 1_000_000 / clock_hz as u64) as u32
+    }
+
+    fn claim(&self) -> Result<(bool,u32),I2cError> {
+        let mut state = self.state.lock();
+        if !state.initialized {
+            Err(I2cError::NotInitialized)
+        } else if state.busy {
+            Err(I2cError::Busy)
+        } else {
+            state.busy = true;
+            Ok((state.interrupt, state.clock_hz))
+        }
+    }
+
+    fn release(&self) {
+        self.state.lock().busy = false;
+    }
+}
+
+impl I2cBus for BscDriver {
+    fn transaction(&self, addr: I2cAddress, tx: &[u8], rx: &mut [u8]) -> Result<(),I2cError> {
+        let (addr, prefix) = addr.encode()?;
+        let mut transfer = BscTransfer::new(prefix, tx, rx);
+        if transfer.tx_len > 0xffff || transfer.rx_len > 0xffff ||
+            (transfer.is_reading() && transfer.tx_len > BSC_FIFO_SIZE) {
+            return Err(I2cError::TooLong);
+        }
+        let (interrupt, clock_hz) = self.claim()?;
+        let bsc = Bsc::master(self.master);
+        let timeout = BscDriver::timeout_us(clock_hz, &transfer);
+        bsc.reset();
+
+        // Bis der Zustand hinterlegt ist, darf die Serviceroutine nicht laufen.
+        let irq_state = Cpu::save_and_disable_interrupts();
+        BscDriver::start(bsc, addr, &mut transfer, interrupt);
+        self.state.lock().transfer = Some(transfer);
+        Cpu::restore_interrupts(irq_state);
+
+        let timer = SystemTimer::get();
+        let started = timer.get_counter();
+        let expired = || timer.get_counter().wrapping_sub(started) > timeout;
+        if interrupt {
+            self.queue.wait_until(|| {
+                let finished = match self.state.lock().transfer {
+                    Some(ref transfer) => transfer.result.is_some(),
+                    None               => true
+                };
+                finished || expired()
+            });
+        } else {
+            loop {
+                let finished = match self.state.lock().transfer {
+                    Some(ref mut transfer) => {
+                        BscDriver::service(bsc, transfer);
+                        transfer.result.is_some()
+                    },
+                    None => true
+                };
+                if finished || expired() {
+                    break;
+                }
+            }
+        }
+
+        let result = match self.state.lock().transfer.take() {
+            Some(BscTransfer { result: Some(result), .. }) => result,
+            _                                               => Err(I2cError::Timeout)
+        };
+        // Bricht eine hängende Transaktion ab und sperrt die Interrupts.
+        bsc.reset();
+        self.release();
+        result
+    }
+}
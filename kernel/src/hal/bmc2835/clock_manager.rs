@@ -0,0 +1,220 @@
+#![allow(dead_code)]
+//! Taktgeneratoren (clock manager).
+//!
+//! Die Taktgeneratoren leiten aus einer Quelle (Oszillator oder PLL) mit einem gebrochenen
+//! Teiler die Takte für die Pins GPCLK0 bis GPCLK2, PCM und PWM ab. Register können nur mit
+//! dem Passwort `0x5A` im obersten Byte beschrieben werden. Quelle und Teiler dürfen nur
+//! geändert werden, solange der Generator steht (BUSY = 0).
+//!
+//! Vgl. BMC2835 Manual, S. 105ff. Die Register für PCM und PWM sind dort nicht beschrieben,
+//! haben aber denselben Aufbau.
+use core::ptr::{read_volatile, write_volatile};
+use bit_field::BitField;
+use hal::cpu::Cpu;
+
+/// Passwort für Schreibzugriffe
+const CM_PASSWORD: u32 = 0x5a << 24;
+
+/// Bits des CTL-Registers
+const CTL_ENAB: u8 = 4;
+const CTL_KILL: u8 = 5;
+const CTL_BUSY: u8 = 7;
+
+/// Höchstzahl an Abfragen beim Warten auf BUSY
+const CM_BUSY_LOOPS: u32 = 100_000;
+
+/// Taktgenerator
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CmClock {
+    /// Allgemeiner Takt 0 (GPCLK0, z.B. Pin 4)
+    Gp0,
+    /// Allgemeiner Takt 1 (GPCLK1, von der Firmware genutzt)
+    Gp1,
+    /// Allgemeiner Takt 2 (GPCLK2, z.B. Pin 6)
+    Gp2,
+    /// Takt des PCM/I2S-Audio
+    Pcm,
+    /// Takt des Pulsweitenmodulators
+    Pwm,
+}
+
+/// Taktquelle
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CmSource {
+    /// Kein Takt
+    Ground     = 0,
+    /// Quarzoszillator (19,2 MHz)
+    Oscillator = 1,
+    /// PLLA
+    PllA       = 4,
+    /// PLLC (Kerntakt, ändert sich mit dessen Rate)
+    PllC       = 5,
+    /// PLLD (500 MHz)
+    PllD       = 6,
+    /// HDMI-Hilfstakt
+    HdmiAux    = 7,
+}
+
+impl CmSource {
+    /// Feste Rate der Quelle in Hz, falls bekannt
+    pub fn rate(&self) -> Option<u32> {
+        match *self {
+            CmSource::Oscillator => Some(19_200_000),
+            CmSource::PllD       => Some(500_000_000),
+            _                    => None
+        }
+    }
+}
+
+/// Fehler der Taktgeneratoren
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CmError {
+    /// Die Rate kann aus der Quelle nicht erzeugt werden.
+    InvalidRate,
+    /// Die Rate der Quelle ist unbekannt.
+    UnknownSource,
+    /// Der Generator hält nicht an.
+    Busy,
+}
+
+/// Berechnet ganzzahligen und gebrochenen Teil (in 1/4096) des Teilers für die Rate `hz`
+/// aus der Quellrate `source` und die erreichte (mittlere) Rate.
+///
+/// Ohne MASH (`mash == 0`) wird nur der ganzzahlige Teil genutzt. Mit MASH-Stufe 1 liegt
+/// die Rate im Mittel genau bei `source / (divi + divf / 4096)`; der Teiler muss dann
+/// mindestens 2 sein, bei Stufe 2 mindestens 3, bei Stufe 3 mindestens 5.
+pub fn cm_divider(source: u32, hz: u32, mash: u8) -> Result<(u32,u32,u32),CmError> {
+    if source == 0 || hz == 0 || mash > 3 {
+        return Err(CmError::InvalidRate);
+    }
+    let (divi, divf) = if mash == 0 {
+        // Aufrunden, damit die Rate höchstens `hz` ist
+        ((source + hz - 1) / hz, 0)
+    } else {
+        let div = ((source as u64) << 12) / hz as u64;
+        ((div >> 12) as u32, (div & 0xfff) as u32)
+    };
+    let min = match mash {
+        0 => 1,
+        1 => 2,
+        2 => 3,
+        _ => 5,
+    };
+    if divi < min || divi > 0xfff {
+        return Err(CmError::InvalidRate);
+    }
+    let actual = ((source as u64) << 12) / (((divi as u64) << 12) + divf as u64);
+    Ok((divi, divf, actual as u32))
+}
+
+/// Register der Taktgeneratoren
+#[repr(C)]
+pub struct ClockManager {
+    _reserved0: [u32;28],
+    gp0_ctl:    u32,        // Offset 0x70
+    gp0_div:    u32,        // Offset 0x74
+    gp1_ctl:    u32,        // Offset 0x78
+    gp1_div:    u32,        // Offset 0x7C
+    gp2_ctl:    u32,        // Offset 0x80
+    gp2_div:    u32,        // Offset 0x84
+    _reserved1: [u32;4],
+    pcm_ctl:    u32,        // Offset 0x98
+    pcm_div:    u32,        // Offset 0x9C
+    pwm_ctl:    u32,        // Offset 0xA0
+    pwm_div:    u32,        // Offset 0xA4
+}
+
+use super::Bmc2835;
+impl Bmc2835 for ClockManager {
+
+    fn base_offset() -> usize {
+        0x101000
+    }
+}
+
+impl ClockManager {
+    fn regs(&mut self, clock: CmClock) -> (&mut u32, &mut u32) {
+        match clock {
+            CmClock::Gp0 => (&mut self.gp0_ctl, &mut self.gp0_div),
+            CmClock::Gp1 => (&mut self.gp1_ctl, &mut self.gp1_div),
+            CmClock::Gp2 => (&mut self.gp2_ctl, &mut self.gp2_div),
+            CmClock::Pcm => (&mut self.pcm_ctl, &mut self.pcm_div),
+            CmClock::Pwm => (&mut self.pwm_ctl, &mut self.pwm_div),
+        }
+    }
+
+    fn read_ctl(&mut self, clock: CmClock) -> u32 {
+        let (ctl, _) = self.regs(clock);
+        Cpu::data_memory_barrier();
+        unsafe{ read_volatile(ctl) }
+    }
+
+    fn write_ctl(&mut self, clock: CmClock, value: u32) {
+        let (ctl, _) = self.regs(clock);
+        Cpu::data_memory_barrier();
+        unsafe{ write_volatile(ctl, CM_PASSWORD | (value & 0xffffff)); }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Läuft der Generator?
+    pub fn is_busy(&mut self, clock: CmClock) -> bool {
+        self.read_ctl(clock).get_bit(CTL_BUSY)
+    }
+
+    /// Hält den Generator an und wartet, bis er steht. Bleibt er hängen, wird er mit KILL
+    /// angehalten.
+    pub fn stop(&mut self, clock: CmClock) -> Result<(),CmError> {
+        let mut ctl = self.read_ctl(clock);
+        ctl.set_bit(CTL_ENAB, false);
+        self.write_ctl(clock, ctl);
+        for _ in 0..CM_BUSY_LOOPS {
+            if !self.is_busy(clock) {
+                return Ok(());
+            }
+        }
+        ctl.set_bit(CTL_KILL, true);
+        self.write_ctl(clock, ctl);
+        ctl.set_bit(CTL_KILL, false);
+        self.write_ctl(clock, ctl);
+        if self.is_busy(clock) {
+            Err(CmError::Busy)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Startet den Generator mit Quelle, Teiler (siehe `cm_divider`) und MASH-Stufe.
+    pub fn start(&mut self, clock: CmClock, source: CmSource, divi: u32, divf: u32, mash: u8)
+                 -> Result<(),CmError> {
+        self.stop(clock)?;
+        let mut div: u32 = 0;
+        div.set_bits(12..24, divi);
+        div.set_bits(0..12, divf);
+        {
+            let (_, reg) = self.regs(clock);
+            Cpu::data_memory_barrier();
+            unsafe{ write_volatile(reg, CM_PASSWORD | div); }
+        }
+        let mut ctl: u32 = 0;
+        ctl.set_bits(0..4, source as u32);
+        ctl.set_bits(9..11, mash as u32);
+        // Quelle erst bei stehendem Generator setzen, dann einschalten
+        self.write_ctl(clock, ctl);
+        ctl.set_bit(CTL_ENAB, true);
+        self.write_ctl(clock, ctl);
+        Ok(())
+    }
+
+    /// Stellt die Rate `hz` aus `source` ein (MASH 1, außer der Teiler ist ganzzahlig) und
+    /// gibt die erreichte Rate zurück.
+    pub fn set_rate(&mut self, clock: CmClock, source: CmSource, hz: u32) -> Result<u32,CmError> {
+        let rate = source.rate().ok_or(CmError::UnknownSource)?;
+        let (divi, divf, actual) = match cm_divider(rate, hz, 1) {
+            Ok((divi, 0, actual)) => (divi, 0, actual),
+            Ok(div)               => div,
+            Err(_)                => cm_divider(rate, hz, 0)?,
+        };
+        self.start(clock, source, divi, divf, if divf == 0 { 0 } else { 1 })?;
+        Ok(actual)
+    }
+}
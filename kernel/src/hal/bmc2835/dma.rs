@@ -0,0 +1,321 @@
+#![allow(dead_code)]
+use core::ptr::{read_volatile, write_volatile};
+use bit_field::BitField;
+use hal::cpu::{Cpu, MMU};
+use memory::Address;
+
+/// Anzahl der DMA-Kanäle, die der Treiber nutzt (Kanal 15 liegt an anderer Stelle und ist
+/// der GPU vorbehalten).
+pub const DMA_NUM_CHANNELS: usize = 15;
+
+/// Die Kanäle ab 7 sind "Lite"-Kanäle: höchstens 64 kB je Kontrollblock, kein 2D-Modus.
+pub const DMA_FIRST_LITE_CHANNEL: usize = 7;
+
+/// Höchste Länge eines Kontrollblocks auf einem Lite-Kanal
+pub const DMA_LITE_MAX_LEN: usize = 0xffff;
+
+/// Höchste Länge eines Kontrollblocks auf einem normalen Kanal
+pub const DMA_MAX_LEN: usize = 0x3fffffff;
+
+/// Bits des CS-Registers
+const CS_ACTIVE:      u8 = 0;
+const CS_END:         u8 = 1;
+const CS_INT:         u8 = 2;
+const CS_ERROR:       u8 = 8;
+const CS_WAIT_WRITES: u8 = 28;
+const CS_ABORT:       u8 = 30;
+const CS_RESET:       u8 = 31;
+
+/// Bits der Transfer Information (TI)
+const TI_INTEN:       u8 = 0;
+const TI_TDMODE:      u8 = 1;
+const TI_WAIT_RESP:   u8 = 3;
+const TI_DEST_INC:    u8 = 4;
+const TI_DEST_DREQ:   u8 = 6;
+const TI_SRC_INC:     u8 = 8;
+const TI_SRC_DREQ:    u8 = 10;
+const TI_NO_WIDE:     u8 = 26;
+
+/// Fehlerbits im DEBUG-Register (werden durch Schreiben einer 1 gelöscht)
+const DEBUG_ERRORS:   u32 = 0b111;
+
+/// Peripheriegeräte, die den Datenfluss steuern können (_DREQ_, Feld PERMAP)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DmaDreq {
+    /// Kein Gerät, Übertragung mit voller Geschwindigkeit
+    None        = 0,
+    /// PCM, Senden
+    PcmTx       = 2,
+    /// PCM, Empfangen
+    PcmRx       = 3,
+    /// PWM
+    Pwm         = 5,
+    /// SPI0, Senden
+    SpiTx       = 6,
+    /// SPI0, Empfangen
+    SpiRx       = 7,
+    /// BSC/SPI-Slave, Senden
+    SlaveTx     = 8,
+    /// BSC/SPI-Slave, Empfangen
+    SlaveRx     = 9,
+    /// EMMC
+    Emmc        = 11,
+    /// PL011, Senden
+    UartTx      = 12,
+    /// SD-Host
+    SdHost      = 13,
+    /// PL011, Empfangen
+    UartRx      = 14,
+}
+
+/// Richtung, in der ein Gerät den Datenfluss steuert
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DmaDirection {
+    /// Speicher nach Speicher
+    MemToMem,
+    /// Speicher zum Gerät (Ziel ist die FIFO, Adresse fest)
+    MemToPeripheral(DmaDreq),
+    /// Gerät zum Speicher (Quelle ist die FIFO, Adresse fest)
+    PeripheralToMem(DmaDreq),
+}
+
+/// Übersetzt eine (virtuelle) Adresse des Kernels in die Adresse, unter der die DMA-Einheit
+/// auf den Speicher bzw. die Geräte zugreift (_bus address_).
+///
+/// RAM wird wie vom ARM über den L2-kohärenten Alias angesprochen (0x40000000), Geräte
+/// über 0x7E000000. Physische Adressen ab 0x40000000 sind bereits Busadressen (z.B. die des
+/// Framebuffers) und bleiben unverändert. Gibt `None` zurück, wenn `addr` nicht abgebildet
+/// ist.
+pub fn dma_bus_address(addr: Address) -> Option<u32> {
+    MMU::translate_kernel_read(addr).map(|phys| {
+        if phys < 0x20000000 {
+            (phys as u32) | 0x40000000
+        } else if phys < 0x21000000 {
+            (phys - 0x20000000) as u32 + 0x7E000000
+        } else {
+            phys as u32
+        }
+    })
+}
+
+/// Kontrollblock einer DMA-Übertragung
+///
+/// Vgl. BMC2835 Manual, S. 40f. Kontrollblöcke müssen auf 32 Bytes ausgerichtet sein und
+/// enthalten ausschließlich Busadressen.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+#[repr(align(32))]
+pub struct DmaControlBlock {
+    /// Transfer Information
+    pub ti:        u32,
+    /// Quelladresse
+    pub source:    u32,
+    /// Zieladresse
+    pub dest:      u32,
+    /// Länge in Bytes; im 2D-Modus Breite (Bits 0..15) und Zeilenzahl - 1 (Bits 16..29)
+    pub len:       u32,
+    /// Zeilenabstände im 2D-Modus (Ziel in Bits 16..31, Quelle in Bits 0..15)
+    pub stride:    u32,
+    /// Busadresse des nächsten Kontrollblocks, 0 am Ende
+    pub next:      u32,
+    _reserved:     [u32; 2],
+}
+
+impl DmaControlBlock {
+    /// Lineare Übertragung von `len` Bytes von `source` nach `dest` (Busadressen).
+    ///
+    /// Bei Übertragungen zu einem Gerät bleibt dessen Adresse fest und das Gerät bestimmt
+    /// über DREQ das Tempo.
+    pub fn new(direction: DmaDirection, dest: u32, source: u32, len: usize) -> DmaControlBlock {
+        let mut ti: u32 = 0;
+        ti.set_bit(TI_WAIT_RESP, true);
+        match direction {
+            DmaDirection::MemToMem => {
+                ti.set_bit(TI_SRC_INC, true);
+                ti.set_bit(TI_DEST_INC, true);
+            },
+            DmaDirection::MemToPeripheral(dreq) => {
+                ti.set_bit(TI_SRC_INC, true);
+                ti.set_bit(TI_DEST_DREQ, true);
+                ti.set_bits(16..21, dreq as u32);
+                // Geräte vertragen keine Bursts mit 128 Bit.
+                ti.set_bit(TI_NO_WIDE, true);
+            },
+            DmaDirection::PeripheralToMem(dreq) => {
+                ti.set_bit(TI_DEST_INC, true);
+                ti.set_bit(TI_SRC_DREQ, true);
+                ti.set_bits(16..21, dreq as u32);
+                ti.set_bit(TI_NO_WIDE, true);
+            }
+        }
+        DmaControlBlock {
+            ti:        ti,
+            source:    source,
+            dest:      dest,
+            len:       len as u32,
+            stride:    0,
+            next:      0,
+            _reserved: [0; 2],
+        }
+    }
+
+    /// Zweidimensionale Übertragung von `rows` Zeilen zu je `width` Bytes (Speicher nach
+    /// Speicher). `dest_pitch` und `source_pitch` sind die Abstände der Zeilenanfänge.
+    ///
+    /// Nur auf normalen Kanälen möglich.
+    pub fn new_2d(dest: u32, dest_pitch: usize, source: u32, source_pitch: usize,
+                  width: usize, rows: usize) -> DmaControlBlock {
+        let mut cb = DmaControlBlock::new(DmaDirection::MemToMem, dest, source, 0);
+        cb.ti.set_bit(TI_TDMODE, true);
+        cb.len = (width as u32 & 0xffff) | ((rows as u32 - 1) & 0x3fff) << 16;
+        // Die Strides werden nach jeder Zeile addiert, zusätzlich zur Breite.
+        let dest_stride = (dest_pitch as i32 - width as i32) as u32 & 0xffff;
+        let source_stride = (source_pitch as i32 - width as i32) as u32 & 0xffff;
+        cb.stride = dest_stride << 16 | source_stride;
+        cb
+    }
+
+    /// Löst am Ende dieses Kontrollblocks einen Interrupt aus.
+    pub fn set_interrupt(&mut self, enable: bool) {
+        self.ti.set_bit(TI_INTEN, enable);
+    }
+
+    /// Ist dies eine 2D-Übertragung?
+    pub fn is_2d(&self) -> bool {
+        self.ti.get_bit(TI_TDMODE)
+    }
+}
+
+/// Register eines DMA-Kanals
+///
+/// Vgl. BMC2835 Manual, S. 47ff.
+#[repr(C)]
+pub struct DmaChannelRegs {
+    /// Steuerung und Status
+    cs:        u32,    // Offset 0x00
+    /// Busadresse des aktuellen Kontrollblocks
+    conblk:    u32,    // Offset 0x04
+    /// Aktuelle Werte aus dem Kontrollblock (nur lesbar)
+    ti:        u32,    // Offset 0x08
+    source:    u32,    // Offset 0x0C
+    dest:      u32,    // Offset 0x10
+    len:       u32,    // Offset 0x14
+    stride:    u32,    // Offset 0x18
+    next:      u32,    // Offset 0x1C
+    /// Fehler
+    debug:     u32,    // Offset 0x20
+}
+
+/// Globale Register des DMA-Controllers
+#[repr(C)]
+pub struct DmaGlobal {
+    /// Interruptstatus aller Kanäle
+    int_status: u32,   // Offset 0xFE0
+    _padding:   [u32; 3],
+    /// Freigabe der Kanäle
+    enable:     u32,   // Offset 0xFF0
+}
+
+use super::Bmc2835;
+impl Bmc2835 for DmaGlobal {
+
+    fn base_offset() -> usize {
+        0x007FE0
+    }
+}
+
+impl DmaGlobal {
+    /// Gibt den Kanal frei oder sperrt ihn.
+    pub fn enable(&mut self, channel: usize, enable: bool) {
+        Cpu::data_memory_barrier();
+        let mut reg = unsafe{ read_volatile(&self.enable) };
+        reg.set_bit(channel as u8, enable);
+        unsafe{ write_volatile(&mut self.enable, reg); }
+        Cpu::data_memory_barrier();
+    }
+}
+
+impl DmaChannelRegs {
+    /// Register des Kanals `channel` (0 bis 14)
+    pub fn channel(channel: usize) -> &'static mut DmaChannelRegs {
+        unsafe {
+            &mut *((DmaGlobal::device_base() + 0x007000 + channel * 0x100) as *mut DmaChannelRegs)
+        }
+    }
+
+    fn read_cs(&self) -> u32 {
+        Cpu::data_memory_barrier();
+        unsafe{ read_volatile(&self.cs) }
+    }
+
+    /// Setzt den Kanal zurück und löscht Status und Fehler.
+    pub fn reset(&mut self) {
+        Cpu::data_memory_barrier();
+        unsafe{
+            write_volatile(&mut self.cs, 1 << CS_RESET);
+            write_volatile(&mut self.cs, 1 << CS_END | 1 << CS_INT);
+            write_volatile(&mut self.debug, DEBUG_ERRORS);
+        }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Startet die Kette ab dem Kontrollblock mit der Busadresse `cb`.
+    pub fn start(&mut self, cb: u32) {
+        let mut cs: u32 = 0;
+        cs.set_bit(CS_END, true);
+        cs.set_bit(CS_INT, true);
+        cs.set_bits(16..20, 8);  // Priorität
+        cs.set_bits(20..24, 15); // Priorität bei Panik
+        cs.set_bit(CS_WAIT_WRITES, true);
+        Cpu::data_memory_barrier();
+        unsafe{
+            write_volatile(&mut self.conblk, cb);
+            write_volatile(&mut self.cs, cs);
+            cs.set_bit(CS_ACTIVE, true);
+            write_volatile(&mut self.cs, cs);
+        }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Bricht die Übertragung ab.
+    pub fn abort(&mut self) {
+        let mut cs = self.read_cs();
+        cs.set_bit(CS_ACTIVE, false);
+        unsafe{ write_volatile(&mut self.cs, cs); }
+        cs.set_bit(CS_ABORT, true);
+        unsafe{ write_volatile(&mut self.cs, cs); }
+        self.reset();
+    }
+
+    /// Läuft eine Übertragung?
+    pub fn is_active(&self) -> bool {
+        self.read_cs().get_bit(CS_ACTIVE)
+    }
+
+    /// Ist die Kette abgearbeitet (END)?
+    pub fn is_done(&self) -> bool {
+        let cs = self.read_cs();
+        cs.get_bit(CS_END) && !cs.get_bit(CS_ACTIVE)
+    }
+
+    /// Ist ein Fehler aufgetreten?
+    pub fn has_error(&self) -> bool {
+        let debug = unsafe{ read_volatile(&self.debug) };
+        self.read_cs().get_bit(CS_ERROR) || debug & DEBUG_ERRORS != 0
+    }
+
+    /// Liegt ein Interrupt an? Er wird dabei bestätigt.
+    pub fn acknowledge_interrupt(&mut self) -> bool {
+        let cs = self.read_cs();
+        if cs.get_bit(CS_INT) {
+            // INT und END werden durch Schreiben einer 1 gelöscht, ACTIVE bleibt erhalten.
+            let mut ack = cs & (1 << CS_ACTIVE | 0xff << 16 | 1 << CS_WAIT_WRITES);
+            ack.set_bit(CS_INT, true);
+            unsafe{ write_volatile(&mut self.cs, ack); }
+            Cpu::data_memory_barrier();
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,530 @@
+#![warn(missing_docs)]
+//! Treiber für den DMA-Controller.
+//!
+//! Welche Kanäle der ARM nutzen darf, meldet die Firmware (`Tag::GetDmaChannels`). Ein
+//! Kanal wird mit `DmaController::allocate` belegt und beim Freigeben des `DmaChannel`
+//! zurückgegeben.
+//!
+//! Eine Übertragung besteht aus einer Kette von Kontrollblöcken (`DmaChain`). Die
+//! Methoden von `DmaChain` übersetzen die Adressen in Busadressen, teilen lange Bereiche auf
+//! mehrere Kontrollblöcke auf und merken sich, welche Speicherbereiche gelesen und
+//! geschrieben werden. Vor dem Start schreibt der Treiber die Quellbereiche aus dem
+//! Datencache zurück, nach dem Ende markiert er die Zielbereiche als ungültig; Aufrufer
+//! müssen sich also nicht um den Cache kümmern. Ränder von Zielbereichen, die nur einen Teil
+//! einer Cachezeile belegen, laufen dazu über eigene Zeilen.
+//!
+//! Der Interrupt des Kanals wird nur am Ende der Kette ausgelöst. Mit `set_polled` wartet
+//! der Kanal stattdessen aktiv, z.B. wenn Interrupts gesperrt sind.
+use core::ptr;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use hal::cpu::{Cpu, Cache, CACHE_LINE_SIZE};
+use memory::{Address, AddressRange, PAGE_SIZE};
+use data::isr_table::IsrResult;
+use data::kernel::KernelData;
+use sync::{IrqSpinLock, WaitQueue};
+use super::{Bmc2835, IrqController, GeneralInterrupt, Interrupt, Tag, PropertyTagBuffer};
+use super::{DmaChannelRegs, DmaGlobal, DmaControlBlock, DmaDirection, DmaDreq, dma_bus_address};
+use super::{DMA_NUM_CHANNELS, DMA_FIRST_LITE_CHANNEL, DMA_LITE_MAX_LEN};
+
+/// Fehler bei DMA-Übertragungen
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DmaError {
+    /// Der Controller ist nicht initialisiert
+    NotInitialized,
+    /// Kein passender Kanal frei
+    NoChannel,
+    /// Längen passen nicht zusammen oder sind zu groß
+    InvalidLength,
+    /// Die Kette braucht einen normalen Kanal (2D-Modus)
+    NotSupported,
+    /// Ein Puffer ist nicht abgebildet
+    InvalidAddress,
+    /// Ein Puffer ist nicht auf Cachezeilen ausgerichtet
+    Unaligned,
+    /// Die DMA-Einheit hat einen Fehler gemeldet
+    Bus,
+    /// Die Serviceroutine konnte nicht angemeldet werden
+    Failed,
+}
+
+/// Art des gewünschten Kanals
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DmaChannelKind {
+    /// Beliebiger Kanal; Lite-Kanäle werden bevorzugt.
+    Any,
+    /// Normaler Kanal (für 2D-Übertragungen)
+    Full,
+}
+
+/// Eine Cachezeile, siehe `DmaChain::push_to_memory`
+#[derive(Copy, Clone)]
+#[repr(C)]
+#[repr(align(32))]
+struct CacheLine([u8; CACHE_LINE_SIZE]);
+
+/// Randstück eines Zielbereichs, das die DMA in eine eigene Cachezeile schreibt
+struct Bounce {
+    dest: Address,
+    len:  usize,
+    line: Box<CacheLine>,
+}
+
+impl Bounce {
+    /// Adresse, an die die DMA das Randstück schreibt (gleicher Abstand zum Zeilenanfang)
+    fn address(&self) -> Address {
+        &*self.line as *const CacheLine as Address + (self.dest & (CACHE_LINE_SIZE - 1))
+    }
+}
+
+/// Busadresse von `addr`
+fn bus_address(addr: Address) -> Result<u32,DmaError> {
+    dma_bus_address(addr).ok_or(DmaError::InvalidAddress)
+}
+
+/// Anzahl der Bytes von `addr` bis zum Ende der Seite
+fn to_page_end(addr: Address) -> usize {
+    PAGE_SIZE - (addr & (PAGE_SIZE - 1))
+}
+
+/// Liegt der Bereich auch physisch zusammenhängend im Speicher?
+fn is_contiguous(start: Address, len: usize) -> Result<bool,DmaError> {
+    let first = bus_address(start)?;
+    let mut page = (start & !(PAGE_SIZE - 1)) + PAGE_SIZE;
+    while page < start + len {
+        if bus_address(page)? != first.wrapping_add((page - start) as u32) {
+            return Ok(false);
+        }
+        page += PAGE_SIZE;
+    }
+    Ok(true)
+}
+
+/// Ist die Adresse auf eine Cachezeile ausgerichtet?
+fn is_line_aligned(addr: Address) -> bool {
+    addr & (CACHE_LINE_SIZE - 1) == 0
+}
+
+/// Kette von Kontrollblöcken
+///
+/// Die übergebenen Puffer müssen gültig bleiben, bis die Übertragung beendet ist.
+pub struct DmaChain {
+    blocks:  Vec<DmaControlBlock>,
+    sources: Vec<AddressRange>,
+    dests:   Vec<AddressRange>,
+    bounces: Vec<Bounce>,
+}
+
+impl DmaChain {
+    /// Leere Kette
+    pub fn new() -> DmaChain {
+        DmaChain {
+            blocks:  Vec::new(),
+            sources: Vec::new(),
+            dests:   Vec::new(),
+            bounces: Vec::new(),
+        }
+    }
+
+    /// Hängt einen Kontrollblock an; Adressen und Cache sind Sache des Aufrufers.
+    pub fn push(&mut self, cb: DmaControlBlock) -> &mut DmaChain {
+        self.blocks.push(cb);
+        self
+    }
+
+    /// Linearer Bereich, aufgeteilt in Blöcke, die auch ein Lite-Kanal überträgt.
+    ///
+    /// Virtuell zusammenhängende Puffer sind es physisch nicht unbedingt; ein Block endet
+    /// daher spätestens an der nächsten Seitengrenze von Quelle und Ziel.
+    fn push_linear(&mut self, direction: DmaDirection, dest: Address, source: Address, len: usize)
+                   -> Result<(),DmaError> {
+        use core::cmp::min;
+
+        let mut done = 0;
+        while done < len {
+            let (d, s, n) = match direction {
+                DmaDirection::MemToMem              => (dest + done, source + done,
+                                                        min(to_page_end(dest + done), to_page_end(source + done))),
+                DmaDirection::MemToPeripheral(_)    => (dest, source + done, to_page_end(source + done)),
+                DmaDirection::PeripheralToMem(_)    => (dest + done, source, to_page_end(dest + done)),
+            };
+            let n = min(min(n, len - done), DMA_LITE_MAX_LEN & !3);
+            self.blocks.push(DmaControlBlock::new(direction, bus_address(d)?, bus_address(s)?, n));
+            done += n;
+        }
+        Ok(())
+    }
+
+    /// Wie `push_linear` für Übertragungen in den Speicher.
+    ///
+    /// Nach dem Ende werden die Cachezeilen des Ziels verworfen. Randstücke, die sich eine
+    /// Zeile mit anderen Daten teilen, schreibt die DMA daher in eigene Zeilen, aus denen
+    /// `finish` sie an ihr Ziel kopiert.
+    fn push_to_memory(&mut self, direction: DmaDirection, dest: Address, source: Address, len: usize)
+                      -> Result<(),DmaError> {
+        use core::cmp::{min, max};
+
+        let end = dest + len;
+        let head_end = min((dest + CACHE_LINE_SIZE - 1) & !(CACHE_LINE_SIZE - 1), end);
+        let tail_start = max(end & !(CACHE_LINE_SIZE - 1), head_end);
+        for &(start, stop) in [(dest, head_end), (head_end, tail_start), (tail_start, end)].iter() {
+            if start == stop {
+                continue;
+            }
+            let s = match direction {
+                DmaDirection::MemToMem => source + (start - dest),
+                _                      => source,
+            };
+            if is_line_aligned(start) && is_line_aligned(stop) {
+                self.push_linear(direction, start, s, stop - start)?;
+                self.dests.push(start .. stop);
+            } else {
+                let bounce = Bounce {
+                    dest: start,
+                    len:  stop - start,
+                    line: Box::new(CacheLine([0; CACHE_LINE_SIZE])),
+                };
+                let line = &*bounce.line as *const CacheLine as Address;
+                self.push_linear(direction, bounce.address(), s, stop - start)?;
+                self.dests.push(line .. line + CACHE_LINE_SIZE);
+                self.bounces.push(bounce);
+            }
+        }
+        Ok(())
+    }
+
+    /// Kopiert `source` nach `dest` (gleiche Länge).
+    pub fn copy(&mut self, dest: &mut [u8], source: &[u8]) -> Result<&mut DmaChain,DmaError> {
+        if dest.len() != source.len() {
+            return Err(DmaError::InvalidLength);
+        }
+        let (d, s) = (dest.as_ptr() as Address, source.as_ptr() as Address);
+        self.push_to_memory(DmaDirection::MemToMem, d, s, source.len())?;
+        self.sources.push(s .. s + source.len());
+        Ok(self)
+    }
+
+    /// Schreibt `source` wortweise in die FIFO an der Adresse `fifo`; das Gerät steuert den
+    /// Datenfluss über `dreq`.
+    pub fn to_peripheral(&mut self, dreq: DmaDreq, fifo: Address, source: &[u32])
+                         -> Result<&mut DmaChain,DmaError> {
+        let s = source.as_ptr() as Address;
+        let len = source.len() * 4;
+        self.push_linear(DmaDirection::MemToPeripheral(dreq), fifo, s, len)?;
+        self.sources.push(s .. s + len);
+        Ok(self)
+    }
+
+    /// Liest `dest.len()` Wörter aus der FIFO an der Adresse `fifo`.
+    pub fn from_peripheral(&mut self, dreq: DmaDreq, fifo: Address, dest: &mut [u32])
+                           -> Result<&mut DmaChain,DmaError> {
+        let d = dest.as_ptr() as Address;
+        self.push_to_memory(DmaDirection::PeripheralToMem(dreq), d, fifo, dest.len() * 4)?;
+        Ok(self)
+    }
+
+    /// Kopiert ein Rechteck von `rows` Zeilen zu `width` Bytes, z.B. in den Framebuffer.
+    /// `dest_pitch` und `source_pitch` sind die Abstände der Zeilenanfänge in Bytes.
+    ///
+    /// Der Zielbereich (vom Anfang der ersten bis zum Ende der letzten Zeile) muss auf
+    /// Cachezeilen ausgerichtet beginnen und enden.
+    pub fn blit(&mut self, dest: Address, dest_pitch: usize, source: Address, source_pitch: usize,
+                width: usize, rows: usize) -> Result<&mut DmaChain,DmaError> {
+        if width == 0 || rows == 0 {
+            return Ok(self);
+        }
+        // Breite 16 Bit, Zeilen 14 Bit, Abstände als 16-Bit-Werte mit Vorzeichen
+        if width > 0xffff || dest_pitch < width || source_pitch < width ||
+            dest_pitch - width > 0x7fff || source_pitch - width > 0x7fff {
+            return Err(DmaError::InvalidLength);
+        }
+        let dest_end = dest + (rows - 1) * dest_pitch + width;
+        if !is_line_aligned(dest) || !is_line_aligned(dest_end) {
+            return Err(DmaError::Unaligned);
+        }
+        let mut row = 0;
+        while row < rows {
+            let n = ::core::cmp::min(rows - row, 0x4000);
+            let d = dest + row * dest_pitch;
+            let s = source + row * source_pitch;
+            if is_contiguous(d, (n - 1) * dest_pitch + width)? &&
+                is_contiguous(s, (n - 1) * source_pitch + width)? {
+                self.blocks.push(DmaControlBlock::new_2d(bus_address(d)?, dest_pitch,
+                                                         bus_address(s)?, source_pitch, width, n));
+            } else {
+                // Zeilenweise, jeweils nach Seiten aufgeteilt
+                for i in 0..n {
+                    self.push_linear(DmaDirection::MemToMem, d + i * dest_pitch,
+                                     s + i * source_pitch, width)?;
+                }
+            }
+            row += n;
+        }
+        self.sources.push(source .. source + (rows - 1) * source_pitch + width);
+        self.dests.push(dest .. dest_end);
+        Ok(self)
+    }
+
+    /// Anzahl der Kontrollblöcke
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Ist die Kette leer?
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Braucht die Kette einen normalen Kanal?
+    pub fn needs_full_channel(&self) -> bool {
+        self.blocks.iter().any(|cb| cb.is_2d() || cb.len as usize > DMA_LITE_MAX_LEN)
+    }
+
+    /// Verkettet die Blöcke, schreibt Blöcke und Quellen aus dem Cache zurück und gibt die
+    /// Busadresse des ersten Blocks zurück.
+    fn prepare(&mut self) -> Result<u32,DmaError> {
+        let n = self.blocks.len();
+        for i in 0..n {
+            let next = if i + 1 < n { bus_address(&self.blocks[i + 1] as *const _ as Address)? } else { 0 };
+            self.blocks[i].next = next;
+            self.blocks[i].set_interrupt(i + 1 == n);
+        }
+        let start = self.blocks.as_ptr() as Address;
+        Cache::clean_data_range(start .. start + n * ::core::mem::size_of::<DmaControlBlock>());
+        for r in self.sources.iter() {
+            Cache::clean_data_range(r.clone());
+        }
+        // Verhindert, dass verdrängte Cachezeilen die Daten der DMA überschreiben.
+        for r in self.dests.iter() {
+            Cache::clean_invalidate_data_range(r.clone());
+        }
+        bus_address(start)
+    }
+
+    /// Verwirft nach der Übertragung die veralteten Cachezeilen der Ziele und kopiert die
+    /// Randstücke an ihr Ziel.
+    fn finish(&self) {
+        // Alle Zielbereiche bestehen aus ganzen Cachezeilen.
+        for r in self.dests.iter() {
+            Cache::invalidate_data_lines(r.clone());
+        }
+        for bounce in self.bounces.iter() {
+            unsafe{
+                ptr::copy_nonoverlapping(bounce.address() as *const u8, bounce.dest as *mut u8, bounce.len);
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct ChannelState {
+    available: bool,
+    allocated: bool,
+    finished:  bool,
+}
+
+struct DmaState {
+    initialized: bool,
+    channels:    [ChannelState; DMA_NUM_CHANNELS],
+}
+
+/// Der DMA-Controller
+pub struct DmaController {
+    state: IrqSpinLock<DmaState>,
+    queue: WaitQueue,
+}
+
+static DMA_CONTROLLER: DmaController = DmaController::new();
+
+impl DmaController {
+    const fn new() -> DmaController {
+        DmaController {
+            state: IrqSpinLock::new(DmaState {
+                initialized: false,
+                channels:    [ChannelState { available: false, allocated: false, finished: false }; DMA_NUM_CHANNELS],
+            }),
+            queue: WaitQueue::new(),
+        }
+    }
+
+    /// Der Controller
+    pub fn get() -> &'static DmaController {
+        &DMA_CONTROLLER
+    }
+
+    /// Fragt die freien Kanäle bei der Firmware ab, meldet für jeden die Serviceroutine an
+    /// und aktiviert die Interrupts. Gibt die Maske der nutzbaren Kanäle zurück.
+    pub fn init(&self) -> Result<u32,DmaError> {
+        let mut prop = PropertyTagBuffer::new();
+        prop.init();
+        prop.add_tag_with_param(Tag::GetDmaChannels, None);
+        prop.exchange();
+        let mask = match prop.get_answer(Tag::GetDmaChannels) {
+            Some(a) => a[0] & ((1 << DMA_NUM_CHANNELS) - 1),
+            None    => return Err(DmaError::NotInitialized)
+        };
+        for nr in 0..DMA_NUM_CHANNELS {
+            if mask & (1 << nr) == 0 {
+                continue;
+            }
+            // Die Kanäle 11 bis 14 teilen sich einen Interrupt.
+            let uid = if nr <= 10 { 16 + nr } else { 27 };
+            let int = GeneralInterrupt::from_uid(uid).ok_or(DmaError::Failed)?;
+            KernelData::isr_table().add_isr(int, DmaController::isr, nr)
+                .map_err(|_| DmaError::Failed)?;
+            IrqController::get().enable(int);
+            DmaGlobal::get().enable(nr, true);
+            DmaChannelRegs::channel(nr).reset();
+        }
+        let mut state = self.state.lock();
+        for nr in 0..DMA_NUM_CHANNELS {
+            state.channels[nr].available = mask & (1 << nr) != 0;
+        }
+        state.initialized = true;
+        Ok(mask)
+    }
+
+    /// Belegt einen freien Kanal.
+    pub fn allocate(&'static self, kind: DmaChannelKind) -> Result<DmaChannel,DmaError> {
+        let mut state = self.state.lock();
+        if !state.initialized {
+            return Err(DmaError::NotInitialized);
+        }
+        // Lite-Kanäle zuerst, damit normale Kanäle für 2D-Übertragungen frei bleiben.
+        let first = if kind == DmaChannelKind::Any { DMA_FIRST_LITE_CHANNEL } else { 0 };
+        let candidates = (first .. DMA_NUM_CHANNELS).chain(0 .. first);
+        for nr in candidates {
+            if kind == DmaChannelKind::Full && nr >= DMA_FIRST_LITE_CHANNEL {
+                continue;
+            }
+            if state.channels[nr].available && !state.channels[nr].allocated {
+                state.channels[nr].allocated = true;
+                return Ok(DmaChannel { controller: self, nr: nr, polled: false });
+            }
+        }
+        Err(DmaError::NoChannel)
+    }
+
+    /// Serviceroutine; `context` ist die Nummer des Kanals.
+    pub fn isr(context: usize) -> IsrResult {
+        DMA_CONTROLLER.handle_interrupt(context)
+    }
+
+    fn handle_interrupt(&self, nr: usize) -> IsrResult {
+        if !DmaChannelRegs::channel(nr).acknowledge_interrupt() {
+            return IsrResult::NotHandled;
+        }
+        self.state.lock().channels[nr].finished = true;
+        self.queue.wake_all();
+        IsrResult::Handled
+    }
+}
+
+/// Ein belegter DMA-Kanal; wird beim Freigeben zurückgegeben.
+pub struct DmaChannel {
+    controller: &'static DmaController,
+    nr:         usize,
+    polled:     bool,
+}
+
+impl DmaChannel {
+    /// Nummer des Kanals
+    pub fn number(&self) -> usize {
+        self.nr
+    }
+
+    /// Ist es ein Lite-Kanal?
+    pub fn is_lite(&self) -> bool {
+        self.nr >= DMA_FIRST_LITE_CHANNEL
+    }
+
+    /// Aktiv auf das Ende warten statt auf den Interrupt.
+    pub fn set_polled(&mut self, polled: bool) {
+        self.polled = polled;
+    }
+
+    /// Startet die Kette, ohne auf ihr Ende zu warten.
+    ///
+    /// Unsicher, weil die Kette und alle ihre Puffer bis zum Ende von `wait` gültig bleiben
+    /// müssen.
+    pub unsafe fn start(&mut self, chain: &mut DmaChain) -> Result<(),DmaError> {
+        if self.is_lite() && chain.needs_full_channel() {
+            return Err(DmaError::NotSupported);
+        }
+        let first = chain.prepare()?;
+        self.controller.state.lock().channels[self.nr].finished = false;
+        let regs = DmaChannelRegs::channel(self.nr);
+        regs.reset();
+        regs.start(first);
+        Ok(())
+    }
+
+    /// Wartet auf das Ende der mit `start` begonnenen Kette.
+    pub fn wait(&mut self, chain: &DmaChain) -> Result<(),DmaError> {
+        let regs = DmaChannelRegs::channel(self.nr);
+        if self.polled {
+            while !regs.is_done() && !regs.has_error() {
+                Cpu::data_memory_barrier();
+            }
+        } else {
+            let controller = self.controller;
+            let nr = self.nr;
+            controller.queue.wait_until(|| {
+                controller.state.lock().channels[nr].finished || regs.has_error()
+            });
+        }
+        let error = regs.has_error();
+        if error {
+            regs.abort();
+        } else {
+            regs.reset();
+        }
+        chain.finish();
+        if error { Err(DmaError::Bus) } else { Ok(()) }
+    }
+
+    /// Überträgt die Kette und wartet auf ihr Ende.
+    pub fn run(&mut self, chain: &mut DmaChain) -> Result<(),DmaError> {
+        if chain.is_empty() {
+            return Ok(());
+        }
+        unsafe{ self.start(chain)?; }
+        self.wait(chain)
+    }
+
+    /// Kopiert `source` nach `dest`.
+    pub fn copy(&mut self, dest: &mut [u8], source: &[u8]) -> Result<(),DmaError> {
+        let mut chain = DmaChain::new();
+        chain.copy(dest, source)?;
+        self.run(&mut chain)
+    }
+
+    /// Schreibt `source` wortweise in die FIFO eines Geräts, siehe `DmaChain::to_peripheral`.
+    pub fn write_peripheral(&mut self, dreq: DmaDreq, fifo: Address, source: &[u32]) -> Result<(),DmaError> {
+        let mut chain = DmaChain::new();
+        chain.to_peripheral(dreq, fifo, source)?;
+        self.run(&mut chain)
+    }
+
+    /// Liest wortweise aus der FIFO eines Geräts, siehe `DmaChain::from_peripheral`.
+    pub fn read_peripheral(&mut self, dreq: DmaDreq, fifo: Address, dest: &mut [u32]) -> Result<(),DmaError> {
+        let mut chain = DmaChain::new();
+        chain.from_peripheral(dreq, fifo, dest)?;
+        self.run(&mut chain)
+    }
+
+    /// Kopiert ein Rechteck, siehe `DmaChain::blit`. Braucht einen normalen Kanal.
+    pub fn blit(&mut self, dest: Address, dest_pitch: usize, source: Address, source_pitch: usize,
+                width: usize, rows: usize) -> Result<(),DmaError> {
+        let mut chain = DmaChain::new();
+        chain.blit(dest, dest_pitch, source, source_pitch, width, rows)?;
+        self.run(&mut chain)
+    }
+}
+
+impl Drop for DmaChannel {
+    fn drop(&mut self) {
+        DmaChannelRegs::channel(self.nr).abort();
+        self.controller.state.lock().channels[self.nr].allocated = false;
+    }
+}
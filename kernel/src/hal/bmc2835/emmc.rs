@@ -0,0 +1,315 @@
+#![allow(dead_code)]
+use core::ptr::{read_volatile, write_volatile};
+use bit_field::BitField;
+use hal::cpu::Cpu;
+
+/// Bits des Interrupt-Registers (INTERRUPT, IRPT_MASK, IRPT_EN)
+pub const EMMC_INT_CMD_DONE:   u32 = 1 << 0;
+pub const EMMC_INT_DATA_DONE:  u32 = 1 << 1;
+pub const EMMC_INT_WRITE_RDY:  u32 = 1 << 4;
+pub const EMMC_INT_READ_RDY:   u32 = 1 << 5;
+pub const EMMC_INT_ERR:        u32 = 1 << 15;
+pub const EMMC_INT_CTO_ERR:    u32 = 1 << 16;
+pub const EMMC_INT_CCRC_ERR:   u32 = 1 << 17;
+pub const EMMC_INT_DTO_ERR:    u32 = 1 << 20;
+pub const EMMC_INT_DCRC_ERR:   u32 = 1 << 21;
+/// Alle Fehlerbits
+pub const EMMC_INT_ERRORS:     u32 = 0xffff8000;
+
+/// Bits des CONTROL1-Registers
+const C1_CLK_INTLEN:   u8 = 0;
+const C1_CLK_STABLE:   u8 = 1;
+const C1_CLK_EN:       u8 = 2;
+const C1_SRST_HC:      u8 = 24;
+const C1_SRST_CMD:     u8 = 25;
+const C1_SRST_DATA:    u8 = 26;
+
+/// Bits des STATUS-Registers
+const ST_CMD_INHIBIT:  u8 = 0;
+const ST_DAT_INHIBIT:  u8 = 1;
+
+/// Größte Anzahl Blöcke je Kommando
+pub const EMMC_MAX_BLOCKS: usize = 0xffff;
+
+/// Antworttyp eines Kommandos
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EmmcResponse {
+    /// Keine Antwort
+    None,
+    /// 136 Bit (R2)
+    R136,
+    /// 48 Bit mit Prüfung von CRC und Index (R1, R6, R7)
+    R48,
+    /// 48 Bit ohne Prüfung (R3)
+    R48NoCheck,
+    /// 48 Bit, die Karte signalisiert danach "busy" (R1b)
+    R48Busy,
+}
+
+/// Datenphase eines Kommandos
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EmmcData {
+    /// Keine Daten
+    None,
+    /// Lesen von der Karte; bei mehreren Blöcken mit automatischem CMD12
+    Read,
+    /// Schreiben auf die Karte; bei mehreren Blöcken mit automatischem CMD12
+    Write,
+}
+
+/// Berechnet den Wert des CMDTM-Registers.
+pub fn emmc_cmdtm(index: u8, response: EmmcResponse, data: EmmcData, blocks: usize) -> u32 {
+    let mut reg: u32 = 0;
+    reg.set_bits(24..30, index as u32);
+    let (kind, crc, idx) = match response {
+        EmmcResponse::None       => (0, false, false),
+        EmmcResponse::R136       => (1, true,  false),
+        EmmcResponse::R48        => (2, true,  true),
+        EmmcResponse::R48NoCheck => (2, false, false),
+        EmmcResponse::R48Busy    => (3, true,  true),
+    };
+    reg.set_bits(16..18, kind);
+    reg.set_bit(19, crc);
+    reg.set_bit(20, idx);
+    if data != EmmcData::None {
+        reg.set_bit(21, true);                  // CMD_ISDATA
+        reg.set_bit(4, data == EmmcData::Read); // TM_DAT_DIR
+        if blocks > 1 {
+            reg.set_bit(1, true);               // TM_BLKCNT_EN
+            reg.set_bits(2..4, 1);              // TM_AUTO_CMD_EN: CMD12
+            reg.set_bit(5, true);               // TM_MULTI_BLOCK
+        }
+    }
+    reg
+}
+
+/// Berechnet die Taktbits des CONTROL1-Registers für die Rate `hz` beim Basistakt `base`
+/// und die erreichte Rate.
+///
+/// Ab Host-Version 3.00 (`version >= 2`) ist der Teiler 10 Bit breit, davor eine
+/// Zweierpotenz. Die Karte wird mit `base / (2 * n)` getaktet, bei `n = 0` mit `base`.
+pub fn emmc_clock_divider(base: u32, hz: u32, version: u8) -> Option<(u32,u32)> {
+    if base == 0 || hz == 0 {
+        return None;
+    }
+    if hz >= base {
+        return Some((0, base));
+    }
+    let n = if version >= 2 {
+        // Aufrunden, damit die Rate höchstens `hz` ist
+        let n = (base + 2 * hz - 1) / (2 * hz);
+        if n > 0x3ff {
+            return None;
+        }
+        n
+    } else {
+        let mut n = 1;
+        while base / (2 * n) > hz {
+            n <<= 1;
+            if n > 0x80 {
+                return None;
+            }
+        }
+        n
+    };
+    let mut bits: u32 = 0;
+    bits.set_bits(8..16, n & 0xff);
+    bits.set_bits(6..8, n >> 8);
+    Some((bits, base / (2 * n)))
+}
+
+/// Anzahl der 512-Byte-Blöcke einer Karte laut CSD (Antwort auf CMD9).
+///
+/// Die Antwortregister enthalten die Bits 8 bis 127 des CSD (ohne CRC).
+pub fn csd_block_count(csd: &[u32;4]) -> u64 {
+    let bits = |start: usize, len: usize| -> u64 {
+        let mut value = 0;
+        for i in 0..len {
+            let n = start + i - 8;
+            if csd[n / 32].get_bit((n % 32) as u8) {
+                value |= 1 << i;
+            }
+        }
+        value
+    };
+    match bits(126, 2) {
+        // CSD 2.0 (SDHC/SDXC): C_SIZE in Einheiten von 512 KiB
+        1 => (bits(48, 22) + 1) * 1024,
+        // CSD 1.0: (C_SIZE + 1) * 2^(C_SIZE_MULT + 2) Blöcke zu 2^READ_BL_LEN Bytes
+        _ => ((bits(62, 12) + 1) << (bits(47, 3) + 2) << bits(80, 4)) >> 9,
+    }
+}
+
+/// SD-Host-Controller (Arasan SDHCI, "EMMC")
+///
+/// Vgl. BMC2835 Manual, S. 66ff.
+#[repr(C)]
+pub struct Emmc {
+    arg2:           u32,        // Offset 0x00
+    blksizecnt:     u32,        // Offset 0x04
+    arg1:           u32,        // Offset 0x08
+    cmdtm:          u32,        // Offset 0x0C
+    response:       [u32;4],    // Offset 0x10
+    data:           u32,        // Offset 0x20
+    status:         u32,        // Offset 0x24
+    control0:       u32,        // Offset 0x28
+    control1:       u32,        // Offset 0x2C
+    interrupt:      u32,        // Offset 0x30
+    int_mask:       u32,        // Offset 0x34
+    int_enable:     u32,        // Offset 0x38
+    control2:       u32,        // Offset 0x3C
+    capability:     [u32;2],    // Offset 0x40
+    _padding0:      [u32;2],
+    force_int:      u32,        // Offset 0x50
+    _padding1:      [u32;7],
+    boot_timeout:   u32,        // Offset 0x70
+    dbg_sel:        u32,        // Offset 0x74
+    _padding2:      [u32;2],
+    exrdfifo_cfg:   u32,        // Offset 0x80
+    exrdfifo_en:    u32,        // Offset 0x84
+    tune_step:      u32,        // Offset 0x88
+    tune_steps_std: u32,        // Offset 0x8C
+    tune_steps_ddr: u32,        // Offset 0x90
+    _padding3:      [u32;23],
+    spi_int_spt:    u32,        // Offset 0xF0
+    _padding4:      [u32;2],
+    slotisr_ver:    u32,        // Offset 0xFC
+}
+
+use super::Bmc2835;
+impl Bmc2835 for Emmc {
+
+    fn base_offset() -> usize {
+        0x300000
+    }
+}
+
+impl Emmc {
+    fn read(reg: &u32) -> u32 {
+        Cpu::data_memory_barrier();
+        unsafe{ read_volatile(reg) }
+    }
+
+    fn write(reg: &mut u32, value: u32) {
+        Cpu::data_memory_barrier();
+        unsafe{ write_volatile(reg, value); }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Version der SD-Host-Spezifikation (0: 1.00, 1: 2.00, 2: 3.00)
+    pub fn version(&self) -> u8 {
+        Emmc::read(&self.slotisr_ver).get_bits(16..24) as u8
+    }
+
+    /// Basistakt laut Capabilities-Register in Hz (0, wenn nicht angegeben)
+    pub fn base_clock(&self) -> u32 {
+        Emmc::read(&self.capability[0]).get_bits(8..16) * 1_000_000
+    }
+
+    /// Setzt den gesamten Controller zurück und schaltet den Takt ab.
+    pub fn reset(&mut self) {
+        Emmc::write(&mut self.control2, 0);
+        let mut reg = 0;
+        reg.set_bit(C1_SRST_HC, true);
+        Emmc::write(&mut self.control1, reg);
+    }
+
+    /// Setzt die Kommando- und ggf. die Datenlogik nach einem Fehler zurück.
+    pub fn reset_lines(&mut self, data: bool) {
+        let mut reg = Emmc::read(&self.control1);
+        reg.set_bit(C1_SRST_CMD, true);
+        reg.set_bit(C1_SRST_DATA, data);
+        Emmc::write(&mut self.control1, reg);
+    }
+
+    /// Läuft noch ein Reset?
+    pub fn is_resetting(&self) -> bool {
+        Emmc::read(&self.control1).get_bits(24..27) != 0
+    }
+
+    /// Schaltet den Kartentakt (SDCLK) ab, stellt den Teiler ein (siehe `emmc_clock_divider`)
+    /// und startet den internen Takt mit dem längsten Daten-Timeout.
+    pub fn set_clock_divider(&mut self, bits: u32) {
+        let mut reg = Emmc::read(&self.control1);
+        reg.set_bit(C1_CLK_EN, false);
+        Emmc::write(&mut self.control1, reg);
+        reg.set_bits(6..16, 0);
+        reg |= bits;
+        reg.set_bit(C1_CLK_INTLEN, true);
+        reg.set_bits(16..20, 0xe);
+        Emmc::write(&mut self.control1, reg);
+    }
+
+    /// Ist der interne Takt stabil?
+    pub fn clock_stable(&self) -> bool {
+        Emmc::read(&self.control1).get_bit(C1_CLK_STABLE)
+    }
+
+    /// Schaltet den Kartentakt an oder ab.
+    pub fn enable_sd_clock(&mut self, enable: bool) {
+        let mut reg = Emmc::read(&self.control1);
+        reg.set_bit(C1_CLK_EN, enable);
+        Emmc::write(&mut self.control1, reg);
+    }
+
+    /// Wählt den 4-Bit- (`true`) oder 1-Bit-Datenbus.
+    pub fn set_bus_width_4(&mut self, wide: bool) {
+        let mut reg = Emmc::read(&self.control0);
+        reg.set_bit(1, wide);
+        Emmc::write(&mut self.control0, reg);
+    }
+
+    /// Kann noch kein Kommando (bzw. kein Kommando mit Daten) gesendet werden?
+    pub fn is_inhibited(&self, data: bool) -> bool {
+        let reg = Emmc::read(&self.status);
+        reg.get_bit(ST_CMD_INHIBIT) || (data && reg.get_bit(ST_DAT_INHIBIT))
+    }
+
+    /// Anliegende Interrupt-Ereignisse (siehe `EMMC_INT_*`)
+    pub fn interrupts(&self) -> u32 {
+        Emmc::read(&self.interrupt)
+    }
+
+    /// Löscht die Ereignisse in `mask`.
+    pub fn acknowledge(&mut self, mask: u32) {
+        Emmc::write(&mut self.interrupt, mask);
+    }
+
+    /// Legt fest, welche Ereignisse im Interrupt-Register erscheinen (`mask`) und welche einen
+    /// Interrupt auslösen (`enable`).
+    pub fn set_interrupts(&mut self, mask: u32, enable: u32) {
+        Emmc::write(&mut self.int_mask, mask);
+        Emmc::write(&mut self.int_enable, enable);
+    }
+
+    /// Ereignisse, die einen Interrupt auslösen
+    pub fn enabled_interrupts(&self) -> u32 {
+        Emmc::read(&self.int_enable)
+    }
+
+    /// Sendet ein Kommando (siehe `emmc_cmdtm`), ggf. mit `blocks` Blöcken zu `block_size` Bytes.
+    pub fn send_command(&mut self, cmdtm: u32, arg: u32, block_size: usize, blocks: usize) {
+        let mut reg: u32 = 0;
+        reg.set_bits(0..10, block_size as u32);
+        reg.set_bits(16..32, blocks as u32);
+        Emmc::write(&mut self.blksizecnt, reg);
+        Emmc::write(&mut self.arg1, arg);
+        Emmc::write(&mut self.cmdtm, cmdtm);
+    }
+
+    /// Antwort des letzten Kommandos (bei 48 Bit nur das erste Wort)
+    pub fn response(&self) -> [u32;4] {
+        [Emmc::read(&self.response[0]), Emmc::read(&self.response[1]),
+         Emmc::read(&self.response[2]), Emmc::read(&self.response[3])]
+    }
+
+    /// Liest ein Wort aus dem Datenpuffer.
+    pub fn read_data(&self) -> u32 {
+        unsafe{ read_volatile(&self.data) }
+    }
+
+    /// Schreibt ein Wort in den Datenpuffer.
+    pub fn write_data(&mut self, word: u32) {
+        unsafe{ write_volatile(&mut self.data, word); }
+    }
+}
@@ -0,0 +1,494 @@
+#![warn(missing_docs)]
+//! Treiber für SD-Karten am SD-Host-Controller (EMMC).
+//!
+//! `init` setzt den Controller zurück, identifiziert die Karte mit niedrigem Takt
+//! (CMD0, CMD8, ACMD41, CMD2, CMD3), liest ihre Größe (CMD9), wählt sie aus (CMD7) und
+//! schaltet dann auf den 4-Bit-Bus und den gewünschten Takt um. Die Identifikation läuft
+//! immer mit Polling, da die Interrupts beim Start meist noch gesperrt sind.
+//!
+//! Gelesen und geschrieben wird in Blöcken zu 512 Bytes (CMD17/18 bzw. CMD24/25, mehrere
+//! Blöcke mit automatischem CMD12). SDHC- und SDXC-Karten werden mit Blocknummern
+//! adressiert, ältere Karten mit Byteadressen. Im Interrupt-Betrieb bedient die
+//! Serviceroutine den Datenpuffer und meldet das Ende der Übertragung, der Aufrufer wartet
+//! mit aktiven Interrupts.
+//!
+//! Die GPIO-Pins 48 bis 53 stellt bereits die Firmware auf die Karte ein.
+use hal::cpu::Cpu;
+use data::isr_table::IsrResult;
+use data::kernel::KernelData;
+use sync::{IrqSpinLock, WaitQueue};
+use super::{Bmc2835, Emmc, EmmcResponse, EmmcData, IrqController, BasicInterrupt, SystemTimer};
+use super::{BlockDevice, BlockError, PropertyTagBuffer, Tag};
+use super::{ClockId, report_clock_rate, emmc_cmdtm, emmc_clock_divider, csd_block_count};
+use super::{EMMC_INT_CMD_DONE, EMMC_INT_DATA_DONE, EMMC_INT_READ_RDY, EMMC_INT_WRITE_RDY,
+            EMMC_INT_ERRORS, EMMC_INT_CTO_ERR, EMMC_INT_DTO_ERR, EMMC_INT_CCRC_ERR,
+            EMMC_INT_DCRC_ERR, EMMC_MAX_BLOCKS};
+
+/// Blockgröße in Bytes
+const SD_BLOCK_SIZE: usize = 512;
+
+/// Takt während der Identifikation
+const SD_IDENT_CLOCK: u32 = 400_000;
+
+/// Timeouts in µs
+const CMD_TIMEOUT:   u32 = 100_000;
+const DATA_TIMEOUT:  u32 = 500_000;
+const INIT_TIMEOUT:  u32 = 1_000_000;
+
+/// Ereignisse, die im Interrupt-Betrieb einen Interrupt auslösen
+const EMMC_INT_DRIVER: u32 = EMMC_INT_CMD_DONE | EMMC_INT_DATA_DONE | EMMC_INT_READ_RDY |
+                             EMMC_INT_WRITE_RDY | EMMC_INT_ERRORS;
+
+/// Konfiguration des SD-Treibers
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EmmcConfig {
+    /// Gewünschter Kartentakt nach der Identifikation in Hz
+    pub clock_hz:  u32,
+    /// Datenbus mit 4 statt 1 Bit
+    pub wide_bus:  bool,
+    /// Übertragungen in der Serviceroutine bedienen (statt Polling)
+    pub interrupt: bool,
+}
+
+impl EmmcConfig {
+    /// 4-Bit-Bus, Interrupt-Betrieb
+    pub const fn new(clock_hz: u32) -> EmmcConfig {
+        EmmcConfig {
+            clock_hz:  clock_hz,
+            wide_bus:  true,
+            interrupt: true,
+        }
+    }
+}
+
+/// Daten einer erkannten Karte
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SdCard {
+    /// Relative Kartenadresse
+    pub rca:           u16,
+    /// SDHC/SDXC (Blockadressierung)
+    pub high_capacity: bool,
+    /// Anzahl der Blöcke zu 512 Bytes
+    pub blocks:        u64,
+    /// Eingestellter Kartentakt in Hz
+    pub clock_hz:      u32,
+}
+
+/// Zustand des Datenpuffers einer laufenden Übertragung
+struct EmmcTransfer {
+    buffer: *mut u8,
+    blocks: usize,
+    done:   usize,
+}
+
+unsafe impl Send for EmmcTransfer {}
+
+impl EmmcTransfer {
+    fn new(buffer: *mut u8, blocks: usize) -> EmmcTransfer {
+        EmmcTransfer {
+            buffer: buffer,
+            blocks: blocks,
+            done:   0,
+        }
+    }
+
+    fn read_block(&mut self, emmc: &Emmc) {
+        if self.done < self.blocks {
+            let block = unsafe{ self.buffer.offset((self.done * SD_BLOCK_SIZE) as isize) };
+            for i in 0..SD_BLOCK_SIZE / 4 {
+                let word = emmc.read_data();
+                for j in 0..4 {
+                    unsafe{ *block.offset((4 * i + j) as isize) = (word >> (8 * j)) as u8; }
+                }
+            }
+            self.done += 1;
+        }
+    }
+
+    fn write_block(&mut self, emmc: &mut Emmc) {
+        if self.done < self.blocks {
+            let block = unsafe{ self.buffer.offset((self.done * SD_BLOCK_SIZE) as isize) };
+            for i in 0..SD_BLOCK_SIZE / 4 {
+                let mut word = 0;
+                for j in 0..4 {
+                    word |= (unsafe{ *block.offset((4 * i + j) as isize) } as u32) << (8 * j);
+                }
+                emmc.write_data(word);
+            }
+            self.done += 1;
+        }
+    }
+}
+
+struct EmmcState {
+    initialized: bool,
+    busy:        bool,
+    interrupt:   bool,
+    card:        Option<SdCard>,
+    /// Seit dem letzten Kommando eingetroffene Ereignisse
+    events:      u32,
+    transfer:    Option<EmmcTransfer>,
+}
+
+/// Treiber für die SD-Karte
+pub struct EmmcDriver {
+    state: IrqSpinLock<EmmcState>,
+    queue: WaitQueue,
+}
+
+static EMMC_DRIVER: EmmcDriver = EmmcDriver::new();
+
+/// Bildet Fehlerbits auf einen `BlockError` ab.
+fn block_error(events: u32) -> BlockError {
+    if events & (EMMC_INT_CTO_ERR | EMMC_INT_DTO_ERR) != 0 {
+        BlockError::Timeout
+    } else if events & (EMMC_INT_CCRC_ERR | EMMC_INT_DCRC_ERR) != 0 {
+        BlockError::Crc
+    } else {
+        BlockError::Failed
+    }
+}
+
+/// Wartet höchstens `timeout` µs auf `cond`.
+fn wait_timeout<F: FnMut() -> bool>(timeout: u32, mut cond: F) -> bool {
+    let timer = SystemTimer::get();
+    let started = timer.get_counter();
+    loop {
+        if cond() {
+            return true;
+        }
+        if timer.get_counter().wrapping_sub(started) > timeout {
+            return cond();
+        }
+        Cpu::data_memory_barrier();
+    }
+}
+
+/// Schaltet die Karte über die Firmware ein.
+fn power_on() -> Result<(),BlockError> {
+    let mut prob_tag_buf = PropertyTagBuffer::new();
+    prob_tag_buf.init();
+    // Gerät 0 (SD-Karte): einschalten und warten, bis die Spannung stabil ist
+    prob_tag_buf.add_tag_with_param(Tag::SetPowerState,Some(&[0, 3]));
+    prob_tag_buf.exchange();
+    match prob_tag_buf.get_answer(Tag::SetPowerState) {
+        Some(a) if a.len() >= 2 && a[1] & 2 != 0 => Err(BlockError::NoMedium),
+        _                                          => Ok(())
+    }
+}
+
+impl EmmcDriver {
+    const fn new() -> EmmcDriver {
+        EmmcDriver {
+            state: IrqSpinLock::new(EmmcState {
+                initialized: false,
+                busy:        false,
+                interrupt:   false,
+                card:        None,
+                events:      0,
+                transfer:    None,
+            }),
+            queue: WaitQueue::new(),
+        }
+    }
+
+    /// Der Treiber
+    pub fn get() -> &'static EmmcDriver {
+        &EMMC_DRIVER
+    }
+
+    /// Daten der Karte, falls eine erkannt wurde
+    pub fn card(&self) -> Option<SdCard> {
+        self.state.lock().card
+    }
+
+    /// Initialisiert Controller und Karte und meldet die Serviceroutine an.
+    pub fn init(&self, config: &EmmcConfig) -> Result<SdCard,BlockError> {
+        {
+            let mut state = self.state.lock();
+            if state.busy {
+                return Err(BlockError::Busy);
+            }
+            state.busy = true;
+            state.interrupt = false;
+            state.card = None;
+        }
+        let res = self.init_card(config);
+        let mut state = self.state.lock();
+        if let Ok(card) = res {
+            state.card = Some(card);
+            state.interrupt = config.interrupt;
+        }
+        state.busy = false;
+        res
+    }
+
+    fn init_card(&self, config: &EmmcConfig) -> Result<SdCard,BlockError> {
+        let emmc = Emmc::get();
+        emmc.set_interrupts(0, 0);
+        if !self.state.lock().initialized {
+            KernelData::isr_table().add_isr(BasicInterrupt::SDHCI, EmmcDriver::isr, 0)
+                .map_err(|_| BlockError::Failed)?;
+            IrqController::get().enable(BasicInterrupt::SDHCI);
+            self.state.lock().initialized = true;
+        }
+        power_on()?;
+
+        emmc.reset();
+        if !wait_timeout(CMD_TIMEOUT, || !emmc.is_resetting()) {
+            return Err(BlockError::Failed);
+        }
+        emmc.set_interrupts(0xffffffff, 0);
+        emmc.acknowledge(0xffffffff);
+        self.set_clock(SD_IDENT_CLOCK)?;
+
+        // Identifikation
+        self.command(0, EmmcResponse::None, 0)?;
+        let v2 = match self.command(8, EmmcResponse::R48, 0x1aa) {
+            Ok(r) if r[0] & 0xfff == 0x1aa => true,
+            Ok(_)                          => return Err(BlockError::NotSupported),
+            Err(BlockError::Timeout)       => false,
+            Err(err)                       => return Err(err),
+        };
+        // Spannungsbereich 2,7 bis 3,6 V, bei Karten ab 2.0 auch SDHC (HCS)
+        let arg = 0x00ff8000 | if v2 { 1 << 30 } else { 0 };
+        let mut ocr = 0;
+        let ready = wait_timeout(INIT_TIMEOUT, || {
+            ocr = match self.app_command(0, 41, EmmcResponse::R48NoCheck, arg) {
+                Ok(r)  => r[0],
+                Err(_) => 0,
+            };
+            ocr & (1 << 31) != 0
+        });
+        if !ready {
+            return Err(BlockError::NoMedium);
+        }
+        let high_capacity = v2 && ocr & (1 << 30) != 0;
+        self.command(2, EmmcResponse::R136, 0)?;
+        let rca = (self.command(3, EmmcResponse::R48, 0)?[0] >> 16) as u16;
+        let blocks = csd_block_count(&self.command(9, EmmcResponse::R136, (rca as u32) << 16)?);
+        self.command(7, EmmcResponse::R48Busy, (rca as u32) << 16)?;
+        if !high_capacity {
+            self.command(16, EmmcResponse::R48, SD_BLOCK_SIZE as u32)?;
+        }
+        if config.wide_bus {
+            self.app_command(rca, 6, EmmcResponse::R48, 2)?;
+            emmc.set_bus_width_4(true);
+        }
+        let clock_hz = self.set_clock(config.clock_hz)?;
+        if config.interrupt {
+            emmc.set_interrupts(0xffffffff, EMMC_INT_DRIVER);
+        }
+        Ok(SdCard {
+            rca:           rca,
+            high_capacity: high_capacity,
+            blocks:        blocks,
+            clock_hz:      clock_hz,
+        })
+    }
+
+    /// Stellt den Kartentakt ein und gibt die erreichte Rate zurück.
+    fn set_clock(&self, hz: u32) -> Result<u32,BlockError> {
+        let emmc = Emmc::get();
+        let base = match report_clock_rate(ClockId::Emmc) {
+            0    => emmc.base_clock(),
+            rate => rate
+        };
+        let (bits, actual) = emmc_clock_divider(base, hz, emmc.version()).ok_or(BlockError::Failed)?;
+        if !wait_timeout(CMD_TIMEOUT, || !emmc.is_inhibited(true)) {
+            return Err(BlockError::Timeout);
+        }
+        emmc.set_clock_divider(bits);
+        if !wait_timeout(CMD_TIMEOUT, || emmc.clock_stable()) {
+            return Err(BlockError::Failed);
+        }
+        emmc.enable_sd_clock(true);
+        Ok(actual)
+    }
+
+    /// Serviceroutine
+    pub fn isr(_: usize) -> IsrResult {
+        EMMC_DRIVER.handle_interrupt()
+    }
+
+    fn handle_interrupt(&self) -> IsrResult {
+        let emmc = Emmc::get();
+        if emmc.interrupts() & emmc.enabled_interrupts() == 0 {
+            return IsrResult::NotHandled;
+        }
+        EmmcDriver::service(emmc, &mut self.state.lock());
+        self.queue.wake_all();
+        IsrResult::Handled
+    }
+
+    /// Bedient den Datenpuffer und sammelt die übrigen Ereignisse in `state.events`.
+    fn service(emmc: &mut Emmc, state: &mut EmmcState) {
+        let flags = emmc.interrupts();
+        if let Some(ref mut transfer) = state.transfer {
+            if flags & EMMC_INT_READ_RDY != 0 {
+                emmc.acknowledge(EMMC_INT_READ_RDY);
+                transfer.read_block(emmc);
+            }
+            if flags & EMMC_INT_WRITE_RDY != 0 {
+                emmc.acknowledge(EMMC_INT_WRITE_RDY);
+                transfer.write_block(emmc);
+            }
+        }
+        let events = flags & (EMMC_INT_CMD_DONE | EMMC_INT_DATA_DONE | EMMC_INT_ERRORS);
+        if events != 0 {
+            emmc.acknowledge(events);
+            state.events |= events;
+        }
+    }
+
+    /// Wartet auf eines der Ereignisse in `mask` oder einen Fehler.
+    fn wait_events(&self, mask: u32, timeout: u32) -> Result<(),BlockError> {
+        let emmc = Emmc::get();
+        let interrupt = self.state.lock().interrupt;
+        let arrived = || {
+            let mut state = self.state.lock();
+            if !interrupt {
+                EmmcDriver::service(emmc, &mut state);
+            }
+            state.events & (mask | EMMC_INT_ERRORS) != 0
+        };
+        if interrupt {
+            let timer = SystemTimer::get();
+            let started = timer.get_counter();
+            let mut arrived = arrived;
+            self.queue.wait_until(|| arrived() || timer.get_counter().wrapping_sub(started) > timeout);
+        } else {
+            wait_timeout(timeout, arrived);
+        }
+        let mut state = self.state.lock();
+        let events = state.events;
+        state.events &= !mask;
+        if events & EMMC_INT_ERRORS != 0 {
+            Err(block_error(events))
+        } else if events & mask == 0 {
+            Err(BlockError::Timeout)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sendet ein Kommando ohne Daten und gibt die Antwort zurück.
+    fn command(&self, index: u8, response: EmmcResponse, arg: u32) -> Result<[u32;4],BlockError> {
+        self.execute(index, response, arg, EmmcData::None, None)
+    }
+
+    /// Sendet CMD55 und danach das anwendungsspezifische Kommando `index`.
+    fn app_command(&self, rca: u16, index: u8, response: EmmcResponse, arg: u32)
+                   -> Result<[u32;4],BlockError> {
+        self.command(55, EmmcResponse::R48, (rca as u32) << 16)?;
+        self.command(index, response, arg)
+    }
+
+    /// Führt ein Kommando aus, ggf. mit Datenphase, und setzt nach Fehlern die
+    /// Kommando- und Datenlogik zurück.
+    fn execute(&self, index: u8, response: EmmcResponse, arg: u32, data: EmmcData,
+               transfer: Option<EmmcTransfer>) -> Result<[u32;4],BlockError> {
+        let emmc = Emmc::get();
+        let blocks = match transfer {
+            Some(ref transfer) => transfer.blocks,
+            None               => 0
+        };
+        let uses_data = data != EmmcData::None || response == EmmcResponse::R48Busy;
+        if !wait_timeout(CMD_TIMEOUT, || !emmc.is_inhibited(uses_data)) {
+            return Err(BlockError::Timeout);
+        }
+        // Bis der Zustand hinterlegt ist, darf die Serviceroutine nicht laufen.
+        let irq_state = Cpu::save_and_disable_interrupts();
+        {
+            let mut state = self.state.lock();
+            state.events = 0;
+            state.transfer = transfer;
+        }
+        emmc.send_command(emmc_cmdtm(index, response, data, blocks), arg, SD_BLOCK_SIZE, blocks);
+        Cpu::restore_interrupts(irq_state);
+
+        let mut res = self.wait_events(EMMC_INT_CMD_DONE, CMD_TIMEOUT).map(|_| emmc.response());
+        if res.is_ok() && uses_data {
+            let timeout = DATA_TIMEOUT + 1000 * blocks as u32;
+            res = self.wait_events(EMMC_INT_DATA_DONE, timeout).and(res);
+        }
+        if res.is_ok() && blocks > 0 {
+            // Bei Polling kann DATA_DONE vor dem letzten READ_RDY gelesen worden sein.
+            let complete = match self.state.lock().transfer {
+                Some(ref transfer) => transfer.done == transfer.blocks,
+                None               => false
+            };
+            if !complete {
+                res = Err(BlockError::Failed);
+            }
+        }
+        self.state.lock().transfer = None;
+        if res.is_err() {
+            emmc.reset_lines(uses_data);
+            wait_timeout(CMD_TIMEOUT, || !emmc.is_resetting());
+            emmc.acknowledge(0xffffffff);
+        }
+        res
+    }
+
+    /// Überträgt `blocks` Blöcke ab `start` von bzw. nach `buffer`.
+    fn transfer(&self, start: u64, buffer: *mut u8, blocks: u64, write: bool) -> Result<(),BlockError> {
+        let card = {
+            let mut state = self.state.lock();
+            let card = match state.card {
+                Some(card) => card,
+                None       => return Err(BlockError::NotInitialized)
+            };
+            if state.busy {
+                return Err(BlockError::Busy);
+            }
+            state.busy = true;
+            card
+        };
+        let mut res = Ok(());
+        let mut done = 0;
+        while done < blocks && res.is_ok() {
+            let count = ::core::cmp::min(blocks - done, EMMC_MAX_BLOCKS as u64) as usize;
+            let block = start + done;
+            let addr = if card.high_capacity { block } else { block * SD_BLOCK_SIZE as u64 };
+            let (index, data) = match (write, count > 1) {
+                (false, false) => (17, EmmcData::Read),
+                (false, true)  => (18, EmmcData::Read),
+                (true,  false) => (24, EmmcData::Write),
+                (true,  true)  => (25, EmmcData::Write),
+            };
+            let chunk = unsafe{ buffer.offset((done as usize * SD_BLOCK_SIZE) as isize) };
+            res = self.execute(index, EmmcResponse::R48, addr as u32, data,
+                               Some(EmmcTransfer::new(chunk, count))).map(|_| ());
+            done += count as u64;
+        }
+        self.state.lock().busy = false;
+        res
+    }
+}
+
+impl BlockDevice for EmmcDriver {
+    fn block_size(&self) -> usize {
+        SD_BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.card().map(|card| card.blocks).unwrap_or(0)
+    }
+
+    fn read_blocks(&self, start: u64, buf: &mut [u8]) -> Result<(),BlockError> {
+        if self.card().is_none() {
+            return Err(BlockError::NotInitialized);
+        }
+        let blocks = self.check_range(start, buf.len())?;
+        self.transfer(start, buf.as_mut_ptr(), blocks, false)
+    }
+
+    fn write_blocks(&self, start: u64, buf: &[u8]) -> Result<(),BlockError> {
+        if self.card().is_none() {
+            return Err(BlockError::NotInitialized);
+        }
+        let blocks = self.check_range(start, buf.len())?;
+        self.transfer(start, buf.as_ptr() as *mut u8, blocks, true)
+    }
+}
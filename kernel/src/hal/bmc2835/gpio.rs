@@ -58,7 +58,7 @@ impl Into<u32> for GpioPinFunctions {
 
 pub mod gpio_config {
     /// Pinbelegung für UART (Universal Asynchronous Receiver Transmitter)
-    #[derive(Debug,PartialEq)]
+    #[derive(Copy,Clone,Debug,PartialEq)]
     pub enum UART {
         /// Daten senden (transmit data)
         TxD,
@@ -71,7 +71,7 @@ pub mod gpio_config {
     }
 
     /// Pinbelegung für SPI (Serial Peripheral Interface)
-    #[derive(Debug,PartialEq)]
+    #[derive(Copy,Clone,Debug,PartialEq)]
     pub enum SPI {
         /// Auswahl von Slave 0 (chip enable 0) (nur Master)
         CE0,
@@ -90,7 +90,7 @@ pub mod gpio_config {
     }
 
     /// Pinbelegung für JTAG (Joint Test Action Group Interface)
-    #[derive(Debug,PartialEq)]
+    #[derive(Copy,Clone,Debug,PartialEq)]
     pub enum JTAG {
         /// Dateneingang (test data in)
         TDI,
@@ -110,7 +110,7 @@ pub mod gpio_config {
         RTCK, 
     }
 
-    #[derive(Debug,PartialEq)]
+    #[derive(Copy,Clone,Debug,PartialEq)]
     /// Pinbelegung für BSC (Broadcom Serial Controller)
     ///
     /// BSC ist Broadcoms Variante von I2C (Inter-Integrated Circuit)
@@ -122,7 +122,7 @@ pub mod gpio_config {
     }
 
     /// Pinbelegung für PCM/I2S Audio 
-    #[derive(Debug,PartialEq)]
+    #[derive(Copy,Clone,Debug,PartialEq)]
     pub enum PCM {
         /// Takt (clock)
         Clk,
@@ -135,7 +135,7 @@ pub mod gpio_config {
     }
 
     ///
-    #[derive(Debug,PartialEq)]
+    #[derive(Copy,Clone,Debug,PartialEq)]
     pub enum Device {
         /// Nicht belegt / reserviert
         None,
@@ -349,6 +349,7 @@ pub mod gpio_config {
 }
 
 /// Ereignisse, die durch die Ereignisserkennung 
+#[derive(Copy,Clone,Debug,PartialEq)]
 pub enum GpioEvent {
     /// High-Signal (1) am Pin.
     High,
@@ -487,12 +488,7 @@ impl Gpio {
 
     /// Schreibt Wert `b` auf Pin `pin`.
     pub fn output(&mut self, pin: u8, b: bool) {
-        let reg: &mut[u32;2] = if b {
-            &mut self.output_set
-        } else {
-            &mut self.output_clear
-        };
-        reg[pin as usize / 32].set_bit(pin % 32,b);
+        self.set_pin(pin, b);
     }
 
     /// Liest den Wert von Pin `pin`.
@@ -518,7 +514,21 @@ impl Gpio {
 
     /// Löscht Ereignis für gegebenen Pin.
     pub fn reset_event(&mut self, pin: u8) {
-        self.event_status[pin as usize / 32].set_bit(pin % 32,true);
+        if pin <= MAX_PIN_NR {
+            self.acknowledge_events(1 << pin);
+        }
+    }
+
+    /// Löscht die Ereignisse der Pins in der Bit-Menge `pins`.
+    ///
+    /// Es wird nur in die gesetzten Bits eine 1 geschrieben; ein Lesen-Ändern-Schreiben würde
+    /// alle anliegenden Ereignisse löschen.
+    pub fn acknowledge_events(&mut self, pins: u64) {
+        use core::ptr::write_volatile;
+        unsafe{
+            write_volatile(&mut self.event_status[0], pins as u32);
+            write_volatile(&mut self.event_status[1], (pins >> 32) as u32);
+        }
     }
 
     /// Löscht alle Ereignise.
@@ -529,13 +539,14 @@ impl Gpio {
 
     /// Gibt an, ob für den gegebenen Pin ein Ereignis vorliegt.
     pub fn event_detected (&self, pin: u8) -> bool {
-        self.event_status[pin as usize / 32].get_bit(pin & 32)
+        pin <= MAX_PIN_NR && self.get_events().get_bit(pin)
     }
 
     /// Gibt alle vorliegenden Ereignisse als Bit-Menge zurück.
     pub fn get_events(&self) -> u64 {
-        let events: u64 = ((self.level[1] as u64) << 32) + self.level[0] as u64;
-        events
+        use core::ptr::read_volatile;
+        let (low, high) = unsafe{ (read_volatile(&self.event_status[0]), read_volatile(&self.event_status[1])) };
+        (high as u64) << 32 | low as u64
     }
 
     /// Setzt Pullup/pulldown-Verhalten für den gegebenen Pin.
@@ -549,12 +560,19 @@ impl Gpio {
             };
             self.pull_up_down_enable.set_bits(0..2,val);
             SystemTimer::get().busy_csleep(160);
-            self.pull_up_down_clock[pin as usize / 32].set_bit(pin,true);
+            self.pull_up_down_clock[pin as usize / 32].set_bit(pin % 32,true);
             SystemTimer::get().busy_csleep(160);
-            self.pull_up_down_clock[pin as usize / 32].set_bit(pin,false);
+            self.pull_up_down_clock[pin as usize / 32].set_bit(pin % 32,false);
         }
     }
 
+    /// Gibt an, ob der Pin die gegebene Funktion hat.
+    pub fn supports_function(pin: u8, func: &gpio_config::Device) -> bool {
+        use self::gpio_config::GPIO_PIN_ALT_FUNCTIONS;
+        (pin as usize) < GPIO_PIN_ALT_FUNCTIONS.len() &&
+            GPIO_PIN_ALT_FUNCTIONS[pin as usize].iter().any(|f| f == func)
+    }
+
     pub fn config_pin(&mut self, pin: u8, func: gpio_config::Device) -> Result<(),&str> {
         // kprint!("Call config_pin({:x},{:?})\n",pin,func;BLUE);
         use self::gpio_config::GPIO_PIN_ALT_FUNCTIONS;
@@ -0,0 +1,602 @@
+#![warn(missing_docs)]
+//! Ereignis-Interrupts der GPIO.
+//!
+//! Für jeden Pin kann ein Auslöser (Flanke oder Pegel) mit einer Rückruffunktion und/oder
+//! einer Ereigniswarteschlange angemeldet werden. Rückruffunktionen laufen in der
+//! Serviceroutine und sollten kurz sein; Prozesse warten stattdessen mit `wait_event` auf
+//! Einträge in der Warteschlange.
+//!
+//! Wer eine Rückruffunktion anmeldet, hat den Pin bereits über `GpioPins` belegt. Für eine
+//! Warteschlange belegt der Treiber den Pin selbst als Eingang (Eigentümer "GPIO-Event")
+//! und gibt ihn mit `unregister` wieder frei; Pins anderer Treiber werden abgelehnt.
+//!
+//! Die Pins sind auf drei Bänke mit eigenem Interrupt verteilt: GPIO0 für die Pins 0 bis 27,
+//! GPIO1 für 28 bis 45 und GPIO2 für 46 bis 53 (GPIO3 meldet alle Bänke und wird nicht
+//! genutzt). Der Interrupt einer Bank wird erst mit dem ersten angemeldeten Pin aktiviert;
+//! die Serviceroutine bearbeitet und quittiert nur die angemeldeten Pins ihrer Bank. Pins
+//! einer Bank, deren Interrupt als FIQ dient (`Fiq::source`), werden mit `GpioError::InUse`
+//! abgelehnt, da die Serviceroutine des FIQ ihre Ereignisse nicht quittiert. Der FIQ ist
+//! daher vor dem ersten Pin seiner Bank anzumelden.
+//!
+//! Prellen wird in Software unterdrückt: Nach einem Ereignis werden weitere Ereignisse
+//! desselben Pins für die Entprellzeit (gemessen mit dem `SystemTimer`) verworfen. Wurden
+//! Flanken verworfen, wird am Ende des Fensters (Kanal 3 des `SystemTimer`) der Pegel
+//! gelesen; weicht er vom zuletzt gemeldeten ab und passt zum Auslöser, wird er als
+//! weiteres Ereignis gemeldet. So geht bei `GpioTrigger::Both` der letzte Wechsel nicht
+//! verloren.
+//!
+//! Pegel-Auslöser werden nach jedem Ereignis abgeschaltet, da sie sonst sofort erneut
+//! auslösen; `rearm` schaltet sie wieder ein.
+use alloc::vec::Vec;
+use data::isr_table::IsrResult;
+use data::kernel::KernelData;
+use data::fiq::Fiq;
+use sync::{IrqSpinLock, WaitQueue};
+use super::{Bmc2835, Gpio, GpioEvent, IrqController, Interrupt, GeneralInterrupt, SystemTimer};
+use super::{GpioPins, GpioPin};
+use super::gpio_config::Device;
+
+/// Anzahl der Pins
+const GPIO_NUM_PINS: usize = 54;
+
+/// Länge der Ereigniswarteschlange je Pin
+pub const GPIO_EVENT_QUEUE_LEN: usize = 8;
+
+/// Zeitangabe für `wait_event`: ohne Zeitbegrenzung warten
+pub const GPIO_WAIT_FOREVER: u32 = ::core::u32::MAX;
+
+/// Erste Pins der Bänke 0 bis 2 und Ende der letzten Bank
+const GPIO_BANK_START: [u8;4] = [0, 28, 46, 54];
+
+/// Kanal des `SystemTimer` für das Ende der Entprellfenster
+const GPIO_SETTLE_TIMER: u8 = 3;
+
+/// Mindestabstand des Vergleichswerts zum aktuellen Zählerstand in µs
+const GPIO_SETTLE_MIN: u32 = 50;
+
+/// Interrupts der Bänke
+const GPIO_BANK_INTERRUPT: [GeneralInterrupt;3] = [GeneralInterrupt::GPIO0, GeneralInterrupt::GPIO1,
+                                                   GeneralInterrupt::GPIO2];
+
+/// Rückruffunktion: Pin, Pegel beim Ereignis, Kontext der Anmeldung
+pub type GpioCallback = fn(u8, bool, usize);
+
+/// Auslöser eines Pin-Ereignisses
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GpioTrigger {
+    /// Steigende Flanke
+    Rising,
+    /// Fallende Flanke
+    Falling,
+    /// Beide Flanken
+    Both,
+    /// High-Pegel (wird nach jedem Ereignis abgeschaltet)
+    High,
+    /// Low-Pegel (wird nach jedem Ereignis abgeschaltet)
+    Low,
+}
+
+impl GpioTrigger {
+    /// Konvertiert die Nummer eines Auslösers (in der Reihenfolge der Deklaration).
+    pub fn from_u32(n: u32) -> Option<GpioTrigger> {
+        match n {
+            0 => Some(GpioTrigger::Rising),
+            1 => Some(GpioTrigger::Falling),
+            2 => Some(GpioTrigger::Both),
+            3 => Some(GpioTrigger::High),
+            4 => Some(GpioTrigger::Low),
+            _ => None
+        }
+    }
+
+    fn is_level(&self) -> bool {
+        *self == GpioTrigger::High || *self == GpioTrigger::Low
+    }
+
+    /// Löst ein Wechsel auf `level` bei einem Flanken-Auslöser ein Ereignis aus?
+    fn accepts(&self, level: bool) -> bool {
+        match *self {
+            GpioTrigger::Rising  => level,
+            GpioTrigger::Falling => !level,
+            GpioTrigger::Both    => true,
+            _                    => false,
+        }
+    }
+
+    /// Schaltet die Ereigniserkennung für `pin` an oder ab.
+    fn set_detection(&self, gpio: &mut Gpio, pin: u8, enable: bool) {
+        let events: &[GpioEvent] = match *self {
+            GpioTrigger::Rising  => &[GpioEvent::Rising],
+            GpioTrigger::Falling => &[GpioEvent::Falling],
+            GpioTrigger::Both    => &[GpioEvent::Rising, GpioEvent::Falling],
+            GpioTrigger::High    => &[GpioEvent::High],
+            GpioTrigger::Low     => &[GpioEvent::Low],
+        };
+        for ev in events {
+            if enable {
+                gpio.enable_event_detection(pin, *ev);
+            } else {
+                gpio.disable_event_detection(pin, *ev);
+            }
+        }
+    }
+}
+
+/// Fehler bei der Ereignisbehandlung
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GpioError {
+    /// Der Treiber wurde noch nicht initialisiert.
+    NotInitialized,
+    /// Ungültige Pin-Nummer
+    InvalidPin,
+    /// Für den Pin ist bereits ein Ereignis angemeldet.
+    AlreadyRegistered,
+    /// Für den Pin ist kein Ereignis (bzw. keine Warteschlange) angemeldet.
+    NotRegistered,
+    /// Innerhalb der Wartezeit ist kein Ereignis eingetroffen.
+    Timeout,
+    /// Sonstiger Fehler
+    Failed,
+    /// Der Pin ist bereits von einem anderen Treiber belegt (siehe `GpioPins`) oder seine
+    /// Bank dient als FIQ.
+    InUse,
+    /// Der Pin unterstützt die verlangte Funktion nicht.
+    InvalidFunction,
+    /// Ungültiger Auslöser
+    InvalidTrigger,
+}
+
+impl GpioError {
+    /// Fehlercode für Systemaufrufe
+    pub fn as_u32(&self) -> u32 {
+        match *self {
+            GpioError::NotInitialized    => 1,
+            GpioError::InvalidPin        => 2,
+            GpioError::AlreadyRegistered => 3,
+            GpioError::NotRegistered     => 4,
+            GpioError::Timeout           => 5,
+            GpioError::Failed            => 6,
+            GpioError::InUse             => 7,
+            GpioError::InvalidFunction   => 8,
+            GpioError::InvalidTrigger    => 9,
+        }
+    }
+}
+
+/// Ein aufgezeichnetes Ereignis
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct GpioPinEvent {
+    /// Pegel am Pin, als das Ereignis bearbeitet wurde
+    pub level: bool,
+    /// Stand des `SystemTimer` (µs)
+    pub time:  u32,
+}
+
+/// Anmeldung eines Pins
+#[derive(Copy, Clone)]
+struct PinHandler {
+    trigger:  GpioTrigger,
+    debounce: u32,
+    last:     Option<u32>,
+    /// Zuletzt gemeldeter Pegel
+    level:    bool,
+    /// Im laufenden Entprellfenster wurden Ereignisse verworfen.
+    settling: bool,
+    callback: Option<(GpioCallback, usize)>,
+    queued:   bool,
+    queue:    [GpioPinEvent; GPIO_EVENT_QUEUE_LEN],
+    head:     usize,
+    len:      usize,
+    dropped:  u32,
+}
+
+/// Ereignis für die Rückruffunktion
+type PendingCallback = (GpioCallback, usize, u8, bool);
+
+impl PinHandler {
+    /// Meldet ein Ereignis; gibt die zu rufende Rückruffunktion zurück.
+    fn record(&mut self, pin: u8, level: bool, now: u32) -> Option<PendingCallback> {
+        self.last = Some(now);
+        self.level = level;
+        if self.queued {
+            self.push(GpioPinEvent { level: level, time: now });
+        }
+        self.callback.map(|(callback, context)| (callback, context, pin, level))
+    }
+
+    fn push(&mut self, event: GpioPinEvent) {
+        if self.len == GPIO_EVENT_QUEUE_LEN {
+            // Das älteste Ereignis wird verworfen.
+            self.head = (self.head + 1) % GPIO_EVENT_QUEUE_LEN;
+            self.len -= 1;
+            self.dropped = self.dropped.wrapping_add(1);
+        }
+        self.queue[(self.head + self.len) % GPIO_EVENT_QUEUE_LEN] = event;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<GpioPinEvent> {
+        if self.len == 0 {
+            None
+        } else {
+            let event = self.queue[self.head];
+            self.head = (self.head + 1) % GPIO_EVENT_QUEUE_LEN;
+            self.len -= 1;
+            Some(event)
+        }
+    }
+}
+
+struct GpioState {
+    initialized:  bool,
+    /// Bit-Menge der angemeldeten Pins
+    registered:   u64,
+    bank_enabled: [bool;3],
+    /// Eingestellter Vergleichswert für das Ende eines Entprellfensters
+    settle_at:    Option<u32>,
+    pins:         [Option<PinHandler>; GPIO_NUM_PINS],
+    /// Für Warteschlangen belegte Pins (ab `init`)
+    claims:       Option<Vec<GpioPin>>,
+}
+
+/// Verteilung der GPIO-Ereignisse
+pub struct GpioDriver {
+    state: IrqSpinLock<GpioState>,
+    queue: WaitQueue,
+}
+
+static GPIO_DRIVER: GpioDriver = GpioDriver::new();
+
+/// Bank des Pins
+fn bank_of(pin: u8) -> usize {
+    if pin < GPIO_BANK_START[1] {
+        0
+    } else if pin < GPIO_BANK_START[2] {
+        1
+    } else {
+        2
+    }
+}
+
+/// Bit-Menge der Pins einer Bank
+fn bank_mask(bank: usize) -> u64 {
+    let start = GPIO_BANK_START[bank];
+    let end = GPIO_BANK_START[bank + 1];
+    ((1u64 << (end - start)) - 1) << start
+}
+
+/// Liegt der Zeitpunkt `at` (µs) zum Zählerstand `now` bereits in der Vergangenheit?
+fn is_due(at: u32, now: u32) -> bool {
+    (now.wrapping_sub(at) as i32) >= 0
+}
+
+/// Stellt den `SystemTimer` auf das Ende eines Entprellfensters, sofern nicht schon ein
+/// früheres eingestellt ist.
+fn arm_settle(settle_at: &mut Option<u32>, at: u32, now: u32) {
+    let at = if at.wrapping_sub(now) as i32 >= GPIO_SETTLE_MIN as i32 { at } else { now.wrapping_add(GPIO_SETTLE_MIN) };
+    let earlier = match *settle_at {
+        Some(pending) => at.wrapping_sub(now) < pending.wrapping_sub(now),
+        None          => true
+    };
+    if earlier {
+        *settle_at = Some(at);
+        SystemTimer::get().set_compare(GPIO_SETTLE_TIMER, at);
+    }
+}
+
+impl GpioDriver {
+    const fn new() -> GpioDriver {
+        GpioDriver {
+            state: IrqSpinLock::new(GpioState {
+                initialized:  false,
+                registered:   0,
+                bank_enabled: [false;3],
+                settle_at:    None,
+                pins:         [None; GPIO_NUM_PINS],
+                claims:       None,
+            }),
+            queue: WaitQueue::new(),
+        }
+    }
+
+    /// Der Treiber
+    pub fn get() -> &'static GpioDriver {
+        &GPIO_DRIVER
+    }
+
+    /// Meldet die Serviceroutinen der drei Bänke und des Entprell-Timers an. Die
+    /// Interrupts der Bänke werden erst mit dem ersten Pin der Bank aktiviert.
+    pub fn init(&self) -> Result<(),GpioError> {
+        if self.state.lock().initialized {
+            return Ok(());
+        }
+        for bank in 0..3 {
+            KernelData::isr_table().add_isr(GPIO_BANK_INTERRUPT[bank], GpioDriver::isr, bank)
+                .map_err(|_| GpioError::Failed)?;
+        }
+        KernelData::isr_table().add_isr(GeneralInterrupt::SystemTimer3, GpioDriver::settle_isr, 0)
+            .map_err(|_| GpioError::Failed)?;
+        SystemTimer::get().reset_match(GPIO_SETTLE_TIMER);
+        IrqController::get().enable(GeneralInterrupt::SystemTimer3);
+        let mut state = self.state.lock();
+        state.claims = Some(Vec::new());
+        state.initialized = true;
+        Ok(())
+    }
+
+    /// Meldet für `pin` eine Rückruffunktion an, die bei jedem (entprellten) Ereignis mit
+    /// `context` gerufen wird. Der Aufrufer muss den Pin belegt haben.
+    pub fn register_callback(&self, pin: u8, trigger: GpioTrigger, debounce_us: u32,
+                             callback: GpioCallback, context: usize) -> Result<(),GpioError> {
+        self.register(pin, trigger, debounce_us, Some((callback, context)), false)
+    }
+
+    /// Belegt `pin` als Eingang und meldet für ihn eine Ereigniswarteschlange an (siehe
+    /// `next_event` und `wait_event`). Gehört der Pin einem anderen Treiber oder dient der
+    /// Interrupt seiner Bank als FIQ, wird `GpioError::InUse` zurückgegeben.
+    pub fn register_queue(&self, pin: u8, trigger: GpioTrigger, debounce_us: u32) -> Result<(),GpioError> {
+        if !self.state.lock().initialized {
+            return Err(GpioError::NotInitialized);
+        }
+        // Bei einem Fehler wird der Pin mit `claim` wieder freigegeben.
+        let claim = GpioPins::get().claim(pin, Device::Input, "GPIO-Event")?;
+        self.register(pin, trigger, debounce_us, None, true)?;
+        if let Some(ref mut claims) = self.state.lock().claims {
+            claims.push(claim);
+        }
+        Ok(())
+    }
+
+    fn register(&self, pin: u8, trigger: GpioTrigger, debounce_us: u32,
+                callback: Option<(GpioCallback, usize)>, queued: bool) -> Result<(),GpioError> {
+        if pin as usize >= GPIO_NUM_PINS {
+            return Err(GpioError::InvalidPin);
+        }
+        let gpio = Gpio::get();
+        let mut state = self.state.lock();
+        if !state.initialized {
+            return Err(GpioError::NotInitialized);
+        }
+        if state.pins[pin as usize].is_some() {
+            return Err(GpioError::AlreadyRegistered);
+        }
+        let bank = bank_of(pin);
+        if Fiq::source().is_some() && Fiq::source() == GPIO_BANK_INTERRUPT[bank].uid() {
+            return Err(GpioError::InUse);
+        }
+        state.pins[pin as usize] = Some(PinHandler {
+            trigger:  trigger,
+            debounce: debounce_us,
+            last:     None,
+            level:    gpio.get_pin(pin),
+            settling: false,
+            callback: callback,
+            queued:   queued,
+            queue:    [GpioPinEvent::default(); GPIO_EVENT_QUEUE_LEN],
+            head:     0,
+            len:      0,
+            dropped:  0,
+        });
+        state.registered |= 1 << pin;
+        gpio.reset_event(pin);
+        trigger.set_detection(gpio, pin, true);
+        if !state.bank_enabled[bank] {
+            state.bank_enabled[bank] = true;
+            IrqController::get().enable(GPIO_BANK_INTERRUPT[bank]);
+        }
+        Ok(())
+    }
+
+    /// Meldet `pin` ab und schaltet seine Ereigniserkennung ab. Ein von `register_queue`
+    /// belegter Pin wird freigegeben.
+    pub fn unregister(&self, pin: u8) -> Result<(),GpioError> {
+        if pin as usize >= GPIO_NUM_PINS {
+            return Err(GpioError::InvalidPin);
+        }
+        let gpio = Gpio::get();
+        let claim = {
+            let mut state = self.state.lock();
+            let handler = state.pins[pin as usize].take().ok_or(GpioError::NotRegistered)?;
+            handler.trigger.set_detection(gpio, pin, false);
+            gpio.reset_event(pin);
+            state.registered &= !(1 << pin);
+            match state.claims {
+                Some(ref mut claims) => {
+                    let index = claims.iter().position(|claim| claim.pin() == pin);
+                    index.map(|i| claims.remove(i))
+                },
+                None => None
+            }
+        };
+        // Die Freigabe sperrt das Verzeichnis der Pins, daher erst nach der eigenen Sperre.
+        drop(claim);
+        Ok(())
+    }
+
+    /// Schaltet einen Pegel-Auslöser nach einem Ereignis wieder ein.
+    pub fn rearm(&self, pin: u8) -> Result<(),GpioError> {
+        if pin as usize >= GPIO_NUM_PINS {
+            return Err(GpioError::InvalidPin);
+        }
+        let state = self.state.lock();
+        match state.pins[pin as usize] {
+            Some(ref handler) => {
+                let gpio = Gpio::get();
+                gpio.reset_event(pin);
+                handler.trigger.set_detection(gpio, pin, true);
+                Ok(())
+            },
+            None => Err(GpioError::NotRegistered)
+        }
+    }
+
+    /// Entnimmt das älteste Ereignis aus der Warteschlange von `pin`.
+    pub fn next_event(&self, pin: u8) -> Result<Option<GpioPinEvent>,GpioError> {
+        if pin as usize >= GPIO_NUM_PINS {
+            return Err(GpioError::InvalidPin);
+        }
+        match self.state.lock().pins[pin as usize] {
+            Some(ref mut handler) => {
+                if handler.queued {
+                    Ok(handler.pop())
+                } else {
+                    Err(GpioError::NotRegistered)
+                }
+            },
+            None => Err(GpioError::NotRegistered)
+        }
+    }
+
+    /// Wartet höchstens `timeout_us` µs (bzw. mit `GPIO_WAIT_FOREVER` unbegrenzt) auf ein
+    /// Ereignis an `pin` und entnimmt es der Warteschlange.
+    ///
+    /// Die Interrupts müssen aktiv sein, es sei denn, es liegt bereits ein Ereignis vor
+    /// oder `timeout_us` ist 0.
+    pub fn wait_event(&self, pin: u8, timeout_us: u32) -> Result<GpioPinEvent,GpioError> {
+        if let Some(event) = self.next_event(pin)? {
+            return Ok(event);
+        }
+        if timeout_us == 0 {
+            return Err(GpioError::Timeout);
+        }
+        let timer = SystemTimer::get();
+        let started = timer.get_counter();
+        let mut result = Err(GpioError::Timeout);
+        self.queue.wait_until(|| {
+            match self.next_event(pin) {
+                Ok(Some(event)) => { result = Ok(event); true },
+                Ok(None)        => timeout_us != GPIO_WAIT_FOREVER &&
+                                   timer.get_counter().wrapping_sub(started) > timeout_us,
+                Err(err)        => { result = Err(err); true },
+            }
+        });
+        result
+    }
+
+    /// Anzahl der Ereignisse, die wegen einer vollen Warteschlange verworfen wurden
+    pub fn dropped_events(&self, pin: u8) -> u32 {
+        if pin as usize >= GPIO_NUM_PINS {
+            return 0;
+        }
+        match self.state.lock().pins[pin as usize] {
+            Some(ref handler) => handler.dropped,
+            None              => 0
+        }
+    }
+
+    /// Serviceroutine; `context` ist die Bank.
+    pub fn isr(context: usize) -> IsrResult {
+        GPIO_DRIVER.handle_interrupt(context)
+    }
+
+    fn handle_interrupt(&self, bank: usize) -> IsrResult {
+        let gpio = Gpio::get();
+        let mut callbacks: [Option<PendingCallback>; 28] = [None; 28];
+        let mut num_callbacks = 0;
+        {
+            let mut state = self.state.lock();
+            let pending = gpio.get_events() & bank_mask(bank) & state.registered;
+            if pending == 0 {
+                return IsrResult::NotHandled;
+            }
+            gpio.acknowledge_events(pending);
+            let now = SystemTimer::get().get_counter();
+            let mut settle = None;
+            for pin in GPIO_BANK_START[bank]..GPIO_BANK_START[bank + 1] {
+                if pending & (1 << pin) == 0 {
+                    continue;
+                }
+                if let Some(ref mut handler) = state.pins[pin as usize] {
+                    if handler.trigger.is_level() {
+                        handler.trigger.set_detection(gpio, pin, false);
+                    }
+                    let bouncing = match handler.last {
+                        Some(last) => now.wrapping_sub(last) < handler.debounce,
+                        None       => false
+                    };
+                    if bouncing {
+                        // Am Ende des Fensters wird der Pegel nachgesehen.
+                        if !handler.trigger.is_level() {
+                            handler.settling = true;
+                            settle = handler.last.map(|last| last.wrapping_add(handler.debounce));
+                        }
+                        continue;
+                    }
+                    handler.settling = false;
+                    let level = gpio.get_pin(pin);
+                    if let Some(callback) = handler.record(pin, level, now) {
+                        callbacks[num_callbacks] = Some(callback);
+                        num_callbacks += 1;
+                    }
+                }
+                if let Some(at) = settle.take() {
+                    arm_settle(&mut state.settle_at, at, now);
+                }
+            }
+        }
+        self.run_callbacks(&callbacks[..num_callbacks]);
+        IsrResult::Handled
+    }
+
+    /// Ruft die Rückruffunktionen und weckt die Wartenden.
+    fn run_callbacks(&self, callbacks: &[Option<PendingCallback>]) {
+        // Die Rückruffunktionen dürfen Pins an- und abmelden, laufen also ohne Sperre.
+        for entry in callbacks.iter() {
+            if let Some((callback, context, pin, level)) = *entry {
+                callback(pin, level, context);
+            }
+        }
+        self.queue.wake_all();
+    }
+
+    /// Serviceroutine für das Ende der Entprellfenster
+    pub fn settle_isr(_: usize) -> IsrResult {
+        GPIO_DRIVER.handle_settle()
+    }
+
+    fn handle_settle(&self) -> IsrResult {
+        let timer = SystemTimer::get();
+        if !timer.found_match(GPIO_SETTLE_TIMER) {
+            return IsrResult::NotHandled;
+        }
+        timer.reset_match(GPIO_SETTLE_TIMER);
+        let gpio = Gpio::get();
+        let mut callbacks: [Option<PendingCallback>; GPIO_NUM_PINS] = [None; GPIO_NUM_PINS];
+        let mut num_callbacks = 0;
+        {
+            let mut state = self.state.lock();
+            state.settle_at = None;
+            let now = timer.get_counter();
+            let mut next: Option<u32> = None;
+            for pin in 0..GPIO_NUM_PINS as u8 {
+                if let Some(ref mut handler) = state.pins[pin as usize] {
+                    if !handler.settling {
+                        continue;
+                    }
+                    let end = handler.last.unwrap_or(now).wrapping_add(handler.debounce);
+                    if !is_due(end, now) {
+                        next = match next {
+                            Some(n) if n.wrapping_sub(now) <= end.wrapping_sub(now) => Some(n),
+                            _                                                       => Some(end),
+                        };
+                        continue;
+                    }
+                    handler.settling = false;
+                    let level = gpio.get_pin(pin);
+                    if level != handler.level && handler.trigger.accepts(level) {
+                        if let Some(callback) = handler.record(pin, level, now) {
+                            callbacks[num_callbacks] = Some(callback);
+                            num_callbacks += 1;
+                        }
+                    }
+                }
+            }
+            if let Some(at) = next {
+                arm_settle(&mut state.settle_at, at, now);
+            }
+        }
+        self.run_callbacks(&callbacks[..num_callbacks]);
+        IsrResult::Handled
+    }
+}
@@ -0,0 +1,157 @@
+#![warn(missing_docs)]
+//! Belegung der GPIO-Pins.
+//!
+//! Treiber belegen Pins über `GpioPins::claim` bzw. `claim_all` und erhalten dafür je Pin
+//! einen `GpioPin`. Die Belegung stellt die Funktion des Pins ein und gilt, bis der
+//! `GpioPin` freigegeben wird; danach ist der Pin wieder ein Eingang. Ein Pin, der schon
+//! einem anderen Eigentümer gehört, wird nicht umgestellt, sondern mit `GpioError::InUse`
+//! abgelehnt. `owner` und `assignments` geben Auskunft über die aktuelle Belegung.
+//!
+//! `Gpio::config_pin` prüft dagegen nur, ob der Pin die Funktion unterstützt, und bleibt
+//! der Hardware-Ebene vorbehalten.
+use alloc::vec::Vec;
+use sync::IrqSpinLock;
+use super::{Bmc2835, Gpio, GpioPinFunctions, GpioPull, GpioError};
+use super::gpio_config::Device;
+
+/// Anzahl der Pins
+const GPIO_NUM_PINS: usize = 54;
+
+/// Belegung eines Pins
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GpioPinAssignment {
+    /// Pin-Nummer
+    pub pin:    u8,
+    /// Eingestellte Funktion
+    pub device: Device,
+    /// Eigentümer (Name des Treibers)
+    pub owner:  &'static str,
+}
+
+/// Verzeichnis der belegten Pins
+pub struct GpioPins {
+    pins: IrqSpinLock<[Option<(Device, &'static str)>; GPIO_NUM_PINS]>,
+}
+
+static GPIO_PINS: GpioPins = GpioPins {
+    pins: IrqSpinLock::new([None; GPIO_NUM_PINS]),
+};
+
+impl GpioPins {
+    /// Das Verzeichnis
+    pub fn get() -> &'static GpioPins {
+        &GPIO_PINS
+    }
+
+    /// Belegt `pin` für `owner` und stellt die Funktion `device` ein.
+    pub fn claim(&'static self, pin: u8, device: Device, owner: &'static str) -> Result<GpioPin,GpioError> {
+        if pin as usize >= GPIO_NUM_PINS {
+            return Err(GpioError::InvalidPin);
+        }
+        if !Gpio::supports_function(pin, &device) {
+            return Err(GpioError::InvalidFunction);
+        }
+        {
+            let mut pins = self.pins.lock();
+            if pins[pin as usize].is_some() {
+                return Err(GpioError::InUse);
+            }
+            pins[pin as usize] = Some((device, owner));
+        }
+        if Gpio::get().config_pin(pin, device).is_err() {
+            self.release(pin);
+            return Err(GpioError::InvalidFunction);
+        }
+        Ok(GpioPin {
+            registry: self,
+            pin:      pin,
+            device:   device,
+        })
+    }
+
+    /// Belegt alle `pins` für `owner` oder keinen.
+    pub fn claim_all(&'static self, pins: &[(u8, Device)], owner: &'static str) -> Result<Vec<GpioPin>,GpioError> {
+        let mut claimed = Vec::with_capacity(pins.len());
+        for &(pin, device) in pins {
+            // Bei einem Fehler gibt `claimed` die bereits belegten Pins wieder frei.
+            claimed.push(self.claim(pin, device, owner)?);
+        }
+        Ok(claimed)
+    }
+
+    /// Belegung von `pin`, falls er belegt ist
+    pub fn owner(&self, pin: u8) -> Option<GpioPinAssignment> {
+        if pin as usize >= GPIO_NUM_PINS {
+            return None;
+        }
+        self.pins.lock()[pin as usize].map(|(device, owner)| GpioPinAssignment {
+            pin:    pin,
+            device: device,
+            owner:  owner,
+        })
+    }
+
+    /// Alle belegten Pins in aufsteigender Reihenfolge
+    pub fn assignments(&self) -> Vec<GpioPinAssignment> {
+        let pins = *self.pins.lock();
+        pins.iter().enumerate()
+            .filter_map(|(pin, entry)| entry.map(|(device, owner)| GpioPinAssignment {
+                pin:    pin as u8,
+                device: device,
+                owner:  owner,
+            }))
+            .collect()
+    }
+
+    fn release(&self, pin: u8) {
+        self.pins.lock()[pin as usize] = None;
+    }
+}
+
+/// Ein belegter Pin; wird bei der Freigabe wieder zum Eingang.
+pub struct GpioPin {
+    registry: &'static GpioPins,
+    pin:      u8,
+    device:   Device,
+}
+
+impl GpioPin {
+    /// Pin-Nummer
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    /// Eingestellte Funktion
+    pub fn device(&self) -> Device {
+        self.device
+    }
+
+    /// Setzt den Ausgang (nur bei `Device::Output` wirksam).
+    pub fn set(&self, value: bool) {
+        if self.device == Device::Output {
+            Gpio::get().output(self.pin, value);
+        }
+    }
+
+    /// Liest den Pegel am Pin.
+    pub fn get(&self) -> bool {
+        Gpio::get().get_pin(self.pin)
+    }
+
+    /// Stellt Pullup/Pulldown ein.
+    pub fn set_pull(&self, pull: GpioPull) {
+        Gpio::get().set_pull(self.pin, pull);
+    }
+
+    /// Behält die Belegung bis zum Neustart (z.B. für Pins ohne eigenen Treiber).
+    pub fn keep(self) {
+        ::core::mem::forget(self);
+    }
+}
+
+impl Drop for GpioPin {
+    fn drop(&mut self) {
+        Gpio::get().set_function(self.pin, GpioPinFunctions::Input);
+        self.registry.release(self.pin);
+    }
+}
@@ -0,0 +1,143 @@
+#![warn(missing_docs)]
+//! Gemeinsame Schnittstelle für I2C-Master.
+//!
+//! Eine Transaktion (`I2cBus::transaction`) schreibt zuerst die Sendedaten und liest danach
+//! nach einem wiederholten Start (_repeated start_) die Empfangsdaten, ohne den Bus
+//! dazwischen freizugeben. Das ist die übliche Form für das Lesen von Registern eines
+//! Sensors. Ist einer der Puffer leer, entfällt der entsprechende Teil.
+//!
+//! 10-Bit-Adressen werden nach dem Standard übertragen: die Adresse `11110xx` mit den beiden
+//! oberen Bits, gefolgt vom unteren Adressbyte als erstem Datenbyte.
+use super::GpioError;
+
+/// Adresse eines Slaves
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum I2cAddress {
+    /// 7-Bit-Adresse (0 bis 0x7f)
+    SevenBit(u8),
+    /// 10-Bit-Adresse (0 bis 0x3ff)
+    TenBit(u16),
+}
+
+impl I2cAddress {
+    /// Die 7-Bit-Adresse, die auf dem Bus gesendet wird, und ggf. das Byte, das allen
+    /// Sendedaten vorangestellt werden muss (unteres Byte einer 10-Bit-Adresse).
+    pub fn encode(&self) -> Result<(u8,Option<u8>),I2cError> {
+        match *self {
+            I2cAddress::SevenBit(addr) if addr <= 0x7f => Ok((addr, None)),
+            I2cAddress::TenBit(addr) if addr <= 0x3ff  => Ok((0x78 | (addr >> 8) as u8, Some(addr as u8))),
+            _                                           => Err(I2cError::InvalidAddress),
+        }
+    }
+}
+
+/// Fehler bei I2C-Transaktionen
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum I2cError {
+    /// Ungültige Adresse
+    InvalidAddress,
+    /// Die Taktrate kann nicht eingestellt werden
+    InvalidClock,
+    /// Der Bus ist nicht initialisiert
+    NotInitialized,
+    /// Der Bus führt bereits eine Transaktion aus
+    Busy,
+    /// Der Slave hat Adresse oder Daten nicht bestätigt (NACK)
+    Nack,
+    /// Der Slave hat den Takt zu lange gehalten (_clock stretching_)
+    ClockStretchTimeout,
+    /// Die Transaktion wurde nicht rechtzeitig beendet
+    Timeout,
+    /// Zu viele Daten für eine Transaktion
+    TooLong,
+    /// Die Serviceroutine konnte nicht angemeldet werden
+    Failed,
+    /// Die GPIO-Pins können nicht belegt werden
+    Gpio(GpioError),
+}
+
+impl I2cError {
+    /// Fehlercode für Systemaufrufe (0 steht für Erfolg)
+    pub fn as_u32(&self) -> u32 {
+        match *self {
+            I2cError::InvalidAddress      => 1,
+            I2cError::InvalidClock        => 2,
+            I2cError::NotInitialized      => 3,
+            I2cError::Busy                => 4,
+            I2cError::Nack                => 5,
+            I2cError::ClockStretchTimeout => 6,
+            I2cError::Timeout             => 7,
+            I2cError::TooLong             => 8,
+            I2cError::Failed              => 9,
+            I2cError::Gpio(_)             => 10,
+        }
+    }
+}
+
+/// I2C-Master
+pub trait I2cBus {
+    /// Sendet `tx` an den Slave `addr` und liest danach `rx.len()` Bytes.
+    fn transaction(&self, addr: I2cAddress, tx: &[u8], rx: &mut [u8]) -> Result<(),I2cError>;
+
+    /// Sendet `data`.
+    fn write(&self, addr: I2cAddress, data: &[u8]) -> Result<(),I2cError> {
+        self.transaction(addr, data, &mut [])
+    }
+
+    /// Liest `buf.len()` Bytes.
+    fn read(&self, addr: I2cAddress, buf: &mut [u8]) -> Result<(),I2cError> {
+        self.transaction(addr, &[], buf)
+    }
+
+    /// Sendet `data` und liest danach ohne Freigabe des Busses `buf.len()` Bytes.
+    fn write_read(&self, addr: I2cAddress, data: &[u8], buf: &mut [u8]) -> Result<(),I2cError> {
+        self.transaction(addr, data, buf)
+    }
+
+    /// Schreibt `value` in das Register `reg` des Slaves.
+    fn write_register(&self, addr: I2cAddress, reg: u8, value: u8) -> Result<(),I2cError> {
+        self.write(addr, &[reg, value])
+    }
+
+    /// Liest das Register `reg` des Slaves.
+    fn read_register(&self, addr: I2cAddress, reg: u8) -> Result<u8,I2cError> {
+        let mut value = [0u8; 1];
+        self.write_read(addr, &[reg], &mut value)?;
+        Ok(value[0])
+    }
+}
+
+/// Flag in `I2cRequest`: 10-Bit-Adresse
+pub const I2C_TEN_BIT: u16 = 0x1;
+
+/// Beschreibung einer Transaktion für den Systemaufruf `I2cTransfer`
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct I2cRequest {
+    /// Adresse des Slaves
+    pub addr:   u16,
+    /// `I2C_TEN_BIT` oder 0
+    pub flags:  u16,
+    /// Sendedaten
+    pub tx:     *const u8,
+    /// Anzahl der Sendedaten
+    pub tx_len: u32,
+    /// Empfangspuffer
+    pub rx:     *mut u8,
+    /// Anzahl der zu empfangenden Bytes
+    pub rx_len: u32,
+}
+
+impl I2cRequest {
+    /// Adresse des Slaves
+    pub fn address(&self) -> I2cAddress {
+        if self.flags & I2C_TEN_BIT != 0 {
+            I2cAddress::TenBit(self.addr)
+        } else if self.addr <= 0xff {
+            I2cAddress::SevenBit(self.addr as u8)
+        } else {
+            // Wird von `encode` abgelehnt.
+            I2cAddress::SevenBit(0xff)
+        }
+    }
+}
@@ -1,6 +1,70 @@
 pub const NUM_INTERRUPTS: usize        = 72;
 pub const FIRST_BASIC_INTERRUPT: usize = 64;
 
+/// Allgemeine Interrupts, die auch im Basic-Pending-Register gemeldet werden:
+/// (Bit im Basic-Pending-Register, Nummer des allgemeinen Interrupts).
+///
+/// Vgl. BMC2835 ARM Peripherals 7.5, S. 113f.
+pub const SHARED_INTERRUPTS: [(u32, usize); 11] = [
+    (10,  7), (11,  9), (12, 10), (13, 18), (14, 19),
+    (15, 53), (16, 54), (17, 55), (18, 56), (19, 57), (20, 62)];
+
+/// Bits im Basic-Pending-Register, die anzeigen, dass in den allgemeinen Pending-Registern
+/// 0 bzw. 1 weitere Interrupts anliegen.
+pub const GENERAL_PENDING_BITS: [u32; 2] = [8, 9];
+
+// # Nummernmodell
+//
+// Jeder Interrupt hat genau eine UID (siehe `Interrupt::uid`):
+//
+// - 0 ... 63:  allgemeine Interrupts; Bit `uid % 32` im allgemeinen Register `uid / 32`
+// - 64 ... 71: Nur-ARM-Interrupts; Bit `uid - 64` in den Basic-Registern
+//
+// Die allgemeinen Interrupts aus `SHARED_INTERRUPTS` erscheinen zusätzlich im
+// Basic-Pending-Register. Aktiviert und deaktiviert werden sie ausschließlich über die
+// allgemeinen Register.
+
+/// UID des Interrupts, der durch das Bit `bit` im Basic-Pending-Register angezeigt wird.
+///
+/// Für die Sammelbits (`GENERAL_PENDING_BITS`) und ungültige Bits wird `None` zurückgegeben.
+pub fn uid_from_basic_bit(bit: u32) -> Option<usize> {
+    if bit < 8 {
+        return Some(FIRST_BASIC_INTERRUPT + bit as usize);
+    }
+    for &(basic, general) in SHARED_INTERRUPTS.iter() {
+        if basic == bit {
+            return Some(general);
+        }
+    }
+    None
+}
+
+/// Bit im Basic-Pending-Register für den Interrupt mit der UID `uid`, sofern es eins gibt.
+pub fn basic_bit_from_uid(uid: usize) -> Option<u32> {
+    if uid >= NUM_INTERRUPTS {
+        return None;
+    }
+    if uid >= FIRST_BASIC_INTERRUPT {
+        return Some((uid - FIRST_BASIC_INTERRUPT) as u32);
+    }
+    for &(basic, general) in SHARED_INTERRUPTS.iter() {
+        if general == uid {
+            return Some(basic);
+        }
+    }
+    None
+}
+
+/// Registerindex und Bit in den allgemeinen Registern für die UID `uid`, sofern es ein
+/// allgemeiner Interrupt ist.
+pub fn general_bit_from_uid(uid: usize) -> Option<(usize, u32)> {
+    if uid < FIRST_BASIC_INTERRUPT {
+        Some((uid / 32, (uid % 32) as u32))
+    } else {
+        None
+    }
+}
+
 /// Der `Interrupt`-Trait dient zum Überladen von `IrqController`-Methoden.
 ///
 /// # Arten von Interrupts
@@ -18,15 +82,16 @@ pub const FIRST_BASIC_INTERRUPT: usize = 64;
 /// irq_controller.enable(GeneralInterrupt::SystemTimer1);
 /// ```
 pub trait Interrupt {
-    /// Eindeutige Id des Interrupts.
+    /// Eindeutige Id des Interrupts (kleiner als `NUM_INTERRUPTS`).
     ///
     /// Diese entspricht der Nummer, die bei der Auswahl des FIQ genutzt wird,
-    /// siehe BMC2835 ARM Peripherals 7.5, S.116.
-    fn uid(&self) -> usize {
+    /// siehe BMC2835 ARM Peripherals 7.5, S.116. Die Sammelinterrupts `General1` und
+    /// `General2` haben keine UID, da sie keine eigene Quelle sind.
+    fn uid(&self) -> Option<usize> {
         if let Some(int) = self.as_general_interrupt() {
-            int.as_u32() as usize
+            Some(int.as_u32() as usize)
         } else {
-            FIRST_BASIC_INTERRUPT + self.as_u32() as usize
+            uid_from_basic_bit(self.as_u32())
         }
     }
 
@@ -145,12 +210,8 @@ impl GeneralInterrupt {
     /// Liefert für die General-Interrupt die Adresse (Wort- und Bitindex)  für
     /// die Doppelregister (`Pending`, `Enable` und `Disable`)
     pub(super) fn index_and_bit(&self) -> (usize, usize) {
-        let bit = self.as_u32() as usize;
-        if bit > 31 {
-            (1, bit - 32)
-        } else {
-            (0, bit)
-        }
+        let (ndx, bit) = general_bit_from_uid(self.as_u32() as usize).unwrap();
+        (ndx, bit as usize)
     }
 }
 
@@ -175,20 +236,7 @@ impl Interrupt for GeneralInterrupt {
     }
 
     fn as_basic_interrupt(&self) -> Option<BasicInterrupt> {
-       match *self {
-           GeneralInterrupt::JPEG  => Some(BasicInterrupt::JPEG),
-           GeneralInterrupt::USB   => Some(BasicInterrupt::USB),
-           GeneralInterrupt::GPU3D => Some(BasicInterrupt::GPU3D),
-           GeneralInterrupt::DMA2  => Some(BasicInterrupt::DMA2),
-           GeneralInterrupt::DMA3  => Some(BasicInterrupt::DMA3),
-           GeneralInterrupt::I2C   => Some(BasicInterrupt::I2C),
-           GeneralInterrupt::SPI   => Some(BasicInterrupt::SPI),
-           GeneralInterrupt::PCM   => Some(BasicInterrupt::PCM),
-           GeneralInterrupt::SDIO  => Some(BasicInterrupt::SDIO),
-           GeneralInterrupt::UART  => Some(BasicInterrupt::UART),
-           GeneralInterrupt::SDHCI => Some(BasicInterrupt::SDHCI),
-           _                       => None,
-        }
+        basic_bit_from_uid(self.as_u32() as usize).and_then(BasicInterrupt::from_bit)
     }
 
     fn from_uid(uid: usize) -> Option<Self>  {
@@ -196,7 +244,7 @@ impl Interrupt for GeneralInterrupt {
            None
         } else {
             unsafe{
-                Some(::core::intrinsics::transmute::<usize,GeneralInterrupt>(uid))
+                Some(::core::intrinsics::transmute::<u32,GeneralInterrupt>(uid as u32))
             }
         }
     }
@@ -212,7 +260,7 @@ impl Interrupt for GeneralInterrupt {
 /// Die Basic-Interrupts enthalten einige General-Interrupts (d.h. Board-Interrupts), sowie
 /// Nur-ARM-Interrupts.
 /// Zu den Letzteren zählen auch die Sammelinterrupts `General1` und `General2`.
-#[derive(Copy,Clone,Debug)]
+#[derive(Copy,Clone,Debug,PartialEq)]
 #[repr(u32)]
 #[allow(dead_code)]
 pub enum BasicInterrupt {
@@ -258,36 +306,100 @@ impl Interrupt for BasicInterrupt {
 
     /// Gibt den General-Interrupt, der dem gegebenen Basic-Interrupt entspricht, oder `None`.
     fn as_general_interrupt(&self) -> Option<GeneralInterrupt> {
-        match *self {
-            BasicInterrupt::JPEG  => Some(GeneralInterrupt::JPEG),
-            BasicInterrupt::USB   => Some(GeneralInterrupt::USB),
-            BasicInterrupt::GPU3D => Some(GeneralInterrupt::GPU3D),
-            BasicInterrupt::DMA2  => Some(GeneralInterrupt::DMA2),
-            BasicInterrupt::DMA3  => Some(GeneralInterrupt::DMA3),
-            BasicInterrupt::I2C   => Some(GeneralInterrupt::I2C),
-            BasicInterrupt::SPI   => Some(GeneralInterrupt::SPI),
-            BasicInterrupt::PCM   => Some(GeneralInterrupt::PCM),
-            BasicInterrupt::SDIO  => Some(GeneralInterrupt::SDIO),
-            BasicInterrupt::UART  => Some(GeneralInterrupt::UART),
-            BasicInterrupt::SDHCI => Some(GeneralInterrupt::SDHCI),
-            _                     => None,
-        }
+        uid_from_basic_bit(self.as_u32()).and_then(GeneralInterrupt::from_uid)
     }
 
     fn as_basic_interrupt(&self) -> Option<BasicInterrupt> {
         Some(*self)
     }
 
+    /// Basic-Interrupt zur UID.
+    ///
+    /// Für geteilte Interrupts (siehe `SHARED_INTERRUPTS`) wird die Basic-Variante
+    /// zurückgegeben, z.B. `BasicInterrupt::UART` für die UID 57.
     fn from_uid(uid: usize) -> Option<Self>  {
-        if uid >= FIRST_BASIC_INTERRUPT {
-          unsafe{
-                Some(::core::intrinsics::transmute::<usize,BasicInterrupt>(uid))
+        basic_bit_from_uid(uid).and_then(BasicInterrupt::from_bit)
+    }
+}
+
+impl BasicInterrupt {
+    /// Basic-Interrupt zum Bit im Basic-Pending-Register
+    pub fn from_bit(bit: u32) -> Option<BasicInterrupt> {
+        if bit <= BasicInterrupt::SDHCI as u32 {
+            unsafe{
+                Some(::core::intrinsics::transmute::<u32,BasicInterrupt>(bit))
             }
         } else {
-            //for i in 
-            // ToDo: Shared Interrupts korrekt als BasicInterrupt zurückgeben
             None
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn general_uids() {
+        for uid in 0..FIRST_BASIC_INTERRUPT {
+            let int = GeneralInterrupt::from_uid(uid).unwrap();
+            assert_eq!(int.uid(), Some(uid));
+            assert_eq!(general_bit_from_uid(uid), Some((uid / 32, (uid % 32) as u32)));
+        }
+        for uid in FIRST_BASIC_INTERRUPT..NUM_INTERRUPTS + 8 {
+            assert_eq!(GeneralInterrupt::from_uid(uid), None);
+            assert_eq!(general_bit_from_uid(uid), None);
+        }
+    }
+
+    #[test]
+    fn basic_uids() {
+        for bit in 0..32 {
+            let int = match BasicInterrupt::from_bit(bit) {
+                Some(int) => int,
+                None      => {
+                    assert!(bit > BasicInterrupt::SDHCI as u32);
+                    continue;
+                }
+            };
+            assert_eq!(int.as_u32(), bit);
+            match int.uid() {
+                Some(uid) => {
+                    assert!(uid < NUM_INTERRUPTS);
+                    assert_eq!(uid_from_basic_bit(bit), Some(uid));
+                    assert_eq!(basic_bit_from_uid(uid), Some(bit));
+                    assert_eq!(BasicInterrupt::from_uid(uid), Some(int));
+                },
+                None => assert!(GENERAL_PENDING_BITS.contains(&bit)),
+            }
+        }
+        assert_eq!(BasicInterrupt::General1.uid(), None);
+        assert_eq!(BasicInterrupt::General2.uid(), None);
+        assert_eq!(BasicInterrupt::ARMtimer.uid(), Some(FIRST_BASIC_INTERRUPT));
+        assert_eq!(BasicInterrupt::UART.uid(), Some(GeneralInterrupt::UART as usize));
+    }
+
+    #[test]
+    fn shared_interrupts() {
+        for &(bit, uid) in SHARED_INTERRUPTS.iter() {
+            let basic = BasicInterrupt::from_bit(bit).unwrap();
+            let general = GeneralInterrupt::from_uid(uid).unwrap();
+            assert_eq!(basic.as_general_interrupt(), Some(general));
+            assert_eq!(general.as_basic_interrupt(), Some(basic));
+            assert_eq!(basic.uid(), general.uid());
+        }
+    }
+
+    #[test]
+    fn every_uid_has_an_interrupt() {
+        for uid in 0..NUM_INTERRUPTS {
+            let general = GeneralInterrupt::from_uid(uid).and_then(|int| int.uid());
+            let basic = BasicInterrupt::from_uid(uid).and_then(|int| int.uid());
+            assert!(general.is_some() || basic.is_some());
+            for u in general.iter().chain(basic.iter()) {
+                assert_eq!(*u, uid);
+            }
+        }
+        assert_eq!(basic_bit_from_uid(NUM_INTERRUPTS), None);
+    }
+}
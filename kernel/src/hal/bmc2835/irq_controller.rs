@@ -1,8 +1,11 @@
 #![allow(dead_code)]
 use alloc::vec::Vec;
-const FIQ_BASIC_INTR_OFFSET: u32 = 64;
+use sync::IrqSpinLock;
 const FIQ_ENABLE_BIT: u8        = 7;
-const GENERAL_INTS: [usize;11] = [7,9,10,18,19,53,54,55,56,57,62];
+
+/// Mit `IrqController::disable` gesperrte Interrupts, die seitdem nicht wieder mit `enable`
+/// aktiviert wurden, siehe `IrqController::without_disabled`.
+static DISABLED: IrqSpinLock<IrqMask> = IrqSpinLock::new(IrqMask::empty());
 
 /// Interrupt-Controller.
 ///
@@ -22,6 +25,108 @@ pub struct IrqController {
     disable_basic:   u32
 }
 
+/// Menge von Interrupts in der Darstellung der Enable- und Disable-Register.
+///
+/// Allgemeine Interrupts, die es auch als Basic-Interrupts gibt, werden nur über die
+/// allgemeinen Register geführt.
+#[derive(Copy,Clone,Debug,Default,PartialEq)]
+pub struct IrqMask {
+    general: [u32;2],
+    basic:   u32,
+}
+
+impl IrqMask {
+    /// Leere Menge
+    pub const fn empty() -> IrqMask {
+        IrqMask {
+            general: [0,0],
+            basic:   0,
+        }
+    }
+
+    /// Fügt den Interrupt mit der UID `uid` hinzu.
+    pub fn insert(&mut self, uid: usize) {
+        if let Some((ndx, bit)) = general_bit_from_uid(uid) {
+            self.general[ndx].set_bit(bit as u8,true);
+        } else if let Some(bit) = basic_bit_from_uid(uid) {
+            self.basic.set_bit(bit as u8,true);
+        }
+    }
+
+    /// Entfernt den Interrupt mit der UID `uid`.
+    pub fn remove(&mut self, uid: usize) {
+        if let Some((ndx, bit)) = general_bit_from_uid(uid) {
+            self.general[ndx].set_bit(bit as u8,false);
+        } else if let Some(bit) = basic_bit_from_uid(uid) {
+            self.basic.set_bit(bit as u8,false);
+        }
+    }
+
+    /// Gibt an, ob der Interrupt mit der UID `uid` enthalten ist.
+    pub fn contains(&self, uid: usize) -> bool {
+        if let Some((ndx, bit)) = general_bit_from_uid(uid) {
+            self.general[ndx].get_bit(bit as u8)
+        } else if let Some(bit) = basic_bit_from_uid(uid) {
+            self.basic.get_bit(bit as u8)
+        } else {
+            false
+        }
+    }
+
+    /// Schnittmenge
+    pub fn intersect(&self, other: &IrqMask) -> IrqMask {
+        IrqMask {
+            general: [self.general[0] & other.general[0], self.general[1] & other.general[1]],
+            basic:   self.basic & other.basic,
+        }
+    }
+
+    /// Differenzmenge: alle Interrupts, die nicht in `other` enthalten sind
+    pub fn difference(&self, other: &IrqMask) -> IrqMask {
+        IrqMask {
+            general: [self.general[0] & !other.general[0], self.general[1] & !other.general[1]],
+            basic:   self.basic & !other.basic,
+        }
+    }
+
+    /// Gibt an, ob die Menge leer ist.
+    pub fn is_empty(&self) -> bool {
+        self.general[0] == 0 && self.general[1] == 0 && self.basic == 0
+    }
+
+    /// Iterator über die UIDs der enthaltenen Interrupts in aufsteigender Reihenfolge
+    pub fn iter(&self) -> IrqMaskIterator {
+        IrqMaskIterator {
+            words: [self.general[0], self.general[1], self.basic],
+            ndx:   0,
+        }
+    }
+}
+
+/// Iterator über eine `IrqMask`, siehe `IrqMask::iter`
+pub struct IrqMaskIterator {
+    words: [u32;3],
+    ndx:   usize,
+}
+
+impl Iterator for IrqMaskIterator {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.ndx < 3 {
+            let word = self.words[self.ndx];
+            if word != 0 {
+                let bit = word.trailing_zeros() as usize;
+                // Niedrigstes gesetztes Bit löschen
+                self.words[self.ndx] = word & (word - 1);
+                return Some(self.ndx * 32 + bit);
+            }
+            self.ndx += 1;
+        }
+        None
+    }
+}
+
 use super::Bmc2835;
 impl Bmc2835 for IrqController {
     /// Basisadresse der IrqController-Hardwareregister.
@@ -34,25 +139,32 @@ impl Bmc2835 for IrqController {
     }
 }
 
-use super::{Interrupt,NUM_INTERRUPTS,FIRST_BASIC_INTERRUPT};
+use super::{Interrupt,SHARED_INTERRUPTS,GENERAL_PENDING_BITS};
+use super::{general_bit_from_uid,basic_bit_from_uid};
 use bit_field::BitField;
 impl IrqController {
 
     /// Schaltet den gegebenen Interrupt aktiv.
+    ///
+    /// Geteilte Interrupts (siehe `SHARED_INTERRUPTS`) werden nur über die allgemeinen
+    /// Register aktiviert, auch wenn sie als `BasicInterrupt` angegeben werden.
     pub fn enable<T: Interrupt + Sized>(&mut self, int: T) -> &mut Self {
         if let Some(general_int) = int.as_general_interrupt() {
             let (ndx, shift) = general_int.index_and_bit();
-            kprint!("General: Setze bit {} @ {:08x}\n",shift, &self.enable_general[ndx] as *const _ as u32;RED);
             self.enable_general[ndx] = 0x1u32 << shift;
-        } 
-        if let Some(basic_int) = int.as_basic_interrupt() {
-            kprint!("Basic Setze bit {} @ {:08x}\n",basic_int.as_u32(), &self.enable_basic as *const _ as u32;RED);
+        } else {
+            let basic_int = int.as_basic_interrupt().unwrap();
             self.enable_basic = 0x1u32 << basic_int.as_u32();
         }
+        if let Some(uid) = int.uid() {
+            DISABLED.lock().remove(uid);
+        }
         self
     }
 
     /// Deaktiviert den gegebenen Interrupt.
+    ///
+    /// Wie bei `enable` werden geteilte Interrupts über die allgemeinen Register deaktiviert.
     pub fn disable<T: Interrupt + Sized>(&mut self, int: T) -> &mut Self {
          if let Some(general_int) = int.as_general_interrupt() {
             let (ndx, shift) = general_int.index_and_bit();
@@ -61,24 +173,69 @@ impl IrqController {
             let basic_int = int.as_basic_interrupt().unwrap();
             self.disable_basic = 0x1u32 << basic_int.as_u32();
         }
+        if let Some(uid) = int.uid() {
+            DISABLED.lock().insert(uid);
+        }
         self
     }
  
+    /// Gibt die Menge der aktiven Interrupts zurück.
+    pub fn enabled(&self) -> IrqMask {
+        use core::ptr::read_volatile;
+        unsafe{
+            IrqMask {
+                general: [read_volatile(&self.enable_general[0]), read_volatile(&self.enable_general[1])],
+                basic:   read_volatile(&self.enable_basic) & 0xff,
+            }
+        }
+    }
+
+    /// Schaltet alle Interrupts der Menge `mask` aktiv.
+    pub fn enable_mask(&mut self, mask: IrqMask) -> &mut Self {
+        self.enable_general[0] = mask.general[0];
+        self.enable_general[1] = mask.general[1];
+        self.enable_basic      = mask.basic;
+        self
+    }
+
+    /// Deaktiviert alle Interrupts der Menge `mask`.
+    pub fn disable_mask(&mut self, mask: IrqMask) -> &mut Self {
+        self.disable_general[0] = mask.general[0];
+        self.disable_general[1] = mask.general[1];
+        self.disable_basic      = mask.basic;
+        self
+    }
+
+    /// Vergisst, dass Interrupts aus `mask` mit `disable` gesperrt wurden.
+    ///
+    /// Zusammen mit `without_disabled` lässt sich feststellen, welche Interrupts in der
+    /// Zwischenzeit gesperrt wurden, siehe `IsrTable::run`.
+    pub fn forget_disabled(&mut self, mask: IrqMask) -> &mut Self {
+        let mut disabled = DISABLED.lock();
+        let remaining = disabled.difference(&mask);
+        *disabled = remaining;
+        self
+    }
+
+    /// Gibt `mask` ohne die Interrupts zurück, die seit `forget_disabled` mit `disable`
+    /// gesperrt (und nicht wieder aktiviert) wurden.
+    pub fn without_disabled(&self, mask: IrqMask) -> IrqMask {
+        mask.difference(&DISABLED.lock())
+    }
+
     /// Wählt einen Interrupt als Schnellen Interrupt (FIQ) aus, und aktiviert den ihn.
     ///
     /// Bei Angabe eines ungültigen Interrupts (Basic-Sammelinterrupt) wird FIQ deaktiviert.
     pub fn set_and_enable_fiq<T: Interrupt>(&mut self, int: T) -> &mut Self {
-        let nr =
-            if int.is_general() {
-                int.as_u32()
-            } else {
-                FIQ_BASIC_INTR_OFFSET + int.as_u32()
-            };
-        if nr < NUM_INTERRUPTS as u32 {
-            self.fiq_control.set_bits(0..7,nr);
-            self.fiq_control.set_bit(FIQ_ENABLE_BIT,true);
-        } else {
-            self.fiq_control.set_bit(FIQ_ENABLE_BIT,false);
+        // Die Nummer für die FIQ-Auswahl entspricht der UID.
+        match int.uid() {
+            Some(nr) => {
+                self.fiq_control.set_bits(0..7,nr as u32);
+                self.fiq_control.set_bit(FIQ_ENABLE_BIT,true);
+            },
+            None => {
+                self.fiq_control.set_bit(FIQ_ENABLE_BIT,false);
+            }
         }
         self
     }
@@ -99,57 +256,48 @@ impl IrqController {
         }
     }
 
-    /// Gibt einen Vektor mit den UIDs aller anliegenden Interrupts zurück.
-    pub fn get_all_pending(&self) -> Vec<usize> {
-        let mut res = Vec::new();
+    /// Gibt die Menge aller anliegenden Interrupts zurück.
+    ///
+    /// Die Pending-Register werden nur einmal gelesen, es wird kein Speicher angefordert.
+    /// Die Funktion kann daher in der Interruptbearbeitung genutzt werden.
+    pub fn pending(&self) -> IrqMask {
+        use core::ptr::read_volatile;
+        let mut res = IrqMask::empty();
         // Wenn überhaupt ein Interrupt anliegt, dann ist das ist mindestens ein Bit im
         // Basic-Pending-Register gesetzt.
         // Um einen stabilen Zustand zu haben, wird das Register kopiert und dann nur noch mit
         // der Kopie gearbeitet.
-        let basic = self.basic_pending.clone();
+        let basic = unsafe{ read_volatile(&self.basic_pending) };
         if basic != 0 {
-            // Teste die Nur-ARM-Interrupts
-            for i in 0 .. 8 {
-                if basic.get_bit(i)  {
-                    res.push(FIRST_BASIC_INTERRUPT+i as usize);
-                }
-            }
-            // Das Array enthält die allgemeinen Interrupts, die es auch als Basic-Interrupts
-            // gibt. Die korrespondierenden Bits beginnen im Register ab Bit 10.
-            for (i,val) in GENERAL_INTS.into_iter().enumerate() {
-                if basic.get_bit(i as u8 + 10) {  
-                    res.push(val.clone());
+            // Die Nur-ARM-Interrupts
+            res.basic = basic & 0xff;
+            // Allgemeine Interrupts, die es auch als Basic-Interrupts gibt.
+            for &(bit, uid) in SHARED_INTERRUPTS.iter() {
+                if basic.get_bit(bit as u8) {
+                    res.insert(uid);
                 }
             }
             // Wenn noch sonstige allgemeine Interrupts gesetzt sind, sind die Bits 8
-            // oder/und 9 gesetzt.
-            // Bit 8 ist für die Interrupts 0 ... 31.
-            if basic.get_bit(8) {
-                // Die bereits als Basic-Interrupt behandelten Interrupts werden ausgefiltert.
-                let general = self.general_pending[0] &
-                    !(0x1 << 7 | 0x1 << 9 | 0x1 << 10 | 0x1 << 18 | 0x1 << 19);
-                for i in 0..32 {
-                    if general.get_bit(i) {
-                        res.push(i as usize)
-                    }
+            // oder/und 9 gesetzt. Bit 8 ist für die Interrupts 0 ... 31, Bit 9 für 32 ... 63.
+            // Interrupts, die bereits als Basic-Interrupts erfasst wurden, sind in der Menge
+            // höchstens einmal enthalten.
+            for (ndx, bit) in GENERAL_PENDING_BITS.iter().enumerate() {
+                if basic.get_bit(*bit as u8) {
+                    res.general[ndx] |= unsafe{ read_volatile(&self.general_pending[ndx]) };
                 }
             }
-            // Bit 9 ist für die Interrupts 32 ... 71.
-            if basic.get_bit(9) {
-                let general = self.general_pending[1] &
-                    !(0x1 << (53-32) | 0x1 << (54-32) | 0x1 << (55-32) | 0x1 << (56-32) |
-                      0x1 << (57-32) | 0x1 << (62-32));
-                for i in 0..32 {
-                    if general.get_bit(i) {
-                        res.push(32 + i as usize)
-                    }
-                }
-            }
-           
         }
-        //kprint!("Interrupts pending: {:?}\n",res;CYAN);
         res
     }
-    
 
+    /// Gibt einen Vektor mit den UIDs aller anliegenden Interrupts zurück.
+    ///
+    /// # Anmerkung
+    /// Fordert Speicher an und sollte daher nicht in der Interruptbearbeitung genutzt werden,
+    /// dort ist `pending` zu verwenden.
+    pub fn get_all_pending(&self) -> Vec<usize> {
+        let res: Vec<usize> = self.pending().iter().collect();
+        //kprint!("Interrupts pending: {:?}\n",res;CYAN);
+        res
+    }
 }
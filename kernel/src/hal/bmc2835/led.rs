@@ -1,4 +1,5 @@
 pub use super::{Gpio,GpioPinFunctions,GpioPull,GpioEvent,gpio_config};
+use super::GpioPins;
 use hal::bmc2835::Bmc2835;
 
 #[allow(dead_code)]
@@ -8,21 +9,42 @@ pub enum LedType {
 }
 
 pub struct Led {
-    pin: u8
+    pin: u8,
 }
 
-impl Led {
-    pub fn init(led: LedType) -> Led {
-        let pin: u8 = match led {
+impl LedType {
+    fn pin(&self) -> u8 {
+        match *self {
             // Pins siehe 
             LedType::Red => 35,   
             LedType::Green => 47
-        };
-        let gpio = Gpio::get();
-        gpio.set_pull(pin,GpioPull::Off);
-        gpio.config_pin(pin,gpio_config::Device::Output).unwrap();
+        }
+    }
+}
+
+impl Led {
+    /// Belegt den Pin der LED bis zum Neustart und stellt ihn als Ausgang ein.
+    pub fn init(led: LedType) -> Led {
+        let pin = led.pin();
+        // Ist der Pin schon belegt (z.B. durch einen früheren Aufruf), wird er nicht
+        // umgestellt, aber trotzdem angesteuert.
+        if let Ok(claim) = GpioPins::get().claim(pin,gpio_config::Device::Output,"LED") {
+            Gpio::get().set_pull(pin,GpioPull::Off);
+            claim.keep();
+        }
+        Led {
+            pin: pin,
+        }
+    }
+
+    /// Die LED ohne Zugriff auf das Verzeichnis der Pins, z.B. zur Fehlerausgabe nach einer
+    /// Panic, während dessen Sperre gehalten sein kann. Der Pin wird direkt als Ausgang
+    /// eingestellt.
+    pub fn raw(led: LedType) -> Led {
+        let pin = led.pin();
+        Gpio::get().set_function(pin,GpioPinFunctions::Output);
         Led {
-            pin: pin
+            pin: pin,
         }
     }
 
@@ -0,0 +1,114 @@
+#![warn(missing_docs)]
+//! Interruptgesteuerter Treiber für die Mini-UART (UART1).
+//!
+//! Puffer und Lese-/Schreibfunktionen stellt der `SerialDriver`. Der Sendeinterrupt der
+//! Mini-UART liegt an, solange die Sende-FIFO leer ist; er ist daher nur aktiv, solange der
+//! Sendepuffer Daten enthält.
+//!
+//! Der AUX-Interrupt wird mit SPI1 und SPI2 geteilt. Die Serviceroutine meldet daher
+//! `NotHandled`, wenn die Mini-UART keinen Interrupt anmeldet.
+use data::ring_buffer::RingBuffer;
+use data::isr_table::IsrResult;
+use data::kernel::KernelData;
+use super::{Bmc2835, MiniUart, AuxInterrupt, IrqController, GeneralInterrupt};
+use super::{Uart, UartEnable, UartError, UartConfig, UartBaud, UartFlowControl};
+use super::serial_driver::{SerialDriver, SerialPort};
+use super::gpio_config::Device;
+use super::{ClockId, report_clock_rate};
+
+/// Zähler für Empfangsfehler
+#[derive(Copy,Clone,Debug,Default,PartialEq)]
+#[repr(C)]
+pub struct MiniUartErrorCounts {
+    /// Überlauf der Empfangs-FIFO
+    pub overrun: u32,
+    /// Zeichen, die wegen vollem Empfangspuffer verworfen wurden
+    pub dropped: u32,
+}
+
+impl SerialPort<MiniUartErrorCounts> for MiniUart {
+    fn receive(&mut self, rx: &mut RingBuffer, errors: &mut MiniUartErrorCounts) {
+        while let Some((b, overrun)) = self.read_raw() {
+            if overrun {
+                errors.overrun += 1;
+            }
+            if !rx.push(b) {
+                errors.dropped += 1;
+            }
+        }
+    }
+
+    fn tx_ready(&self) -> bool {
+        self.tx_has_space()
+    }
+
+    fn put(&mut self, b: u8) {
+        self.try_write(b);
+    }
+
+    fn set_tx_interrupt(&mut self, enable: bool) {
+        if enable {
+            self.enable_interrupt(AuxInterrupt::UartTransmit);
+        } else {
+            self.disable_interrupt(AuxInterrupt::UartTransmit);
+        }
+    }
+
+    fn tx_done(&self) -> bool {
+        self.tx_is_idle()
+    }
+}
+
+/// Treiber für die Mini-UART
+pub type MiniUartDriver = SerialDriver<MiniUart, MiniUartErrorCounts>;
+
+static MINI_UART_DRIVER: MiniUartDriver =
+    SerialDriver::new(MiniUartErrorCounts { overrun: 0, dropped: 0 });
+
+impl SerialDriver<MiniUart, MiniUartErrorCounts> {
+    /// Der Treiber
+    pub fn get() -> &'static MiniUartDriver {
+        &MINI_UART_DRIVER
+    }
+
+    /// Belegt die GPIO-Pins (alt5), aktiviert die Mini-UART im AUX, konfiguriert sie, meldet
+    /// (beim ersten Aufruf) die Serviceroutine an und aktiviert den Interrupt.
+    ///
+    /// Die Baudrate wird aus dem Kerntakt berechnet, den die Firmware meldet. Gibt die
+    /// erreichte Baudrate zurück.
+    pub fn init(&self, config: &UartConfig) -> Result<UartBaud,UartError> {
+        // Bei erneuter Initialisierung zuerst die eigenen Pins freigeben
+        self.reset();
+        let flow = config.flow_control == UartFlowControl::RtsCts;
+        let pins = config.pins.claim(flow, Device::Uart1, "UART1")?;
+        let clock = report_clock_rate(ClockId::Core);
+        let uart = MiniUart::get();
+        let baud = uart.configure(clock, config)?;
+        if !self.is_initialized() {
+            KernelData::isr_table().add_isr(GeneralInterrupt::AUX, MiniUartDriver::isr, 0)
+                .map_err(|_| UartError::Failed)?;
+            IrqController::get().enable(GeneralInterrupt::AUX);
+        }
+        self.set_initialized(pins);
+        uart.enable_interrupt(AuxInterrupt::UartReceive);
+        uart.enable(UartEnable::Both);
+        Ok(baud)
+    }
+
+    /// Serviceroutine
+    pub fn isr(_: usize) -> IsrResult {
+        MINI_UART_DRIVER.handle_interrupt()
+    }
+
+    fn handle_interrupt(&self) -> IsrResult {
+        let uart = MiniUart::get();
+        if !uart.is_pending() {
+            return IsrResult::NotHandled;
+        }
+        // Beide Interrupts sind pegelgesteuert, daher wird einfach nachgesehen, was zu tun ist.
+        let rx = !uart.rx_is_empty();
+        let tx = uart.is_interrupt_enabled(AuxInterrupt::UartTransmit) && uart.tx_has_space();
+        self.service(rx, tx);
+        IsrResult::Handled
+    }
+}
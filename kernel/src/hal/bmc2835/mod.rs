@@ -1,20 +1,67 @@
 mod uart;
 pub use self::uart::*;
 mod aux;
-pub use self::aux::{MiniUart};
+pub use self::aux::{Aux,AuxDevice,AuxInterrupt,MiniUart,AuxSpi,aux_spi_speed,AUX_SPI_NUM_CS,AUX_SPI_FIFO_SIZE};
 mod pl011;
 pub use self::pl011::{Pl011,Pl011Interrupt,Pl011Flag,Pl011Error,Pl011FillLevel};
+mod serial_driver;
+pub use self::serial_driver::{SerialDriver,SerialPort};
+mod pl011_driver;
+pub use self::pl011_driver::{Pl011Driver,Pl011ErrorCounts};
+mod mini_uart_driver;
+pub use self::mini_uart_driver::{MiniUartDriver,MiniUartErrorCounts};
+mod spi;
+pub use self::spi::{SpiMode,SpiTransfer,SpiSettings,SpiError,SpiBus,SpiDevice,SpiChip};
+mod spi0;
+pub use self::spi0::{Spi0,spi0_clock_divider,spi0_dma_header,SPI0_NUM_CS};
+mod spi_driver;
+mod spi0_driver;
+pub use self::spi0_driver::Spi0Driver;
+mod aux_spi_driver;
+pub use self::aux_spi_driver::AuxSpiDriver;
+mod i2c;
+pub use self::i2c::{I2cAddress,I2cError,I2cBus,I2cRequest,I2C_TEN_BIT};
+mod bsc;
+pub use self::bsc::{Bsc,BscMaster,BscStatus,bsc_clock_divider,BSC_FIFO_SIZE};
+mod bsc_driver;
+pub use self::bsc_driver::{BscDriver,BscConfig};
+mod dma;
+pub use self::dma::{DmaControlBlock,DmaChannelRegs,DmaGlobal,DmaDreq,DmaDirection,dma_bus_address,
+                    DMA_NUM_CHANNELS,DMA_FIRST_LITE_CHANNEL,DMA_LITE_MAX_LEN,DMA_MAX_LEN};
+mod dma_driver;
+pub use self::dma_driver::{DmaController,DmaChannel,DmaChannelKind,DmaChain,DmaError};
+mod block;
+pub use self::block::{BlockDevice,BlockError};
+mod emmc;
+pub use self::emmc::{Emmc,EmmcResponse,EmmcData,emmc_cmdtm,emmc_clock_divider,csd_block_count,
+                     EMMC_INT_CMD_DONE,EMMC_INT_DATA_DONE,EMMC_INT_WRITE_RDY,EMMC_INT_READ_RDY,
+                     EMMC_INT_ERR,EMMC_INT_CTO_ERR,EMMC_INT_CCRC_ERR,EMMC_INT_DTO_ERR,
+                     EMMC_INT_DCRC_ERR,EMMC_INT_ERRORS,EMMC_MAX_BLOCKS};
+mod emmc_driver;
+pub use self::emmc_driver::{EmmcDriver,EmmcConfig,SdCard};
+mod clock_manager;
+pub use self::clock_manager::{ClockManager,CmClock,CmSource,CmError,cm_divider};
+mod pwm;
+pub use self::pwm::{Pwm,PwmChannel,PwmMode,PwmError,PWM_FIFO_SIZE};
+mod pwm_driver;
+pub use self::pwm_driver::{PwmDriver,PWM_AUDIO_PINS};
 mod gpio;
 pub use self::gpio::{Gpio,GpioPinFunctions,GpioPull,GpioEvent,gpio_config};
+mod gpio_pins;
+pub use self::gpio_pins::{GpioPins,GpioPin,GpioPinAssignment};
+mod gpio_driver;
+pub use self::gpio_driver::{GpioDriver,GpioTrigger,GpioCallback,GpioError,GpioPinEvent,
+                            GPIO_EVENT_QUEUE_LEN,GPIO_WAIT_FOREVER};
 mod system_timer;
 pub use self::system_timer::SystemTimer;
     
 mod arm_timer;
 pub use self::arm_timer::{ArmTimer,ArmTimerResolution};
 mod interrupts;
-pub use self::interrupts::{Interrupt,BasicInterrupt,GeneralInterrupt,NUM_INTERRUPTS,FIRST_BASIC_INTERRUPT};
+pub use self::interrupts::{Interrupt,BasicInterrupt,GeneralInterrupt,NUM_INTERRUPTS,FIRST_BASIC_INTERRUPT,
+                           SHARED_INTERRUPTS,uid_from_basic_bit,basic_bit_from_uid,general_bit_from_uid};
 mod irq_controller;
-pub use self::irq_controller::IrqController;
+pub use self::irq_controller::{IrqController,IrqMask,IrqMaskIterator};
 mod led;
 pub use self::led::{Led,LedType};
 
@@ -76,9 +123,7 @@ pub fn report_board_info(kind: BoardReport) -> u32 {
         BoardReport::SerialNumber    => Tag::GetBoardSerial
     };
     prob_tag_buf.add_tag_with_param(tag,None);
-    let mb = mailbox(0);
-    mb.write(Channel::ATags, prob_tag_buf.data_addr() as u32);
-    mb.read(Channel::ATags);
+    prob_tag_buf.exchange();
     match prob_tag_buf.get_answer(tag) {
         Some(n) => n[0],
         _       => 0
@@ -107,9 +152,7 @@ pub fn report_memory(kind: MemReport) -> usize {
         MemReport::VcStart  | MemReport::VcSize  => Tag::GetVcMemory
     };    
     prob_tag_buf.add_tag_with_param(tag,None);
-    let mb = mailbox(0);
-    mb.write(Channel::ATags, prob_tag_buf.data_addr() as u32);
-    mb.read(Channel::ATags);
+    prob_tag_buf.exchange();
     let array = prob_tag_buf.get_answer(tag);
     match array {
         Some(a) => match kind {
@@ -119,3 +162,32 @@ pub fn report_memory(kind: MemReport) -> usize {
         None => 0
     }
 }
+
+/// Takte, deren Rate über die Firmware abgefragt werden kann
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[allow(dead_code)]
+pub enum ClockId {
+    Emmc  = 1,
+    Uart  = 2,
+    Arm   = 3,
+    /// Takt des VideoCore, auch Takt der AUX-Geräte (Mini-UART, SPI1, SPI2) und des SPI0
+    Core  = 4,
+    V3d   = 5,
+    H264  = 6,
+    Isp   = 7,
+    Sdram = 8,
+    Pixel = 9,
+    Pwm   = 10,
+}
+
+/// Gibt die aktuelle Rate eines Taktes in Hz zurück (0, falls die Abfrage fehlschlägt).
+pub fn report_clock_rate(clock: ClockId) -> u32 {
+    let mut prob_tag_buf = PropertyTagBuffer::new();
+    prob_tag_buf.init();
+    prob_tag_buf.add_tag_with_param(Tag::GetClockRate,Some(&[clock as u32]));
+    prob_tag_buf.exchange();
+    match prob_tag_buf.get_answer(Tag::GetClockRate) {
+        Some(a) if a.len() >= 2 => a[1],
+        _                       => 0
+    }
+}
@@ -84,6 +84,7 @@ impl Pl011Interrupt {
 }
 
 /// Füllstand der FIFOs, zu denen ein Interrupt ausgelöst wird.
+#[derive(Copy, Clone, Debug, PartialEq)]
 #[allow(dead_code)]
 pub enum Pl011FillLevel{
     OneEighth,
@@ -122,7 +123,8 @@ pub struct Pl011 {
         intr:          u32,      // Offset 0x40 (MIS)
         /// Rücksetzen von Interrupts
         reset_intr:    u32,      // Offset 0x44 (IRC)
-        _dma_ctrl:     u32,      // Offset 0x48 (DMACR)
+        /// DMA-Steuerung
+        dma_ctrl:      u32,      // Offset 0x48 (DMACR)
         _padding_2:    [u32;15], // Offset 0x4C 
         _test:         [u32;4]   // Offset 0x80 (ITCR+ITIP+ITOP+TDR)
 }
@@ -140,21 +142,85 @@ use hal::cpu::Cpu;
 use bit_field::BitField;
 impl Pl011 {
 
-    /// Setze die Baudrate.
+    /// Setze die Baudrate und gib die erreichte Rate zurück.
     ///
     /// Einheit der Rate ist baud.
     #[allow(dead_code)]
-    pub fn set_baud_rate(&mut self, rate: u32) -> Result<(),UartError> {
+    pub fn set_baud_rate(&mut self, rate: u32) -> Result<UartBaud,UartError> {
+        let (int, frac, baud) = pl011_baud_divisor(PL011_CLOCK_RATE, rate)?;
         Cpu::data_memory_barrier();
-        let div: u32 = if rate * 16 > PL011_CLOCK_RATE {
-            (PL011_CLOCK_RATE * 8)/ rate
-        } else {
-            (PL011_CLOCK_RATE * 4) / rate
-        };
-        self.baud_frac.set_bits(0..6, div & 0x3f);
-        self.baud_int.set_bits(0..16, div >> 6);
+        unsafe{
+            ::core::ptr::write_volatile(&mut self.baud_int, int);
+            ::core::ptr::write_volatile(&mut self.baud_frac, frac);
+            // Die Teiler werden erst mit dem Schreiben von LCRH übernommen; der Zugriff muss
+            // daher tatsächlich stattfinden.
+            let lcrh = ::core::ptr::read_volatile(&self.line_control);
+            ::core::ptr::write_volatile(&mut self.line_control, lcrh);
+        }
         Cpu::data_memory_barrier();
-        Ok(())
+        Ok(baud)
+    }
+
+    /// Wendet die gesamte Konfiguration an und gibt die erreichte Baudrate zurück.
+    ///
+    /// Alle Werte werden vorher geprüft; bei einem Fehler bleibt die UART unverändert.
+    /// Während der Umstellung ist die UART abgeschaltet, die FIFOs werden geleert. Danach ist
+    /// sie im selben Zustand (an/aus) wie vorher. Die GPIO-Pins werden nicht verändert, sie
+    /// belegt `UartPins::claim`.
+    // Siehe PrimeCell UART (PL011) TRM, 3.3.8 (Programmierung des Control Registers)
+    pub fn configure(&mut self, config: &UartConfig) -> Result<UartBaud,UartError> {
+        let (int, frac, baud) = pl011_baud_divisor(PL011_CLOCK_RATE, config.baud_rate)?;
+        if config.data_width < 5 || config.data_width > 8 {
+            return Err(UartError::Invalid);
+        }
+        if config.stop_bits != 1 && config.stop_bits != 2 {
+            return Err(UartError::NoSupported);
+        }
+        let flow = config.flow_control == UartFlowControl::RtsCts;
+
+        let mut lcrh: u32 = 0;
+        lcrh.set_bits(5..7, config.data_width as u32 - 5);
+        lcrh.set_bit(4, true);  // FIFOs an
+        lcrh.set_bit(3, config.stop_bits == 2);
+        lcrh.set_bits(1..3, parity_bits(config.parity));
+        lcrh.set_bit(7, config.parity == UartParity::StickOne || config.parity == UartParity::StickZero);
+
+        let state = Cpu::save_and_disable_interrupts();
+        Cpu::data_memory_barrier();
+        let control = unsafe{ ::core::ptr::read_volatile(&self.control) };
+        let mut disabled = control;
+        disabled.set_bit(0, false);
+        unsafe{ ::core::ptr::write_volatile(&mut self.control, disabled); }
+        // Laufende Übertragung abwarten, dann FIFOs leeren.
+        while self.get_state(Pl011Flag::Busy) {}
+        self.enable_fifo(false);
+        unsafe{
+            ::core::ptr::write_volatile(&mut self.baud_int, int);
+            ::core::ptr::write_volatile(&mut self.baud_frac, frac);
+            // LCRH zuletzt, erst damit werden die Teiler übernommen.
+            ::core::ptr::write_volatile(&mut self.line_control, lcrh);
+        }
+        self.set_rcv_trigger_level(config.rx_level);
+        self.set_trm_trigger_level(config.tx_level);
+
+        let flow_bits = Pl011Control::FlowCTS as u32 | Pl011Control::FlowRTS as u32;
+        let control = if flow { control | flow_bits } else { control & !flow_bits };
+        Cpu::data_memory_barrier();
+        unsafe{ ::core::ptr::write_volatile(&mut self.control, control); }
+        Cpu::data_memory_barrier();
+        Cpu::restore_interrupts(state);
+        Ok(baud)
+    }
+
+    /// Gibt an, ob Hardware-Flusssteuerung aktiv ist.
+    pub fn flow_control(&self) -> UartFlowControl {
+        Cpu::data_memory_barrier();
+        let mask = Pl011Control::FlowCTS as u32 | Pl011Control::FlowRTS as u32;
+        if self.control & mask == mask {
+            UartFlowControl::RtsCts
+        } else {
+            UartFlowControl::None
+        }
     }
 
     /// Lösche (bestätige) die gegebenen Interrupts.
@@ -177,7 +243,7 @@ impl Pl011 {
     #[allow(dead_code)]
     pub fn disable_interrupt(&mut self, mask: Pl011Interrupt) {
         Cpu::data_memory_barrier();
-        self.intr_mask &= !mask.as_u32() & 0b1101;
+        self.intr_mask &= !mask.as_u32() & Pl011Interrupt::All.as_u32();
         Cpu::data_memory_barrier();
     }
 
@@ -207,6 +273,13 @@ impl Pl011 {
                                  });
     }
 
+    /// Gibt an, ob der gegebene (nicht maskierte) Interrupt anliegt.
+    pub fn is_interrupt_pending(&self, int: Pl011Interrupt) -> bool {
+        Cpu::data_memory_barrier();
+        let intr = unsafe{ ::core::ptr::read_volatile(&self.intr) };
+        (intr & int.as_u32()) != 0
+    }
+
     /// Gibt den angefragten Zustand zurück.
     #[allow(dead_code)]
     pub fn get_state(&self, flag: Pl011Flag) -> bool {
@@ -230,30 +303,65 @@ impl Pl011 {
         Cpu::data_memory_barrier();
     }
 
-    /// Ist die Empfangsqueue leer?
+    /// Ist die Sendequeue leer?
     pub fn tx_is_empty(&self) -> bool {
         Cpu::data_memory_barrier();
         (self.flags & Pl011Flag::TxEmpty as u32) != 0
     }
 
-    /// Ist die Empfangsqueue voll?
+    /// Ist die Sendequeue voll?
     pub fn tx_is_full(&self) -> bool {
         Cpu::data_memory_barrier();
         (self.flags & Pl011Flag::TxFull as u32) != 0
     }
 
-    /// Ist die Sendequeue leer?
+    /// Ist die Empfangsqueue leer?
     pub fn rx_is_empty(&self) -> bool {
         Cpu::data_memory_barrier();
         (self.flags & Pl011Flag::RxEmpty as u32) != 0
     }
 
-    /// Ist die Sendequeue voll?
+    /// Ist die Empfangsqueue voll?
     pub fn rx_is_full(&self) -> bool {
         Cpu::data_memory_barrier();
         (self.flags & Pl011Flag::RxFull as u32) != 0
     }
 
+    /// Liest ein Wort aus der Empfangsqueue einschließlich der Fehlerbits 8..11
+    /// (siehe `Pl011Error`).
+    pub fn read_raw(&self) -> Option<u32> {
+        if self.rx_is_empty() {
+            None
+        } else {
+            let data = unsafe{ ::core::ptr::read_volatile(&self.data) };
+            Some(data & 0xfff)
+        }
+    }
+
+    /// Schreibt ein Byte in die Sendequeue; `false`, wenn sie voll ist.
+    pub fn try_write(&mut self, b: u8) -> bool {
+        if self.tx_is_full() {
+            false
+        } else {
+            unsafe{ ::core::ptr::write_volatile(&mut self.data, b as u32); }
+            Cpu::data_memory_barrier();
+            true
+        }
+    }
+
+    /// Schaltet die DMA-Anforderungen für Senden und Empfangen.
+    pub fn set_dma(&mut self, tx: bool, rx: bool) {
+        let reg = (tx as u32) << 1 | rx as u32;
+        Cpu::data_memory_barrier();
+        unsafe{ ::core::ptr::write_volatile(&mut self.dma_ctrl, reg); }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Adresse des Datenregisters (für DMA)
+    pub fn data_address(&self) -> usize {
+        &self.data as *const u32 as usize
+    }
+
     pub fn write_str(&mut self,str: &str) {
         for b in str.bytes() {
             //kprint!("Try to write {}\n",b);
@@ -270,6 +378,38 @@ impl Pl011 {
 
 use hal::bmc2835::uart::*;
 
+/// Bits PEN (1) und EPS (2) des LCRH für die Parität.
+///
+/// Bei _stick parity_ (SPS) wird das Paritätsbit für EPS = 0 als 1, für EPS = 1 als 0
+/// gesendet und geprüft.
+fn parity_bits(parity: UartParity) -> u32 {
+    match parity {
+        UartParity::None      =>  0b00,
+        UartParity::Even      =>  0b11,
+        UartParity::Odd       =>  0b01,
+        UartParity::StickOne  =>  0b01,
+        UartParity::StickZero =>  0b11,
+    }
+}
+
+/// Berechnet die Teiler (IBRD, FBRD) für die Baudrate `rate` bei der Taktrate `clock`.
+///
+/// Der Teiler ist `clock / (16 * rate)` mit 6 Bit Nachkommastellen. Gibt zusätzlich die
+/// damit erreichte Baudrate zurück.
+pub fn pl011_baud_divisor(clock: u32, rate: u32) -> Result<(u32,u32,UartBaud),UartError> {
+    if rate == 0 {
+        return Err(UartError::Invalid);
+    }
+    // Teiler in 64steln, gerundet: clock * 64 / (16 * rate)
+    let div = (clock as u64 * 4 + rate as u64 / 2) / rate as u64;
+    let int = div >> 6;
+    if int == 0 || int > 0xffff {
+        return Err(UartError::NoSupported);
+    }
+    let actual = (clock as u64 * 4 / div) as u32;
+    Ok((int as u32, (div & 0x3f) as u32, UartBaud { requested: rate, actual: actual }))
+}
+
 impl Uart for Pl011 {
     
     fn enable(&mut self, e: UartEnable) {
@@ -315,15 +455,8 @@ impl Uart for Pl011 {
         if self.control.get_bit(0) == true {
             Err(UartError::Failed)
         } else {
-            let bits: u32 =  match parity {
-                UartParity::None      =>  0b00,
-                UartParity::Even      =>  0b11,
-                UartParity::Odd       =>  0b01,
-                UartParity::StickOne  =>  0b11,
-                UartParity::StickZero =>  0b01,
-            };
-            self.line_control.set_bit(7,parity == UartParity::StickOne || parity == UartParity::StickOne);
-            self.line_control.set_bits(1..3,bits);
+            self.line_control.set_bit(7,parity == UartParity::StickOne || parity == UartParity::StickZero);
+            self.line_control.set_bits(1..3,parity_bits(parity));
             Cpu::data_memory_barrier();
             Ok(())
         }
@@ -334,6 +467,7 @@ impl Uart for Pl011 {
     /// #Anmerkung
     /// Es werden nur 1 oder 2 Stop-Bits unterstützt.
     fn set_stop_bits(&mut self, number: u8) -> Result<(),UartError>{
+        Cpu::data_memory_barrier();
         if self.control.get_bit(0) == true {
             Err(UartError::Failed)
         } else {
@@ -342,16 +476,17 @@ impl Uart for Pl011 {
                 2 => self.line_control.set_bit(3,true),
                 _ => return Err(UartError::NoSupported)
             };
+            Cpu::data_memory_barrier();
             Ok(())
         }
     }
 
     /// Lese ein Wort von der UART.
     fn read(&self) -> Result<u8,UartError>{
-        let data: u32 = self.data & 0xfff;
-        if self.rx_is_empty() {
-            return Err(UartError::NoData);
-        }
+        let data: u32 = match self.read_raw() {
+            Some(data) => data,
+            None       => return Err(UartError::NoData)
+        };
         if data & (0x1 << 8) != 0 {
             return Err(UartError::FrameError);
         }
@@ -369,13 +504,10 @@ impl Uart for Pl011 {
     
     fn write(&mut self, data: u8) -> Result<u8,UartError>{
         Cpu::data_memory_barrier();
-        if self.get_state(Pl011Flag::RxFull) {
-            Err(UartError::FIFOfull)
-        } else {
-            //kprint!("{}",data as char);
-            self.data = data as u32 & 0x0f;  // nur die letzen 8 Bits sind Datenbits.
-            Cpu::data_memory_barrier();
+        if self.try_write(data) {
             Ok(data)
+        } else {
+            Err(UartError::FIFOfull)
         }
     }
 
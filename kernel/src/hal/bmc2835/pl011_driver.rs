@@ -0,0 +1,171 @@
+#![warn(missing_docs)]
+//! Interruptgesteuerter Treiber für die PL011-UART.
+//!
+//! Puffer und Lese-/Schreibfunktionen stellt der `SerialDriver`. Die Sende-FIFO wird
+//! nachgefüllt, sobald sie unter die Füllschwelle fällt.
+//!
+//! Empfangsfehler werden gezählt (`Pl011ErrorCounts`). Zeichen mit Rahmen-, Paritäts- oder
+//! Break-Fehler werden verworfen; bei einem Überlauf der FIFO ist das gelesene Zeichen gültig,
+//! aber vorher gingen Zeichen verloren.
+use alloc::vec::Vec;
+use data::ring_buffer::RingBuffer;
+use data::isr_table::IsrResult;
+use data::kernel::KernelData;
+use super::{Bmc2835, Pl011, Pl011Interrupt, Pl011Error, IrqController, BasicInterrupt};
+use super::{Uart, UartEnable, UartError, UartConfig, UartBaud, UartFlowControl};
+use super::serial_driver::{SerialDriver, SerialPort};
+use super::gpio_config::Device;
+use super::{DmaController, DmaChannelKind, DmaDreq, DmaError};
+
+/// Zähler für Empfangsfehler
+#[derive(Copy,Clone,Debug,Default,PartialEq)]
+#[repr(C)]
+pub struct Pl011ErrorCounts {
+    /// Überlauf der Empfangs-FIFO
+    pub overrun:    u32,
+    /// Break erkannt
+    pub break_cond: u32,
+    /// Paritätsfehler
+    pub parity:     u32,
+    /// Rahmenfehler
+    pub frame:      u32,
+    /// Zeichen, die wegen vollem Empfangspuffer verworfen wurden
+    pub dropped:    u32,
+}
+
+impl Pl011ErrorCounts {
+    const fn new() -> Pl011ErrorCounts {
+        Pl011ErrorCounts { overrun: 0, break_cond: 0, parity: 0, frame: 0, dropped: 0 }
+    }
+
+    /// Summe aller Fehler
+    pub fn total(&self) -> u32 {
+        self.overrun + self.break_cond + self.parity + self.frame + self.dropped
+    }
+}
+
+impl SerialPort<Pl011ErrorCounts> for Pl011 {
+    /// Zeichen mit Rahmen-, Paritäts- oder Break-Fehler werden verworfen.
+    fn receive(&mut self, rx: &mut RingBuffer, errors: &mut Pl011ErrorCounts) {
+        while let Some(data) = self.read_raw() {
+            if data & Pl011Error::Overrun.as_u32() != 0 {
+                errors.overrun += 1;
+            }
+            if data & Pl011Error::Break.as_u32() != 0 {
+                errors.break_cond += 1;
+            } else if data & Pl011Error::Frame.as_u32() != 0 {
+                errors.frame += 1;
+            } else if data & Pl011Error::Parity.as_u32() != 0 {
+                errors.parity += 1;
+            } else if !rx.push(data as u8) {
+                errors.dropped += 1;
+            }
+        }
+    }
+
+    fn tx_ready(&self) -> bool {
+        !self.tx_is_full()
+    }
+
+    fn put(&mut self, b: u8) {
+        self.try_write(b);
+    }
+
+    fn set_tx_interrupt(&mut self, enable: bool) {
+        if enable {
+            self.enable_interrupt(Pl011Interrupt::Trm);
+        } else {
+            self.disable_interrupt(Pl011Interrupt::Trm);
+        }
+    }
+
+    fn tx_done(&self) -> bool {
+        self.tx_is_empty()
+    }
+}
+
+/// Treiber für die PL011-UART
+pub type Pl011Driver = SerialDriver<Pl011, Pl011ErrorCounts>;
+
+static PL011_DRIVER: Pl011Driver = SerialDriver::new(Pl011ErrorCounts::new());
+
+impl SerialDriver<Pl011, Pl011ErrorCounts> {
+    /// Der Treiber
+    pub fn get() -> &'static Pl011Driver {
+        &PL011_DRIVER
+    }
+
+    /// Belegt die GPIO-Pins, initialisiert die UART mit der gegebenen Konfiguration, meldet
+    /// (beim ersten Aufruf) die Serviceroutine an und aktiviert den Interrupt.
+    ///
+    /// Gibt die erreichte Baudrate zurück.
+    pub fn init(&self, config: &UartConfig) -> Result<UartBaud,UartError> {
+        // Bei erneuter Initialisierung zuerst die eigenen Pins freigeben
+        self.reset();
+        let flow = config.flow_control == UartFlowControl::RtsCts;
+        let pins = config.pins.claim(flow, Device::Uart0, "UART0")?;
+        let uart = Pl011::get();
+        uart.enable(UartEnable::None);
+        uart.disable_interrupt(Pl011Interrupt::All);
+        uart.clear_interrupt(Pl011Interrupt::All);
+        let baud = uart.configure(config)?;
+        if !self.is_initialized() {
+            KernelData::isr_table().add_isr(BasicInterrupt::UART, Pl011Driver::isr, 0)
+                .map_err(|_| UartError::Failed)?;
+            IrqController::get().enable(BasicInterrupt::UART);
+        }
+        self.set_initialized(pins);
+        uart.enable_interrupt(Pl011Interrupt::Rcv);
+        uart.enable_interrupt(Pl011Interrupt::RcvTimeout);
+        uart.enable_interrupt(Pl011Interrupt::Overrun);
+        uart.enable(UartEnable::Both);
+        Ok(baud)
+    }
+
+    /// Serviceroutine
+    pub fn isr(_: usize) -> IsrResult {
+        PL011_DRIVER.handle_interrupt()
+    }
+
+    fn handle_interrupt(&self) -> IsrResult {
+        let uart = Pl011::get();
+        let rx = uart.is_interrupt_pending(Pl011Interrupt::Rcv) ||
+            uart.is_interrupt_pending(Pl011Interrupt::RcvTimeout);
+        let tx = uart.is_interrupt_pending(Pl011Interrupt::Trm);
+        let overrun = uart.is_interrupt_pending(Pl011Interrupt::Overrun);
+        if !(rx || tx || overrun) {
+            return IsrResult::NotHandled;
+        }
+        // Der Überlauf wird beim Lesen des betroffenen Zeichens gezählt.
+        if overrun {
+            uart.clear_interrupt(Pl011Interrupt::Overrun);
+        }
+        self.service(rx || overrun, tx);
+        if rx || overrun {
+            uart.clear_interrupt(Pl011Interrupt::Rcv);
+            uart.clear_interrupt(Pl011Interrupt::RcvTimeout);
+        }
+        if tx {
+            uart.clear_interrupt(Pl011Interrupt::Trm);
+        }
+        IsrResult::Handled
+    }
+
+    /// Sendet `data` per DMA, ohne Sendepuffer und Serviceroutine; blockiert bis zum Ende.
+    ///
+    /// Lohnt sich für größere Datenmengen. Vorher wird der Sendepuffer geleert.
+    pub fn write_dma(&self, data: &[u8]) -> Result<(),DmaError> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        // Jeder Schreibzugriff auf das Datenregister überträgt ein Zeichen.
+        let words: Vec<u32> = data.iter().map(|b| *b as u32).collect();
+        let mut channel = DmaController::get().allocate(DmaChannelKind::Any)?;
+        self.flush();
+        let uart = Pl011::get();
+        uart.set_dma(true, false);
+        let res = channel.write_peripheral(DmaDreq::UartTx, uart.data_address(), &words);
+        uart.set_dma(false, false);
+        res
+    }
+}
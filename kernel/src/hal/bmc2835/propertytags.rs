@@ -1,10 +1,14 @@
 #![allow(dead_code)]
 use core::mem;
+use hal::cpu::{Cache, CACHE_LINE_SIZE};
+use memory::AddressRange;
+use super::mailbox::{mailbox,Channel};
 //use debug::blink;
 //use debug::kprint::fkprint;
 
 /// Siehe https://github.com/raspberrypi/firmware/wiki/Mailbox-property-interface
 
+/// Größe des Puffers in Wörtern; ein Vielfaches einer Cachezeile
 pub const BUFFER_SIZE: usize = 1024;
 
 #[derive(Clone,Copy)]
@@ -218,8 +222,11 @@ enum TagOffset {
 }
 
 #[repr(C)]
-#[repr(align(16))]
+#[repr(align(32))]
 /// Pufferspeicher für Property-Tag-Nachrichten
+///
+/// Die Daten beginnen an einer Cachezeile (`CACHE_LINE_SIZE`) und füllen ganze Zeilen, so
+/// dass sie keine Zeile mit anderen Daten teilen.
 pub struct PropertyTagBuffer {
     data: [u32; BUFFER_SIZE],
     index:    usize,
@@ -229,6 +236,7 @@ impl PropertyTagBuffer {
 
     /// Erzeugt einen neuen Puffer.
     pub fn new() -> PropertyTagBuffer {
+        assert!(mem::size_of::<[u32; BUFFER_SIZE]>() % CACHE_LINE_SIZE == 0);
         PropertyTagBuffer{
             data: [0; BUFFER_SIZE],
             index: 2,
@@ -302,4 +310,22 @@ impl PropertyTagBuffer {
     pub fn data_addr(&self) -> usize {
         &self.data as *const _ as usize
     }
+
+    /// Adressbereich der Puffer-Daten
+    pub fn data_range(&self) -> AddressRange {
+        self.data_addr() .. self.data_addr() + mem::size_of::<[u32; BUFFER_SIZE]>()
+    }
+
+    /// Übergibt den Puffer über die Mailbox an die GPU und wartet auf die Antwort.
+    ///
+    /// Die GPU greift am Datencache vorbei auf den Puffer zu. Daher wird der Puffer vorher
+    /// zurückgeschrieben und die Cachezeilen danach nur als ungültig markiert. Ein erneutes
+    /// Zurückschreiben würde die Antwort der GPU überschreiben.
+    pub fn exchange(&mut self) {
+        Cache::clean_data_range(self.data_range());
+        let mb = mailbox(0);
+        mb.write(Channel::ATags, self.data_addr() as u32);
+        mb.read(Channel::ATags);
+        Cache::invalidate_data_lines(self.data_range());
+    }
 }
@@ -0,0 +1,255 @@
+#![allow(dead_code)]
+use core::ptr::{read_volatile, write_volatile};
+use bit_field::BitField;
+use hal::cpu::Cpu;
+use super::{CmError, DmaError, GpioError};
+
+/// Bits des CTL-Registers für Kanal 1; Kanal 2 nutzt dieselben Bits um 8 verschoben.
+const CTL_PWEN: u8 = 0;
+const CTL_MODE: u8 = 1;
+const CTL_RPTL: u8 = 2;
+const CTL_SBIT: u8 = 3;
+const CTL_POLA: u8 = 4;
+const CTL_USEF: u8 = 5;
+const CTL_CLRF: u8 = 6;
+const CTL_MSEN: u8 = 7;
+
+/// Bits des STA-Registers
+const STA_FULL: u8 = 0;
+const STA_EMPT: u8 = 1;
+const STA_WERR: u8 = 2;
+const STA_RERR: u8 = 3;
+const STA_GAPO1: u8 = 4;
+const STA_GAPO2: u8 = 5;
+const STA_BERR: u8 = 8;
+
+/// Bit ENAB des DMAC-Registers
+const DMAC_ENAB: u8 = 31;
+
+/// Tiefe der FIFO in Wörtern
+pub const PWM_FIFO_SIZE: usize = 8;
+
+/// Kanal des Pulsweitenmodulators
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PwmChannel {
+    /// Kanal 1 (PWM0, z.B. Pin 12, 18 oder 40)
+    Ch1,
+    /// Kanal 2 (PWM1, z.B. Pin 13, 19 oder 45)
+    Ch2,
+}
+
+impl PwmChannel {
+    fn shift(&self) -> u8 {
+        match *self {
+            PwmChannel::Ch1 => 0,
+            PwmChannel::Ch2 => 8,
+        }
+    }
+}
+
+/// Art der Modulation
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PwmMode {
+    /// Die Einsen werden möglichst gleichmäßig über die Periode verteilt (Standard der
+    /// Hardware); gut für Audio und als D/A-Wandler mit Tiefpass.
+    Balanced,
+    /// Klassische Pulsweite: `data` Takte hoch, dann `range - data` Takte tief; nötig z.B.
+    /// für Servos.
+    MarkSpace,
+}
+
+/// Fehler des Pulsweitenmodulators
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PwmError {
+    /// Der Treiber ist nicht initialisiert
+    NotInitialized,
+    /// Der Kanal ist nicht eingeschaltet
+    NotEnabled,
+    /// Ungültiger Parameter (z.B. Bereich 0 oder Tastgrad größer als der Bereich)
+    Invalid,
+    /// Die Ausgabe läuft bereits
+    Busy,
+    /// Der Takt kann nicht eingestellt werden
+    Clock(CmError),
+    /// Die DMA-Übertragung ist fehlgeschlagen
+    Dma(DmaError),
+    /// Die GPIO-Pins können nicht belegt werden
+    Gpio(GpioError),
+}
+
+/// Pulsweitenmodulator
+///
+/// Vgl. BMC2835 Manual, S. 138ff.
+#[repr(C)]
+pub struct Pwm {
+    /// Steuerung
+    ctl:        u32,    // Offset 0x00
+    /// Status
+    sta:        u32,    // Offset 0x04
+    /// DMA-Steuerung
+    dmac:       u32,    // Offset 0x08
+    _reserved0: u32,
+    /// Bereich (Periode) Kanal 1
+    rng1:       u32,    // Offset 0x10
+    /// Daten Kanal 1
+    dat1:       u32,    // Offset 0x14
+    /// FIFO (für beide Kanäle)
+    fif1:       u32,    // Offset 0x18
+    _reserved1: u32,
+    /// Bereich (Periode) Kanal 2
+    rng2:       u32,    // Offset 0x20
+    /// Daten Kanal 2
+    dat2:       u32,    // Offset 0x24
+}
+
+use super::Bmc2835;
+impl Bmc2835 for Pwm {
+
+    fn base_offset() -> usize {
+        0x20C000
+    }
+}
+
+impl Pwm {
+    fn read_ctl(&self) -> u32 {
+        Cpu::data_memory_barrier();
+        unsafe{ read_volatile(&self.ctl) }
+    }
+
+    fn write_ctl(&mut self, value: u32) {
+        Cpu::data_memory_barrier();
+        unsafe{ write_volatile(&mut self.ctl, value); }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Schaltet alle Kanäle ab und löscht FIFO und Statusbits.
+    pub fn reset(&mut self) {
+        self.write_ctl(0);
+        self.clear_fifo();
+        self.clear_status();
+        self.set_dma(false, 0, 0);
+    }
+
+    /// Stellt einen Kanal ein: Modulationsart, FIFO statt Datenregister, Polarität. Der
+    /// Kanal bleibt dabei ausgeschaltet.
+    pub fn configure(&mut self, channel: PwmChannel, mode: PwmMode, use_fifo: bool, invert: bool) {
+        let shift = channel.shift();
+        let mut ctl = self.read_ctl();
+        ctl.set_bit(shift + CTL_PWEN, false);
+        ctl.set_bit(shift + CTL_MODE, false);
+        ctl.set_bit(shift + CTL_RPTL, false);
+        ctl.set_bit(shift + CTL_SBIT, false);
+        ctl.set_bit(shift + CTL_POLA, invert);
+        ctl.set_bit(shift + CTL_USEF, use_fifo);
+        ctl.set_bit(shift + CTL_MSEN, mode == PwmMode::MarkSpace);
+        // CLRF gibt es nur einmal (Bit 6) und wird hier nicht gesetzt.
+        ctl.set_bit(CTL_CLRF, false);
+        self.write_ctl(ctl);
+    }
+
+    /// Schaltet einen Kanal ein oder aus.
+    pub fn enable(&mut self, channel: PwmChannel, enable: bool) {
+        let mut ctl = self.read_ctl();
+        ctl.set_bit(channel.shift() + CTL_PWEN, enable);
+        ctl.set_bit(CTL_CLRF, false);
+        self.write_ctl(ctl);
+    }
+
+    /// Ist der Kanal eingeschaltet?
+    pub fn is_enabled(&self, channel: PwmChannel) -> bool {
+        self.read_ctl().get_bit(channel.shift() + CTL_PWEN)
+    }
+
+    /// Wiederholt bei leerer FIFO das letzte Wort, statt den Ausgang abzuschalten.
+    pub fn set_repeat_last(&mut self, channel: PwmChannel, repeat: bool) {
+        let mut ctl = self.read_ctl();
+        ctl.set_bit(channel.shift() + CTL_RPTL, repeat);
+        ctl.set_bit(CTL_CLRF, false);
+        self.write_ctl(ctl);
+    }
+
+    /// Setzt den Bereich (Anzahl der Takte einer Periode).
+    pub fn set_range(&mut self, channel: PwmChannel, range: u32) {
+        Cpu::data_memory_barrier();
+        match channel {
+            PwmChannel::Ch1 => unsafe{ write_volatile(&mut self.rng1, range) },
+            PwmChannel::Ch2 => unsafe{ write_volatile(&mut self.rng2, range) },
+        }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Setzt die Daten (Anzahl der Einsen je Periode), wenn der Kanal nicht die FIFO nutzt.
+    pub fn set_data(&mut self, channel: PwmChannel, data: u32) {
+        Cpu::data_memory_barrier();
+        match channel {
+            PwmChannel::Ch1 => unsafe{ write_volatile(&mut self.dat1, data) },
+            PwmChannel::Ch2 => unsafe{ write_volatile(&mut self.dat2, data) },
+        }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Leert die FIFO.
+    pub fn clear_fifo(&mut self) {
+        let mut ctl = self.read_ctl();
+        ctl.set_bit(CTL_CLRF, true);
+        self.write_ctl(ctl);
+    }
+
+    /// Schreibt ein Wort in die FIFO. Nutzen beide Kanäle die FIFO, werden die Wörter
+    /// abwechselnd auf Kanal 1 und 2 ausgegeben.
+    pub fn write_fifo(&mut self, data: u32) {
+        Cpu::data_memory_barrier();
+        unsafe{ write_volatile(&mut self.fif1, data); }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Adresse der FIFO (für DMA)
+    pub fn fifo_address(&self) -> usize {
+        &self.fif1 as *const u32 as usize
+    }
+
+    fn read_status(&self) -> u32 {
+        Cpu::data_memory_barrier();
+        unsafe{ read_volatile(&self.sta) }
+    }
+
+    /// Ist die FIFO voll?
+    pub fn fifo_full(&self) -> bool {
+        self.read_status().get_bit(STA_FULL)
+    }
+
+    /// Ist die FIFO leer?
+    pub fn fifo_empty(&self) -> bool {
+        self.read_status().get_bit(STA_EMPT)
+    }
+
+    /// Ist ein Fehler aufgetreten (Schreiben in volle, Lesen aus leerer FIFO, Busfehler)?
+    pub fn has_error(&self) -> bool {
+        let sta = self.read_status();
+        sta.get_bit(STA_WERR) || sta.get_bit(STA_RERR) || sta.get_bit(STA_BERR)
+    }
+
+    /// Löscht die Fehler- und Lückenbits (durch Schreiben von Einsen).
+    pub fn clear_status(&mut self) {
+        let mut sta: u32 = 0;
+        sta.set_bit(STA_WERR, true);
+        sta.set_bit(STA_RERR, true);
+        sta.set_bit(STA_GAPO1, true);
+        sta.set_bit(STA_GAPO2, true);
+        sta.set_bit(STA_BERR, true);
+        Cpu::data_memory_barrier();
+        unsafe{ write_volatile(&mut self.sta, sta); }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Schaltet DREQ ein oder aus; `dreq` und `panic` sind die Schwellwerte der FIFO.
+    pub fn set_dma(&mut self, enable: bool, dreq: u8, panic: u8) {
+        let mut reg: u32 = 0;
+        reg.set_bits(0..8, dreq as u32);
+        reg.set_bits(8..16, panic as u32);
+        reg.set_bit(DMAC_ENAB, enable);
+        Cpu::data_memory_barrier();
+        unsafe{ write_volatile(&mut self.dmac, reg); }
+        Cpu::data_memory_barrier();
+    }
+}
@@ -0,0 +1,268 @@
+#![warn(missing_docs)]
+//! Treiber für den Pulsweitenmodulator.
+//!
+//! Beide Kanäle teilen sich den PWM-Takt des Taktgenerators, der mit `init` eingestellt
+//! wird. Ein Kanal zählt `range` Takte je Periode; davon ist der Ausgang `data` Takte hoch.
+//! Mit `enable`, `set_duty` und `disable` wird ein Kanal direkt über sein Datenregister
+//! betrieben, z.B. für LEDs oder Servos (`PwmMode::MarkSpace`).
+//!
+//! `play` gibt PCM-Daten über beide Kanäle an der Audiobuchse des Raspberry Pi aus (Pins
+//! 40 und 45). Die Kanäle lesen dann abwechselnd aus der gemeinsamen FIFO, die ein
+//! DMA-Kanal nachfüllt; `range` ergibt sich aus PWM-Takt und Abtastrate. Die Ausgabe
+//! blockiert bis zum Ende und braucht freigegebene Interrupts.
+use alloc::vec::Vec;
+use sync::IrqSpinLock;
+use super::{Bmc2835, Pwm, PwmChannel, PwmMode, PwmError, ClockManager, CmClock, CmSource, CmError};
+use super::{GpioPins, GpioPin, SystemTimer, DmaController, DmaChannelKind, DmaDreq};
+
+/// Pins der Audiobuchse (links, rechts)
+pub const PWM_AUDIO_PINS: (u8,u8) = (40, 45);
+
+/// Abtastrate von `tone`
+const PWM_TONE_RATE: u32 = 22050;
+
+/// Höchste Wartezeit auf das Leeren der FIFO nach einer Ausgabe in µs
+const PWM_DRAIN_TIMEOUT: u32 = 10000;
+
+/// Eingeschalteter Kanal
+struct PwmChannelState {
+    range: u32,
+    _pin:  Option<GpioPin>,
+}
+
+struct PwmState {
+    initialized: bool,
+    clock_hz:    u32,
+    playing:     bool,
+    channels:    [Option<PwmChannelState>; 2],
+}
+
+/// Treiber für den Pulsweitenmodulator
+pub struct PwmDriver {
+    state: IrqSpinLock<PwmState>,
+}
+
+static PWM_DRIVER: PwmDriver = PwmDriver::new();
+
+fn index(channel: PwmChannel) -> usize {
+    match channel {
+        PwmChannel::Ch1 => 0,
+        PwmChannel::Ch2 => 1,
+    }
+}
+
+impl PwmDriver {
+    const fn new() -> PwmDriver {
+        PwmDriver {
+            state: IrqSpinLock::new(PwmState {
+                initialized: false,
+                clock_hz:    0,
+                playing:     false,
+                channels:    [None, None],
+            }),
+        }
+    }
+
+    /// Der Treiber
+    pub fn get() -> &'static PwmDriver {
+        &PWM_DRIVER
+    }
+
+    /// Schaltet alle Kanäle ab und stellt den PWM-Takt auf höchstens `clock_hz` ein.
+    /// Gibt die erreichte Rate zurück.
+    ///
+    /// Der Takt wird aus PLLD (500 MHz) abgeleitet, für Raten unter 122 kHz aus dem
+    /// Oszillator (19,2 MHz).
+    pub fn init(&self, clock_hz: u32) -> Result<u32,PwmError> {
+        if self.state.lock().playing {
+            return Err(PwmError::Busy);
+        }
+        Pwm::get().reset();
+        {
+            let mut state = self.state.lock();
+            state.initialized = false;
+            state.channels = [None, None];
+        }
+        let cm = ClockManager::get();
+        let actual = match cm.set_rate(CmClock::Pwm, CmSource::PllD, clock_hz) {
+            Err(CmError::InvalidRate) => cm.set_rate(CmClock::Pwm, CmSource::Oscillator, clock_hz),
+            res                       => res,
+        }.map_err(PwmError::Clock)?;
+        let mut state = self.state.lock();
+        state.initialized = true;
+        state.clock_hz = actual;
+        Ok(actual)
+    }
+
+    /// Eingestellter PWM-Takt in Hz (0, falls nicht initialisiert)
+    pub fn clock_rate(&self) -> u32 {
+        self.state.lock().clock_hz
+    }
+
+    /// Schaltet `channel` mit `range` Takten je Periode und Tastgrad 0 ein. Ist `pin`
+    /// angegeben, wird er für den Kanal belegt (PWM0 bzw. PWM1).
+    pub fn enable(&self, channel: PwmChannel, pin: Option<u8>, mode: PwmMode, range: u32)
+                  -> Result<(),PwmError> {
+        use super::gpio_config::Device;
+
+        if range == 0 {
+            return Err(PwmError::Invalid);
+        }
+        {
+            let mut state = self.state.lock();
+            if !state.initialized {
+                return Err(PwmError::NotInitialized);
+            }
+            if state.playing {
+                return Err(PwmError::Busy);
+            }
+            // Eine bestehende Belegung des Kanals aufheben
+            state.channels[index(channel)] = None;
+        }
+        let pin = match pin {
+            Some(pin) => {
+                let device = match channel {
+                    PwmChannel::Ch1 => Device::Pwm0,
+                    PwmChannel::Ch2 => Device::Pwm1,
+                };
+                Some(GpioPins::get().claim(pin, device, "PWM").map_err(PwmError::Gpio)?)
+            },
+            None => None,
+        };
+        let pwm = Pwm::get();
+        pwm.enable(channel, false);
+        pwm.configure(channel, mode, false, false);
+        pwm.set_range(channel, range);
+        pwm.set_data(channel, 0);
+        pwm.enable(channel, true);
+        self.state.lock().channels[index(channel)] = Some(PwmChannelState {
+            range: range,
+            _pin:  pin,
+        });
+        Ok(())
+    }
+
+    /// Setzt die Anzahl der Takte je Periode, in denen der Ausgang hoch ist (höchstens
+    /// `range`).
+    pub fn set_duty(&self, channel: PwmChannel, data: u32) -> Result<(),PwmError> {
+        let state = self.state.lock();
+        match state.channels[index(channel)] {
+            Some(ref ch) if data <= ch.range => {
+                Pwm::get().set_data(channel, data);
+                Ok(())
+            },
+            Some(_) => Err(PwmError::Invalid),
+            None    => Err(PwmError::NotEnabled),
+        }
+    }
+
+    /// Setzt den Tastgrad in Promille.
+    pub fn set_duty_permille(&self, channel: PwmChannel, permille: u32) -> Result<(),PwmError> {
+        if permille > 1000 {
+            return Err(PwmError::Invalid);
+        }
+        let range = match self.state.lock().channels[index(channel)] {
+            Some(ref ch) => ch.range,
+            None         => return Err(PwmError::NotEnabled),
+        };
+        self.set_duty(channel, (range as u64 * permille as u64 / 1000) as u32)
+    }
+
+    /// Schaltet `channel` ab und gibt seinen Pin frei.
+    pub fn disable(&self, channel: PwmChannel) {
+        Pwm::get().enable(channel, false);
+        self.state.lock().channels[index(channel)] = None;
+    }
+
+    /// Gibt PCM-Daten (16 Bit mit Vorzeichen) an der Audiobuchse aus. Bei `stereo` sind die
+    /// Abtastwerte abwechselnd links und rechts, sonst wird jeder Wert auf beiden Kanälen
+    /// ausgegeben. Kehrt nach dem Ende der Ausgabe zurück.
+    ///
+    /// Die Auflösung ist PWM-Takt / Abtastrate Stufen, z.B. gut 10 Bit bei 50 MHz und
+    /// 44,1 kHz. Keiner der Kanäle darf gerade mit `enable` genutzt werden.
+    pub fn play(&self, samples: &[i16], sample_rate: u32, stereo: bool) -> Result<(),PwmError> {
+        if sample_rate == 0 || (stereo && samples.len() % 2 != 0) {
+            return Err(PwmError::Invalid);
+        }
+        let range = {
+            let mut state = self.state.lock();
+            if !state.initialized {
+                return Err(PwmError::NotInitialized);
+            }
+            if state.playing || state.channels.iter().any(|ch| ch.is_some()) {
+                return Err(PwmError::Busy);
+            }
+            let range = state.clock_hz / sample_rate;
+            if range < 2 {
+                return Err(PwmError::Invalid);
+            }
+            state.playing = true;
+            range
+        };
+        let res = self.stream(samples, range, stereo);
+        self.state.lock().playing = false;
+        res
+    }
+
+    fn stream(&self, samples: &[i16], range: u32, stereo: bool) -> Result<(),PwmError> {
+        use super::gpio_config::Device;
+
+        // Je Kanal und Abtastwert ein Wort im Bereich 0 .. range; die Kanäle lesen die FIFO
+        // abwechselnd.
+        let mut words = Vec::with_capacity(if stereo { samples.len() } else { samples.len() * 2 });
+        for s in samples {
+            let value = ((*s as i32 + 32768) as u64 * range as u64 >> 16) as u32;
+            words.push(value);
+            if !stereo {
+                words.push(value);
+            }
+        }
+        if words.is_empty() {
+            return Ok(());
+        }
+        let mut channel = DmaController::get().allocate(DmaChannelKind::Any).map_err(PwmError::Dma)?;
+        let _pins = GpioPins::get().claim_all(&[(PWM_AUDIO_PINS.0, Device::Pwm0),
+                                                (PWM_AUDIO_PINS.1, Device::Pwm1)], "AUDIO")
+            .map_err(PwmError::Gpio)?;
+
+        let pwm = Pwm::get();
+        for &ch in &[PwmChannel::Ch1, PwmChannel::Ch2] {
+            pwm.configure(ch, PwmMode::Balanced, true, false);
+            pwm.set_range(ch, range);
+        }
+        pwm.clear_fifo();
+        pwm.clear_status();
+        pwm.set_dma(true, 7, 7);
+        pwm.enable(PwmChannel::Ch1, true);
+        pwm.enable(PwmChannel::Ch2, true);
+        let res = channel.write_peripheral(DmaDreq::Pwm, pwm.fifo_address(), &words);
+
+        // Die letzten Wörter in der FIFO noch ausgeben
+        let timer = SystemTimer::get();
+        let start = timer.get_counter();
+        while !pwm.fifo_empty() && timer.get_counter().wrapping_sub(start) < PWM_DRAIN_TIMEOUT {}
+        pwm.set_dma(false, 0, 0);
+        pwm.enable(PwmChannel::Ch1, false);
+        pwm.enable(PwmChannel::Ch2, false);
+        pwm.clear_fifo();
+        pwm.clear_status();
+        res.map_err(PwmError::Dma)
+    }
+
+    /// Gibt einen Rechteckton mit `frequency` Hz für `duration_ms` Millisekunden an der
+    /// Audiobuchse aus; `volume` reicht von 0 bis 255.
+    pub fn tone(&self, frequency: u32, duration_ms: u32, volume: u8) -> Result<(),PwmError> {
+        if frequency == 0 || frequency >= PWM_TONE_RATE / 2 {
+            return Err(PwmError::Invalid);
+        }
+        let count = (PWM_TONE_RATE as u64 * duration_ms as u64 / 1000) as usize;
+        let amplitude = (volume as i32 * 127) as i16;
+        let mut samples = Vec::with_capacity(count);
+        for i in 0..count as u64 {
+            // Halbperiode, in der der Abtastwert liegt
+            let half = i * 2 * frequency as u64 / PWM_TONE_RATE as u64;
+            samples.push(if half % 2 == 0 { amplitude } else { -amplitude });
+        }
+        self.play(&samples, PWM_TONE_RATE, false)
+    }
+}
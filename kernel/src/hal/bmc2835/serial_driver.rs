@@ -0,0 +1,176 @@
+#![warn(missing_docs)]
+//! Gemeinsamer Kern der interruptgesteuerten UART-Treiber.
+//!
+//! Empfangene Zeichen legt die Serviceroutine in einen Empfangspuffer, zu sendende Zeichen
+//! werden in einen Sendepuffer geschrieben und von der Serviceroutine in die Sende-FIFO
+//! übertragen. Der Sendeinterrupt ist nur aktiv, solange der Sendepuffer Daten enthält.
+//!
+//! Die Geräte unterscheiden sich nur im Zugriff auf ihre FIFOs (`SerialPort`) und in den
+//! gezählten Empfangsfehlern `E`. Initialisierung und Serviceroutine liegen bei den
+//! einzelnen Treibern, siehe `Pl011Driver` und `MiniUartDriver`.
+use core::marker::PhantomData;
+use alloc::vec::Vec;
+use hal::cpu::Cpu;
+use data::ring_buffer::RingBuffer;
+use sync::{IrqSpinLock, WaitQueue};
+use super::{Bmc2835, GpioPin};
+
+/// FIFO-Zugriff einer UART für den `SerialDriver`
+pub trait SerialPort<E>: Bmc2835 {
+    /// Leert die Empfangs-FIFO in `rx` und zählt dabei Fehler und verworfene Zeichen.
+    fn receive(&mut self, rx: &mut RingBuffer, errors: &mut E);
+    /// Die Sende-FIFO kann ein weiteres Zeichen aufnehmen.
+    fn tx_ready(&self) -> bool;
+    /// Schreibt ein Zeichen in die Sende-FIFO.
+    fn put(&mut self, b: u8);
+    /// Schaltet den Sendeinterrupt ein oder aus.
+    fn set_tx_interrupt(&mut self, enable: bool);
+    /// Sende-FIFO und Schieberegister sind leer.
+    fn tx_done(&self) -> bool;
+}
+
+pub(super) struct SerialState<E> {
+    initialized:       bool,
+    pub(super) rx:     RingBuffer,
+    pub(super) tx:     RingBuffer,
+    pub(super) errors: E,
+    pub(super) pins:   Option<Vec<GpioPin>>,
+}
+
+/// Gepufferte serielle Schnittstelle über der UART `P` mit den Fehlerzählern `E`
+pub struct SerialDriver<P, E> {
+    pub(super) state:    IrqSpinLock<SerialState<E>>,
+    pub(super) rx_queue: WaitQueue,
+    pub(super) tx_queue: WaitQueue,
+    port:                PhantomData<P>,
+}
+
+impl<P, E> SerialDriver<P, E> {
+    pub(super) const fn new(errors: E) -> SerialDriver<P, E> {
+        SerialDriver {
+            state:    IrqSpinLock::new(SerialState {
+                initialized: false,
+                rx:          RingBuffer::new(),
+                tx:          RingBuffer::new(),
+                errors:      errors,
+                pins:        None,
+            }),
+            rx_queue: WaitQueue::new(),
+            tx_queue: WaitQueue::new(),
+            port:     PhantomData,
+        }
+    }
+}
+
+impl<P: SerialPort<E>, E: Copy> SerialDriver<P, E> {
+    /// Gibt die eigenen Pins frei und leert beide Puffer; vor einer (erneuten)
+    /// Initialisierung.
+    pub(super) fn reset(&self) {
+        let mut state = self.state.lock();
+        state.pins = None;
+        state.rx.clear();
+        state.tx.clear();
+    }
+
+    /// Wurde die Serviceroutine bereits angemeldet?
+    pub(super) fn is_initialized(&self) -> bool {
+        self.state.lock().initialized
+    }
+
+    /// Übernimmt die belegten Pins; die Serviceroutine ist angemeldet.
+    pub(super) fn set_initialized(&self, pins: Vec<GpioPin>) {
+        let mut state = self.state.lock();
+        state.initialized = true;
+        state.pins = Some(pins);
+    }
+
+    /// Teil der Serviceroutine: leert die Empfangs-FIFO (`rx`) bzw. füllt die Sende-FIFO
+    /// nach (`tx`) und weckt die Wartenden.
+    pub(super) fn service(&self, rx: bool, tx: bool) {
+        {
+            let mut state = self.state.lock();
+            let state = &mut *state;
+            let port = P::get();
+            if rx {
+                port.receive(&mut state.rx, &mut state.errors);
+            }
+            if tx {
+                SerialDriver::<P, E>::transmit(port, &mut state.tx);
+            }
+        }
+        if rx {
+            self.rx_queue.wake_all();
+        }
+        if tx {
+            self.tx_queue.wake_all();
+        }
+    }
+
+    /// Füllt die Sende-FIFO aus dem Sendepuffer; schaltet den Sendeinterrupt ab, wenn der
+    /// Puffer leer ist.
+    fn transmit(port: &mut P, tx: &mut RingBuffer) {
+        while port.tx_ready() {
+            match tx.pop() {
+                Some(b) => port.put(b),
+                None    => break
+            }
+        }
+        port.set_tx_interrupt(!tx.is_empty());
+    }
+
+    /// Liest die bereits empfangenen Zeichen (höchstens `buf.len()`) und gibt ihre Anzahl
+    /// zurück. Blockiert nicht.
+    pub fn read(&self, buf: &mut [u8]) -> usize {
+        self.state.lock().rx.pop_slice(buf)
+    }
+
+    /// Liest wie `read`, blockiert aber, bis mindestens ein Zeichen empfangen wurde.
+    pub fn read_blocking(&self, buf: &mut [u8]) -> usize {
+        if buf.is_empty() {
+            return 0;
+        }
+        let mut n = 0;
+        self.rx_queue.wait_until(|| {
+            n = self.read(buf);
+            n > 0
+        });
+        n
+    }
+
+    /// Schreibt so viele Zeichen aus `data` in den Sendepuffer, wie Platz ist, und gibt ihre
+    /// Anzahl zurück. Blockiert nicht.
+    pub fn write(&self, data: &[u8]) -> usize {
+        let mut state = self.state.lock();
+        let n = state.tx.push_slice(data);
+        // Die Übertragung wird angestoßen, der Rest folgt im Sendeinterrupt.
+        SerialDriver::<P, E>::transmit(P::get(), &mut state.tx);
+        n
+    }
+
+    /// Schreibt alle Zeichen aus `data`; blockiert, solange der Sendepuffer voll ist.
+    pub fn write_blocking(&self, data: &[u8]) {
+        let mut written = 0;
+        self.tx_queue.wait_until(|| {
+            written += self.write(&data[written..]);
+            written == data.len()
+        });
+    }
+
+    /// Schreibt eine Zeichenkette, siehe `write_blocking`.
+    pub fn write_str(&self, s: &str) {
+        self.write_blocking(s.as_bytes());
+    }
+
+    /// Wartet, bis alle Zeichen gesendet wurden.
+    pub fn flush(&self) {
+        self.tx_queue.wait_until(|| self.state.lock().tx.is_empty());
+        while !P::get().tx_done() {
+            Cpu::data_memory_barrier();
+        }
+    }
+
+    /// Bisherige Empfangsfehler
+    pub fn errors(&self) -> E {
+        self.state.lock().errors
+    }
+}
@@ -0,0 +1,235 @@
+#![warn(missing_docs)]
+//! Gemeinsame Schnittstelle der SPI-Master (SPI0 und die AUX-Geräte SPI1, SPI2).
+//!
+//! Ein Bus (`SpiBus`) führt Übertragungen mit einem seiner Slaves aus; welcher Slave gemeint
+//! ist, bestimmt die Nummer der Chip-Select-Leitung. Treiber für Peripheriegeräte arbeiten
+//! dagegen mit einem `SpiDevice`, das Bus, Chip-Select und Einstellungen zusammenfasst
+//! (`SpiChip`). Damit muss ein Gerätetreiber nicht wissen, an welchem Bus das Gerät hängt.
+//!
+//! SPI überträgt immer in beide Richtungen gleichzeitig. Eine Übertragung ist so lang wie der
+//! längere der beiden Puffer; fehlende Sendedaten werden als 0 gesendet, überzählige
+//! Empfangsdaten verworfen.
+use super::{DmaError, GpioError};
+
+/// SPI-Modus (Taktpolarität CPOL und Taktphase CPHA)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SpiMode {
+    /// CPOL = 0, CPHA = 0
+    Mode0,
+    /// CPOL = 0, CPHA = 1
+    Mode1,
+    /// CPOL = 1, CPHA = 0
+    Mode2,
+    /// CPOL = 1, CPHA = 1
+    Mode3,
+}
+
+impl SpiMode {
+    /// Takt im Ruhezustand high?
+    pub fn cpol(&self) -> bool {
+        *self == SpiMode::Mode2 || *self == SpiMode::Mode3
+    }
+
+    /// Daten mit der zweiten Taktflanke übernehmen?
+    pub fn cpha(&self) -> bool {
+        *self == SpiMode::Mode1 || *self == SpiMode::Mode3
+    }
+}
+
+/// Art der Übertragung
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SpiTransfer {
+    /// Die CPU wartet aktiv auf die FIFOs.
+    Polled,
+    /// Die FIFOs werden in der Serviceroutine bedient, der Aufrufer wartet blockierend.
+    Interrupt,
+    /// Die Daten werden per DMA übertragen, der Aufrufer wartet blockierend (nur SPI0).
+    Dma,
+}
+
+/// Einstellungen für die Übertragung mit einem Slave
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SpiSettings {
+    /// Gewünschte Taktrate in Hz; es wird die nächste nicht größere Rate eingestellt.
+    pub clock_hz:       u32,
+    /// Modus
+    pub mode:           SpiMode,
+    /// Chip-Select ist aktiv high (statt low)
+    pub cs_active_high: bool,
+    /// Art der Übertragung
+    pub transfer:       SpiTransfer,
+}
+
+impl SpiSettings {
+    /// Mode 0, Chip-Select aktiv low, Übertragung mit Polling
+    pub const fn new(clock_hz: u32) -> SpiSettings {
+        SpiSettings {
+            clock_hz:       clock_hz,
+            mode:           SpiMode::Mode0,
+            cs_active_high: false,
+            transfer:       SpiTransfer::Polled,
+        }
+    }
+}
+
+/// Fehler bei SPI-Übertragungen
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SpiError {
+    /// Ungültige Chip-Select-Nummer
+    InvalidChipSelect,
+    /// Die Taktrate kann nicht eingestellt werden
+    InvalidClock,
+    /// Der Bus ist nicht initialisiert
+    NotInitialized,
+    /// Der Bus führt bereits eine Übertragung aus
+    Busy,
+    /// Die Übertragungsart wird vom Bus nicht unterstützt
+    NotSupported,
+    /// Die Serviceroutine konnte nicht angemeldet werden
+    Failed,
+    /// Fehler bei der DMA-Übertragung
+    Dma(DmaError),
+    /// Die GPIO-Pins können nicht belegt werden
+    Gpio(GpioError),
+}
+
+/// SPI-Master
+pub trait SpiBus {
+    /// Überträgt mit dem Slave an Chip-Select `cs` gleichzeitig `tx` und `rx`.
+    ///
+    /// Chip-Select ist während der gesamten Übertragung aktiv.
+    fn transfer(&self, cs: u8, settings: &SpiSettings, tx: &[u8], rx: &mut [u8]) -> Result<(),SpiError>;
+
+    /// Die Taktrate, die für `hz` tatsächlich eingestellt würde
+    fn effective_clock(&self, hz: u32) -> Result<u32,SpiError>;
+}
+
+/// Ein Slave an einem SPI-Bus, Grundlage für Gerätetreiber
+pub trait SpiDevice {
+    /// Überträgt gleichzeitig `tx` und `rx`, siehe `SpiBus::transfer`.
+    fn transfer(&mut self, tx: &[u8], rx: &mut [u8]) -> Result<(),SpiError>;
+
+    /// Sendet `tx`, empfangene Daten werden verworfen.
+    fn write(&mut self, tx: &[u8]) -> Result<(),SpiError> {
+        self.transfer(tx, &mut [])
+    }
+
+    /// Empfängt `rx.len()` Bytes und sendet dabei Nullen.
+    fn read(&mut self, rx: &mut [u8]) -> Result<(),SpiError> {
+        self.transfer(&[], rx)
+    }
+}
+
+/// Slave an einem Bus mit festem Chip-Select und festen Einstellungen
+pub struct SpiChip<'a, B: SpiBus + 'a> {
+    bus:      &'a B,
+    cs:       u8,
+    settings: SpiSettings,
+}
+
+impl<'a, B: SpiBus> SpiChip<'a, B> {
+    /// Slave an Chip-Select `cs` von `bus`
+    pub fn new(bus: &'a B, cs: u8, settings: SpiSettings) -> SpiChip<'a, B> {
+        SpiChip {
+            bus:      bus,
+            cs:       cs,
+            settings: settings,
+        }
+    }
+
+    /// Einstellungen
+    pub fn settings(&self) -> &SpiSettings {
+        &self.settings
+    }
+
+    /// Ändert die Einstellungen (z.B. höhere Taktrate nach der Initialisierung einer SD-Karte).
+    pub fn set_settings(&mut self, settings: SpiSettings) {
+        self.settings = settings;
+    }
+}
+
+impl<'a, B: SpiBus> SpiDevice for SpiChip<'a, B> {
+    fn transfer(&mut self, tx: &[u8], rx: &mut [u8]) -> Result<(),SpiError> {
+        self.bus.transfer(self.cs, &self.settings, tx, rx)
+    }
+}
+
+/// Zustand einer laufenden Übertragung, gemeinsam für Polling und Serviceroutine.
+///
+/// Die Puffer werden als rohe Zeiger gehalten, da die Übertragung in der Serviceroutine
+/// weiterläuft. Der Aufrufer wartet, bis sie beendet ist, die Puffer bleiben also gültig.
+pub(super) struct SpiTransferState {
+    tx:       *const u8,
+    tx_len:   usize,
+    rx:       *mut u8,
+    rx_len:   usize,
+    len:      usize,
+    sent:     usize,
+    received: usize,
+}
+
+unsafe impl Send for SpiTransferState {}
+
+impl SpiTransferState {
+    /// Übertragung von `tx` und `rx`
+    ///
+    /// # Sicherheit
+    /// Die Übertragung hält nur Zeiger auf die Puffer. Sie darf nicht länger leben als `tx`
+    /// und `rx`, und `rx` darf währenddessen nicht anderweitig genutzt werden.
+    pub(super) unsafe fn new(tx: &[u8], rx: &mut [u8]) -> SpiTransferState {
+        SpiTransferState {
+            tx:       tx.as_ptr(),
+            tx_len:   tx.len(),
+            rx:       rx.as_mut_ptr(),
+            rx_len:   rx.len(),
+            len:      ::core::cmp::max(tx.len(), rx.len()),
+            sent:     0,
+            received: 0,
+        }
+    }
+
+    /// Gesamtlänge in Bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Nächstes zu sendendes Byte, `None`, wenn alles gesendet wurde.
+    pub fn next_tx(&mut self) -> Option<u8> {
+        if self.sent < self.len {
+            let b = if self.sent < self.tx_len { unsafe{ *self.tx.offset(self.sent as isize) } } else { 0 };
+            self.sent += 1;
+            Some(b)
+        } else {
+            None
+        }
+    }
+
+    /// Sind noch Bytes zu senden?
+    pub fn tx_pending(&self) -> bool {
+        self.sent < self.len
+    }
+
+    /// Speichert ein empfangenes Byte.
+    pub fn store_rx(&mut self, b: u8) {
+        if self.received < self.rx_len {
+            unsafe{ *self.rx.offset(self.received as isize) = b; }
+        }
+        self.received += 1;
+    }
+
+    /// Gesendete, aber noch nicht empfangene Bytes; darf die Tiefe der Empfangs-FIFO nicht
+    /// überschreiten.
+    pub fn in_flight(&self) -> usize {
+        self.sent - self.received
+    }
+
+    /// Wurden noch nicht alle Bytes empfangen?
+    pub fn rx_pending(&self) -> bool {
+        self.received < self.len
+    }
+
+    /// Ist die Übertragung abgeschlossen?
+    pub fn is_done(&self) -> bool {
+        self.received >= self.len
+    }
+}
@@ -0,0 +1,199 @@
+#![allow(dead_code)]
+use core::ptr::{read_volatile, write_volatile};
+use bit_field::BitField;
+use hal::cpu::Cpu;
+use super::{SpiMode, SpiError};
+
+/// Bits des CS-Registers
+const CS_CPHA:   u8 = 2;
+const CS_CPOL:   u8 = 3;
+const CS_CSPOL:  u8 = 6;
+const CS_TA:     u8 = 7;
+const CS_DMAEN:  u8 = 8;
+const CS_INTD:   u8 = 9;
+const CS_INTR:   u8 = 10;
+const CS_ADCS:   u8 = 11;
+const CS_DONE:   u8 = 16;
+const CS_RXD:    u8 = 17;
+const CS_TXD:    u8 = 18;
+const CS_RXR:    u8 = 19;
+const CS_RXF:    u8 = 20;
+const CS_CSPOL0: u8 = 21;
+
+/// Anzahl der Chip-Select-Leitungen
+pub const SPI0_NUM_CS: u8 = 3;
+
+/// SPI-Master 0
+///
+/// Vgl. BMC2835 Manual, S. 148ff.
+#[repr(C)]
+pub struct Spi0 {
+    /// Steuerung und Status
+    cs:    u32,    // Offset 0x00
+    /// Sende- und Empfangs-FIFO
+    fifo:  u32,    // Offset 0x04
+    /// Taktteiler
+    clk:   u32,    // Offset 0x08
+    /// Datenlänge (nur DMA)
+    dlen:  u32,    // Offset 0x0C
+    /// LoSSI-Ausgabeverzögerung
+    ltoh:  u32,    // Offset 0x10
+    /// DMA-Schwellwerte
+    dc:    u32,    // Offset 0x14
+}
+
+use super::Bmc2835;
+impl Bmc2835 for Spi0 {
+
+    fn base_offset() -> usize {
+        0x204000
+    }
+}
+
+/// Berechnet den Taktteiler für die Rate `hz` beim Kerntakt `core` und die erreichte Rate.
+///
+/// Der Teiler muss gerade sein; 0 steht für 65536.
+pub fn spi0_clock_divider(core: u32, hz: u32) -> Result<(u32,u32),SpiError> {
+    if hz == 0 || core == 0 {
+        return Err(SpiError::InvalidClock);
+    }
+    // Aufrunden, damit die Rate höchstens `hz` ist
+    let mut div = (core + hz - 1) / hz;
+    div += div & 1;
+    if div < 2 {
+        div = 2;
+    }
+    if div > 65536 {
+        return Err(SpiError::InvalidClock);
+    }
+    Ok((div & 0xffff, core / div))
+}
+
+/// Erstes Wort einer DMA-Übertragung: Länge (Bits 16..31) und die unteren 8 Bits des
+/// CS-Registers, einschließlich TA. Die Hardware übernimmt es, statt es zu senden.
+pub fn spi0_dma_header(cs: u8, mode: SpiMode, cs_active_high: bool, len: u16) -> u32 {
+    let mut reg: u32 = 0;
+    reg.set_bits(0..2, cs as u32);
+    reg.set_bit(CS_CPHA, mode.cpha());
+    reg.set_bit(CS_CPOL, mode.cpol());
+    reg.set_bit(CS_CSPOL, cs_active_high);
+    reg.set_bit(CS_TA, true);
+    reg.set_bits(16..32, len as u32);
+    reg
+}
+
+impl Spi0 {
+    fn read_cs(&self) -> u32 {
+        Cpu::data_memory_barrier();
+        unsafe{ read_volatile(&self.cs) }
+    }
+
+    fn write_cs(&mut self, value: u32) {
+        Cpu::data_memory_barrier();
+        unsafe{ write_volatile(&mut self.cs, value); }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Stellt Chip-Select, Modus und Polarität des Chip-Selects ein und leert die FIFOs.
+    ///
+    /// Darf nur ohne laufende Übertragung gerufen werden.
+    pub fn setup(&mut self, cs: u8, mode: SpiMode, cs_active_high: bool) {
+        let mut reg = self.read_cs();
+        reg.set_bits(0..2, cs as u32);
+        reg.set_bit(CS_CPHA, mode.cpha());
+        reg.set_bit(CS_CPOL, mode.cpol());
+        reg.set_bit(CS_CSPOL, cs_active_high);
+        reg.set_bit(CS_CSPOL0 + cs, cs_active_high);
+        reg.set_bit(CS_TA, false);
+        reg.set_bit(CS_INTD, false);
+        reg.set_bit(CS_INTR, false);
+        reg.set_bit(CS_DMAEN, false);
+        reg.set_bits(4..6, 0b11);  // FIFOs leeren
+        self.write_cs(reg);
+    }
+
+    /// Setzt den Taktteiler (siehe `spi0_clock_divider`).
+    pub fn set_clock_divider(&mut self, div: u32) {
+        Cpu::data_memory_barrier();
+        unsafe{ write_volatile(&mut self.clk, div & 0xffff); }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Startet (`true`) oder beendet eine Übertragung (_transfer active_).
+    pub fn set_active(&mut self, active: bool) {
+        let mut reg = self.read_cs();
+        reg.set_bits(4..6, 0);
+        reg.set_bit(CS_TA, active);
+        self.write_cs(reg);
+    }
+
+    /// Schaltet die Interrupts für _done_ und _RX needs reading_ an oder ab.
+    pub fn enable_interrupts(&mut self, enable: bool) {
+        let mut reg = self.read_cs();
+        reg.set_bits(4..6, 0);
+        reg.set_bit(CS_INTD, enable);
+        reg.set_bit(CS_INTR, enable);
+        self.write_cs(reg);
+    }
+
+    /// Sind die Interrupts aktiv?
+    pub fn interrupts_enabled(&self) -> bool {
+        self.read_cs().get_bit(CS_INTD)
+    }
+
+    /// Schaltet den DMA-Modus an oder ab. Mit DMA beendet die Hardware die Übertragung nach
+    /// `len` Bytes selbst (ADCS).
+    pub fn enable_dma(&mut self, enable: bool, len: u16) {
+        Cpu::data_memory_barrier();
+        unsafe{ write_volatile(&mut self.dlen, len as u32); }
+        let mut reg = self.read_cs();
+        reg.set_bits(4..6, 0);
+        reg.set_bit(CS_DMAEN, enable);
+        reg.set_bit(CS_ADCS, enable);
+        self.write_cs(reg);
+    }
+
+    /// Setzt die DMA-Schwellwerte (Panic und Request jeweils für RX und TX).
+    pub fn set_dma_thresholds(&mut self, rx_panic: u8, rx_req: u8, tx_panic: u8, tx_req: u8) {
+        let reg = (rx_panic as u32) << 24 | (rx_req as u32) << 16 | (tx_panic as u32) << 8 | tx_req as u32;
+        Cpu::data_memory_barrier();
+        unsafe{ write_volatile(&mut self.dc, reg); }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Adresse der FIFO (für DMA)
+    pub fn fifo_address(&self) -> usize {
+        &self.fifo as *const u32 as usize
+    }
+
+    /// Ist die Übertragung abgeschlossen (alles gesendet)?
+    pub fn is_done(&self) -> bool {
+        self.read_cs().get_bit(CS_DONE)
+    }
+
+    /// Kann die Sende-FIFO ein weiteres Byte aufnehmen?
+    pub fn tx_has_space(&self) -> bool {
+        self.read_cs().get_bit(CS_TXD)
+    }
+
+    /// Enthält die Empfangs-FIFO Daten?
+    pub fn rx_has_data(&self) -> bool {
+        self.read_cs().get_bit(CS_RXD)
+    }
+
+    /// Ist die Empfangs-FIFO mindestens 3/4 voll bzw. voll?
+    pub fn rx_needs_reading(&self) -> bool {
+        let reg = self.read_cs();
+        reg.get_bit(CS_RXR) || reg.get_bit(CS_RXF)
+    }
+
+    /// Schreibt ein Byte in die Sende-FIFO.
+    pub fn write_fifo(&mut self, b: u8) {
+        unsafe{ write_volatile(&mut self.fifo, b as u32); }
+    }
+
+    /// Liest ein Byte aus der Empfangs-FIFO.
+    pub fn read_fifo(&self) -> u8 {
+        unsafe{ read_volatile(&self.fifo) as u8 }
+    }
+}
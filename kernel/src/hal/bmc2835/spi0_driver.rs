@@ -0,0 +1,227 @@
+#![warn(missing_docs)]
+//! Treiber für den SPI-Master 0.
+//!
+//! Der Treiber ist ein `SpiBus` mit drei Chip-Select-Leitungen (CE0, CE1 und das nur intern
+//! nutzbare CE2). Jede Übertragung stellt Takt, Modus und Chip-Select neu ein, Slaves mit
+//! unterschiedlichen Einstellungen können sich also den Bus teilen.
+//!
+//! Bei `SpiTransfer::Polled` bedient der Aufrufer die FIFOs selbst, bei
+//! `SpiTransfer::Interrupt` die Serviceroutine, während der Aufrufer wartet. Es werden nie
+//! mehr Bytes gesendet, als die Empfangs-FIFO aufnehmen kann, damit keine Daten verloren gehen.
+//! Bei `SpiTransfer::Dma` übertragen zwei DMA-Kanäle (Senden und Empfangen) die Daten
+//! wortweise; die Übertragung ist dann auf 65535 Bytes begrenzt.
+//!
+//! Zum Test ohne Slave genügt eine Brücke zwischen MOSI (Pin 10) und MISO (Pin 9): die
+//! empfangenen Daten müssen dann den gesendeten gleichen.
+use alloc::vec::Vec;
+use hal::cpu::Cpu;
+use data::isr_table::IsrResult;
+use data::kernel::KernelData;
+use super::{Bmc2835, Spi0, IrqController, GeneralInterrupt, GpioPins};
+use super::{SpiBus, SpiSettings, SpiTransfer, SpiError};
+use super::spi::SpiTransferState;
+use super::spi_driver::SpiDriverCore;
+use super::{ClockId, report_clock_rate, spi0_clock_divider, spi0_dma_header, SPI0_NUM_CS};
+use super::{DmaController, DmaChannelKind, DmaChain, DmaDreq};
+
+/// Tiefe der FIFOs in Bytes
+const SPI0_FIFO_SIZE: usize = 16;
+
+/// Eingeschalteter DMA-Modus; wird beim Freigeben wieder abgeschaltet.
+struct Spi0DmaMode<'a> {
+    spi: &'a mut Spi0,
+}
+
+impl<'a> Spi0DmaMode<'a> {
+    fn enable(spi: &'a mut Spi0) -> Spi0DmaMode<'a> {
+        spi.enable_dma(true, 0);
+        Spi0DmaMode { spi: spi }
+    }
+}
+
+impl<'a> Drop for Spi0DmaMode<'a> {
+    fn drop(&mut self) {
+        self.spi.enable_dma(false, 0);
+        self.spi.set_active(false);
+    }
+}
+
+/// Treiber für SPI0
+pub struct Spi0Driver {
+    core: SpiDriverCore,
+}
+
+static SPI0_DRIVER: Spi0Driver = Spi0Driver::new();
+
+impl Spi0Driver {
+    const fn new() -> Spi0Driver {
+        Spi0Driver {
+            core: SpiDriverCore::new(),
+        }
+    }
+
+    /// Der Treiber
+    pub fn get() -> &'static Spi0Driver {
+        &SPI0_DRIVER
+    }
+
+    /// Belegt die GPIO-Pins 7 bis 11 für SPI0 (alt0), meldet die Serviceroutine an und
+    /// aktiviert den Interrupt.
+    pub fn init(&self) -> Result<(),SpiError> {
+        use super::GpioPull;
+        use super::gpio_config::{Device,SPI};
+
+        let spi = Spi0::get();
+        spi.enable_interrupts(false);
+        spi.set_active(false);
+
+        // Bei erneuter Initialisierung zuerst die eigenen Pins freigeben
+        self.core.release_pins();
+        let pins = GpioPins::get().claim_all(&[(7, Device::Spi0(SPI::CE1)),
+                                               (8, Device::Spi0(SPI::CE0)),
+                                               (9, Device::Spi0(SPI::MiSo)),
+                                               (10, Device::Spi0(SPI::MoSi)),
+                                               (11, Device::Spi0(SPI::SClk))], "SPI0")
+            .map_err(SpiError::Gpio)?;
+        pins[2].set_pull(GpioPull::Down);
+
+        if !self.core.is_initialized() {
+            KernelData::isr_table().add_isr(GeneralInterrupt::SPI, Spi0Driver::isr, 0)
+                .map_err(|_| SpiError::Failed)?;
+            IrqController::get().enable(GeneralInterrupt::SPI);
+        }
+        self.core.set_initialized(pins);
+        Ok(())
+    }
+
+    /// Serviceroutine
+    pub fn isr(_: usize) -> IsrResult {
+        SPI0_DRIVER.handle_interrupt()
+    }
+
+    fn handle_interrupt(&self) -> IsrResult {
+        let spi = Spi0::get();
+        if !spi.interrupts_enabled() {
+            return IsrResult::NotHandled;
+        }
+        let done = self.core.with_transfer(true, |transfer| Spi0Driver::service(spi, transfer));
+        if done {
+            // Die Interrupts enden erst mit dem Ende der Übertragung (TA = 0).
+            spi.enable_interrupts(false);
+            spi.set_active(false);
+            self.core.wake();
+        }
+        IsrResult::Handled
+    }
+
+    /// Leert die Empfangs-FIFO und füllt die Sende-FIFO nach. Gibt `true` zurück, wenn alle
+    /// Bytes übertragen wurden.
+    fn service(spi: &mut Spi0, transfer: &mut SpiTransferState) -> bool {
+        while transfer.rx_pending() && spi.rx_has_data() {
+            transfer.store_rx(spi.read_fifo());
+        }
+        while transfer.tx_pending() && transfer.in_flight() < SPI0_FIFO_SIZE && spi.tx_has_space() {
+            if let Some(b) = transfer.next_tx() {
+                spi.write_fifo(b);
+            }
+        }
+        transfer.is_done()
+    }
+
+    fn transfer_polled(&self, spi: &mut Spi0, mut transfer: SpiTransferState) {
+        spi.set_active(true);
+        while !Spi0Driver::service(spi, &mut transfer) {
+            Cpu::data_memory_barrier();
+        }
+        while !spi.is_done() {
+            Cpu::data_memory_barrier();
+        }
+        spi.set_active(false);
+    }
+
+    fn transfer_interrupt(&self, spi: &mut Spi0, transfer: SpiTransferState) {
+        // Mit TA = 1 ist die Sende-FIFO leer und DONE gesetzt, die Serviceroutine beginnt
+        // also sofort mit dem Senden.
+        self.core.transfer_interrupt(transfer, || {
+            spi.enable_interrupts(true);
+            spi.set_active(true);
+        });
+    }
+
+    fn transfer_dma(&self, spi: &mut Spi0, cs: u8, settings: &SpiSettings, tx: &[u8], rx: &mut [u8])
+                    -> Result<(),SpiError> {
+        let len = ::core::cmp::max(tx.len(), rx.len());
+        if len > 0xffff {
+            return Err(SpiError::NotSupported);
+        }
+        // Im DMA-Modus nimmt die FIFO je Zugriff vier Bytes auf bzw. gibt sie ab. Das erste
+        // Wort enthält Länge und Einstellungen.
+        let words = (len + 3) / 4;
+        let mut out: Vec<u32> = Vec::with_capacity(words + 1);
+        out.push(spi0_dma_header(cs, settings.mode, settings.cs_active_high, len as u16));
+        for chunk in tx.chunks(4) {
+            let mut word = 0;
+            for (i, b) in chunk.iter().enumerate() {
+                word |= (*b as u32) << (8 * i);
+            }
+            out.push(word);
+        }
+        out.resize(words + 1, 0);
+        let mut input: Vec<u32> = Vec::new();
+        input.resize(words, 0);
+
+        let mut rx_chain = DmaChain::new();
+        rx_chain.from_peripheral(DmaDreq::SpiRx, spi.fifo_address(), &mut input).map_err(SpiError::Dma)?;
+        let mut tx_chain = DmaChain::new();
+        tx_chain.to_peripheral(DmaDreq::SpiTx, spi.fifo_address(), &out).map_err(SpiError::Dma)?;
+        // Die Kanäle werden vor den Ketten freigegeben und brechen dabei ggf. ab.
+        let dma = DmaController::get();
+        let mut rx_channel = dma.allocate(DmaChannelKind::Any).map_err(SpiError::Dma)?;
+        let mut tx_channel = dma.allocate(DmaChannelKind::Any).map_err(SpiError::Dma)?;
+
+        {
+            // Der DMA-Modus endet auf jedem Weg aus diesem Block, auch bei Fehlern.
+            let _dma = Spi0DmaMode::enable(&mut *spi);
+            unsafe{
+                rx_channel.start(&mut rx_chain).map_err(SpiError::Dma)?;
+                tx_channel.start(&mut tx_chain).map_err(SpiError::Dma)?;
+            }
+            let tx_res = tx_channel.wait(&tx_chain);
+            let rx_res = rx_channel.wait(&rx_chain);
+            tx_res.and(rx_res).map_err(SpiError::Dma)?;
+        }
+
+        for (i, b) in rx.iter_mut().enumerate() {
+            *b = (input[i / 4] >> (8 * (i % 4))) as u8;
+        }
+        Ok(())
+    }
+}
+
+impl SpiBus for Spi0Driver {
+    fn transfer(&self, cs: u8, settings: &SpiSettings, tx: &[u8], rx: &mut [u8]) -> Result<(),SpiError> {
+        if cs >= SPI0_NUM_CS {
+            return Err(SpiError::InvalidChipSelect);
+        }
+        let (div, _) = spi0_clock_divider(report_clock_rate(ClockId::Core), settings.clock_hz)?;
+        // Die Übertragung endet, bevor `transfer` zurückkehrt.
+        let transfer = unsafe{ SpiTransferState::new(tx, rx) };
+        if transfer.len() == 0 {
+            return Ok(());
+        }
+        let _claim = self.core.claim()?;
+        let spi = Spi0::get();
+        spi.set_clock_divider(div);
+        spi.setup(cs, settings.mode, settings.cs_active_high);
+        let res = match settings.transfer {
+            SpiTransfer::Interrupt => Ok(self.transfer_interrupt(spi, transfer)),
+            SpiTransfer::Polled    => Ok(self.transfer_polled(spi, transfer)),
+            SpiTransfer::Dma       => self.transfer_dma(spi, cs, settings, tx, rx),
+        };
+        res
+    }
+
+    fn effective_clock(&self, hz: u32) -> Result<u32,SpiError> {
+        spi0_clock_divider(report_clock_rate(ClockId::Core), hz).map(|(_, actual)| actual)
+    }
+}
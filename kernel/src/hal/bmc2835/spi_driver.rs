@@ -0,0 +1,107 @@
+#![warn(missing_docs)]
+//! Gemeinsamer Zustand der SPI-Treiber (`Spi0Driver`, `AuxSpiDriver`).
+//!
+//! Verwaltet die belegten Pins, die exklusive Belegung des Busses für eine Übertragung und
+//! die laufende interruptgesteuerte Übertragung samt Warteschlange. Der Zugriff auf die
+//! FIFOs bleibt bei den einzelnen Treibern.
+use alloc::vec::Vec;
+use sync::{IrqSpinLock, WaitQueue};
+use super::{GpioPin, SpiError};
+use super::spi::SpiTransferState;
+
+struct SpiCoreState {
+    initialized: bool,
+    busy:        bool,
+    transfer:    Option<SpiTransferState>,
+    pins:        Option<Vec<GpioPin>>,
+}
+
+/// Zustand eines SPI-Treibers
+pub(super) struct SpiDriverCore {
+    state: IrqSpinLock<SpiCoreState>,
+    queue: WaitQueue,
+}
+
+/// Belegung des Busses für eine Übertragung, siehe `SpiDriverCore::claim`
+pub(super) struct SpiClaim<'a> {
+    core: &'a SpiDriverCore,
+}
+
+impl<'a> Drop for SpiClaim<'a> {
+    fn drop(&mut self) {
+        self.core.state.lock().busy = false;
+    }
+}
+
+impl SpiDriverCore {
+    pub(super) const fn new() -> SpiDriverCore {
+        SpiDriverCore {
+            state: IrqSpinLock::new(SpiCoreState {
+                initialized: false,
+                busy:        false,
+                transfer:    None,
+                pins:        None,
+            }),
+            queue: WaitQueue::new(),
+        }
+    }
+
+    /// Wurde die Serviceroutine bereits angemeldet?
+    pub(super) fn is_initialized(&self) -> bool {
+        self.state.lock().initialized
+    }
+
+    /// Gibt die eigenen Pins frei, vor einer erneuten Initialisierung.
+    pub(super) fn release_pins(&self) {
+        self.state.lock().pins = None;
+    }
+
+    /// Übernimmt die belegten Pins und gibt den Bus für Übertragungen frei.
+    pub(super) fn set_initialized(&self, pins: Vec<GpioPin>) {
+        let mut state = self.state.lock();
+        state.initialized = true;
+        state.pins = Some(pins);
+    }
+
+    /// Belegt den Bus für eine Übertragung; er wird mit dem Ende der Belegung wieder frei.
+    pub(super) fn claim(&self) -> Result<SpiClaim,SpiError> {
+        let mut state = self.state.lock();
+        if !state.initialized {
+            Err(SpiError::NotInitialized)
+        } else if state.busy {
+            Err(SpiError::Busy)
+        } else {
+            state.busy = true;
+            Ok(SpiClaim { core: self })
+        }
+    }
+
+    /// Für die Serviceroutine: ruft `f` mit der laufenden Übertragung auf bzw. gibt `idle`
+    /// zurück, wenn es keine gibt.
+    pub(super) fn with_transfer<R, F>(&self, idle: R, f: F) -> R
+        where F: FnOnce(&mut SpiTransferState) -> R {
+        match self.state.lock().transfer {
+            Some(ref mut transfer) => f(transfer),
+            None                   => idle
+        }
+    }
+
+    /// Weckt den Aufrufer von `transfer_interrupt`.
+    pub(super) fn wake(&self) {
+        self.queue.wake_all();
+    }
+
+    /// Übergibt `transfer` der Serviceroutine, stößt sie mit `start` an und wartet, bis
+    /// alle Bytes übertragen wurden.
+    pub(super) fn transfer_interrupt<F: FnOnce()>(&self, transfer: SpiTransferState, start: F) {
+        self.state.lock().transfer = Some(transfer);
+        start();
+        self.queue.wait_until(|| {
+            match self.state.lock().transfer {
+                Some(ref transfer) => transfer.is_done(),
+                None               => true
+            }
+        });
+        self.state.lock().transfer = None;
+    }
+}
@@ -23,34 +23,30 @@ impl Bmc2835 for SystemTimer {
 impl SystemTimer {
 
     pub fn set_compare(&mut self, channel: u8, val: u32) {
-        match channel {
-            0 => { self.compare_0 = val; },
-            1 => { self.compare_1 = val; },
-            2 => { self.compare_2 = val; },
-            3 => { self.compare_3 = val; },
-            _ => {}
-        };
-       
+        use core::ptr::write_volatile;
+        unsafe{
+            match channel {
+                0 => { write_volatile(&mut self.compare_0, val); },
+                1 => { write_volatile(&mut self.compare_1, val); },
+                2 => { write_volatile(&mut self.compare_2, val); },
+                3 => { write_volatile(&mut self.compare_3, val); },
+                _ => {}
+            };
+        }
     }
 
     pub fn found_match(&self, channel: u8) -> bool {
-        match channel {
-            0 => self.status.get_bit(0),
-            1 => self.status.get_bit(1),
-            2 => self.status.get_bit(2),
-            3 => self.status.get_bit(3),
-            _ => false
-        }
+        use core::ptr::read_volatile;
+        channel < 4 && unsafe{ read_volatile(&self.status) }.get_bit(channel)
     }
 
+    /// Löscht den Treffer eines Kanals. Das Statusregister wird durch Schreiben einer 1
+    /// gelöscht; die übrigen Kanäle (auch die der GPU) bleiben daher unberührt.
     pub fn reset_match(&mut self, channel: u8) {
-        match channel {
-            0 => { self.status.set_bit(0,true); },
-            1 => { self.status.set_bit(1,true); },
-            2 => { self.status.set_bit(2,true); },
-            3 => { self.status.set_bit(3,true); },
-            _ => {}
-        };
+        use core::ptr::write_volatile;
+        if channel < 4 {
+            unsafe{ write_volatile(&mut self.status, 1 << channel); }
+        }
     }
 
     pub fn get_counter(&self) -> u32 {
@@ -28,7 +28,9 @@ pub enum UartError {
     Invalid,
     FIFOfull,
     NoData,
-    Failed
+    Failed,
+    /// Die GPIO-Pins können nicht belegt werden
+    Gpio(GpioError),
 }
 
 pub trait Uart {
@@ -45,3 +47,116 @@ pub trait Uart {
     fn write(&mut self, data: u8) -> Result<u8,UartError>;
 
 }
+
+/// Flusssteuerung
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[allow(dead_code)]
+pub enum UartFlowControl {
+    /// Keine
+    None,
+    /// Hardware-Flusssteuerung über RTS und CTS
+    RtsCts,
+}
+
+/// GPIO-Pins einer UART
+///
+/// CTS und RTS werden nur bei `UartFlowControl::RtsCts` eingestellt.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UartPins {
+    /// Senden
+    pub tx:  u8,
+    /// Empfangen
+    pub rx:  u8,
+    /// Sendeerlaubnis
+    pub cts: u8,
+    /// Sendeanforderung
+    pub rts: u8,
+}
+
+/// Standardbelegung (Pins 14 und 15 der Stiftleiste, CTS/RTS auf 16 und 17)
+pub const UART_PINS_DEFAULT: UartPins = UartPins { tx: 14, rx: 15, cts: 16, rts: 17 };
+
+use alloc::vec::Vec;
+use super::{Pl011FillLevel, GpioError, GpioPins, GpioPin, GpioPull};
+use super::gpio_config::{Device, UART};
+
+impl UartPins {
+    /// Belegt die Pins für `owner` und stellt die Pull-Widerstände ein; `device` ist
+    /// `Device::Uart0` oder `Device::Uart1`.
+    pub fn claim(&self, flow: bool, device: fn(UART) -> Device, owner: &'static str)
+                 -> Result<Vec<GpioPin>,UartError> {
+        let mut requested = Vec::with_capacity(4);
+        requested.push((self.tx, device(UART::TxD)));
+        requested.push((self.rx, device(UART::RxD)));
+        if flow {
+            requested.push((self.cts, device(UART::CTS)));
+            requested.push((self.rts, device(UART::RTS)));
+        }
+        let pins = GpioPins::get().claim_all(&requested, owner).map_err(|err| match err {
+            GpioError::InvalidPin | GpioError::InvalidFunction => UartError::Invalid,
+            err                                                => UartError::Gpio(err),
+        })?;
+        // Die Eingänge (RxD, CTS) werden hochgezogen, damit eine offene Leitung als Ruhepegel
+        // gilt.
+        pins[0].set_pull(GpioPull::Off);
+        pins[1].set_pull(GpioPull::Up);
+        if flow {
+            pins[2].set_pull(GpioPull::Up);
+            pins[3].set_pull(GpioPull::Off);
+        }
+        Ok(pins)
+    }
+}
+
+/// Vollständige Konfiguration einer UART
+#[derive(Copy, Clone, Debug)]
+pub struct UartConfig {
+    /// Gewünschte Baudrate
+    pub baud_rate:    u32,
+    /// Anzahl der Datenbits (5..8)
+    pub data_width:   u8,
+    /// Parität
+    pub parity:       UartParity,
+    /// Anzahl der Stop-Bits (1 oder 2)
+    pub stop_bits:    u8,
+    /// Flusssteuerung
+    pub flow_control: UartFlowControl,
+    /// Füllstand der Empfangs-FIFO, bei dem der Empfangsinterrupt ausgelöst wird
+    pub rx_level:     Pl011FillLevel,
+    /// Füllstand der Sende-FIFO, bei dem der Sendeinterrupt ausgelöst wird
+    pub tx_level:     Pl011FillLevel,
+    /// GPIO-Pins
+    pub pins:         UartPins,
+}
+
+impl UartConfig {
+    /// 8N1 ohne Flusssteuerung auf den Standardpins
+    pub const fn new(baud_rate: u32) -> UartConfig {
+        UartConfig {
+            baud_rate:    baud_rate,
+            data_width:   8,
+            parity:       UartParity::None,
+            stop_bits:    1,
+            flow_control: UartFlowControl::None,
+            rx_level:     Pl011FillLevel::OneQuarter,
+            tx_level:     Pl011FillLevel::OneQuarter,
+            pins:         UART_PINS_DEFAULT,
+        }
+    }
+}
+
+/// Tatsächlich eingestellte Baudrate
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UartBaud {
+    /// Gewünschte Rate
+    pub requested: u32,
+    /// Erreichte Rate
+    pub actual:    u32,
+}
+
+impl UartBaud {
+    /// Abweichung der erreichten von der gewünschten Rate in ppm (Millionstel)
+    pub fn error_ppm(&self) -> i32 {
+        ((self.actual as i64 - self.requested as i64) * 1000000 / self.requested as i64) as i32
+    }
+}
@@ -1,5 +1,10 @@
 #![warn(missing_docs)]
 use bit_field::BitField;
+use memory::{Address,AddressRange};
+use super::Cpu;
+
+/// Größe einer Cachezeile des ARM1176JZF-S, vgl. TRM 3.2.3 (Cache Type Register)
+pub const CACHE_LINE_SIZE: usize = 32;
 
 /// Interface für Cacheoperationen des ARM.
 ///
@@ -89,6 +94,97 @@ impl Cache {
             asm!("mcr p15, 0, $0, c1, c0, 0"::"r"(reg)::"volatile");
         }
     }
-    
+
+    /// Gibt die Adressen der Cachezeilen zurück, die den Bereich `r` überdecken
+    fn lines(r: &AddressRange) -> ::core::iter::StepBy<::core::ops::Range<Address>> {
+        let start = r.start & !(CACHE_LINE_SIZE - 1);
+        (start .. r.end).step_by(CACHE_LINE_SIZE)
+    }
+
+    /// Schreibe alle Datencache-Zeilen des Adressbereichs in den Speicher zurück (_clean_).
+    ///
+    /// Wird z.B. benötigt, bevor ein anderer Busmaster (GPU, DMA) Daten liest, die die CPU
+    /// geschrieben hat.
+    // Siehe ARM ARM B6.6.5, "Clean Data Cache Line (using MVA)"
+    pub fn clean_data_range(r: AddressRange) {
+        for addr in Cache::lines(&r) {
+            unsafe{
+                asm!("mcr p15, 0, $0, c7, c10, 1"::"r"(addr):"memory":"volatile");
+            }
+        }
+        Cpu::data_synchronization_barrier();
+    }
+
+    /// Markiere alle Datencache-Zeilen des Adressbereichs als ungültig (_invalidate_).
+    ///
+    /// Wird z.B. benötigt, bevor die CPU Daten liest, die ein anderer Busmaster geschrieben hat.
+    /// Zeilen, die nur teilweise im Bereich liegen, werden vorher zurückgeschrieben, damit
+    /// benachbarte Daten nicht verloren gehen.
+    // Siehe ARM ARM B6.6.5, "Invalidate Data Cache Line (using MVA)"
+    pub fn invalidate_data_range(r: AddressRange) {
+        if r.start & (CACHE_LINE_SIZE - 1) != 0 {
+            Cache::clean_invalidate_data_range(r.start .. r.start + 1);
+        }
+        if r.end & (CACHE_LINE_SIZE - 1) != 0 {
+            Cache::clean_invalidate_data_range(r.end - 1 .. r.end);
+        }
+        Cache::invalidate_lines(&r);
+    }
+
+    /// Markiere die Datencache-Zeilen eines Bereichs aus ganzen Cachezeilen als ungültig,
+    /// ohne sie zurückzuschreiben.
+    ///
+    /// Für Puffer, die ein anderer Busmaster beschrieben hat und die nur aus eigenen
+    /// Cachezeilen bestehen. Geänderte Daten der CPU in diesen Zeilen gehen verloren.
+    pub fn invalidate_data_lines(r: AddressRange) {
+        assert!(r.start & (CACHE_LINE_SIZE - 1) == 0 && r.end & (CACHE_LINE_SIZE - 1) == 0);
+        Cache::invalidate_lines(&r);
+    }
+
+    fn invalidate_lines(r: &AddressRange) {
+        for addr in Cache::lines(r) {
+            unsafe{
+                asm!("mcr p15, 0, $0, c7, c6, 1"::"r"(addr):"memory":"volatile");
+            }
+        }
+        Cpu::data_synchronization_barrier();
+    }
+
+    /// Schreibe alle Datencache-Zeilen des Adressbereichs zurück und markiere sie als ungültig.
+    // Siehe ARM ARM B6.6.5, "Clean and Invalidate Data Cache Line (using MVA)"
+    pub fn clean_invalidate_data_range(r: AddressRange) {
+        for addr in Cache::lines(&r) {
+            unsafe{
+                asm!("mcr p15, 0, $0, c7, c14, 1"::"r"(addr):"memory":"volatile");
+            }
+        }
+        Cpu::data_synchronization_barrier();
+    }
+
+    /// Markiere alle Befehlscache-Zeilen des Adressbereichs als ungültig.
+    ///
+    /// Wird benötigt, nachdem Code in den Speicher geschrieben wurde (z.B. durch einen Lader).
+    /// Vorher muss der Datencache für den Bereich mit `clean_data_range` zurückgeschrieben werden.
+    /// Die Sprungvorhersage für den Bereich wird ebenfalls gelöscht.
+    // Siehe ARM ARM B6.6.5, "Invalidate Instruction Cache Line (using MVA)"
+    pub fn invalidate_instruction_range(r: AddressRange) {
+        for addr in Cache::lines(&r) {
+            unsafe{
+                asm!("mcr p15, 0, $0, c7, c5, 1"::"r"(addr):"memory":"volatile");
+                asm!("mcr p15, 0, $0, c7, c5, 7"::"r"(addr):"memory":"volatile");
+            }
+        }
+        Cpu::data_synchronization_barrier();
+        Cpu::prefetch_flush();
+    }
+
+    /// Lösche die gesamte Sprungvorhersage (_branch target cache_).
+    // Siehe ARM ARM B6.6.5, "Flush Entire Branch Target Cache"
+    pub fn invalidate_branch_predictor() {
+        unsafe{
+            asm!("mcr p15, 0, $0, c7, c5, 6"::"r"(0)::"volatile");
+        }
+        Cpu::prefetch_flush();
+    }
 }
 
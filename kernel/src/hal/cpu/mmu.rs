@@ -1,8 +1,8 @@
 use super::cache::Cache;
-use super::tlb::Tlb;
+use super::tlb::{Tlb,Asid};
 use super::Cpu;
 use bit_field::BitField;
-use memory::{DomainAccess,Address};
+use memory::{DomainAccess,Address,AddressRange,MemAttribute,prrr,nmrr};
 
 /// Interface für die *Memory Management Unit* (MMU)
 pub struct MMU {}
@@ -105,4 +105,209 @@ impl MMU {
             asm!("mcr p15, 0, $0, c3, c0, 0\n" : : "r"(reg));
         }
     }
+
+    /// Setzt das _Context ID Register_ (CP15c13): Prozesskennung in den Bits 8..31 und
+    /// ASID in den Bits 0..7.
+    ///
+    /// Die ASID markiert alle TLB-Einträge für prozessspezifische Seiten (nG), die danach
+    /// geladen werden. Beim Prozesswechsel muss der TLB daher nicht geleert werden.
+    // Siehe ARM ARM B4.2.2 und ARM1176JZF-S TRM 3.2.45
+    pub fn set_context_id(process: u32, asid: Asid) {
+        let reg: u32 = (process << 8) | asid as u32;
+        Cpu::data_synchronization_barrier();
+        unsafe{
+            asm!("mcr p15, 0, $0, c13, c0, 1"::"r"(reg)::"volatile");
+        }
+        Cpu::prefetch_flush();
+    }
+
+    /// Gibt den Inhalt des _Context ID Registers_ zurück.
+    pub fn context_id() -> u32 {
+        let reg: u32;
+        unsafe{
+            asm!("mrc p15, 0, $0, c13, c0, 1":"=r"(reg));
+        }
+        reg
+    }
+
+    /// Gibt die aktuelle ASID zurück.
+    pub fn asid() -> Asid {
+        MMU::context_id() as Asid
+    }
+
+    /// Wechselt den Adressraum: neues Seitenverzeichnis und neue Kontext-ID.
+    ///
+    /// Damit während des Wechsels keine Einträge des neuen Seitenverzeichnisses mit der alten
+    /// ASID in den TLB geladen werden, wird zwischendurch die reservierte ASID 0 gesetzt. Sie
+    /// darf daher keinem Prozess zugeteilt werden.
+    // Siehe ARM ARM B4.2.2, "Synchronization of changes of ASID and TTBR"
+    pub fn switch_address_space(dir: Address, process: u32, asid: Asid) {
+        MMU::set_context_id(0, 0);
+        MMU::set_page_dir(dir);
+        Cpu::prefetch_flush();
+        MMU::set_context_id(process, asid);
+    }
+
+    /// Muss aufgerufen werden, nachdem Einträge für den Bereich `range` geändert wurden.
+    ///
+    /// Es werden nur die betroffenen TLB-Einträge invalidiert. Für prozessspezifische Seiten
+    /// muss die ASID des Adressraumes angegeben werden, dem die Seiten gehören.
+    pub fn mapping_changed(range: &AddressRange, asid: Asid) {
+        Tlb::invalidate_range(range, asid);
+    }
+
+    /// Muss aufgerufen werden, wenn ein Prozess beendet wurde und seine ASID neu vergeben wird.
+    pub fn address_space_released(asid: Asid) {
+        Tlb::invalidate_asid(asid);
+    }
+
+    /// Lädt die Remapping-Tabelle in PRRR und NMRR und aktiviert TEX-Remapping.
+    ///
+    /// Danach legen TEX[0], C und B eines Eintrags den Index in `table` fest
+    /// (siehe `MemAttr`), TEX[2:1] werden von der MMU ignoriert.
+    /// Alle Einträge, die mit `MemType` erzeugt wurden, sollten vorher überprüft werden.
+    // Siehe ARM ARM B4.4 und ARM1176JZF-S TRM 3.2.46
+    pub fn enable_tex_remap(table: &[MemAttribute;8]) {
+        let mut reg: u32;
+        Cache::clean();
+        unsafe{
+            asm!("mcr p15, 0, $0, c10, c2, 0"::"r"(prrr(table))::"volatile");
+            asm!("mcr p15, 0, $0, c10, c2, 1"::"r"(nmrr(table))::"volatile");
+            asm!("mrc p15, 0, $0, c1, c0, 0":"=r"(reg));
+            reg.set_bit(28,true);  // TEX-Remapping an
+            asm!("mcr p15, 0, $0, c1, c0, 0"::"r"(reg)::"volatile");
+        }
+        Cpu::prefetch_flush();
+        Tlb::flush();
+    }
+
+    /// Deaktiviert TEX-Remapping.
+    pub fn disable_tex_remap() {
+        let mut reg: u32;
+        Cache::clean();
+        unsafe{
+            asm!("mrc p15, 0, $0, c1, c0, 0":"=r"(reg));
+            reg.set_bit(28,false);
+            asm!("mcr p15, 0, $0, c1, c0, 0"::"r"(reg)::"volatile");
+        }
+        Cpu::prefetch_flush();
+        Tlb::flush();
+    }
+
+    /// Gibt an, ob TEX-Remapping aktiv ist.
+    pub fn tex_remap_enabled() -> bool {
+        let reg: u32;
+        unsafe{
+            asm!("mrc p15, 0, $0, c1, c0, 0":"=r"(reg));
+        }
+        reg.get_bit(28)
+    }
+
+    /// Gibt den Status des letzten Datenzugriffsfehlers zurück (_Data Fault Status Register_).
+    ///
+    /// Die Fehlerursache ergibt sich aus den Bits 0..4 und 10, siehe ARM ARM B4.6.
+    pub fn data_fault_status() -> u32 {
+        let reg: u32;
+        unsafe{
+            asm!("mrc p15, 0, $0, c5, c0, 0":"=r"(reg));
+        }
+        reg
+    }
+
+    /// Gibt die Adresse des letzten Datenzugriffsfehlers zurück (_Fault Address Register_).
+    pub fn data_fault_address() -> Address {
+        let reg: u32;
+        unsafe{
+            asm!("mrc p15, 0, $0, c6, c0, 0":"=r"(reg));
+        }
+        reg as Address
+    }
+
+    /// Gibt den Status des letzten Befehlszugriffsfehlers zurück
+    /// (_Instruction Fault Status Register_).
+    pub fn instruction_fault_status() -> u32 {
+        let reg: u32;
+        unsafe{
+            asm!("mrc p15, 0, $0, c5, c0, 1":"=r"(reg));
+        }
+        reg
+    }
+
+    /// Gibt die Adresse des letzten Befehlszugriffsfehlers zurück
+    /// (_Instruction Fault Address Register_).
+    pub fn instruction_fault_address() -> Address {
+        let reg: u32;
+        unsafe{
+            asm!("mrc p15, 0, $0, c6, c0, 2":"=r"(reg));
+        }
+        reg as Address
+    }
+
+    /// Übersetzt eine virtuelle Adresse des aktuellen Adressraums in die physische Adresse,
+    /// so wie die MMU es für einen lesenden Zugriff im Usermodus tut.
+    ///
+    /// Gibt `None` zurück, wenn der Zugriff zu einem Fehler führen würde. Die Zugriffsrechte
+    /// (AP) prüft die MMU nur in Domains mit `DomainAccess::Client`; in Manager-Domains wird
+    /// nur abgelehnt, was nicht abgebildet ist. Zeiger aus Systemrufen müssen daher zusätzlich
+    /// anhand des Seitenverzeichnisses geprüft werden (`check_user_range`).
+    // Siehe ARM1176JZF-S TRM 3.2.22 (c7, VA to PA translation operations)
+    pub fn translate_user_read(addr: Address) -> Option<Address> {
+        let par: u32;
+        unsafe{
+            asm!("mcr p15, 0, $0, c7, c8, 2"::"r"(addr)::"volatile");
+            Cpu::prefetch_flush();
+            asm!("mrc p15, 0, $0, c7, c4, 0":"=r"(par):::"volatile");
+        }
+        if par.get_bit(0) {
+            None
+        } else {
+            Some((par & !0xfff) as Address | (addr & 0xfff))
+        }
+    }
+
+    /// Übersetzt eine virtuelle Adresse des aktuellen Adressraums in die physische Adresse,
+    /// so wie die MMU es für einen schreibenden Zugriff im Usermodus tut.
+    ///
+    /// Gibt `None` zurück, wenn der Zugriff zu einem Fehler führen würde; wie bei
+    /// `translate_user_read` werden die Zugriffsrechte nur in Client-Domains geprüft.
+    // Siehe ARM1176JZF-S TRM 3.2.22 (c7, VA to PA translation operations)
+    pub fn translate_user_write(addr: Address) -> Option<Address> {
+        let par: u32;
+        unsafe{
+            asm!("mcr p15, 0, $0, c7, c8, 3"::"r"(addr)::"volatile");
+            Cpu::prefetch_flush();
+            asm!("mrc p15, 0, $0, c7, c4, 0":"=r"(par):::"volatile");
+        }
+        if par.get_bit(0) {
+            None
+        } else {
+            Some((par & !0xfff) as Address | (addr & 0xfff))
+        }
+    }
+
+    /// Übersetzt eine virtuelle Adresse des aktuellen Adressraums in die physische Adresse,
+    /// so wie die MMU es für einen lesenden Zugriff im privilegierten Modus tut.
+    ///
+    /// Gibt `None` zurück, wenn die Adresse nicht abgebildet ist.
+    // Siehe ARM1176JZF-S TRM 3.2.22 (c7, VA to PA translation operations)
+    pub fn translate_kernel_read(addr: Address) -> Option<Address> {
+        let par: u32;
+        unsafe{
+            asm!("mcr p15, 0, $0, c7, c8, 0"::"r"(addr)::"volatile");
+            Cpu::prefetch_flush();
+            asm!("mrc p15, 0, $0, c7, c4, 0":"=r"(par):::"volatile");
+        }
+        if par.get_bit(0) {
+            None
+        } else {
+            Some((par & !0xfff) as Address | (addr & 0xfff))
+        }
+    }
+
+    /// Gibt an, ob der Fehlerstatus einen Übersetzungsfehler (_translation fault_) beschreibt,
+    /// d.h. einen Zugriff auf einen Fault-Eintrag im Seitenverzeichnis oder einer Seitentabelle.
+    pub fn is_translation_fault(status: u32) -> bool {
+        let fs = (status.get_bits(10..11) << 4) | status.get_bits(0..4);
+        fs == 0b00101 || fs == 0b00111
+    }
 }
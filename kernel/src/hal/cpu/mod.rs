@@ -4,6 +4,8 @@ mod tlb;
 mod mmu;
 
 pub use self::mmu::MMU;
+pub use self::cache::{Cache,CACHE_LINE_SIZE};
+pub use self::tlb::{Tlb,Asid};
 use memory::Address;
 
 /// AMR-Prozessor-Modi, siehe ARM Architectur Reference Manual A2-3
@@ -80,6 +82,39 @@ impl Cpu {
         unsafe {asm!("cpsie i":::"memory");}
     }
 
+    /// Sperrt Interrupts und gibt den vorherigen Zustand des CPSR zurück.
+    ///
+    /// Mit `restore_interrupts` wird der vorherige Zustand wiederhergestellt. Damit können
+    /// kritische Abschnitte auch dort genutzt werden, wo Interrupts bereits gesperrt sind.
+    #[inline(always)]
+    pub fn save_and_disable_interrupts() -> u32 {
+        let cpsr: u32;
+        unsafe {asm!("mrs $0, cpsr
+                      cpsid i":"=r"(cpsr)::"memory":"volatile");}
+        cpsr
+    }
+
+    /// Erlaubt Interrupts, falls sie laut `state` (Ergebnis von `save_and_disable_interrupts`)
+    /// erlaubt waren.
+    #[inline(always)]
+    pub fn restore_interrupts(state: u32) {
+        if state & (1 << 7) == 0 {
+            Cpu::enable_interrupts();
+        }
+    }
+
+    /// Sperrt schnelle Interrupts (FIQ)
+    #[inline(always)]
+    pub fn disable_fast_interrupts(){
+        unsafe {asm!("cpsid f":::"memory");}
+    }
+
+    /// Erlaubt schnelle Interrupts (FIQ)
+    #[inline(always)]
+    pub fn enable_fast_interrupts(){
+        unsafe {asm!("cpsie f":::"memory");}
+    }
+
     /// Speicherzugriffsbarriere (DMB):
     ///
     /// Alle expliziten Speicherzugriffe vor DMB werden *vor* allen
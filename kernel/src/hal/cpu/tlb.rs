@@ -1,8 +1,18 @@
 use super::Cpu;
+use memory::{Address,AddressRange,PAGE_SIZE};
 
+/// Adressraumkennung (_Address Space Identifier_), 8 Bit
+pub type Asid = u8;
+
+/// Interface für den *Translation Lookaside Buffer* (TLB)
+///
+/// Einträge für prozessspezifische Seiten (nG) sind mit der ASID des Prozesses markiert, der sie
+/// geladen hat. Beim Invalidieren über die virtuelle Adresse muss daher die ASID mit angegeben
+/// werden; für globale Seiten wird sie ignoriert. Siehe ARM ARM B4.2.2 und B4.9.13.
 pub struct Tlb{}
 
 impl Tlb {
+    /// Invalidiert den gesamten TLB
     #[inline(always)]
     pub fn flush() {
         unsafe {
@@ -12,6 +22,7 @@ impl Tlb {
         Cpu::prefetch_flush();
     }
 
+    /// Invalidiert den gesamten Befehls-TLB
     #[inline(always)]
     pub fn invalidate_instruction() {
         unsafe {
@@ -21,6 +32,7 @@ impl Tlb {
         Cpu::prefetch_flush();
     }
 
+    /// Invalidiert den gesamten Daten-TLB
     #[inline(always)]
     pub fn invalidate_data() {
         unsafe {
@@ -28,4 +40,73 @@ impl Tlb {
         }
         Cpu::data_synchronization_barrier();
     }
+
+    /// Invalidiert den Eintrag für die Seite mit der Adresse `addr` in Befehls- und Daten-TLB
+    #[inline(always)]
+    pub fn invalidate_entry(addr: Address, asid: Asid) {
+        unsafe {
+            asm!("mcr p15, #0, $0, c8, c7, #1"::"r"(Tlb::mva(addr, asid))::"volatile");
+        }
+        Cpu::data_synchronization_barrier();
+        Cpu::prefetch_flush();
+    }
+
+    /// Invalidiert den Eintrag für die Seite mit der Adresse `addr` im Befehls-TLB
+    #[inline(always)]
+    pub fn invalidate_instruction_entry(addr: Address, asid: Asid) {
+        unsafe {
+            asm!("mcr p15, #0, $0, c8, c5, #1"::"r"(Tlb::mva(addr, asid))::"volatile");
+        }
+        Cpu::data_synchronization_barrier();
+        Cpu::prefetch_flush();
+    }
+
+    /// Invalidiert den Eintrag für die Seite mit der Adresse `addr` im Daten-TLB
+    #[inline(always)]
+    pub fn invalidate_data_entry(addr: Address, asid: Asid) {
+        unsafe {
+            asm!("mcr p15, #0, $0, c8, c6, #1"::"r"(Tlb::mva(addr, asid))::"volatile");
+        }
+        Cpu::data_synchronization_barrier();
+    }
+
+    /// Invalidiert die Einträge für alle Seiten im Bereich `range`.
+    ///
+    /// Ab einer gewissen Größe ist es günstiger, den gesamten TLB zu invalidieren. Da der
+    /// ARM1176 nur 64 Einträge im Haupt-TLB hat, wird bei mehr Seiten `flush()` genutzt.
+    pub fn invalidate_range(range: &AddressRange, asid: Asid) {
+        if range.end <= range.start {
+            return;
+        }
+        let first = range.start & !(PAGE_SIZE - 1);
+        if (range.end - first) / PAGE_SIZE > 64 {
+            Tlb::flush();
+            return;
+        }
+        for addr in (first .. range.end).step_by(PAGE_SIZE) {
+            unsafe {
+                asm!("mcr p15, #0, $0, c8, c7, #1"::"r"(Tlb::mva(addr, asid))::"volatile");
+            }
+        }
+        Cpu::data_synchronization_barrier();
+        Cpu::prefetch_flush();
+    }
+
+    /// Invalidiert alle prozessspezifischen Einträge mit der ASID `asid` in Befehls- und Daten-TLB.
+    ///
+    /// Einträge für globale Seiten bleiben erhalten.
+    #[inline(always)]
+    pub fn invalidate_asid(asid: Asid) {
+        unsafe {
+            asm!("mcr p15, #0, $0, c8, c7, #2"::"r"(asid as u32)::"volatile");
+        }
+        Cpu::data_synchronization_barrier();
+        Cpu::prefetch_flush();
+    }
+
+    /// Wert für die Operationen über die virtuelle Adresse: Bits 12..31 Adresse, 0..7 ASID
+    #[inline(always)]
+    fn mva(addr: Address, asid: Asid) -> u32 {
+        (addr & !(PAGE_SIZE - 1)) as u32 | asid as u32
+    }
 }
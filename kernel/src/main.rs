@@ -65,8 +65,9 @@ use debug::*;
 use entry::syscall;
 use hal::cpu::{Cpu,ProcessorMode,MMU};
 use core::mem::size_of;
-//use sync::no_concurrency::NoConcurrency;
+use sync::no_concurrency::NoConcurrency;
 use data::kernel::{KernelData,KERNEL_PID};
+use data::isr_table::{IsrTable,IsrResult};
 mod memory;
 use memory::*;
 
@@ -76,8 +77,6 @@ import_linker_symbol!(__data_end);
 import_linker_symbol!(__bss_start);
 import_linker_symbol!(__kernel_stack);
 
-const IRQ_STACK_SIZE: usize = 2048;
-const SVC_STACK_SIZE: usize = 64 * 1024;
 pub  const INIT_HEAP_SIZE: usize = 25 * 4096; // 25 Seiten = 100 kB
 
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
@@ -126,9 +125,9 @@ pub(self) fn kernel_init() {
 }
 
 
-// Wahrscheinlich sollte nur mit einem Stack gearbeitet werden? => ToDo
+/// Bestimmt das obere Ende des Stackbereiches (= Ende des ARM-Speichers).
 #[inline(never)]
-fn determine_irq_stack() -> Address {
+fn determine_stack_top() -> Address {
     if KernelData::get_toss().is_some() {
         KernelData::get_toss().unwrap()
     } else {
@@ -138,10 +137,15 @@ fn determine_irq_stack() -> Address {
     }
 }
 
+#[inline(never)]
+fn stack_layout() -> StackLayout {
+    StackLayout::new(determine_stack_top())
+}
+
 #[inline(never)]
 fn determine_svc_stack() -> Address {
     kprint!("determine stack called.\n";WHITE);
-    self::determine_irq_stack() - IRQ_STACK_SIZE
+    stack_layout().top_of_stack(KernelStack::Svc)
 }
 
 #[inline(never)]
@@ -152,108 +156,125 @@ fn init_mem() {
     memory::init_heap(__bss_start as Address, INIT_HEAP_SIZE);
     kprint!("done.\nInit pagetable...");
     init_paging();
-    kprint!("done.\n");
+    kprint!("done.\nSpeicherabbildung:\n");
+    dump_memory_map();
 }
 
 /// Es werden die Stacks für alle Ausname-Modi gesetzt.
-/// Irq, Fiq, Abort und Undef teilen sich einen Stack, der System-Mode nutzt
-/// den User-Mode-Stack und muss nicht gesetzt werden.
+/// Irq, Fiq, Abort und Undef haben jeweils einen eigenen Stack, unter dem eine Schutzseite
+/// liegt (siehe `memory::StackLayout`). Der System-Mode nutzt den User-Mode-Stack und muss
+/// nicht gesetzt werden.
 #[inline(never)]
 fn init_stacks() {
-    let adr = determine_irq_stack();
+    let layout = stack_layout();
     Cpu::set_mode(ProcessorMode::Irq);
-    Cpu::set_stack(adr);
+    Cpu::set_stack(layout.top_of_stack(KernelStack::Irq));
     Cpu::set_mode(ProcessorMode::Fiq);
-    Cpu::set_stack(adr);
+    Cpu::set_stack(layout.top_of_stack(KernelStack::Fiq));
     Cpu::set_mode(ProcessorMode::Abort);
-    Cpu::set_stack(adr);
+    Cpu::set_stack(layout.top_of_stack(KernelStack::Abort));
     Cpu::set_mode(ProcessorMode::Undef);
-    Cpu::set_stack(adr);
+    Cpu::set_stack(layout.top_of_stack(KernelStack::Undef));
     // ...und zurück in den Svc-Mode
     Cpu::set_mode(ProcessorMode::Svc);
 }
 
 fn init_paging() {
     MMU::set_page_dir(PageDirectory::addr());
-    let page_directory: &mut PageDirectory = KernelData::page_directory();
-    let frame_allocator: &mut FrameManager = KernelData::frame_allocator();
+    // Vor dem Belegen der Kerneldaten bestimmen, `stack_layout` liest sie selbst.
+    let layout = stack_layout();
+    {
+        // Die Guards sperren Interrupts bis zum Ende der Einrichtung; diese sind hier ohnehin
+        // noch nicht zugelassen.
+        let mut page_directory = KernelData::page_directory();
+        let mut frame_allocator = KernelData::frame_allocator();
+        let mut page_tables = KernelData::page_tables();
     
-    // Standard ist Seitenfehler
-    for section in Section::iter(0 .. MAX_ADDRESS) {
-        page_directory[section.nr()] = MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::Fault)
-            .entry();
-    }
-    // Für den Kernel richten wir zwei Seitentabellen ein:
-    // * Code (Textsegment), Daten und Heap => kpage_table
-    // * Stacks                             => spage_table
-    //
-    // # Anmerkung:
-    // Der Kernel ist relativ klein, daher reicht eine Seitentabelle für Code und Daten.
-    // Sollte sich dies mal ändern, müsste zunächst die Anzahl der benötigten Tabellen bestimmt
-    // werden. In diesem Fall sollten die Tabellen auf dem Heap angelegt werden.
-    let kpage_table: &mut PageTable = &mut KernelData::kpages();
-    kpage_table.invalidate();
-    let spage_table: &mut PageTable = &mut KernelData::spages();
-    spage_table.invalidate();
-    // Die Seitentabellen werden in das Seitenverzeichnis eingetragen
-    page_directory[0] = MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::CoarsePageTable)
-        .base_addr(kpage_table.addr())
-        .entry();
-    page_directory[Section::from_addr(determine_svc_stack() - 65556 ).nr()] =
-        MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::CoarsePageTable)
-        .base_addr(spage_table.addr())
-        .entry();
-    // Der Kernel-Bereich wird auf sich selbst gemappt:
-    // Dabei ist der Code ausführbar, Daten nicht.
-    // Code
-    for frm in Frame::iter(0 .. __text_end as usize) {
-        kpage_table[frm.rel()] = MemoryBuilder::<TableEntry>::new_entry(TableEntry::SmallPage)
-            .base_addr(frm.start())
-            //.rights(MemoryAccessRight::SysRwUsrNone)
-            .rights(MemoryAccessRight::SysRwUsrRw)
-            .mem_type(MemType::NormalWT)
-            .domain(0)
-            .entry();
-        frame_allocator.reserve(frm).expect("frame allocator failed");
-    }
-    // Kernel-Daten + BSS
-    for frm in Frame::iter(__data_start as usize .. __bss_start as usize + INIT_HEAP_SIZE) {
-        kpage_table[frm.rel()] = MemoryBuilder::<TableEntry>::new_entry(TableEntry::SmallPage)
-            .base_addr(frm.start())
-            //.rights(MemoryAccessRight::SysRwUsrNone)
-            .rights(MemoryAccessRight::SysRwUsrRw)
-            .mem_type(MemType::NormalWB)
-            .no_execute(true)
-            .domain(0)
-            .entry();
-        frame_allocator.reserve(frm).expect("frame allocator failed");
-    }
-    // Stacks
-    for frm in  Frame::iter(determine_svc_stack() - SVC_STACK_SIZE .. determine_irq_stack() -1) {
-        spage_table[frm.rel()] = MemoryBuilder::<TableEntry>::new_entry(TableEntry::SmallPage)
-            .base_addr(frm.start())
-            //.rights(MemoryAccessRight::SysRwUsrNone)
-            .rights(MemoryAccessRight::SysRwUsrRw)
-            .mem_type(MemType::NormalWT)
-            .no_execute(true)
-            .domain(0)
+        // Standard ist Seitenfehler
+        for section in Section::iter(0 .. MAX_ADDRESS) {
+            page_directory[section.nr()] = MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::Fault)
+                .entry();
+        }
+        // Für den Kernel richten wir zwei Seitentabellen ein:
+        // * Code (Textsegment), Daten und Heap => kpage_table
+        // * Stacks                             => spage_table
+        //
+        // # Anmerkung:
+        // Der Kernel ist relativ klein, daher reicht eine Seitentabelle für Code und Daten.
+        // Sollte sich dies mal ändern, müsste zunächst die Anzahl der benötigten Tabellen bestimmt
+        // werden. In diesem Fall sollten die Tabellen auf dem Heap angelegt werden.
+        let (kpage_table, spage_table): (&mut PageTable, &mut PageTable) = page_tables.split();
+        kpage_table.invalidate();
+        spage_table.invalidate();
+        // Die Seitentabellen werden in das Seitenverzeichnis eingetragen
+        page_directory[0] = MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::CoarsePageTable)
+            .base_addr(kpage_table.addr())
             .entry();
-        frame_allocator.reserve(frm).expect("frame allocator failed");
-    }
-    // Der Rest des Speichers (Geräte) wird auf sich selbst gemappt
-    // TODO: nur die tatsächlichen Geräte mappen
-    for section in Section::iter(determine_irq_stack() .. MAX_ADDRESS) {
-        let pde = MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::Section)
-            .base_addr(section.start())
-            //.rights(MemoryAccessRight::SysRwUsrNone)
-            .rights(MemoryAccessRight::SysRwUsrRw)
-            .mem_type(MemType::NormalUncashed)
-            .domain(0)
-            .no_execute(true)
+        // Alle Stacks samt Schutzseiten müssen in der Section von `spage_table` liegen.
+        assert_eq!(Section::from_addr(layout.bottom()).nr(), Section::from_addr(layout.top() - 1).nr());
+        page_directory[Section::from_addr(layout.bottom()).nr()] =
+            MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::CoarsePageTable)
+            .base_addr(spage_table.addr())
             .entry();
-        page_directory[section.nr()] = pde;
+        // Der Kernel-Bereich wird auf sich selbst gemappt:
+        // Dabei ist der Code ausführbar, Daten nicht.
+        // Code
+        for frm in Frame::iter(0 .. __text_end as usize) {
+            kpage_table[frm.rel()] = MemoryBuilder::<TableEntry>::new_entry(TableEntry::SmallPage)
+                .base_addr(frm.start())
+                //.rights(MemoryAccessRight::SysRwUsrNone)
+                .rights(MemoryAccessRight::SysRwUsrRw)
+                .mem_type(MemType::NormalWT)
+                .domain(0)
+                .entry();
+            frame_allocator.reserve(frm).expect("frame allocator failed");
+        }
+        // Kernel-Daten + BSS
+        for frm in Frame::iter(__data_start as usize .. __bss_start as usize + INIT_HEAP_SIZE) {
+            kpage_table[frm.rel()] = MemoryBuilder::<TableEntry>::new_entry(TableEntry::SmallPage)
+                .base_addr(frm.start())
+                //.rights(MemoryAccessRight::SysRwUsrNone)
+                .rights(MemoryAccessRight::SysRwUsrRw)
+                .mem_type(MemType::NormalWB)
+                .no_execute(true)
+                .domain(0)
+                .entry();
+            frame_allocator.reserve(frm).expect("frame allocator failed");
+        }
+        // Stacks
+        // Die Schutzseiten bleiben als Seitenfehler eingetragen, ihre Frames werden aber
+        // reserviert, damit sie nicht anderweitig vergeben werden.
+        for stack in KERNEL_STACKS.iter() {
+            for frm in Frame::iter(layout.stack_range(*stack)) {
+                spage_table[frm.rel()] = MemoryBuilder::<TableEntry>::new_entry(TableEntry::SmallPage)
+                    .base_addr(frm.start())
+                    //.rights(MemoryAccessRight::SysRwUsrNone)
+                    .rights(MemoryAccessRight::SysRwUsrRw)
+                    .mem_type(MemType::NormalWT)
+                    .no_execute(true)
+                    .domain(0)
+                    .entry();
+                frame_allocator.reserve(frm).expect("frame allocator failed");
+            }
+            for frm in Frame::iter(layout.guard_range(*stack)) {
+                frame_allocator.reserve(frm).expect("frame allocator failed");
+            }
+        }
+        // Der Rest des Speichers (Geräte) wird auf sich selbst gemappt
+        // TODO: nur die tatsächlichen Geräte mappen
+        for section in Section::iter(layout.top() .. MAX_ADDRESS) {
+            let pde = MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::Section)
+                .base_addr(section.start())
+                //.rights(MemoryAccessRight::SysRwUsrNone)
+                .rights(MemoryAccessRight::SysRwUsrRw)
+                .mem_type(MemType::NormalUncashed)
+                .domain(0)
+                .no_execute(true)
+                .entry();
+            page_directory[section.nr()] = pde;
+        }
+        MMU::set_domain_access(0,DomainAccess::Manager);
     }
-    MMU::set_domain_access(0,DomainAccess::Manager);
     kprint!("Vorbereitungen für MMU-Aktivierung abgeschlossen.\n");
     unsafe{ MMU::start(); }
     kprint!("MMU aktiviert.\n");
@@ -261,35 +282,26 @@ fn init_paging() {
 
 fn init_devices() {
     let irq_controller = IrqController::get();
+    // Status-LED für Blinkzeichen
+    debug::blink::init();
     // Uart
     //
-    // Schalte die entsprechenden Pins frei.
-    use hal::bmc2835::{Gpio,GpioPull,gpio_config};
+    // Die Konsole liegt auf den Pins 14 und 15, die andere UART auf 32 und 33.
+    use hal::bmc2835::{Gpio,GpioPull,gpio_config,Pl011Driver,MiniUartDriver,UartConfig,
+                       UART_PINS_DEFAULT};
     let gpio = Gpio::get();
-    //gpio.config_pin(14,gpio_config::Device::Output).unwrap();
-    gpio.config_pin(14,gpio_config::Device::Uart0(gpio_config::UART::TxD)).unwrap();
-    gpio.config_pin(15,gpio_config::Device::Uart0(gpio_config::UART::RxD)).unwrap();
-    // Setze Pullup/down für diese Pins.
-    gpio.set_pull(14,GpioPull::Off);
-    gpio.set_pull(15,GpioPull::Up); // Signal ist immer "low".
-
-    use hal::bmc2835::{Pl011,Pl011Interrupt,Pl011FillLevel,Uart,UartEnable,UartParity};
-    let uart0 = Pl011::get();
-    uart0.enable(UartEnable::None);
-    // Löscht alle Interrupts
-    uart0.disable_interrupt(Pl011Interrupt::All);
-    uart0.clear_interrupt(Pl011Interrupt::All);
-    // Flush FIFOs
-    uart0.enable_fifo(false);
-    uart0.set_baud_rate(115200).expect("Can't set baud rate");
-    uart0.set_data_width(8).expect("Can't set data width");
-    uart0.set_parity(UartParity::None).expect("Can't set parity");
-    uart0.set_rcv_trigger_level(Pl011FillLevel::OneQuarter);
-    uart0.enable_interrupt(Pl011Interrupt::Rcv);
-    uart0.enable_fifo(true);
-    uart0.enable(UartEnable::Both);
-    irq_controller.enable(BasicInterrupt::UART);
-    kprint!("UART: set up.\n";WHITE);
+    let (pl011_pins, mini_uart_pins) = if MINI_UART_CONSOLE {
+        (SECOND_UART_PINS, UART_PINS_DEFAULT)
+    } else {
+        (UART_PINS_DEFAULT, SECOND_UART_PINS)
+    };
+    let mut config = UartConfig::new(115200);
+    config.pins = pl011_pins;
+    let baud = Pl011Driver::get().init(&config).expect("Can't set up UART");
+    kprint!("UART: set up, {} baud ({} ppm).\n",baud.actual,baud.error_ppm();WHITE);
+    config.pins = mini_uart_pins;
+    let baud = MiniUartDriver::get().init(&config).expect("Can't set up mini UART");
+    kprint!("Mini-UART: set up, {} baud ({} ppm).\n",baud.actual,baud.error_ppm();WHITE);
     //
     // Timer
     //
@@ -305,10 +317,100 @@ fn init_devices() {
     kprint!("Interrupt aktiviert.\n";BLUE);
     kprint!("Setze Isr...");
     use hal::bmc2835::BasicInterrupt;
-    let isr_table = KernelData::isr_table();
-    isr_table.add_isr(BasicInterrupt::ARMtimer, timer_tick);
-    isr_table.add_isr(BasicInterrupt::UART, uart_intr);
+    {
+        let mut isr_table = KernelData::isr_table();
+        isr_table.add_isr(BasicInterrupt::ARMtimer, timer_tick, 0).expect("ISR table full");
+        // Die UART darf die Serviceroutine des Timers unterbrechen, so dass Eingaben auch
+        // bei langen Timer-Routinen nicht verloren gehen.
+        isr_table.set_priority(BasicInterrupt::ARMtimer, 1);
+        isr_table.set_priority(BasicInterrupt::UART, 2);
+    }
     kprint!("Done.\n");
+    //
+    // GPIO-Ereignisse (die Bank-Interrupts werden erst mit dem ersten Pin aktiviert)
+    //
+    use hal::bmc2835::GpioDriver;
+    match GpioDriver::get().init() {
+        Ok(())   => { kprint!("GPIO: events set up.\n";WHITE); },
+        Err(err) => { kprint!("GPIO: {:?}\n",err;RED); },
+    }
+    //
+    // FIQ-Demo: Steigende Flanken an einem GPIO-Pin werden per FIQ gezählt,
+    // Timer und UART laufen weiter über den normalen Interrupt.
+    //
+    use hal::bmc2835::{GpioEvent,GeneralInterrupt};
+    use data::fiq::{Fiq,FiqRegisters};
+    use hal::bmc2835::GpioPins;
+    let pin = GpioPins::get().claim(FIQ_DEMO_PIN,gpio_config::Device::Input,"FIQ-Demo")
+        .expect("FIQ demo pin in use");
+    pin.set_pull(GpioPull::Down);
+    pin.keep();
+    gpio.reset_event(FIQ_DEMO_PIN);
+    gpio.enable_event_detection(FIQ_DEMO_PIN,GpioEvent::Rising);
+    let regs = FiqRegisters {
+        r9: FIQ_DEMO_PIN as u32,
+        .. Default::default()
+    };
+    match Fiq::register(GeneralInterrupt::GPIO0, gpio_edge_fiq, regs) {
+        Ok(())   => { kprint!("FIQ: zähle Flanken an GPIO {}.\n",FIQ_DEMO_PIN;WHITE); },
+        Err(err) => { kprint!("FIQ: {:?}\n",err;RED); },
+    }
+    //
+    // DMA
+    //
+    use hal::bmc2835::DmaController;
+    match DmaController::get().init() {
+        Ok(mask) => { kprint!("DMA: set up, channels {:#06x}.\n",mask;WHITE); },
+        Err(err) => { kprint!("DMA: {:?}\n",err;RED); },
+    }
+    //
+    // SPI0
+    //
+    use hal::bmc2835::Spi0Driver;
+    match Spi0Driver::get().init() {
+        Ok(())   => { kprint!("SPI0: set up.\n";WHITE); },
+        Err(err) => { kprint!("SPI0: {:?}\n",err;RED); },
+    }
+    if SPI_LOOPBACK_TEST {
+        spi_loopback_test();
+    }
+    //
+    // I2C am Header (BSC1, Pins 2 und 3)
+    //
+    use hal::bmc2835::{BscDriver,BscConfig};
+    match BscDriver::bsc1().init(&BscConfig::new(100000)) {
+        Ok(hz)   => { kprint!("I2C1: set up, {} Hz.\n",hz;WHITE); },
+        Err(err) => { kprint!("I2C1: {:?}\n",err;RED); },
+    }
+    //
+    // SD-Karte
+    //
+    use hal::bmc2835::{EmmcDriver,EmmcConfig};
+    match EmmcDriver::get().init(&EmmcConfig::new(25000000)) {
+        Ok(card) => { kprint!("SD: {} MiB, {} Hz{}.\n",card.blocks / 2048,card.clock_hz,
+                              if card.high_capacity { ", SDHC" } else { "" };WHITE); },
+        Err(err) => { kprint!("SD: {:?}\n",err;RED); },
+    }
+    if SD_READ_TEST {
+        sd_read_test();
+    }
+    //
+    // PWM (Takt für Audio)
+    //
+    use hal::bmc2835::PwmDriver;
+    match PwmDriver::get().init(PWM_CLOCK) {
+        Ok(hz)   => { kprint!("PWM: set up, {} Hz.\n",hz;WHITE); },
+        Err(err) => { kprint!("PWM: {:?}\n",err;RED); },
+    }
+    if AUDIO_TEST {
+        audio_test();
+    }
+    //
+    // Belegung der GPIO-Pins
+    //
+    for pin in GpioPins::get().assignments() {
+        kprint!("GPIO {}: {:?} ({})\n",pin.pin,pin.device,pin.owner;WHITE);
+    }
 }
  
 fn report() {
@@ -335,8 +437,12 @@ fn report() {
     kprint!("0x{:08x} ({:10}): Ende Kerneldaten\n",__data_end as usize,__data_end as usize; WHITE);
     kprint!("0x{:08x} ({:10}): Anfang Kernelheap\n",__bss_start as usize, __bss_start as usize; WHITE);
     kprint!("0x{:08x} ({:10}): Initiales Ende Kernelheap\n",__bss_start as usize + INIT_HEAP_SIZE, __bss_start as usize + INIT_HEAP_SIZE; WHITE);
-    kprint!("0x{:08x} ({:10}): TOS System\n",determine_svc_stack() as usize, determine_svc_stack() as usize; WHITE);
-    kprint!("0x{:08x} ({:10}): TOS Interrupt\n",determine_irq_stack() as usize, determine_irq_stack() as usize; WHITE);
+    let layout = stack_layout();
+    for stack in KERNEL_STACKS.iter() {
+        let tos = layout.top_of_stack(*stack);
+        kprint!("0x{:08x} ({:10}): TOS {}\n",tos,tos,stack.name(); WHITE);
+    }
+    kprint!("0x{:08x} ({:10}): Ende Stackbereich (Schutzseite)\n",layout.bottom(),layout.bottom(); WHITE);
     debug::kprint::deb_info();
 }
 //#[macro_use]
@@ -359,37 +465,39 @@ fn test() {
     
     //Cpu::set_mode(ProcessorMode::System);
     //Cpu::set_stack(&stack as *const _ as usize);
-    use hal::bmc2835::{Pl011};
-    let uart = Pl011::get();
+    use hal::bmc2835::{Pl011Driver,MiniUartDriver};
+    let uart = Pl011Driver::get();
+    let mini_uart = MiniUartDriver::get();
     uart.write_str("Hello world\n");
+    mini_uart.write_str("Hello world\n");
     Cpu::enable_interrupts();
 
-    // flush FIFO
-    let mut old_flags = (true,true,true,true,0);
+    let mut old_edges = 0;
+    let mut old_errors = uart.errors();
+    let mut input = [0u8; 16];
     loop {
-        let (tx_e,tx_f,rx_e,rx_f,intr) = (
-            uart.tx_is_empty(),
-            uart.tx_is_full(),
-            uart.rx_is_empty(),
-            uart.rx_is_full(),
-            uart.raw_intr & !0b1101);
-        if old_flags != (tx_e,tx_f,rx_e,rx_f,intr) {
+        // Solange es keine Prozesse gibt, ist die Testschleife der Kernel-Worker.
+        data::deferred::run_work();
+        let edges = *FIQ_DEMO_EDGES.get();
+        if edges != old_edges {
             Cpu::disable_interrupts();
-            kprint!("TX e: {}, TX f: {},  RX e: {}, RX f: {} intr: {:b}\n",tx_e,tx_f,rx_e,rx_f,intr);
+            kprint!("FIQ: {} Flanken\n",edges;GREEN);
             Cpu::enable_interrupts();
-            old_flags = (tx_e,tx_f,rx_e,rx_f,intr);
+            old_edges = edges;
         }
-        if !rx_e {
-            /*
-            let c = uart.read();
-            Cpu::disable_interrupts();
-            if let Ok(ch) = c {
-                kprint!("{}",ch);
-            } else {
-                kprint!("{:?} ",c);
-            }
-            Cpu::enable_interrupts();
-             */
+        // Eingaben beider UARTs
+        let n = uart.read(&mut input);
+        for &ch in input[..n].iter() {
+            uart_echo(ch as u32);
+        }
+        let n = mini_uart.read(&mut input);
+        for &ch in input[..n].iter() {
+            uart_echo(ch as u32);
+        }
+        let errors = uart.errors();
+        if errors != old_errors {
+            uart_report_errors(&errors, &old_errors);
+            old_errors = errors;
         }
         //SystemTimer::get().busy_csleep(0xF000000);         
     }
@@ -465,59 +573,133 @@ fn test() {
     debug::blink::blink(debug::blink::BS_SOS);
 }
 
-pub fn timer_tick() {
-    //kprint!("."; GREEN);
-    let timer = ArmTimer::get();
-    timer.next_count(1000000);
-    timer.reset_interrupt();
-}
+/// Serielle Konsole auf der Mini-UART statt auf der PL011.
+///
+/// Beide UARTs können die Pins 14 und 15 nutzen (alt0 bzw. alt5), aber nicht gleichzeitig.
+/// Die Konsole erhält diese Pins, die andere UART `SECOND_UART_PINS`.
+const MINI_UART_CONSOLE: bool = false;
 
-pub fn timer_tick2() {
-    kprint!("me too! "; GREEN);
+/// Pins der zweiten UART (alt3 bzw. alt5, CTS/RTS auf 30 und 31)
+const SECOND_UART_PINS: hal::bmc2835::UartPins =
+    hal::bmc2835::UartPins { tx: 32, rx: 33, cts: 30, rts: 31 };
+
+/// Selbsttest von SPI0 beim Start; MOSI (Pin 10) muss mit MISO (Pin 9) verbunden sein.
+const SPI_LOOPBACK_TEST: bool = false;
+
+/// Sendet ein Muster über SPI0 und vergleicht es mit den empfangenen Daten.
+fn spi_loopback_test() {
+    use hal::bmc2835::{Spi0Driver,SpiChip,SpiDevice,SpiSettings,SpiBus};
+    let bus = Spi0Driver::get();
+    let settings = SpiSettings::new(1000000);
+    let mut chip = SpiChip::new(bus, 0, settings);
+    let mut tx = [0u8; 32];
+    for (i, b) in tx.iter_mut().enumerate() {
+        *b = (i as u8).wrapping_mul(37) ^ 0x5a;
+    }
+    let mut rx = [0u8; 32];
+    match chip.transfer(&tx, &mut rx) {
+        Ok(()) if rx == tx => {
+            kprint!("SPI0: Loopback ok ({} Hz).\n",bus.effective_clock(settings.clock_hz).unwrap_or(0);GREEN);
+        },
+        Ok(())   => { kprint!("SPI0: Loopback fehlerhaft: {:?}\n",&rx[..];RED); },
+        Err(err) => { kprint!("SPI0: {:?}\n",err;RED); },
+    }
 }
 
-pub fn uart_intr() {
-    use hal::bmc2835::{Pl011,Pl011Interrupt,Pl011Flag,Pl011Error,Uart};
-    let uart0 = Pl011::get();
-    loop {
-        let c = uart0.read();
-        if let Ok(ch) = c {
-            kprint!("{}",ch as char);
-            //uart0.write(ch);
-            if ch as char == '0' {
-                uart0.write_str("Hallo world!\n");
-            }
-        }
+/// Liest beim Start den ersten Block der SD-Karte und prüft die MBR-Signatur.
+const SD_READ_TEST: bool = false;
 
-        if uart0.get_state(Pl011Flag::RxEmpty)
-        {
-            break;
-        }
-    } 
-    if uart0.get_rvc_state(Pl011Error::Overrun) {
-        kprint!("Overrun ";RED);
+/// Liest Block 0 der SD-Karte (mit aktiven Interrupts, wie im Interrupt-Betrieb nötig).
+fn sd_read_test() {
+    use hal::bmc2835::{EmmcDriver,BlockDevice};
+    let mut block = [0u8; 512];
+    Cpu::enable_interrupts();
+    let res = EmmcDriver::get().read_blocks(0, &mut block);
+    Cpu::disable_interrupts();
+    match res {
+        Ok(()) if block[510] == 0x55 && block[511] == 0xaa => { kprint!("SD: MBR gefunden.\n";GREEN); },
+        Ok(())   => { kprint!("SD: keine MBR-Signatur.\n";WHITE); },
+        Err(err) => { kprint!("SD: {:?}\n",err;RED); },
     }
-    if uart0.get_rvc_state(Pl011Error::Break) {
-        kprint!("Break ";RED);
+}
+
+/// PWM-Takt; ergibt bei 22050 Hz Abtastrate gut 11 Bit Auflösung.
+const PWM_CLOCK: u32 = 50000000;
+
+/// Spielt beim Start einen Ton an der Audiobuchse.
+const AUDIO_TEST: bool = false;
+
+/// Gibt einen Kammerton A (440 Hz, eine halbe Sekunde) aus.
+fn audio_test() {
+    use hal::bmc2835::PwmDriver;
+    Cpu::enable_interrupts();
+    let res = PwmDriver::get().tone(440, 500, 128);
+    Cpu::disable_interrupts();
+    match res {
+        Ok(())   => { kprint!("PWM: Ton ausgegeben.\n";GREEN); },
+        Err(err) => { kprint!("PWM: {:?}\n",err;RED); },
     }
-    if uart0.get_rvc_state(Pl011Error::Frame) {
-        kprint!("Frameerror ";RED);
+}
+
+/// Pin für die FIQ-Demo
+///
+/// Liegt außerhalb der Pins von SPI1 (16 bis 21) und der UART-Flusssteuerung (16, 17).
+const FIQ_DEMO_PIN: u8 = 22;
+
+/// Anzahl der bisher per FIQ gezählten Flanken
+static FIQ_DEMO_EDGES: NoConcurrency<u32> = NoConcurrency::new(0);
+
+/// FIQ-Serviceroutine der Demo.
+///
+/// Der Zähler liegt im gebankten r8, die Pinnummer in r9. Für die Ausgabe wird der Zähler
+/// zusätzlich in `FIQ_DEMO_EDGES` veröffentlicht.
+fn gpio_edge_fiq(regs: &mut data::fiq::FiqRegisters) {
+    use hal::bmc2835::Gpio;
+    Gpio::get().reset_event(regs.r9 as u8);
+    regs.r8 = regs.r8.wrapping_add(1);
+    FIQ_DEMO_EDGES.set(regs.r8);
+}
+
+/// Ausgabe eines empfangenen Zeichens
+fn uart_echo(ch: u32) {
+    use hal::bmc2835::Pl011Driver;
+    kprint!("{}",ch as u8 as char);
+    match ch as u8 as char {
+        // Längere Arbeiten werden einem Kernel-Worker übergeben.
+        '0' => { let _ = data::deferred::schedule_work(|_| { Pl011Driver::get().write_str("Hallo world!\n"); }, 0); },
+        // Interruptstatistik
+        'i' => { let _ = data::deferred::schedule_work(|_| { IsrTable::dump_stats(); }, 0); },
+        _   => {}
     }
-    if uart0.get_rvc_state(Pl011Error::Parity) {
-        kprint!("Parityerror ";RED);
+}
+
+/// Ausgabe der neu aufgetretenen Empfangsfehler
+fn uart_report_errors(errors: &hal::bmc2835::Pl011ErrorCounts, old: &hal::bmc2835::Pl011ErrorCounts) {
+    let counts = [
+        ("Overrun ",     errors.overrun    - old.overrun),
+        ("Break ",       errors.break_cond - old.break_cond),
+        ("Frameerror ",  errors.frame      - old.frame),
+        ("Parityerror ", errors.parity     - old.parity),
+        ("Dropped ",     errors.dropped    - old.dropped),
+    ];
+    for &(name, n) in counts.iter() {
+        if n != 0 {
+            kprint!("{}",name;RED);
+        }
     }
-    /*
-    let (tx_e,tx_f,rx_e,rx_f,intr) = (
-        uart0.tx_is_empty(),
-        uart0.tx_is_full(),
-        uart0.rx_is_empty(),
-        uart0.rx_is_full(),
-        uart0.raw_intr & !0b1101);
-    kprint!("TX e: {}, TX f: {},  RX e: {}, RX f: {} intr: {:b}\n",tx_e,tx_f,rx_e,rx_f,intr);
-    */    
-    uart0.clear_interrupt(Pl011Interrupt::All);
-    //uart0.disable_interrupt(Pl011Interrupt::All);
-    //Cpu::disable_interrupts();
+}
+
+pub fn timer_tick(_: usize) -> IsrResult {
+    //kprint!("."; GREEN);
+    let timer = ArmTimer::get();
+    timer.next_count(1000000);
+    timer.reset_interrupt();
+    IsrResult::Handled
+}
+
+pub fn timer_tick2(_: usize) -> IsrResult {
+    kprint!("me too! "; GREEN);
+    IsrResult::NotHandled
 }
 
 /*
@@ -12,5 +12,8 @@ pub fn init_heap(start: Address, size: usize) {
 
 mod paging;
 pub use self::paging::*;
+
+mod stacks;
+pub use self::stacks::{KernelStack,StackLayout,KERNEL_STACKS};
  
 
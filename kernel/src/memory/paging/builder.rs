@@ -2,14 +2,15 @@
 //! Generierung von Tabelleneinträgen für die Speicherverwaltung
 extern crate bit_field;
 use self::bit_field::BitField;
-use super::{MemType,MemoryAccessRight};
+use super::{MemType,MemoryAccessRight,Address};
+use super::tex_remap::{MemAttr,SoftwareFlag};
 use core::marker::PhantomData;
 
 pub type PageDirectoryEntry = u32;
 pub type PageTableEntry     = u32;
 
 #[allow(dead_code)]
-#[derive(PartialEq,Clone,Copy)]
+#[derive(PartialEq,Clone,Copy,Debug)]
 /// Art des Eintrages in das Seitenverzeichnis.
 ///
 /// Im Seitenverzeichnis können vier verschiedene Arten von Einträgen enthalten sein:
@@ -31,7 +32,7 @@ pub enum DirectoryEntry {
     Supersection    = 0x40002
 }
 
-#[derive(PartialEq,Clone,Copy)]
+#[derive(PartialEq,Clone,Copy,Debug)]
 /// Art des Eintrages in eine Seitentabelle.
 ///
 /// In einer Seitentabelle können drei Arten von Einträgen enthalten sein:
@@ -105,6 +106,47 @@ pub trait EntryBuilder<T> {
 
     /// Gibt den Eintrag zurück
     fn entry(self) -> u32;
+
+    /// Erzeugt einen Builder aus einem vorhandenen (rohen) Eintrag, z.B. um ihn auszuwerten
+    fn from_entry(e: u32) -> MemoryBuilder<T>;
+
+    /// Gibt die Basisadresse zurück, oder `None` bei einem Seitenfehler
+    fn get_base_addr(&self) -> Option<Address>;
+
+    /// Gibt die Art des Speichers zurück, oder `None`, falls der Eintrag keinen Speichertyp
+    /// enthält oder die Bitkombination keinem `MemType` entspricht
+    fn get_mem_type(&self) -> Option<MemType>;
+
+    /// Gibt die Zugriffsrechte zurück, oder `None`, falls der Eintrag keine Rechte enthält
+    /// oder die Bitkombination reserviert ist
+    fn get_rights(&self) -> Option<MemoryAccessRight>;
+
+    /// Gibt die Domain zurück, oder `None`, falls der Eintrag keine Domain enthält
+    fn get_domain(&self) -> Option<u32>;
+
+    /// Gibt an, ob der Speicherbereich gemeinsam (_shared_) ist
+    fn is_shared(&self) -> bool;
+
+    /// Gibt an, ob der Speicherbereich prozessspezifisch (_not global_) ist
+    fn is_process_specific(&self) -> bool;
+
+    /// Gibt an, ob der Speicherinhalt nicht ausgeführt werden darf
+    fn is_no_execute(&self) -> bool;
+
+    /// Legt den Index in die Remapping-Tabelle fest (nur bei aktiviertem TEX-Remapping)
+    ///
+    /// Im Gegensatz zu `mem_type` bleiben die Software-Flags erhalten.
+    fn mem_attr(self, a: MemAttr) -> MemoryBuilder<T>;
+
+    /// Gibt den Index in die Remapping-Tabelle zurück, oder `None`, falls der Eintrag keinen
+    /// Speichertyp enthält
+    fn get_mem_attr(&self) -> Option<MemAttr>;
+
+    /// Setzt oder löscht ein Software-Flag (nur bei aktiviertem TEX-Remapping)
+    fn sw_flag(self, f: SoftwareFlag, b: bool) -> MemoryBuilder<T>;
+
+    /// Gibt an, ob das Software-Flag gesetzt ist
+    fn has_sw_flag(&self, f: SoftwareFlag) -> bool;
 }
 
 /// Implementation für Einträge in das Seitenverzeichnis
@@ -202,6 +244,105 @@ impl EntryBuilder<DirectoryEntry> for MemoryBuilder<DirectoryEntry> {
     fn entry(self) -> PageDirectoryEntry {
         self.0.clone()
     }
+
+    fn from_entry(e: PageDirectoryEntry) -> MemoryBuilder<DirectoryEntry> {
+        MemoryBuilder::<DirectoryEntry>(e,PhantomData)
+    }
+
+    fn get_base_addr(&self) -> Option<Address> {
+        match self.kind() {
+            DirectoryEntry::CoarsePageTable => Some((self.0.get_bits(10..32) << 10) as Address),
+            DirectoryEntry::Section         => Some((self.0.get_bits(20..32) << 20) as Address),
+            DirectoryEntry::Supersection    => Some((self.0.get_bits(24..32) << 24) as Address),
+            _                               => None
+        }
+    }
+
+    fn get_mem_type(&self) -> Option<MemType> {
+        match self.kind() {
+            DirectoryEntry::Section | DirectoryEntry::Supersection
+                => MemType::from_bits((self.0.get_bits(12..15) << 2) | self.0.get_bits(2..4)),
+            _   => None
+        }
+    }
+
+    fn get_rights(&self) -> Option<MemoryAccessRight> {
+        match self.kind() {
+            DirectoryEntry::Section | DirectoryEntry::Supersection
+                => MemoryAccessRight::from_bits((self.0.get_bits(15..16) << 2) | self.0.get_bits(10..12)),
+            _   => None
+        }
+    }
+
+    fn get_domain(&self) -> Option<u32> {
+        match self.kind() {
+            DirectoryEntry::CoarsePageTable | DirectoryEntry::Section
+                => Some(self.0.get_bits(5..9)),
+            _   => None
+        }
+    }
+
+    fn is_shared(&self) -> bool {
+        match self.kind() {
+            DirectoryEntry::Section | DirectoryEntry::Supersection => self.0.get_bit(16),
+            _                                                      => false
+        }
+    }
+
+    fn is_process_specific(&self) -> bool {
+        match self.kind() {
+            DirectoryEntry::Section | DirectoryEntry::Supersection => self.0.get_bit(17),
+            _                                                      => false
+        }
+    }
+
+    fn is_no_execute(&self) -> bool {
+        match self.kind() {
+            DirectoryEntry::Section | DirectoryEntry::Supersection => self.0.get_bit(4),
+            _                                                      => false
+        }
+    }
+
+    fn mem_attr(mut self, a: MemAttr) -> MemoryBuilder<DirectoryEntry> {
+        match self.kind() {
+            DirectoryEntry::Section | DirectoryEntry::Supersection
+                => {
+                    let ai = a as u32;
+                    self.0.set_bit(12,ai.get_bit(2));
+                    self.0.set_bits(2..4,ai.get_bits(0..2));
+                },
+            _   => { assert!(false); }
+        }
+        self
+    }
+
+    fn get_mem_attr(&self) -> Option<MemAttr> {
+        match self.kind() {
+            DirectoryEntry::Section | DirectoryEntry::Supersection
+                => Some(MemAttr::from_bits((self.0.get_bits(12..13) << 2) | self.0.get_bits(2..4))),
+            _   => None
+        }
+    }
+
+    fn sw_flag(mut self, f: SoftwareFlag, b: bool) -> MemoryBuilder<DirectoryEntry> {
+        match self.kind() {
+            DirectoryEntry::Section | DirectoryEntry::Supersection
+                => {
+                    let flags = self.0.get_bits(13..15);
+                    self.0.set_bits(13..15, if b { flags | f as u32 } else { flags & !(f as u32) });
+                },
+            _   => { assert!(false); }
+        }
+        self
+    }
+
+    fn has_sw_flag(&self, f: SoftwareFlag) -> bool {
+        match self.kind() {
+            DirectoryEntry::Section | DirectoryEntry::Supersection
+                => self.0.get_bits(13..15) & f as u32 != 0,
+            _   => false
+        }
+    }
 }
 
 /// Implementation für Einträge in Seitentabellen
@@ -298,6 +439,108 @@ impl EntryBuilder<TableEntry> for MemoryBuilder<TableEntry> {
         self.0.clone()
     }
 
+    fn from_entry(e: PageTableEntry) -> MemoryBuilder<TableEntry> {
+        MemoryBuilder::<TableEntry>(e,PhantomData)
+    }
+
+    fn get_base_addr(&self) -> Option<Address> {
+        match self.kind() {
+            TableEntry::LargePage => Some((self.0.get_bits(16..32) << 16) as Address),
+            TableEntry::SmallPage => Some((self.0.get_bits(12..32) << 12) as Address),
+            _                     => None
+        }
+    }
+
+    fn get_mem_type(&self) -> Option<MemType> {
+        match self.kind() {
+            TableEntry::LargePage
+                => MemType::from_bits((self.0.get_bits(12..15) << 2) | self.0.get_bits(2..4)),
+            TableEntry::SmallPage
+                => MemType::from_bits((self.0.get_bits(6..9) << 2) | self.0.get_bits(2..4)),
+            _   => None
+        }
+    }
+
+    fn get_rights(&self) -> Option<MemoryAccessRight> {
+        match self.kind() {
+            TableEntry::LargePage | TableEntry::SmallPage
+                => MemoryAccessRight::from_bits((self.0.get_bits(9..10) << 2) | self.0.get_bits(4..6)),
+            _   => None
+        }
+    }
+
+    /// Seitentabelleneinträge haben keine eigene Domain; es gilt die Domain des
+    /// Seitenverzeichniseintrages.
+    fn get_domain(&self) -> Option<u32> {
+        None
+    }
+
+    fn is_shared(&self) -> bool {
+        match self.kind() {
+            TableEntry::LargePage | TableEntry::SmallPage => self.0.get_bit(10),
+            _                                             => false
+        }
+    }
+
+    fn is_process_specific(&self) -> bool {
+        match self.kind() {
+            TableEntry::LargePage | TableEntry::SmallPage => self.0.get_bit(11),
+            _                                             => false
+        }
+    }
+
+    fn is_no_execute(&self) -> bool {
+        match self.kind() {
+            TableEntry::LargePage => self.0.get_bit(15),
+            TableEntry::SmallPage => self.0.get_bit(0),
+            _                     => false
+        }
+    }
+
+    fn mem_attr(mut self, a: MemAttr) -> MemoryBuilder<TableEntry> {
+        let ai = a as u32;
+        match self.kind() {
+            TableEntry::LargePage => {
+                self.0.set_bit(12,ai.get_bit(2));
+                self.0.set_bits(2..4,ai.get_bits(0..2));
+            },
+            TableEntry::SmallPage => {
+                self.0.set_bit(6,ai.get_bit(2));
+                self.0.set_bits(2..4,ai.get_bits(0..2));
+            },
+            _   => {}
+        }
+        self
+    }
+
+    fn get_mem_attr(&self) -> Option<MemAttr> {
+        match self.kind() {
+            TableEntry::LargePage
+                => Some(MemAttr::from_bits((self.0.get_bits(12..13) << 2) | self.0.get_bits(2..4))),
+            TableEntry::SmallPage
+                => Some(MemAttr::from_bits((self.0.get_bits(6..7) << 2) | self.0.get_bits(2..4))),
+            _   => None
+        }
+    }
+
+    fn sw_flag(mut self, f: SoftwareFlag, b: bool) -> MemoryBuilder<TableEntry> {
+        let bits = match self.kind() {
+            TableEntry::LargePage => 13..15,
+            TableEntry::SmallPage => 7..9,
+            _                     => return self
+        };
+        let flags = self.0.get_bits(bits.clone());
+        self.0.set_bits(bits, if b { flags | f as u32 } else { flags & !(f as u32) });
+        self
+    }
+
+    fn has_sw_flag(&self, f: SoftwareFlag) -> bool {
+        match self.kind() {
+            TableEntry::LargePage => self.0.get_bits(13..15) & f as u32 != 0,
+            TableEntry::SmallPage => self.0.get_bits(7..9) & f as u32 != 0,
+            _                     => false
+        }
+    }
 }
 
 /*
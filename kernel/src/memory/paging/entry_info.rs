@@ -0,0 +1,441 @@
+#![warn(missing_docs)]
+//! Ausgewertete Einträge in das Seitenverzeichnis und in Seitentabellen.
+//!
+//! `MemoryBuilder` erzeugt Einträge aus einzelnen Feldern. `DirectoryEntryInfo` und
+//! `TableEntryInfo` sind die Umkehrung: sie zerlegen einen rohen Eintrag in seine Felder.
+//! Für jeden Eintrag, der mit dem Builder erzeugt wurde, gilt
+//!
+//! ```text
+//! EntryInfo::from(e).entry() == e
+//! ```
+//!
+//! Bits, die der Builder nicht setzt (z.B. _non secure_), gehen bei der Rückumwandlung verloren.
+//!
+//! Der Speichertyp wird zusätzlich roh als `MemAttr` (TEX[0]:C:B) und Software-Flags
+//! (TEX[2:1]) gespeichert und daraus wiederhergestellt. Damit gilt die Gleichung auch für
+//! Einträge mit TEX-Remapping, bei denen `mem_type` `None` ist, weil die Bits keinem
+//! `MemType` entsprechen.
+use super::{Address, MemType, MemoryAccessRight, MemAttr, SoftwareFlag};
+use super::builder::{MemoryBuilder, EntryBuilder, DirectoryEntry, TableEntry, PageDirectoryEntry, PageTableEntry};
+
+/// Software-Flags eines Builders als Bitmaske
+fn sw_flags<T, B: EntryBuilder<T>>(b: &B) -> u32 {
+    let mut flags = 0;
+    for f in &[SoftwareFlag::Dirty, SoftwareFlag::CopyOnWrite] {
+        if b.has_sw_flag(*f) {
+            flags |= *f as u32;
+        }
+    }
+    flags
+}
+
+/// Felder eines Eintrags in das Seitenverzeichnis
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct DirectoryEntryInfo {
+    kind:             DirectoryEntry,
+    base_addr:        Option<Address>,
+    mem_type:         Option<MemType>,
+    mem_attr:         Option<MemAttr>,
+    sw_flags:         u32,
+    rights:           Option<MemoryAccessRight>,
+    domain:           Option<u32>,
+    shared:           bool,
+    process_specific: bool,
+    no_execute:       bool,
+}
+
+impl DirectoryEntryInfo {
+    /// Wertet den Eintrag eines Builders aus
+    pub fn from_builder(b: &MemoryBuilder<DirectoryEntry>) -> DirectoryEntryInfo {
+        let kind = match b.kind() {
+            DirectoryEntry::AltFault => DirectoryEntry::Fault,
+            k                        => k
+        };
+        DirectoryEntryInfo {
+            kind:             kind,
+            base_addr:        b.get_base_addr(),
+            mem_type:         b.get_mem_type(),
+            mem_attr:         b.get_mem_attr(),
+            sw_flags:         sw_flags(b),
+            rights:           b.get_rights(),
+            domain:           b.get_domain(),
+            shared:           b.is_shared(),
+            process_specific: b.is_process_specific(),
+            no_execute:       b.is_no_execute(),
+        }
+    }
+
+    /// Art des Eintrags; reservierte Einträge werden als `Fault` gemeldet
+    pub fn kind(&self) -> DirectoryEntry {
+        self.kind
+    }
+
+    /// Basisadresse der Section, Supersection oder Seitentabelle
+    pub fn base_addr(&self) -> Option<Address> {
+        self.base_addr
+    }
+
+    /// Speichertyp (nur Section und Supersection); `None` bei TEX-Remapping-Werten ohne
+    /// entsprechenden `MemType`
+    pub fn mem_type(&self) -> Option<MemType> {
+        self.mem_type
+    }
+
+    /// Index in die Remapping-Tabelle (TEX[0]:C:B), siehe `EntryBuilder::get_mem_attr`
+    pub fn mem_attr(&self) -> Option<MemAttr> {
+        self.mem_attr
+    }
+
+    /// Ist das Software-Flag (TEX[2:1]) gesetzt?
+    pub fn has_sw_flag(&self, f: SoftwareFlag) -> bool {
+        self.sw_flags & f as u32 != 0
+    }
+
+    /// Zugriffsrechte (nur Section und Supersection)
+    pub fn rights(&self) -> Option<MemoryAccessRight> {
+        self.rights
+    }
+
+    /// Domain (nur Section und Seitentabelle)
+    pub fn domain(&self) -> Option<u32> {
+        self.domain
+    }
+
+    /// Gemeinsamer Speicher
+    pub fn shared(&self) -> bool {
+        self.shared
+    }
+
+    /// Prozessspezifisch (nG)
+    pub fn process_specific(&self) -> bool {
+        self.process_specific
+    }
+
+    /// Nicht ausführbar (XN)
+    pub fn no_execute(&self) -> bool {
+        self.no_execute
+    }
+
+    /// Erzeugt einen Builder, der den Eintrag wiederherstellt
+    pub fn builder(&self) -> MemoryBuilder<DirectoryEntry> {
+        let mut b = MemoryBuilder::<DirectoryEntry>::new_entry(self.kind);
+        if let Some(a) = self.base_addr {
+            b = b.base_addr(a);
+        }
+        // TEX:C:B vollständig aus Index und Software-Flags, nicht aus `mem_type`
+        if let Some(a) = self.mem_attr {
+            b = b.mem_attr(a)
+                .sw_flag(SoftwareFlag::Dirty, self.has_sw_flag(SoftwareFlag::Dirty))
+                .sw_flag(SoftwareFlag::CopyOnWrite, self.has_sw_flag(SoftwareFlag::CopyOnWrite));
+        }
+        if let Some(r) = self.rights {
+            b = b.rights(r);
+        }
+        if let Some(d) = self.domain {
+            b = b.domain(d);
+        }
+        match self.kind {
+            DirectoryEntry::Section | DirectoryEntry::Supersection => {
+                b.shared(self.shared)
+                    .process_specific(self.process_specific)
+                    .no_execute(self.no_execute)
+            },
+            _ => b
+        }
+    }
+
+    /// Rohdarstellung des Eintrags
+    pub fn entry(&self) -> PageDirectoryEntry {
+        self.builder().entry()
+    }
+}
+
+impl From<PageDirectoryEntry> for DirectoryEntryInfo {
+    fn from(e: PageDirectoryEntry) -> DirectoryEntryInfo {
+        DirectoryEntryInfo::from_builder(&MemoryBuilder::<DirectoryEntry>::from_entry(e))
+    }
+}
+
+impl From<DirectoryEntryInfo> for PageDirectoryEntry {
+    fn from(info: DirectoryEntryInfo) -> PageDirectoryEntry {
+        info.entry()
+    }
+}
+
+/// Felder eines Eintrags in eine Seitentabelle
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct TableEntryInfo {
+    kind:             TableEntry,
+    base_addr:        Option<Address>,
+    mem_type:         Option<MemType>,
+    mem_attr:         Option<MemAttr>,
+    sw_flags:         u32,
+    rights:           Option<MemoryAccessRight>,
+    shared:           bool,
+    process_specific: bool,
+    no_execute:       bool,
+}
+
+impl TableEntryInfo {
+    /// Wertet den Eintrag eines Builders aus
+    pub fn from_builder(b: &MemoryBuilder<TableEntry>) -> TableEntryInfo {
+        TableEntryInfo {
+            kind:             b.kind(),
+            base_addr:        b.get_base_addr(),
+            mem_type:         b.get_mem_type(),
+            mem_attr:         b.get_mem_attr(),
+            sw_flags:         sw_flags(b),
+            rights:           b.get_rights(),
+            shared:           b.is_shared(),
+            process_specific: b.is_process_specific(),
+            no_execute:       b.is_no_execute(),
+        }
+    }
+
+    /// Art des Eintrags
+    pub fn kind(&self) -> TableEntry {
+        self.kind
+    }
+
+    /// Basisadresse der Seite
+    pub fn base_addr(&self) -> Option<Address> {
+        self.base_addr
+    }
+
+    /// Speichertyp; `None` bei TEX-Remapping-Werten ohne entsprechenden `MemType`
+    pub fn mem_type(&self) -> Option<MemType> {
+        self.mem_type
+    }
+
+    /// Index in die Remapping-Tabelle (TEX[0]:C:B), siehe `EntryBuilder::get_mem_attr`
+    pub fn mem_attr(&self) -> Option<MemAttr> {
+        self.mem_attr
+    }
+
+    /// Ist das Software-Flag (TEX[2:1]) gesetzt?
+    pub fn has_sw_flag(&self, f: SoftwareFlag) -> bool {
+        self.sw_flags & f as u32 != 0
+    }
+
+    /// Zugriffsrechte
+    pub fn rights(&self) -> Option<MemoryAccessRight> {
+        self.rights
+    }
+
+    /// Gemeinsamer Speicher
+    pub fn shared(&self) -> bool {
+        self.shared
+    }
+
+    /// Prozessspezifisch (nG)
+    pub fn process_specific(&self) -> bool {
+        self.process_specific
+    }
+
+    /// Nicht ausführbar (XN)
+    pub fn no_execute(&self) -> bool {
+        self.no_execute
+    }
+
+    /// Erzeugt einen Builder, der den Eintrag wiederherstellt
+    pub fn builder(&self) -> MemoryBuilder<TableEntry> {
+        let mut b = MemoryBuilder::<TableEntry>::new_entry(self.kind);
+        if let Some(a) = self.base_addr {
+            b = b.base_addr(a);
+        }
+        // TEX:C:B vollständig aus Index und Software-Flags, nicht aus `mem_type`
+        if let Some(a) = self.mem_attr {
+            b = b.mem_attr(a)
+                .sw_flag(SoftwareFlag::Dirty, self.has_sw_flag(SoftwareFlag::Dirty))
+                .sw_flag(SoftwareFlag::CopyOnWrite, self.has_sw_flag(SoftwareFlag::CopyOnWrite));
+        }
+        if let Some(r) = self.rights {
+            b = b.rights(r);
+        }
+        b.shared(self.shared)
+            .process_specific(self.process_specific)
+            .no_execute(self.no_execute)
+    }
+
+    /// Rohdarstellung des Eintrags
+    pub fn entry(&self) -> PageTableEntry {
+        self.builder().entry()
+    }
+}
+
+impl From<PageTableEntry> for TableEntryInfo {
+    fn from(e: PageTableEntry) -> TableEntryInfo {
+        TableEntryInfo::from_builder(&MemoryBuilder::<TableEntry>::from_entry(e))
+    }
+}
+
+impl From<TableEntryInfo> for PageTableEntry {
+    fn from(info: TableEntryInfo) -> PageTableEntry {
+        info.entry()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEM_TYPES: [MemType;6] = [MemType::StronglyOrdered, MemType::SharedDevice, MemType::ExclusiveDevice,
+                                    MemType::NormalUncashed, MemType::NormalWT, MemType::NormalWB];
+    const RIGHTS: [MemoryAccessRight;6] = [MemoryAccessRight::SysNonUsrNone, MemoryAccessRight::SysRwUsrNone,
+                                           MemoryAccessRight::SysRwUsrRo, MemoryAccessRight::SysRwUsrRw,
+                                           MemoryAccessRight::SysRoUsrNone, MemoryAccessRight::SysRoUsrRw];
+    const MEM_ATTRS: [MemAttr;8] = [MemAttr::StronglyOrdered, MemAttr::Device, MemAttr::NormalWT,
+                                    MemAttr::NormalWBNoAlloc, MemAttr::NormalUncached,
+                                    MemAttr::NormalInnerWBOuterUncached, MemAttr::NormalInnerUncachedOuterWB,
+                                    MemAttr::NormalWB];
+    const BOOLS: [bool;2] = [false, true];
+
+    /// Alle Kombinationen von Schalter-Bits (shared, nG, XN)
+    fn flags() -> [(bool,bool,bool);8] {
+        let mut v = [(false, false, false);8];
+        for i in 0..8 {
+            v[i] = (i & 1 != 0, i & 2 != 0, i & 4 != 0);
+        }
+        v
+    }
+
+    #[test]
+    fn section_round_trip() {
+        for t in MEM_TYPES.iter() {
+            for r in RIGHTS.iter() {
+                for d in 0..16 {
+                    for &(s, ng, xn) in flags().iter() {
+                        let e = MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::Section)
+                            .base_addr(0x1230_0000)
+                            .mem_type(*t)
+                            .rights(*r)
+                            .domain(d)
+                            .shared(s)
+                            .process_specific(ng)
+                            .no_execute(xn)
+                            .entry();
+                        let info = DirectoryEntryInfo::from(e);
+                        assert_eq!(info.kind(), DirectoryEntry::Section);
+                        assert_eq!(info.base_addr(), Some(0x1230_0000));
+                        assert_eq!(info.mem_type(), Some(*t));
+                        assert_eq!(info.rights(), Some(*r));
+                        assert_eq!(info.domain(), Some(d));
+                        assert_eq!((info.shared(), info.process_specific(), info.no_execute()), (s, ng, xn));
+                        assert_eq!(info.entry(), e);
+                        assert_eq!(PageDirectoryEntry::from(info), e);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn supersection_round_trip() {
+        for t in MEM_TYPES.iter() {
+            for r in RIGHTS.iter() {
+                for &(s, ng, xn) in flags().iter() {
+                    let e = MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::Supersection)
+                        .base_addr(0x5000_0000)
+                        .mem_type(*t)
+                        .rights(*r)
+                        .shared(s)
+                        .process_specific(ng)
+                        .no_execute(xn)
+                        .entry();
+                    let info = DirectoryEntryInfo::from(e);
+                    assert_eq!(info.kind(), DirectoryEntry::Supersection);
+                    assert_eq!(info.base_addr(), Some(0x5000_0000));
+                    assert_eq!(info.mem_type(), Some(*t));
+                    assert_eq!(info.rights(), Some(*r));
+                    // Supersections haben keine Domain
+                    assert_eq!(info.domain(), None);
+                    assert_eq!((info.shared(), info.process_specific(), info.no_execute()), (s, ng, xn));
+                    assert_eq!(info.entry(), e);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn coarse_table_and_fault_round_trip() {
+        for d in 0..16 {
+            let e = MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::CoarsePageTable)
+                .base_addr(0x0012_3400)
+                .domain(d)
+                .entry();
+            let info = DirectoryEntryInfo::from(e);
+            assert_eq!(info.kind(), DirectoryEntry::CoarsePageTable);
+            assert_eq!(info.base_addr(), Some(0x0012_3400));
+            assert_eq!(info.domain(), Some(d));
+            assert_eq!(info.mem_type(), None);
+            assert_eq!(info.entry(), e);
+        }
+        assert_eq!(DirectoryEntryInfo::from(0).entry(), 0);
+        assert_eq!(TableEntryInfo::from(0).entry(), 0);
+    }
+
+    #[test]
+    fn page_round_trip() {
+        for &(kind, base) in &[(TableEntry::SmallPage, 0x0004_5000), (TableEntry::LargePage, 0x0123_0000)] {
+            for t in MEM_TYPES.iter() {
+                for r in RIGHTS.iter() {
+                    for &(s, ng, xn) in flags().iter() {
+                        let e = MemoryBuilder::<TableEntry>::new_entry(kind)
+                            .base_addr(base)
+                            .mem_type(*t)
+                            .rights(*r)
+                            .shared(s)
+                            .process_specific(ng)
+                            .no_execute(xn)
+                            .entry();
+                        let info = TableEntryInfo::from(e);
+                        assert_eq!(info.kind(), kind);
+                        assert_eq!(info.base_addr(), Some(base));
+                        assert_eq!(info.mem_type(), Some(*t));
+                        assert_eq!(info.rights(), Some(*r));
+                        assert_eq!((info.shared(), info.process_specific(), info.no_execute()), (s, ng, xn));
+                        assert_eq!(info.entry(), e);
+                        assert_eq!(PageTableEntry::from(info), e);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tex_remap_round_trip() {
+        for a in MEM_ATTRS.iter() {
+            for dirty in BOOLS.iter() {
+                for cow in BOOLS.iter() {
+                    let e = MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::Section)
+                        .base_addr(0x0010_0000)
+                        .rights(MemoryAccessRight::SysRwUsrRw)
+                        .mem_attr(*a)
+                        .sw_flag(SoftwareFlag::Dirty, *dirty)
+                        .sw_flag(SoftwareFlag::CopyOnWrite, *cow)
+                        .entry();
+                    let info = DirectoryEntryInfo::from(e);
+                    assert_eq!(info.mem_attr(), Some(*a));
+                    assert_eq!(info.has_sw_flag(SoftwareFlag::Dirty), *dirty);
+                    assert_eq!(info.has_sw_flag(SoftwareFlag::CopyOnWrite), *cow);
+                    assert_eq!(info.entry(), e);
+
+                    for &kind in &[TableEntry::SmallPage, TableEntry::LargePage] {
+                        let e = MemoryBuilder::<TableEntry>::new_entry(kind)
+                            .base_addr(0x0001_0000)
+                            .mem_attr(*a)
+                            .sw_flag(SoftwareFlag::Dirty, *dirty)
+                            .sw_flag(SoftwareFlag::CopyOnWrite, *cow)
+                            .no_execute(true)
+                            .entry();
+                        let info = TableEntryInfo::from(e);
+                        assert_eq!(info.mem_attr(), Some(*a));
+                        assert_eq!(info.has_sw_flag(SoftwareFlag::Dirty), *dirty);
+                        assert_eq!(info.has_sw_flag(SoftwareFlag::CopyOnWrite), *cow);
+                        assert_eq!(info.entry(), e);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -5,7 +5,7 @@ use core::cmp::min;
 use core::usize;
 use core::mem;
 
-use sync::no_concurrency::NoConcurrency;
+use sync::{IrqSpinLock, IrqSpinLockGuard};
 use super::{Address, AddressRange, Frame, MEM_SIZE, PAGE_SIZE};
 
 const BITVECTOR_SIZE: usize = (MEM_SIZE / (PAGE_SIZE * mem::size_of::<u64>() * 8)) as usize;
@@ -42,9 +42,9 @@ impl FrameManager {
         }
     }
 
-    /// Gibt eine Referenz auf Framemanager-Singleton zurück
-    pub fn get() -> &'static mut FrameManager {
-        FRAME_MANAGER.get()
+    /// Belegt das Framemanager-Singleton
+    pub fn get() -> IrqSpinLockGuard<'static, FrameManager> {
+        FRAME_MANAGER.lock()
     }
 
     /// Markiert einen Frame als reserviert und gibt ihn im Erfolgsfall zurück.
@@ -141,5 +141,5 @@ impl FrameManager {
     }
 }
 
-/// Das Singleton für den Framemanager, nicht geschützt vor nebenläufigen Zugriff
-static FRAME_MANAGER: NoConcurrency<FrameManager> = NoConcurrency::new(FrameManager::new());
+/// Das Singleton für den Framemanager
+static FRAME_MANAGER: IrqSpinLock<FrameManager> = IrqSpinLock::new(FrameManager::new());
@@ -3,8 +3,9 @@
 //! Der Code geht von folgender Konfiguration aus:
 //!
 //!  - keine Rückwärtskompatibilität zu ARMv5.
-//!  - TEX-Remapping aus (muss wahrscheinlich für spätere Unterstützung von virtuellen Speicher
-//!    geändert werden)
+//!  - TEX-Remapping ist standardmäßig aus; Speichertypen werden dann mit `MemType` angegeben.
+//!    Bei aktiviertem TEX-Remapping (`MMU::enable_tex_remap`) werden Speichertypen mit `MemAttr`
+//!    angegeben und die frei gewordenen Bits können als `SoftwareFlag` genutzt werden.
 use core::usize;
 use core::ops::Range;
 
@@ -16,6 +17,8 @@ pub const MEM_SIZE:          usize = 512*1024*1024;
 pub const MAX_ADDRESS:       usize = usize::MAX;
 pub const PAGE_SIZE:         usize = 4*1024;
 pub const SECTION_SIZE:      usize = 1024 * 1024;
+pub const LARGE_PAGE_SIZE:   usize = 64*1024;
+pub const SUPERSECTION_SIZE: usize = 16 * SECTION_SIZE;
 #[allow(dead_code)]
 pub const PAGES_PER_SECTION: usize = SECTION_SIZE / PAGE_SIZE; // 256
 
@@ -28,6 +31,7 @@ pub const PAGES_PER_SECTION: usize = SECTION_SIZE / PAGE_SIZE; // 256
 ///  - Write trough => ohne Allocate
 ///  - Write back   => mit Allocate
 #[allow(dead_code)]
+#[derive(Copy,Clone,Debug,PartialEq)]
 #[repr(u32)]
 pub enum MemType {
     StronglyOrdered = 0b00000,
@@ -38,6 +42,24 @@ pub enum MemType {
     NormalWB        = 0b00111
 }
 
+impl MemType {
+    /// Speichertyp aus der Bitkombination TEX:C:B.
+    ///
+    /// Für Kombinationen, die keinem der unterstützten Typen entsprechen, wird `None`
+    /// zurückgegeben.
+    pub fn from_bits(bits: u32) -> Option<MemType> {
+        match bits {
+            0b00000 => Some(MemType::StronglyOrdered),
+            0b00001 => Some(MemType::SharedDevice),
+            0b01000 => Some(MemType::ExclusiveDevice),
+            0b00100 => Some(MemType::NormalUncashed),
+            0b00010 => Some(MemType::NormalWT),
+            0b00111 => Some(MemType::NormalWB),
+            _       => None
+        }
+    }
+}
+
 /// Zugriffsrechte auf eine Speicherseite oder -section.
 ///
 /// Bei den Zugriffsrechten wird zwischen privilegierten (Sys) und nichtpreviligierten
@@ -48,6 +70,7 @@ pub enum MemType {
 ///  - Ro: Nur Lesen
 ///  - None: weder Lesen noch Schreiben
 #[allow(dead_code)]
+#[derive(Copy,Clone,Debug,PartialEq)]
 #[repr(u32)]
 pub enum MemoryAccessRight {
     SysNonUsrNone   = 0b000,
@@ -58,6 +81,40 @@ pub enum MemoryAccessRight {
     SysRoUsrRw      = 0b110
 }
 
+impl MemoryAccessRight {
+    /// Zugriffsrechte aus der Bitkombination APX:AP.
+    ///
+    /// Für reservierte Kombinationen wird `None` zurückgegeben.
+    pub fn from_bits(bits: u32) -> Option<MemoryAccessRight> {
+        match bits {
+            0b000 => Some(MemoryAccessRight::SysNonUsrNone),
+            0b001 => Some(MemoryAccessRight::SysRwUsrNone),
+            0b010 => Some(MemoryAccessRight::SysRwUsrRo),
+            0b011 => Some(MemoryAccessRight::SysRwUsrRw),
+            0b101 => Some(MemoryAccessRight::SysRoUsrNone),
+            0b110 => Some(MemoryAccessRight::SysRoUsrRw),
+            _     => None
+        }
+    }
+
+    /// Darf im Usermodus gelesen werden?
+    pub fn user_readable(&self) -> bool {
+        match *self {
+            MemoryAccessRight::SysRwUsrRo | MemoryAccessRight::SysRwUsrRw |
+            MemoryAccessRight::SysRoUsrRw => true,
+            _                             => false
+        }
+    }
+
+    /// Darf im Usermodus geschrieben werden?
+    ///
+    /// APX:AP = 0b110 (`SysRoUsrRw`) erlaubt laut ARM1176JZF-S TRM (Tabelle 6-2) in beiden
+    /// Modi nur Lesen.
+    pub fn user_writable(&self) -> bool {
+        *self == MemoryAccessRight::SysRwUsrRw
+    }
+}
+
 /// Art des erlaubten Zugriffs für eine gegebene Speicherdomaine.
 #[allow(dead_code)]
 pub enum DomainAccess {
@@ -71,7 +128,15 @@ pub enum DomainAccess {
 }
 
 mod builder;
-pub use self::builder::{MemoryBuilder,EntryBuilder,DirectoryEntry,TableEntry};
+pub use self::builder::{MemoryBuilder,EntryBuilder,DirectoryEntry,TableEntry,
+                        PageDirectoryEntry,PageTableEntry};
+
+mod tex_remap;
+pub use self::tex_remap::{MemAttr,MemAttribute,MemRegion,CachePolicy,SoftwareFlag,DEFAULT_MEM_ATTRIBUTES,
+                          prrr,nmrr};
+
+mod entry_info;
+pub use self::entry_info::{DirectoryEntryInfo,TableEntryInfo};
 
 mod page_table;
 pub use self::page_table::PageTable;
@@ -87,3 +152,6 @@ pub use self::frame_manager::{FrameManager,FrameError};
 
 mod page_directory;
 pub use self::page_directory::PageDirectory;
+
+mod walker;
+pub use self::walker::{PageWalker,Translation,MappingKind,MappingAttributes,MapRange,dump_memory_map};
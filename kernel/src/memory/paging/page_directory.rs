@@ -2,7 +2,7 @@ use core::ops::{Index, IndexMut};
 use super::builder::{PageDirectoryEntry,DirectoryEntry};
 use super::Address;
 
-use sync::no_concurrency::NoConcurrency;
+use sync::{IrqSpinLock, IrqSpinLockGuard};
 
 #[repr(C)]
 #[repr(align(16384))]
@@ -32,14 +32,19 @@ impl PageDirectory {
         }
     }
 
-    /// Gibt eine Referenz auf Seitenverzeichnis-Singleton zurück
-    pub fn get() -> &'static mut PageDirectory {
-        PAGE_DIR.get()
+    /// Belegt das Seitenverzeichnis-Singleton
+    pub fn get() -> IrqSpinLockGuard<'static, PageDirectory> {
+        PAGE_DIR.lock()
     }
 
     /// Gibt die Addresse des Seitenverzeichnisses zurück
     pub fn addr() -> Address {
-        &PAGE_DIR as *const _ as Address
+        PAGE_DIR.data_ptr() as Address
+    }
+
+    /// Gibt alle Einträge des Seitenverzeichnisses zurück
+    pub fn entries(&self) -> &[PageDirectoryEntry] {
+        &self.dir
     }
 }
 
@@ -60,5 +65,5 @@ impl Index<usize> for PageDirectory {
     }
 }
 
-/// Das Singleton für das Seitenverzeichnis, nicht geschützt vor nebenläufigen Zugriff
-static PAGE_DIR: NoConcurrency<PageDirectory> = NoConcurrency::new(PageDirectory::new());
+/// Das Singleton für das Seitenverzeichnis
+static PAGE_DIR: IrqSpinLock<PageDirectory> = IrqSpinLock::new(PageDirectory::new());
@@ -31,6 +31,11 @@ impl PageTable {
     pub fn addr(&self) -> Address {
         self as *const _ as usize
     }
+
+    /// Gibt alle Einträge der Tabelle zurück
+    pub fn entries(&self) -> &[PageTableEntry] {
+        &self.table
+    }
 }
 
 impl Index<usize> for PageTable {
@@ -0,0 +1,147 @@
+#![warn(missing_docs)]
+//! Speichertypen bei aktiviertem TEX-Remapping.
+//!
+//! Ohne TEX-Remapping legen die Bits TEX[2:0], C und B eines Eintrags den Speichertyp fest
+//! (siehe `MemType`). Bei aktiviertem TEX-Remapping (CP15c1, Bit 28) bilden nur TEX[0], C und B
+//! einen Index in eine Tabelle mit acht Speicherattributen. Die Tabelle steht in den Registern
+//! PRRR (_Primary Region Remap Register_) und NMRR (_Normal Memory Remap Register_), siehe
+//! ARM ARM B4.4.
+//!
+//! Die Bits TEX[2:1] werden dann von der MMU ignoriert und stehen dem Betriebssystem zur
+//! Verfügung (`SoftwareFlag`).
+//!
+//! Die Tabelle `DEFAULT_MEM_ATTRIBUTES` ist so gewählt, dass alle `MemType`-Werte außer
+//! `ExclusiveDevice` auch mit Remapping den gleichen Speichertyp beschreiben.
+
+/// Grundlegende Art eines Speicherbereichs
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum MemRegion {
+    /// Strikt geordnet
+    StronglyOrdered = 0b00,
+    /// Gerätespeicher
+    Device          = 0b01,
+    /// Normaler Speicher
+    Normal          = 0b10,
+}
+
+/// Cacheverhalten für normalen Speicher (getrennt für inneren und äußeren Cache)
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum CachePolicy {
+    /// Kein Caching
+    NonCacheable        = 0b00,
+    /// Write back mit Allocate
+    WriteBackAllocate   = 0b01,
+    /// Write through ohne Allocate
+    WriteThrough        = 0b10,
+    /// Write back ohne Allocate
+    WriteBackNoAllocate = 0b11,
+}
+
+/// Eintrag der Remapping-Tabelle
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct MemAttribute {
+    /// Art des Speichers
+    pub region: MemRegion,
+    /// Cacheverhalten des inneren Caches (nur für `MemRegion::Normal` relevant)
+    pub inner:  CachePolicy,
+    /// Cacheverhalten des äußeren Caches (nur für `MemRegion::Normal` relevant)
+    pub outer:  CachePolicy,
+}
+
+impl MemAttribute {
+    /// Attribut für normalen Speicher
+    pub const fn normal(inner: CachePolicy, outer: CachePolicy) -> MemAttribute {
+        MemAttribute { region: MemRegion::Normal, inner: inner, outer: outer }
+    }
+
+    /// Attribut für Geräte- oder strikt geordneten Speicher
+    pub const fn uncached(region: MemRegion) -> MemAttribute {
+        MemAttribute { region: region, inner: CachePolicy::NonCacheable, outer: CachePolicy::NonCacheable }
+    }
+}
+
+/// Index in die Remapping-Tabelle (TEX[0]:C:B)
+#[derive(Copy,Clone,Debug,PartialEq)]
+#[repr(u32)]
+#[allow(missing_docs)]
+pub enum MemAttr {
+    StronglyOrdered            = 0b000,
+    Device                     = 0b001,
+    NormalWT                   = 0b010,
+    NormalWBNoAlloc            = 0b011,
+    NormalUncached             = 0b100,
+    NormalInnerWBOuterUncached = 0b101,
+    NormalInnerUncachedOuterWB = 0b110,
+    NormalWB                   = 0b111,
+}
+
+impl MemAttr {
+    /// Index aus den Bits TEX[0]:C:B
+    pub fn from_bits(bits: u32) -> MemAttr {
+        match bits & 0b111 {
+            0b000 => MemAttr::StronglyOrdered,
+            0b001 => MemAttr::Device,
+            0b010 => MemAttr::NormalWT,
+            0b011 => MemAttr::NormalWBNoAlloc,
+            0b100 => MemAttr::NormalUncached,
+            0b101 => MemAttr::NormalInnerWBOuterUncached,
+            0b110 => MemAttr::NormalInnerUncachedOuterWB,
+            _     => MemAttr::NormalWB,
+        }
+    }
+
+    /// Attribut aus der Standardtabelle
+    pub fn attribute(&self) -> MemAttribute {
+        DEFAULT_MEM_ATTRIBUTES[*self as usize]
+    }
+}
+
+/// Standardbelegung der Remapping-Tabelle, Index entsprechend `MemAttr`
+pub const DEFAULT_MEM_ATTRIBUTES: [MemAttribute;8] = [
+    MemAttribute::uncached(MemRegion::StronglyOrdered),
+    MemAttribute::uncached(MemRegion::Device),
+    MemAttribute::normal(CachePolicy::WriteThrough, CachePolicy::WriteThrough),
+    MemAttribute::normal(CachePolicy::WriteBackNoAllocate, CachePolicy::WriteBackNoAllocate),
+    MemAttribute::normal(CachePolicy::NonCacheable, CachePolicy::NonCacheable),
+    MemAttribute::normal(CachePolicy::WriteBackAllocate, CachePolicy::NonCacheable),
+    MemAttribute::normal(CachePolicy::NonCacheable, CachePolicy::WriteBackAllocate),
+    MemAttribute::normal(CachePolicy::WriteBackAllocate, CachePolicy::WriteBackAllocate),
+];
+
+/// Vom Betriebssystem genutzte Bits TEX[2:1] (nur bei aktiviertem TEX-Remapping)
+///
+/// Es gibt kein Flag für "Seite wurde gelesen" (_accessed_): Die beiden Bits sind mit
+/// `Dirty` und `CopyOnWrite` belegt, und die Einträge haben sonst keine freien Bits. Die
+/// Hardware sieht dafür das Access Flag vor (AP[0] bei gesetztem SCTLR.AFE); das verkleinert
+/// aber die Zugriffsrechte auf das vereinfachte Modell, das `MemoryAccessRight` nicht
+/// abbildet.
+#[derive(Copy,Clone,Debug,PartialEq)]
+#[repr(u32)]
+pub enum SoftwareFlag {
+    /// Seite wurde beschrieben
+    Dirty       = 0b01,
+    /// Seite wird bei Schreibzugriff kopiert
+    CopyOnWrite = 0b10,
+}
+
+/// Berechnet den Wert des PRRR für die gegebene Tabelle.
+///
+/// Gerätespeicher und normaler Speicher werden unabhängig vom S-Bit als gemeinsam bzw.
+/// entsprechend dem S-Bit behandelt (DS0 = DS1 = 1, NS0 = 0, NS1 = 1).
+pub fn prrr(table: &[MemAttribute;8]) -> u32 {
+    let mut reg: u32 = 0;
+    for (n, a) in table.iter().enumerate() {
+        reg |= (a.region as u32) << (2 * n);
+    }
+    reg | 1 << 16 | 1 << 17 | 1 << 19
+}
+
+/// Berechnet den Wert des NMRR für die gegebene Tabelle.
+pub fn nmrr(table: &[MemAttribute;8]) -> u32 {
+    let mut reg: u32 = 0;
+    for (n, a) in table.iter().enumerate() {
+        reg |= (a.inner as u32) << (2 * n);
+        reg |= (a.outer as u32) << (2 * n + 16);
+    }
+    reg
+}
@@ -0,0 +1,439 @@
+#![warn(missing_docs)]
+//! Auswertung von Seitenverzeichnis und Seitentabellen (_page table walk_).
+//!
+//! Der `PageWalker` liest die Einträge so, wie es die MMU bei der Adressübersetzung tut.
+//! Damit kann nach `init_paging` überprüft werden, wie eine virtuelle Adresse abgebildet wird
+//! und welche Speicherbereiche mit welchen Attributen gemappt sind.
+//!
+//! Der Walker arbeitet nur auf den rohen Einträgen. Wie eine Seitentabelle zu ihrer
+//! (physischen) Adresse gefunden wird, legt der Nutzer über eine Funktion fest. Damit ist die
+//! Auswertung unabhängig von der Hardware.
+use core::fmt;
+use super::{Address, MemType, MemoryAccessRight, PAGE_SIZE, LARGE_PAGE_SIZE, SECTION_SIZE, SUPERSECTION_SIZE};
+use super::builder::{MemoryBuilder, EntryBuilder, DirectoryEntry, TableEntry, PageDirectoryEntry, PageTableEntry};
+use super::{PageDirectory, PageTable};
+
+/// Art der Abbildung einer Adresse
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum MappingKind {
+    /// 16 MiB, direkt im Seitenverzeichnis
+    Supersection,
+    /// 1 MiB, direkt im Seitenverzeichnis
+    Section,
+    /// 64 kiB, über Seitentabelle
+    LargePage,
+    /// 4 kiB, über Seitentabelle
+    SmallPage,
+}
+
+/// Attribute eines abgebildeten Speicherbereichs
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct MappingAttributes {
+    /// Zugriffsrechte (`None` bei reservierter Bitkombination)
+    pub rights:           Option<MemoryAccessRight>,
+    /// Speichertyp (`None` bei nicht unterstützter Bitkombination)
+    pub mem_type:         Option<MemType>,
+    /// Domain; bei Seiten die des Seitenverzeichniseintrages
+    pub domain:           u32,
+    /// Nicht ausführbar
+    pub no_execute:       bool,
+    /// Gemeinsamer Speicher
+    pub shared:           bool,
+    /// Prozessspezifisch (nG)
+    pub process_specific: bool,
+}
+
+impl MappingAttributes {
+    fn from_entry<T, B: EntryBuilder<T>>(e: &B, domain: u32) -> MappingAttributes {
+        MappingAttributes {
+            rights:           e.get_rights(),
+            mem_type:         e.get_mem_type(),
+            domain:           domain,
+            no_execute:       e.is_no_execute(),
+            shared:           e.is_shared(),
+            process_specific: e.is_process_specific(),
+        }
+    }
+}
+
+impl fmt::Display for MappingAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.rights {
+            Some(r) => write!(f, "{:?}", r)?,
+            None    => write!(f, "Rechte?")?,
+        }
+        match self.mem_type {
+            Some(t) => write!(f, " {:?}", t)?,
+            None    => write!(f, " Typ?")?,
+        }
+        write!(f, " D{}", self.domain)?;
+        if self.no_execute {
+            write!(f, " XN")?;
+        }
+        if self.shared {
+            write!(f, " S")?;
+        }
+        if self.process_specific {
+            write!(f, " nG")?;
+        }
+        Ok(())
+    }
+}
+
+/// Ergebnis der Übersetzung einer virtuellen Adresse
+#[derive(Copy,Clone,Debug)]
+pub struct Translation {
+    /// Virtuelle Adresse
+    pub virt:       Address,
+    /// Physische Adresse
+    pub phys:       Address,
+    /// Art der Abbildung
+    pub kind:       MappingKind,
+    /// Attribute
+    pub attributes: MappingAttributes,
+}
+
+/// Zusammenhängender Speicherbereich mit gleichen Attributen und linearer Abbildung.
+///
+/// Wie bei `Frame` und `Section` ist das Ende inklusiv, damit auch der letzte Bereich des
+/// Adressraums dargestellt werden kann.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct MapRange {
+    /// Erste virtuelle Adresse
+    pub virt_start: Address,
+    /// Letzte virtuelle Adresse
+    pub virt_end:   Address,
+    /// Physische Adresse zu `virt_start`
+    pub phys_start: Address,
+    /// Attribute
+    pub attributes: MappingAttributes,
+}
+
+impl MapRange {
+    /// Kann der Bereich `other` angehängt werden?
+    fn continues_with(&self, other: &MapRange) -> bool {
+        self.virt_end.wrapping_add(1) == other.virt_start &&
+            self.phys_start.wrapping_add(other.virt_start - self.virt_start) == other.phys_start &&
+            self.attributes == other.attributes
+    }
+}
+
+impl fmt::Display for MapRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:08x}-{:08x} -> {:08x}: {}", self.virt_start, self.virt_end, self.phys_start, self.attributes)
+    }
+}
+
+/// Liest Seitenverzeichnis und Seitentabellen aus.
+///
+/// `F` liefert zu der Adresse aus einem Seitenverzeichniseintrag die Einträge der
+/// Seitentabelle, oder `None`, wenn die Tabelle nicht zugreifbar ist.
+pub struct PageWalker<'a, F> where F: Fn(Address) -> Option<&'a [PageTableEntry]> {
+    dir:    &'a [PageDirectoryEntry],
+    tables: F,
+}
+
+impl<'a, F> PageWalker<'a, F> where F: Fn(Address) -> Option<&'a [PageTableEntry]> {
+    /// Erzeugt einen Walker für die gegebenen Seitenverzeichniseinträge
+    pub fn new(dir: &'a [PageDirectoryEntry], tables: F) -> PageWalker<'a, F> {
+        assert_eq!(dir.len(), 4096);
+        PageWalker {
+            dir:    dir,
+            tables: tables,
+        }
+    }
+
+    fn directory_entry(&self, virt: Address) -> MemoryBuilder<DirectoryEntry> {
+        MemoryBuilder::<DirectoryEntry>::from_entry(self.dir[virt / SECTION_SIZE])
+    }
+
+    fn table_entry(&self, pde: &MemoryBuilder<DirectoryEntry>, virt: Address) -> Option<MemoryBuilder<TableEntry>> {
+        match pde.get_base_addr().and_then(|addr| (self.tables)(addr)) {
+            Some(table) => Some(MemoryBuilder::<TableEntry>::from_entry(table[(virt % SECTION_SIZE) / PAGE_SIZE])),
+            None        => None
+        }
+    }
+
+    /// Übersetzt die virtuelle Adresse `virt`; bei einem Seitenfehler wird `None` zurückgegeben.
+    pub fn translate(&self, virt: Address) -> Option<Translation> {
+        let pde = self.directory_entry(virt);
+        match pde.kind() {
+            DirectoryEntry::Section | DirectoryEntry::Supersection => {
+                let (kind, size) = if pde.kind() == DirectoryEntry::Section {
+                    (MappingKind::Section, SECTION_SIZE)
+                } else {
+                    (MappingKind::Supersection, SUPERSECTION_SIZE)
+                };
+                Some(Translation {
+                    virt:       virt,
+                    phys:       pde.get_base_addr().unwrap() + (virt & (size - 1)),
+                    kind:       kind,
+                    attributes: MappingAttributes::from_entry(&pde, pde.get_domain().unwrap_or(0)),
+                })
+            },
+            DirectoryEntry::CoarsePageTable => {
+                let pte = match self.table_entry(&pde, virt) {
+                    Some(pte) => pte,
+                    None      => return None
+                };
+                let (kind, size) = match pte.kind() {
+                    TableEntry::LargePage => (MappingKind::LargePage, LARGE_PAGE_SIZE),
+                    TableEntry::SmallPage => (MappingKind::SmallPage, PAGE_SIZE),
+                    TableEntry::Fault     => return None
+                };
+                Some(Translation {
+                    virt:       virt,
+                    phys:       pte.get_base_addr().unwrap() + (virt & (size - 1)),
+                    kind:       kind,
+                    attributes: MappingAttributes::from_entry(&pte, pde.get_domain().unwrap_or(0)),
+                })
+            },
+            _ => None
+        }
+    }
+
+    /// Gibt einen Iterator über alle gemappten Bereiche des Adressraums zurück.
+    ///
+    /// Benachbarte Bereiche mit gleichen Attributen und linearer Abbildung werden
+    /// zusammengefasst.
+    pub fn ranges<'b>(&'b self) -> MapRangeIterator<'b, 'a, F> {
+        MapRangeIterator {
+            walker:  self,
+            pos:     Some(0),
+            pending: None,
+        }
+    }
+}
+
+/// Iterator über die zusammengefassten gemappten Bereiche, siehe `PageWalker::ranges`
+pub struct MapRangeIterator<'b, 'a: 'b, F: 'b> where F: Fn(Address) -> Option<&'a [PageTableEntry]> {
+    walker:  &'b PageWalker<'a, F>,
+    pos:     Option<Address>,
+    pending: Option<MapRange>,
+}
+
+impl<'b, 'a, F> MapRangeIterator<'b, 'a, F> where F: Fn(Address) -> Option<&'a [PageTableEntry]> {
+    /// Nächster gemappter Abschnitt (Section oder Seite), noch ohne Zusammenfassung
+    fn next_chunk(&mut self) -> Option<MapRange> {
+        while let Some(pos) = self.pos {
+            let pde = self.walker.directory_entry(pos);
+            // Innerhalb einer Seitentabelle wird seitenweise, sonst sectionweise weitergegangen.
+            let step = match pde.kind() {
+                DirectoryEntry::CoarsePageTable if self.walker.table_entry(&pde, pos).is_some() => PAGE_SIZE,
+                _                                                                             => SECTION_SIZE,
+            };
+            self.pos = pos.checked_add(step);
+            if let Some(t) = self.walker.translate(pos) {
+                return Some(MapRange {
+                    virt_start: pos,
+                    virt_end:   pos + (step - 1),
+                    phys_start: t.phys,
+                    attributes: t.attributes,
+                });
+            }
+        }
+        None
+    }
+}
+
+impl<'b, 'a, F> Iterator for MapRangeIterator<'b, 'a, F> where F: Fn(Address) -> Option<&'a [PageTableEntry]> {
+    type Item = MapRange;
+
+    fn next(&mut self) -> Option<MapRange> {
+        loop {
+            let chunk = match self.next_chunk() {
+                Some(chunk) => chunk,
+                None        => return self.pending.take()
+            };
+            if let Some(ref mut pending) = self.pending {
+                if pending.continues_with(&chunk) {
+                    pending.virt_end = chunk.virt_end;
+                    continue;
+                }
+            }
+            let done = self.pending.take();
+            self.pending = Some(chunk);
+            if done.is_some() {
+                return done;
+            }
+        }
+    }
+}
+
+/// Seitentabelle an der gegebenen Adresse.
+///
+/// Der Kernel ist auf sich selbst gemappt, daher ist die physische Adresse einer Tabelle
+/// auch ihre virtuelle.
+fn kernel_table<'a>(addr: Address) -> Option<&'a [PageTableEntry]> {
+    Some(unsafe{ &*(addr as *const PageTable) }.entries())
+}
+
+impl<'a> PageWalker<'a, fn(Address) -> Option<&'a [PageTableEntry]>> {
+    /// Walker für das Seitenverzeichnis des Kernels (siehe `PageDirectory::get`)
+    pub fn kernel(dir: &'a PageDirectory) -> PageWalker<'a, fn(Address) -> Option<&'a [PageTableEntry]>> {
+        PageWalker::new(dir.entries(),
+                        kernel_table as fn(Address) -> Option<&'a [PageTableEntry]>)
+    }
+}
+
+/// Gibt die Speicherabbildung des Kernels auf der Konsole aus
+pub fn dump_memory_map() {
+    let dir = PageDirectory::get();
+    let walker = PageWalker::kernel(&dir);
+    for range in walker.ranges() {
+        kprint!("{}\n", range);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE_ADDR: Address = 0x0010_0400;
+
+    fn section(base: Address) -> PageDirectoryEntry {
+        MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::Section)
+            .base_addr(base)
+            .rights(MemoryAccessRight::SysRwUsrNone)
+            .mem_type(MemType::NormalWB)
+            .domain(3)
+            .no_execute(true)
+            .entry()
+    }
+
+    fn small_page(base: Address) -> PageTableEntry {
+        MemoryBuilder::<TableEntry>::new_entry(TableEntry::SmallPage)
+            .base_addr(base)
+            .rights(MemoryAccessRight::SysRoUsrNone)
+            .mem_type(MemType::NormalWT)
+            .shared(true)
+            .entry()
+    }
+
+    #[test]
+    fn section_entry_decodes() {
+        let pde = MemoryBuilder::<DirectoryEntry>::from_entry(section(0x2340_0000));
+        assert_eq!(pde.kind(), DirectoryEntry::Section);
+        assert_eq!(pde.get_base_addr(), Some(0x2340_0000));
+        assert_eq!(pde.get_rights(), Some(MemoryAccessRight::SysRwUsrNone));
+        assert_eq!(pde.get_mem_type(), Some(MemType::NormalWB));
+        assert_eq!(pde.get_domain(), Some(3));
+        assert!(pde.is_no_execute());
+        assert!(!pde.is_shared());
+        assert!(!pde.is_process_specific());
+    }
+
+    #[test]
+    fn page_table_entry_decodes() {
+        let pde = MemoryBuilder::<DirectoryEntry>::from_entry(
+            MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::CoarsePageTable)
+                .base_addr(TABLE_ADDR)
+                .domain(5)
+                .entry());
+        assert_eq!(pde.kind(), DirectoryEntry::CoarsePageTable);
+        assert_eq!(pde.get_base_addr(), Some(TABLE_ADDR));
+        assert_eq!(pde.get_domain(), Some(5));
+        assert_eq!(pde.get_mem_type(), None);
+        assert_eq!(pde.get_rights(), None);
+
+        let pte = MemoryBuilder::<TableEntry>::from_entry(small_page(0x0004_5000));
+        assert_eq!(pte.kind(), TableEntry::SmallPage);
+        assert_eq!(pte.get_base_addr(), Some(0x0004_5000));
+        assert_eq!(pte.get_rights(), Some(MemoryAccessRight::SysRoUsrNone));
+        assert_eq!(pte.get_mem_type(), Some(MemType::NormalWT));
+        assert_eq!(pte.get_domain(), None);
+        assert!(pte.is_shared());
+        assert!(!pte.is_no_execute());
+
+        let large = MemoryBuilder::<TableEntry>::from_entry(
+            MemoryBuilder::<TableEntry>::new_entry(TableEntry::LargePage)
+                .base_addr(0x0123_0000)
+                .no_execute(true)
+                .process_specific(true)
+                .entry());
+        assert_eq!(large.kind(), TableEntry::LargePage);
+        assert_eq!(large.get_base_addr(), Some(0x0123_0000));
+        assert!(large.is_no_execute());
+        assert!(large.is_process_specific());
+    }
+
+    #[test]
+    fn fault_entries_decode() {
+        for e in &[0u32, 0b11] {
+            let pde = MemoryBuilder::<DirectoryEntry>::from_entry(*e);
+            assert_eq!(pde.kind(), DirectoryEntry::Fault);
+            assert_eq!(pde.get_base_addr(), None);
+            assert_eq!(pde.get_mem_type(), None);
+            assert_eq!(pde.get_domain(), None);
+            assert!(!pde.is_no_execute());
+        }
+        let pte = MemoryBuilder::<TableEntry>::from_entry(0xffff_fff0);
+        assert_eq!(pte.kind(), TableEntry::Fault);
+        assert_eq!(pte.get_base_addr(), None);
+        assert_eq!(pte.get_rights(), None);
+    }
+
+    #[test]
+    fn translate_section_and_page() {
+        let mut dir = [0u32; 4096];
+        dir[0x234] = section(0x0050_0000);
+        dir[0x001] = MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::CoarsePageTable)
+            .base_addr(TABLE_ADDR)
+            .domain(2)
+            .entry();
+        let mut table = [0u32; 256];
+        table[0x45] = small_page(0x0007_8000);
+        let walker = PageWalker::new(&dir, |addr| if addr == TABLE_ADDR { Some(&table[..]) } else { None });
+
+        let t = walker.translate(0x2341_2345).unwrap();
+        assert_eq!(t.kind, MappingKind::Section);
+        assert_eq!(t.phys, 0x0051_2345);
+        assert_eq!(t.attributes.domain, 3);
+        assert!(t.attributes.no_execute);
+
+        let t = walker.translate(0x0014_5678).unwrap();
+        assert_eq!(t.kind, MappingKind::SmallPage);
+        assert_eq!(t.phys, 0x0007_8678);
+        // Seiten haben die Domain des Seitenverzeichniseintrages
+        assert_eq!(t.attributes.domain, 2);
+        assert!(t.attributes.shared);
+
+        assert!(walker.translate(0x0014_6000).is_none());
+        assert!(walker.translate(0x3000_0000).is_none());
+    }
+
+    #[test]
+    fn translate_supersection() {
+        let mut dir = [0u32; 4096];
+        let entry = MemoryBuilder::<DirectoryEntry>::new_entry(DirectoryEntry::Supersection)
+            .base_addr(0x4000_0000)
+            .mem_type(MemType::StronglyOrdered)
+            .entry();
+        // Eine Supersection steht in 16 aufeinanderfolgenden Einträgen.
+        for e in dir[0x100 .. 0x110].iter_mut() {
+            *e = entry;
+        }
+        let walker = PageWalker::new(&dir, |_| None);
+        let t = walker.translate(0x10ab_cdef).unwrap();
+        assert_eq!(t.kind, MappingKind::Supersection);
+        assert_eq!(t.phys, 0x40ab_cdef);
+        assert_eq!(t.attributes.mem_type, Some(MemType::StronglyOrdered));
+    }
+
+    #[test]
+    fn ranges_are_merged() {
+        let mut dir = [0u32; 4096];
+        for (i, nr) in (0x200 .. 0x204).enumerate() {
+            dir[nr] = section(0x0100_0000 + i * SECTION_SIZE);
+        }
+        // Nicht linear fortgesetzt: eigener Bereich
+        dir[0x204] = section(0x0800_0000);
+        let walker = PageWalker::new(&dir, |_| None);
+        let mut ranges = walker.ranges();
+        assert_eq!(ranges.next().map(|r| (r.virt_start, r.virt_end, r.phys_start)),
+                   Some((0x2000_0000, 0x203f_ffff, 0x0100_0000)));
+        assert_eq!(ranges.next().map(|r| (r.virt_start, r.virt_end, r.phys_start)),
+                   Some((0x2040_0000, 0x204f_ffff, 0x0800_0000)));
+        assert_eq!(ranges.next(), None);
+    }
+}
@@ -0,0 +1,137 @@
+#![warn(missing_docs)]
+//! Lage der Kernel-Stacks im Speicher.
+//!
+//! Die Stacks des Kernels liegen am oberen Ende des ARM-Speicherbereiches. Jeder Ausnahmemodus
+//! hat einen eigenen Stack. Unterhalb jedes Stacks liegt eine Schutzseite (_guard page_), die
+//! nicht gemappt wird. Ein Stacküberlauf führt daher zu einem Datenzugriffsfehler, statt
+//! unbemerkt fremden Speicher zu überschreiben.
+//!
+//! Aufteilung (von oben nach unten):
+//!
+//! ```text
+//!  top ─► ┌─────────────┐
+//!         │ Irq         │
+//!         ├─────────────┤
+//!         │ Schutzseite │
+//!         ├─────────────┤
+//!         │ Fiq         │
+//!         ├─────────────┤
+//!         │     ...     │
+//!         ├─────────────┤
+//!         │ Svc         │
+//!         ├─────────────┤
+//!         │ Schutzseite │
+//!         └─────────────┘ ◄─ bottom
+//! ```
+use super::{Address, AddressRange, PAGE_SIZE};
+
+/// Größe einer Schutzseite
+pub const GUARD_SIZE: usize = PAGE_SIZE;
+
+/// Kernel-Stacks, einer pro privilegiertem Modus.
+///
+/// Der System-Modus nutzt den Stack des User-Modus und hat daher keinen eigenen Kernel-Stack.
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum KernelStack {
+    /// Stack für Interrupts
+    Irq,
+    /// Stack für schnelle Interrupts
+    Fiq,
+    /// Stack für Prefetch- und Data-Aborts
+    Abort,
+    /// Stack für unbekannte Befehle
+    Undef,
+    /// Stack des Kernels (Systemrufe)
+    Svc,
+}
+
+/// Reihenfolge der Stacks, beginnend am oberen Speicherende
+pub const KERNEL_STACKS: [KernelStack;5] = [KernelStack::Irq, KernelStack::Fiq, KernelStack::Abort,
+                                            KernelStack::Undef, KernelStack::Svc];
+
+impl KernelStack {
+    /// Größe des Stacks in Bytes, immer ein Vielfaches der Seitengröße
+    pub fn size(&self) -> usize {
+        match *self {
+            KernelStack::Irq   => 2 * PAGE_SIZE,
+            KernelStack::Fiq   => PAGE_SIZE,
+            KernelStack::Abort => PAGE_SIZE,
+            KernelStack::Undef => PAGE_SIZE,
+            KernelStack::Svc   => 16 * PAGE_SIZE,  // 64 kiB
+        }
+    }
+
+    /// Name des Stacks für Ausgaben
+    pub fn name(&self) -> &'static str {
+        match *self {
+            KernelStack::Irq   => "Irq",
+            KernelStack::Fiq   => "Fiq",
+            KernelStack::Abort => "Abort",
+            KernelStack::Undef => "Undef",
+            KernelStack::Svc   => "Svc",
+        }
+    }
+}
+
+/// Berechnet die Lage der Kernel-Stacks und ihrer Schutzseiten.
+#[derive(Copy,Clone,Debug)]
+pub struct StackLayout {
+    top: Address
+}
+
+impl StackLayout {
+    /// Erzeugt das Layout für Stacks, die unterhalb von `top` liegen.
+    ///
+    /// `top` muss seiten-aligned sein.
+    pub fn new(top: Address) -> StackLayout {
+        assert_eq!(top & (PAGE_SIZE - 1), 0);
+        StackLayout {
+            top: top
+        }
+    }
+
+    /// Oberes Ende des Stackbereiches
+    pub fn top(&self) -> Address {
+        self.top
+    }
+
+    /// Unteres Ende des Stackbereiches (Anfang der untersten Schutzseite)
+    pub fn bottom(&self) -> Address {
+        self.top - KERNEL_STACKS.iter().fold(0, |sum, s| sum + s.size() + GUARD_SIZE)
+    }
+
+    /// Anfangswert des Stackzeigers (_top of stack_) für den gegebenen Stack
+    pub fn top_of_stack(&self, stack: KernelStack) -> Address {
+        let mut tos = self.top;
+        for s in KERNEL_STACKS.iter() {
+            if *s == stack {
+                break;
+            }
+            tos -= s.size() + GUARD_SIZE;
+        }
+        tos
+    }
+
+    /// Adressbereich, der vom Stack selbst genutzt wird
+    pub fn stack_range(&self, stack: KernelStack) -> AddressRange {
+        let tos = self.top_of_stack(stack);
+        tos - stack.size() .. tos
+    }
+
+    /// Adressbereich der Schutzseite unterhalb des Stacks
+    pub fn guard_range(&self, stack: KernelStack) -> AddressRange {
+        let end = self.stack_range(stack).start;
+        end - GUARD_SIZE .. end
+    }
+
+    /// Gibt den Stack zurück, in dessen Schutzseite die Adresse `addr` liegt.
+    pub fn guard_hit(&self, addr: Address) -> Option<KernelStack> {
+        for s in KERNEL_STACKS.iter() {
+            let guard = self.guard_range(*s);
+            if guard.start <= addr && addr < guard.end {
+                return Some(*s);
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,109 @@
+#![warn(missing_docs)]
+//! Atomare Operationen auf einem Speicherwort mit `ldrex`/`strex`.
+//!
+//! `ldrex` markiert die Adresse für exklusiven Zugriff, `strex` schreibt nur, wenn seitdem
+//! kein anderer Zugriff (auch kein Interrupt mit `clrex` oder `strex`) stattgefunden hat,
+//! und meldet im Ergebnisregister 0 bei Erfolg. Schlägt das Schreiben fehl, wird die
+//! Operation wiederholt. Siehe ARM ARM A2.9.
+//!
+//! Die Operationen brauchen keine Interruptsperre und sind daher auch im Usermodus nutzbar.
+use core::cell::UnsafeCell;
+use core::ptr::{read_volatile, write_volatile};
+use hal::cpu::Cpu;
+
+/// Speicherwort mit atomaren Operationen
+pub struct AtomicWord {
+    value: UnsafeCell<u32>,
+}
+
+unsafe impl Sync for AtomicWord {}
+unsafe impl Send for AtomicWord {}
+
+impl AtomicWord {
+    /// Neues Wort mit dem Wert `value`
+    pub const fn new(value: u32) -> AtomicWord {
+        AtomicWord {
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Liest den Wert.
+    pub fn load(&self) -> u32 {
+        let value = unsafe{ read_volatile(self.value.get()) };
+        Cpu::data_memory_barrier();
+        value
+    }
+
+    /// Schreibt den Wert.
+    pub fn store(&self, value: u32) {
+        Cpu::data_memory_barrier();
+        unsafe{ write_volatile(self.value.get(), value); }
+        Cpu::data_memory_barrier();
+    }
+
+    /// Setzt den Wert auf `new`, falls er `old` ist, und gibt den vorherigen Wert zurück.
+    pub fn compare_and_swap(&self, old: u32, new: u32) -> u32 {
+        let prev: u32;
+        Cpu::data_memory_barrier();
+        unsafe{
+            asm!("1:
+                  ldrex $0, [$1]
+                  cmp $0, $2
+                  bne 2f
+                  strex r1, $3, [$1]
+                  cmp r1, #0
+                  bne 1b
+                  2:"
+                 :"=&r"(prev)
+                 :"r"(self.value.get()), "r"(old), "r"(new)
+                 :"r1","cc","memory"
+                 :"volatile");
+        }
+        Cpu::data_memory_barrier();
+        prev
+    }
+
+    /// Setzt den Wert auf `new` und gibt den vorherigen Wert zurück.
+    pub fn swap(&self, new: u32) -> u32 {
+        let prev: u32;
+        Cpu::data_memory_barrier();
+        unsafe{
+            asm!("1:
+                  ldrex $0, [$1]
+                  strex r1, $2, [$1]
+                  cmp r1, #0
+                  bne 1b"
+                 :"=&r"(prev)
+                 :"r"(self.value.get()), "r"(new)
+                 :"r1","cc","memory"
+                 :"volatile");
+        }
+        Cpu::data_memory_barrier();
+        prev
+    }
+
+    /// Addiert `n` (modulo 2^32) und gibt den vorherigen Wert zurück.
+    pub fn fetch_add(&self, n: u32) -> u32 {
+        let prev: u32;
+        Cpu::data_memory_barrier();
+        unsafe{
+            asm!("1:
+                  ldrex $0, [$1]
+                  add r2, $0, $2
+                  strex r1, r2, [$1]
+                  cmp r1, #0
+                  bne 1b"
+                 :"=&r"(prev)
+                 :"r"(self.value.get()), "r"(n)
+                 :"r1","r2","cc","memory"
+                 :"volatile");
+        }
+        Cpu::data_memory_barrier();
+        prev
+    }
+
+    /// Adresse des Wortes (z.B. für Futex-Systemrufe)
+    pub fn as_ptr(&self) -> *mut u32 {
+        self.value.get()
+    }
+}
@@ -0,0 +1,43 @@
+#![warn(missing_docs)]
+//! Bedingungsvariable.
+use super::{IrqSpinLock, WaitQueue, MutexGuard};
+
+/// Bedingungsvariable für die Nutzung mit `Mutex`.
+///
+/// Wie üblich kann `wait` auch ohne `notify_*` zurückkehren (_spurious wakeup_); die Bedingung
+/// muss daher in einer Schleife geprüft werden.
+pub struct Condvar {
+    generation: IrqSpinLock<usize>,
+    queue:      WaitQueue,
+}
+
+impl Condvar {
+    /// Neue Bedingungsvariable
+    pub const fn new() -> Condvar {
+        Condvar {
+            generation: IrqSpinLock::new(0),
+            queue:      WaitQueue::new(),
+        }
+    }
+
+    /// Gibt den Mutex frei, wartet auf eine Benachrichtigung und belegt den Mutex wieder.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex();
+        let generation = *self.generation.lock();
+        drop(guard);
+        self.queue.wait_until(|| *self.generation.lock() != generation);
+        mutex.lock()
+    }
+
+    /// Weckt einen Wartenden.
+    pub fn notify_one(&self) {
+        *self.generation.lock() += 1;
+        self.queue.wake_one();
+    }
+
+    /// Weckt alle Wartenden.
+    pub fn notify_all(&self) {
+        *self.generation.lock() += 1;
+        self.queue.wake_all();
+    }
+}
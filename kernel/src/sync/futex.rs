@@ -0,0 +1,178 @@
+#![warn(missing_docs)]
+//! Futex: Warten auf eine Speicherzelle im Userspace.
+//!
+//! Ein Futex (_fast userspace mutex_) ist ein Speicherwort, das Prozesse mit atomaren
+//! Operationen (`AtomicWord`) verändern. Nur wenn ein Prozess warten muss, ruft er den Kernel:
+//!
+//! - `wait(addr, expected)` blockiert, falls an `addr` noch `expected` steht. Der Vergleich
+//!   geschieht unter dem Lock der Tabelle, ein gleichzeitiges `wake` geht also nicht verloren.
+//! - `wake(addr, n)` weckt bis zu `n` Wartende.
+//!
+//! Die Warteschlangen werden über die physische Adresse des Wortes gefunden, damit auch
+//! Prozesse mit gemeinsamem Speicher an unterschiedlichen virtuellen Adressen zusammenfinden.
+//! Die Tabelle hat eine feste Größe, da sie in Systemrufen gefüllt wird.
+use core::ptr::read_volatile;
+use memory::Address;
+use hal::cpu::MMU;
+use super::{IrqSpinLock, WaitQueue};
+
+/// Anzahl der Futexe, auf die gleichzeitig gewartet werden kann
+pub const NUM_FUTEXES: usize = 32;
+
+/// Fehler bei Futex-Operationen
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum FutexError {
+    /// Der Wert entsprach nicht dem erwarteten, es wurde nicht gewartet
+    WouldBlock,
+    /// Die Adresse ist nicht ausgerichtet oder nicht lesbar
+    InvalidAddress,
+    /// Es wird bereits auf `NUM_FUTEXES` Futexe gewartet
+    TableFull,
+}
+
+impl FutexError {
+    /// Rückgabewert für Systemrufe (0 steht für Erfolg)
+    pub fn as_u32(&self) -> u32 {
+        match *self {
+            FutexError::WouldBlock     => 1,
+            FutexError::InvalidAddress => 2,
+            FutexError::TableFull      => 3,
+        }
+    }
+
+    /// Fehler zum Rückgabewert eines Systemrufs; `None` für 0 und unbekannte Werte
+    pub fn from_u32(code: u32) -> Option<FutexError> {
+        match code {
+            1 => Some(FutexError::WouldBlock),
+            2 => Some(FutexError::InvalidAddress),
+            3 => Some(FutexError::TableFull),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy,Clone)]
+struct FutexEntry {
+    /// Physische Adresse
+    addr:    Address,
+    /// Anzahl der Wartenden
+    waiters: usize,
+    /// Anzahl der geweckten, aber noch nicht zurückgekehrten Wartenden
+    woken:   usize,
+}
+
+/// Tabelle der Futexe, auf die gewartet wird
+pub struct FutexTable {
+    entries: IrqSpinLock<[Option<FutexEntry>; NUM_FUTEXES]>,
+    queue:   WaitQueue,
+}
+
+impl FutexTable {
+    /// Leere Tabelle
+    pub const fn new() -> FutexTable {
+        FutexTable {
+            entries: IrqSpinLock::new([None; NUM_FUTEXES]),
+            queue:   WaitQueue::new(),
+        }
+    }
+
+    /// Blockiert, falls an `addr` (virtuell, aktueller Adressraum) der Wert `expected` steht,
+    /// bis ein `wake` für dieselbe Speicherzelle kommt.
+    pub fn wait(&self, addr: Address, expected: u32) -> Result<(),FutexError> {
+        let phys = FutexTable::physical(addr)?;
+        {
+            let mut entries = self.entries.lock();
+            if unsafe{ read_volatile(addr as *const u32) } != expected {
+                return Err(FutexError::WouldBlock);
+            }
+            let mut found = None;
+            let mut free = None;
+            for (ndx, e) in entries.iter().enumerate() {
+                match *e {
+                    Some(ref entry) if entry.addr == phys => {
+                        found = Some(ndx);
+                        break;
+                    },
+                    None if free.is_none() => free = Some(ndx),
+                    _ => {}
+                }
+            }
+            match (found, free) {
+                (Some(ndx), _) => {
+                    if let Some(ref mut entry) = entries[ndx] {
+                        entry.waiters += 1;
+                    }
+                },
+                (None, Some(ndx)) => entries[ndx] = Some(FutexEntry { addr: phys, waiters: 1, woken: 0 }),
+                (None, None)      => return Err(FutexError::TableFull),
+            }
+        }
+        self.queue.wait_until(|| self.consume_wakeup(phys));
+        Ok(())
+    }
+
+    /// Weckt bis zu `n` Prozesse, die an `addr` warten, und gibt ihre Anzahl zurück.
+    pub fn wake(&self, addr: Address, n: usize) -> Result<usize,FutexError> {
+        let phys = FutexTable::physical(addr)?;
+        let mut woken = 0;
+        {
+            let mut entries = self.entries.lock();
+            for e in entries.iter_mut() {
+                if let Some(ref mut entry) = *e {
+                    if entry.addr == phys {
+                        woken = ::core::cmp::min(n, entry.waiters - entry.woken);
+                        entry.woken += woken;
+                        break;
+                    }
+                }
+            }
+        }
+        if woken > 0 {
+            self.queue.wake_all();
+        }
+        Ok(woken)
+    }
+
+    /// Nimmt ein Wecken für `phys` an; `true`, wenn eines vorlag.
+    fn consume_wakeup(&self, phys: Address) -> bool {
+        let mut entries = self.entries.lock();
+        for e in entries.iter_mut() {
+            let remove = match *e {
+                Some(ref mut entry) if entry.addr == phys && entry.woken > 0 => {
+                    entry.woken -= 1;
+                    entry.waiters -= 1;
+                    entry.waiters == 0
+                },
+                _ => continue
+            };
+            if remove {
+                *e = None;
+            }
+            return true;
+        }
+        false
+    }
+
+    /// Prüft die Adresse und übersetzt sie in die physische Adresse.
+    fn physical(addr: Address) -> Result<Address,FutexError> {
+        if addr % 4 != 0 {
+            return Err(FutexError::InvalidAddress);
+        }
+        match MMU::translate_user_read(addr) {
+            Some(phys) => Ok(phys),
+            None       => Err(FutexError::InvalidAddress),
+        }
+    }
+}
+
+static FUTEXES: FutexTable = FutexTable::new();
+
+/// Wartet auf den Futex an `addr`, siehe `FutexTable::wait`.
+pub fn futex_wait(addr: Address, expected: u32) -> Result<(),FutexError> {
+    FUTEXES.wait(addr, expected)
+}
+
+/// Weckt bis zu `n` Wartende am Futex `addr`, siehe `FutexTable::wake`.
+pub fn futex_wake(addr: Address, n: usize) -> Result<usize,FutexError> {
+    FUTEXES.wake(addr, n)
+}
@@ -0,0 +1,109 @@
+#![warn(missing_docs)]
+//! Spinlock, der während des Haltens Interrupts sperrt.
+//!
+//! Daten, die sowohl von Serviceroutinen als auch vom übrigen Kernel genutzt werden, dürfen nur
+//! mit gesperrten Interrupts verändert werden, sonst kann eine Serviceroutine einen halb
+//! veränderten Zustand sehen. `IrqSpinLock::lock` sperrt daher die Interrupts und gibt einen
+//! Guard zurück. Beim Freigeben wird das I-Bit des CPSR auf den Wert vor `lock` zurückgesetzt,
+//! so dass Locks auch verschachtelt und in Serviceroutinen genutzt werden können.
+//!
+//! Auf einem Einkernprozessor kann der Lock bei gesperrten Interrupts nur belegt sein, wenn
+//! er rekursiv angefordert wird. Die Sperrvariable wird trotzdem atomar gesetzt (`AtomicWord`),
+//! damit der Lock auch auf Mehrkernprozessoren korrekt ist.
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use hal::cpu::Cpu;
+use super::AtomicWord;
+
+/// Spinlock mit Interruptsperre
+pub struct IrqSpinLock<T> {
+    locked: AtomicWord,
+    data:   UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for IrqSpinLock<T> {}
+unsafe impl<T: Send> Send for IrqSpinLock<T> {}
+
+impl<T> IrqSpinLock<T> {
+    /// Erzeugt einen freien Lock für `data`
+    pub const fn new(data: T) -> IrqSpinLock<T> {
+        IrqSpinLock {
+            locked: AtomicWord::new(0),
+            data:   UnsafeCell::new(data),
+        }
+    }
+
+    /// Sperrt Interrupts und belegt den Lock.
+    pub fn lock(&self) -> IrqSpinLockGuard<T> {
+        let state = Cpu::save_and_disable_interrupts();
+        while !self.try_acquire() {}
+        IrqSpinLockGuard {
+            lock:  self,
+            state: state,
+        }
+    }
+
+    /// Versucht, den Lock zu belegen, ohne zu warten.
+    pub fn try_lock(&self) -> Option<IrqSpinLockGuard<T>> {
+        let state = Cpu::save_and_disable_interrupts();
+        if self.try_acquire() {
+            Some(IrqSpinLockGuard {
+                lock:  self,
+                state: state,
+            })
+        } else {
+            Cpu::restore_interrupts(state);
+            None
+        }
+    }
+
+    /// Adresse der geschützten Daten, ohne den Lock zu belegen.
+    ///
+    /// Für Daten, die auch die Hardware liest (z.B. das Seitenverzeichnis); der Zugriff über
+    /// den Zeiger ist nicht geschützt.
+    pub fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+
+    /// Gibt an, ob der Lock belegt ist.
+    pub fn is_locked(&self) -> bool {
+        self.locked.load() != 0
+    }
+
+    /// Setzt die Sperrvariable atomar von 0 auf 1; `true` bei Erfolg.
+    fn try_acquire(&self) -> bool {
+        self.locked.compare_and_swap(0, 1) == 0
+    }
+
+    fn release(&self) {
+        self.locked.store(0);
+    }
+}
+
+/// Belegter `IrqSpinLock`; gibt den Lock frei und stellt den Interruptzustand wieder her,
+/// wenn er den Gültigkeitsbereich verlässt.
+pub struct IrqSpinLockGuard<'a, T: 'a> {
+    lock:  &'a IrqSpinLock<T>,
+    state: u32,
+}
+
+impl<'a, T> Deref for IrqSpinLockGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe{ &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for IrqSpinLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe{ &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T> Drop for IrqSpinLockGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.release();
+        Cpu::restore_interrupts(self.state);
+    }
+}
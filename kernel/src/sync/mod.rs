@@ -27,3 +27,18 @@ pub mod no_concurrency {
         
     }
 }
+
+mod atomic;
+pub use self::atomic::AtomicWord;
+mod irq_lock;
+pub use self::irq_lock::{IrqSpinLock,IrqSpinLockGuard};
+mod wait_queue;
+pub use self::wait_queue::WaitQueue;
+mod semaphore;
+pub use self::semaphore::Semaphore;
+mod mutex;
+pub use self::mutex::{Mutex,MutexGuard};
+mod condvar;
+pub use self::condvar::Condvar;
+mod futex;
+pub use self::futex::{FutexTable,FutexError,NUM_FUTEXES,futex_wait,futex_wake};
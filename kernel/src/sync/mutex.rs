@@ -0,0 +1,93 @@
+#![warn(missing_docs)]
+//! Blockierender wechselseitiger Ausschluss.
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use super::{IrqSpinLock, WaitQueue};
+
+/// Blockierender Mutex.
+///
+/// Anders als `IrqSpinLock` bleiben Interrupts beim Halten zugelassen. Ein Mutex darf daher
+/// nicht in Serviceroutinen genutzt werden; dort ist `IrqSpinLock` zu verwenden.
+pub struct Mutex<T> {
+    locked: IrqSpinLock<bool>,
+    queue:  WaitQueue,
+    data:   UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for Mutex<T> {}
+unsafe impl<T: Send> Send for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    /// Erzeugt einen freien Mutex für `data`
+    pub const fn new(data: T) -> Mutex<T> {
+        Mutex {
+            locked: IrqSpinLock::new(false),
+            queue:  WaitQueue::new(),
+            data:   UnsafeCell::new(data),
+        }
+    }
+
+    /// Belegt den Mutex; blockiert, solange er belegt ist.
+    pub fn lock(&self) -> MutexGuard<T> {
+        self.queue.wait_until(|| self.acquire());
+        MutexGuard {
+            mutex: self,
+        }
+    }
+
+    /// Versucht, den Mutex ohne Warten zu belegen.
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        if self.acquire() {
+            Some(MutexGuard { mutex: self })
+        } else {
+            None
+        }
+    }
+
+    fn acquire(&self) -> bool {
+        let mut locked = self.locked.lock();
+        if *locked {
+            false
+        } else {
+            *locked = true;
+            true
+        }
+    }
+
+    fn unlock(&self) {
+        *self.locked.lock() = false;
+        self.queue.wake_one();
+    }
+}
+
+/// Belegter `Mutex`; gibt ihn frei, wenn er den Gültigkeitsbereich verlässt.
+pub struct MutexGuard<'a, T: 'a> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<'a, T> MutexGuard<'a, T> {
+    /// Zugehöriger Mutex (für `Condvar::wait`)
+    pub(super) fn mutex(&self) -> &'a Mutex<T> {
+        self.mutex
+    }
+}
+
+impl<'a, T> Deref for MutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe{ &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe{ &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
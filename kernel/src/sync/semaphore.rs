@@ -0,0 +1,48 @@
+#![warn(missing_docs)]
+//! Zählender Semaphor.
+use super::{IrqSpinLock, WaitQueue};
+
+/// Zählender Semaphor.
+///
+/// `up` darf auch in Serviceroutinen gerufen werden, `down` nur außerhalb.
+pub struct Semaphore {
+    count: IrqSpinLock<usize>,
+    queue: WaitQueue,
+}
+
+impl Semaphore {
+    /// Semaphor mit dem Anfangswert `count`
+    pub const fn new(count: usize) -> Semaphore {
+        Semaphore {
+            count: IrqSpinLock::new(count),
+            queue: WaitQueue::new(),
+        }
+    }
+
+    /// Erniedrigt den Zähler; blockiert, solange er Null ist (P-Operation).
+    pub fn down(&self) {
+        self.queue.wait_until(|| self.try_down());
+    }
+
+    /// Erniedrigt den Zähler, falls er größer als Null ist; gibt an, ob das gelang.
+    pub fn try_down(&self) -> bool {
+        let mut count = self.count.lock();
+        if *count > 0 {
+            *count -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Erhöht den Zähler und weckt einen Wartenden (V-Operation).
+    pub fn up(&self) {
+        *self.count.lock() += 1;
+        self.queue.wake_one();
+    }
+
+    /// Aktueller Zählerstand
+    pub fn count(&self) -> usize {
+        *self.count.lock()
+    }
+}
@@ -0,0 +1,117 @@
+#![warn(missing_docs)]
+//! Warteschlange für blockierende Synchronisationsprimitive.
+//!
+//! Alle blockierenden Primitive (`Mutex`, `Semaphore`, `Condvar`) warten über eine
+//! `WaitQueue`. Solange es keinen Scheduler gibt, ist der Kernel der einzige Kontrollfluss,
+//! der warten kann, und alle Ereignisse, auf die er wartet, werden von Serviceroutinen
+//! ausgelöst. Ein Wartender schläft daher mit `wfi` bis zum nächsten Interrupt und prüft
+//! danach seine Bedingung erneut.
+//!
+//! `wake_one`/`wake_all` hinterlegen Weckrufe, die ein Wartender vor dem Einschlafen abholt.
+//! Mit einem Scheduler wird aus `sleep` ein Prozesswechsel und die Weckrufe machen blockierte
+//! Prozesse wieder bereit. Die Schnittstelle bleibt dabei gleich.
+use hal::cpu::Cpu;
+use super::IrqSpinLock;
+
+/// Zustand der Warteschlange
+struct WaitState {
+    /// Anzahl der Wartenden
+    waiters: usize,
+    /// Noch nicht abgeholte Weckrufe (höchstens `waiters`)
+    wakeups: usize,
+}
+
+/// Warteschlange
+pub struct WaitQueue {
+    state: IrqSpinLock<WaitState>,
+}
+
+impl WaitQueue {
+    /// Leere Warteschlange
+    pub const fn new() -> WaitQueue {
+        WaitQueue {
+            state: IrqSpinLock::new(WaitState {
+                waiters: 0,
+                wakeups: 0,
+            }),
+        }
+    }
+
+    /// Blockiert, bis `cond` wahr ist.
+    ///
+    /// `cond` wird mit gesperrten Interrupts ausgewertet und der Wartende schläft nur ein,
+    /// wenn seit der Auswertung kein Weckruf (`wake_one`, `wake_all`) angekommen ist. Zwischen
+    /// Auswertung und Einschlafen kann also kein Wecken verloren gehen. Darf nicht in
+    /// Serviceroutinen oder mit gesperrten Interrupts gerufen werden, da sonst niemand die
+    /// Bedingung erfüllen kann.
+    pub fn wait_until<F: FnMut() -> bool>(&self, mut cond: F) {
+        let state = Cpu::save_and_disable_interrupts();
+        if cond() {
+            Cpu::restore_interrupts(state);
+            return;
+        }
+        assert!(state & (1 << 7) == 0, "WaitQueue::wait_until mit gesperrten Interrupts");
+        self.state.lock().waiters += 1;
+        loop {
+            if !self.take_wakeup() {
+                WaitQueue::sleep();
+            }
+            // Hier wird die Serviceroutine des weckenden Interrupts ausgeführt.
+            Cpu::restore_interrupts(state);
+            Cpu::disable_interrupts();
+            if cond() {
+                break;
+            }
+        }
+        {
+            // Nicht abgeholte Weckrufe gehören den übrigen Wartenden.
+            let mut s = self.state.lock();
+            s.waiters -= 1;
+            if s.wakeups > s.waiters {
+                s.wakeups = s.waiters;
+            }
+        }
+        Cpu::restore_interrupts(state);
+    }
+
+    /// Holt einen Weckruf ab; `false`, wenn keiner vorliegt.
+    fn take_wakeup(&self) -> bool {
+        let mut s = self.state.lock();
+        if s.wakeups > 0 {
+            s.wakeups -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Weckt einen Wartenden.
+    ///
+    /// Der Weckruf bleibt bis zum Abholen gespeichert, auch wenn der Wartende gerade noch
+    /// nicht schläft.
+    pub fn wake_one(&self) {
+        let mut s = self.state.lock();
+        if s.wakeups < s.waiters {
+            s.wakeups += 1;
+        }
+    }
+
+    /// Weckt alle Wartenden.
+    pub fn wake_all(&self) {
+        let mut s = self.state.lock();
+        s.wakeups = s.waiters;
+    }
+
+    /// Gibt an, ob jemand wartet.
+    pub fn has_waiters(&self) -> bool {
+        self.state.lock().waiters != 0
+    }
+
+    /// Schläft bis zum nächsten Interrupt.
+    ///
+    /// `wfi` kehrt auch bei gesperrten Interrupts zurück, sobald ein Interrupt anliegt,
+    /// siehe ARM ARM B6.6.12.
+    fn sleep() {
+        Cpu::wait_for_interrupt();
+    }
+}
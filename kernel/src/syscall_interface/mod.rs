@@ -1,5 +1,15 @@
 use core::fmt::Arguments;
 use debug::kprint;
+use data::kernel::KernelData;
+use data::isr_table::IrqStats;
+use hal::cpu::Cpu;
+use hal::bmc2835::{Pl011Driver,Pl011ErrorCounts,BscDriver,I2cBus,I2cRequest,I2cError};
+use hal::bmc2835::{GpioDriver,GpioTrigger,GpioError,GpioPinEvent};
+use sync::{futex_wait,futex_wake};
+
+pub mod user_sync;
+pub mod user_buffer;
+use self::user_buffer::{user_slice,user_slice_mut,read_user,write_user,INVALID_POINTER};
 
 #[repr(u32)]
 #[allow(dead_code)]
@@ -11,6 +21,55 @@ pub enum SysCall {
     Receive,
     Write,
     Read,
+    /// Statistik eines Interrupts: arg1 = UID, arg2 = Zeiger auf `IrqStats`.
+    /// Rückgabe 0 bei Erfolg, 1 bei ungültiger UID, `INVALID_POINTER` bei ungültigem Zeiger.
+    InterruptStats,
+    /// Warten auf einen Futex: arg1 = Adresse, arg2 = erwarteter Wert.
+    /// Rückgabe 0 nach dem Wecken, sonst `FutexError::as_u32`.
+    FutexWait,
+    /// Wecken am Futex: arg1 = Adresse, arg2 = Höchstzahl zu weckender Prozesse.
+    /// Rückgabe Anzahl der Geweckten, `u32::MAX` bei ungültiger Adresse.
+    FutexWake,
+    /// Lesen von der seriellen Schnittstelle: arg1 = Puffer, arg2 = Länge,
+    /// arg3 = `SERIAL_NONBLOCKING` oder 0. Rückgabe Anzahl der gelesenen Zeichen,
+    /// `INVALID_POINTER` bei ungültigem Puffer.
+    SerialRead,
+    /// Schreiben auf die serielle Schnittstelle: arg1 = Daten, arg2 = Länge,
+    /// arg3 = `SERIAL_NONBLOCKING` oder 0. Rückgabe Anzahl der geschriebenen Zeichen,
+    /// `INVALID_POINTER` bei ungültigem Puffer.
+    SerialWrite,
+    /// Fehlerzähler der seriellen Schnittstelle: arg1 = Zeiger auf `Pl011ErrorCounts`.
+    /// Rückgabe 0 bei Erfolg, `INVALID_POINTER` bei ungültigem Zeiger.
+    SerialErrors,
+    /// I2C-Transaktion: arg1 = Master (0 oder 1), arg2 = Zeiger auf `I2cRequest`.
+    /// Rückgabe 0 bei Erfolg, `INVALID_POINTER` bei ungültiger Anfrage oder ungültigen
+    /// Puffern, sonst `I2cError::as_u32`.
+    I2cTransfer,
+    /// Ereigniswarteschlange für einen GPIO-Pin anmelden: arg1 = Pin, arg2 = Auslöser
+    /// (siehe `GpioTrigger::from_u32`), arg3 = Entprellzeit in µs.
+    /// Rückgabe 0 bei Erfolg, sonst `GpioError::as_u32` (`InvalidTrigger` bei ungültigem
+    /// Auslöser).
+    GpioWatch,
+    /// GPIO-Pin abmelden: arg1 = Pin. Rückgabe 0 bei Erfolg, sonst `GpioError::as_u32`.
+    GpioUnwatch,
+    /// Auf ein GPIO-Ereignis warten: arg1 = Pin, arg2 = Zeit in µs (0: nicht warten,
+    /// `GPIO_WAIT_FOREVER`: unbegrenzt), arg3 = Zeiger auf `GpioPinEvent`.
+    /// Rückgabe 0 bei Erfolg, `INVALID_POINTER` bei ungültigem Zeiger (wird vor dem Warten
+    /// geprüft), sonst `GpioError::as_u32`.
+    GpioWaitEvent,
+}
+
+/// Flag für `SerialRead` und `SerialWrite`: nicht blockieren
+pub const SERIAL_NONBLOCKING: u32 = 0x1;
+
+/// Pin-Nummer aus einem Argument; größere Werte werden nicht abgeschnitten, sondern
+/// abgelehnt.
+fn gpio_pin(arg: u32) -> Result<u8,GpioError> {
+    if arg > ::core::u8::MAX as u32 {
+        Err(GpioError::InvalidPin)
+    } else {
+        Ok(arg as u8)
+    }
 }
 
 impl SysCall {
@@ -28,6 +87,131 @@ impl SysCall {
                 let out: Arguments = unsafe{ *(arg2 as * const Arguments)};
                 kprint::fkprint(out);
         },
+            SysCall::InterruptStats => {
+                let stats = KernelData::isr_table().stats(arg1 as usize);
+                return match stats {
+                    Some(stats) => match write_user::<IrqStats>(arg2, stats) {
+                        Ok(())  => 0,
+                        Err(_)  => INVALID_POINTER
+                    },
+                    None => 1
+                };
+            },
+            SysCall::FutexWait => {
+                // Während des Wartens müssen Interrupts zugelassen sein, sonst kann niemand
+                // wecken.
+                Cpu::enable_interrupts();
+                let res = futex_wait(arg1 as usize, arg2);
+                Cpu::disable_interrupts();
+                return match res {
+                    Ok(())   => 0,
+                    Err(err) => err.as_u32()
+                };
+            },
+            SysCall::FutexWake => {
+                return match futex_wake(arg1 as usize, arg2 as usize) {
+                    Ok(n)  => n as u32,
+                    Err(_) => ::core::u32::MAX
+                };
+            },
+            SysCall::SerialRead => {
+                let buf = match user_slice_mut(arg1, arg2 as usize) {
+                    Ok(buf) => buf,
+                    Err(_)  => return INVALID_POINTER
+                };
+                let driver = Pl011Driver::get();
+                return if arg3 & SERIAL_NONBLOCKING != 0 {
+                    driver.read(buf) as u32
+                } else {
+                    Cpu::enable_interrupts();
+                    let n = driver.read_blocking(buf);
+                    Cpu::disable_interrupts();
+                    n as u32
+                };
+            },
+            SysCall::SerialWrite => {
+                let data = match user_slice(arg1, arg2 as usize) {
+                    Ok(data) => data,
+                    Err(_)   => return INVALID_POINTER
+                };
+                let driver = Pl011Driver::get();
+                return if arg3 & SERIAL_NONBLOCKING != 0 {
+                    driver.write(data) as u32
+                } else {
+                    Cpu::enable_interrupts();
+                    driver.write_blocking(data);
+                    Cpu::disable_interrupts();
+                    arg2
+                };
+            },
+            SysCall::SerialErrors => {
+                if write_user::<Pl011ErrorCounts>(arg1, Pl011Driver::get().errors()).is_err() {
+                    return INVALID_POINTER;
+                }
+            },
+            SysCall::I2cTransfer => {
+                let driver = match arg1 {
+                    0 => BscDriver::bsc0(),
+                    1 => BscDriver::bsc1(),
+                    _ => return I2cError::NotInitialized.as_u32()
+                };
+                let request = match read_user::<I2cRequest>(arg2) {
+                    Ok(request) => request,
+                    Err(_)      => return INVALID_POINTER
+                };
+                let tx = match user_slice(request.tx as u32, request.tx_len as usize) {
+                    Ok(tx) => tx,
+                    Err(_) => return INVALID_POINTER
+                };
+                let rx = match user_slice_mut(request.rx as u32, request.rx_len as usize) {
+                    Ok(rx) => rx,
+                    Err(_) => return INVALID_POINTER
+                };
+                // Bei interruptgesteuerten Mastern wird blockierend gewartet.
+                Cpu::enable_interrupts();
+                let res = driver.transaction(request.address(), tx, rx);
+                Cpu::disable_interrupts();
+                return match res {
+                    Ok(())   => 0,
+                    Err(err) => err.as_u32()
+                };
+            },
+            SysCall::GpioWatch => {
+                let res = gpio_pin(arg1).and_then(|pin| match GpioTrigger::from_u32(arg2) {
+                    Some(trigger) => GpioDriver::get().register_queue(pin, trigger, arg3),
+                    None          => Err(GpioError::InvalidTrigger)
+                });
+                return match res {
+                    Ok(())   => 0,
+                    Err(err) => err.as_u32()
+                };
+            },
+            SysCall::GpioUnwatch => {
+                return match gpio_pin(arg1).and_then(|pin| GpioDriver::get().unregister(pin)) {
+                    Ok(())   => 0,
+                    Err(err) => err.as_u32()
+                };
+            },
+            SysCall::GpioWaitEvent => {
+                let pin = match gpio_pin(arg1) {
+                    Ok(pin)  => pin,
+                    Err(err) => return err.as_u32()
+                };
+                // Vor dem Warten prüfen, damit kein Ereignis verloren geht
+                if write_user(arg3, GpioPinEvent::default()).is_err() {
+                    return INVALID_POINTER;
+                }
+                Cpu::enable_interrupts();
+                let res = GpioDriver::get().wait_event(pin, arg2);
+                Cpu::disable_interrupts();
+                return match res {
+                    Ok(event) => match write_user(arg3, event) {
+                        Ok(())  => 0,
+                        Err(_)  => INVALID_POINTER
+                    },
+                    Err(err) => err.as_u32()
+                };
+            },
             _             => {
             }
         }
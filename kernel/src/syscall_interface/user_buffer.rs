@@ -0,0 +1,100 @@
+#![warn(missing_docs)]
+//! Prüfung von Zeigern aus Systemrufen.
+//!
+//! Ein Prozess kann dem Kernel beliebige Adressen übergeben. Bevor der Kernel darauf zugreift,
+//! wird jede Seite des Bereichs wie bei einem Zugriff im Usermodus übersetzt
+//! (`MMU::translate_user_read` bzw. `MMU::translate_user_write`). Da die MMU in
+//! Manager-Domains keine Rechte prüft, werden zusätzlich die Zugriffsrechte aus dem
+//! Seitenverzeichnis ausgewertet (`PageWalker`). Nullzeiger, nicht abgebildete oder nicht
+//! zugreifbare Bereiche und Bereiche über das Ende des Adressraums hinaus werden abgelehnt.
+//! Ein leerer Bereich ist immer gültig, auch mit Nullzeiger.
+use core::{mem, ptr, slice};
+use hal::cpu::MMU;
+use memory::{Address, PAGE_SIZE, PageDirectory, PageWalker, MemoryAccessRight};
+
+/// Rückgabewert von Systemrufen, die sonst eine Anzahl zurückgeben, bei ungültigem Zeiger
+pub const INVALID_POINTER: u32 = ::core::u32::MAX;
+
+/// Ungültiger Zeiger aus einem Systemruf
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub struct InvalidPointer;
+
+/// Prüft, ob der Prozess `len` Bytes ab `addr` lesen (bzw. bei `write` schreiben) darf.
+pub fn check_user_range(addr: u32, len: usize, write: bool) -> Result<(),InvalidPointer> {
+    if len == 0 {
+        return Ok(());
+    }
+    let start = addr as Address;
+    let end = match start.checked_add(len) {
+        Some(end) if start != 0 => end,
+        _                       => return Err(InvalidPointer),
+    };
+    let dir = PageDirectory::get();
+    let walker = PageWalker::kernel(&dir);
+    let mut page = start & !(PAGE_SIZE - 1);
+    while page < end {
+        // Innerhalb einer Seite genügt die Prüfung einer Adresse.
+        let probe = if page < start { start } else { page };
+        let mapped = if write {
+            MMU::translate_user_write(probe)
+        } else {
+            MMU::translate_user_read(probe)
+        };
+        let rights = walker.translate(probe).and_then(|t| t.attributes.rights);
+        if mapped.is_none() || !user_access(rights, write) {
+            return Err(InvalidPointer);
+        }
+        page = match page.checked_add(PAGE_SIZE) {
+            Some(next) => next,
+            None       => break,
+        };
+    }
+    Ok(())
+}
+
+/// Erlauben die Rechte `rights` dem Prozess den Zugriff?
+fn user_access(rights: Option<MemoryAccessRight>, write: bool) -> bool {
+    match rights {
+        Some(rights) if write => rights.user_writable(),
+        Some(rights)          => rights.user_readable(),
+        None                  => false
+    }
+}
+
+/// Puffer des Prozesses zum Lesen
+pub fn user_slice<'a>(addr: u32, len: usize) -> Result<&'a [u8],InvalidPointer> {
+    check_user_range(addr, len, false)?;
+    if len == 0 {
+        return Ok(&[]);
+    }
+    Ok(unsafe{ slice::from_raw_parts(addr as *const u8, len) })
+}
+
+/// Puffer des Prozesses zum Schreiben
+pub fn user_slice_mut<'a>(addr: u32, len: usize) -> Result<&'a mut [u8],InvalidPointer> {
+    check_user_range(addr, len, true)?;
+    if len == 0 {
+        return Ok(&mut []);
+    }
+    Ok(unsafe{ slice::from_raw_parts_mut(addr as *mut u8, len) })
+}
+
+/// Liest einen Wert vom Typ `T` aus dem Speicher des Prozesses; `addr` muss passend
+/// ausgerichtet sein.
+pub fn read_user<T: Copy>(addr: u32) -> Result<T,InvalidPointer> {
+    if addr as usize % mem::align_of::<T>() != 0 {
+        return Err(InvalidPointer);
+    }
+    check_user_range(addr, mem::size_of::<T>(), false)?;
+    Ok(unsafe{ *(addr as *const T) })
+}
+
+/// Schreibt `value` in den Speicher des Prozesses; `addr` muss passend ausgerichtet sein.
+pub fn write_user<T>(addr: u32, value: T) -> Result<(),InvalidPointer> {
+    if addr as usize % mem::align_of::<T>() != 0 {
+        return Err(InvalidPointer);
+    }
+    check_user_range(addr, mem::size_of::<T>(), true)?;
+    unsafe{ ptr::write(addr as *mut T, value); }
+    Ok(())
+}
@@ -0,0 +1,183 @@
+#![warn(missing_docs)]
+//! Mutex und Bedingungsvariable für Prozesse im Usermodus.
+//!
+//! Beide arbeiten auf einem `AtomicWord` und rufen den Kernel nur, wenn gewartet oder geweckt
+//! werden muss (`SysCall::FutexWait`, `SysCall::FutexWake`). Im unbelasteten Fall kosten
+//! `lock` und `unlock` je eine atomare Operation.
+//!
+//! Der Mutex folgt U. Drepper, "Futexes Are Tricky", Mutex 2. Zustände des Wortes:
+//!
+//! - 0: frei
+//! - 1: belegt, niemand wartet
+//! - 2: belegt, möglicherweise warten Prozesse
+//!
+//! `FutexWait` kehrt ohne Warten mit `FutexError::WouldBlock` zurück, wenn sich das Wort
+//! schon geändert hat, und mit `FutexError::TableFull`, wenn der Kernel keinen Platz mehr
+//! hat. In beiden Fällen prüfen Mutex und Bedingungsvariable einfach erneut.
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use entry::syscall;
+use sync::{AtomicWord, FutexError};
+use super::SysCall;
+
+const UNLOCKED:  u32 = 0;
+const LOCKED:    u32 = 1;
+const CONTENDED: u32 = 2;
+
+/// Wartet, solange in `word` der Wert `expected` steht (oder bis zum Wecken).
+fn futex_wait(word: &AtomicWord, expected: u32) -> Result<(),FutexError> {
+    match syscall(SysCall::FutexWait, word.as_ptr() as u32, expected, 0) {
+        0    => Ok(()),
+        code => Err(FutexError::from_u32(code).unwrap_or(FutexError::InvalidAddress)),
+    }
+}
+
+/// Weckt bis zu `n` Prozesse, die auf `word` warten, und gibt ihre Anzahl zurück.
+fn futex_wake(word: &AtomicWord, n: u32) -> Result<u32,FutexError> {
+    match syscall(SysCall::FutexWake, word.as_ptr() as u32, n, 0) {
+        ::core::u32::MAX => Err(FutexError::InvalidAddress),
+        woken            => Ok(woken),
+    }
+}
+
+/// Wartet an `word`; kehrt auch zurück, wenn nicht gewartet werden musste oder konnte.
+///
+/// Eine ungültige Adresse ist ein Programmierfehler.
+fn wait(word: &AtomicWord, expected: u32) {
+    match futex_wait(word, expected) {
+        Ok(()) | Err(FutexError::WouldBlock) | Err(FutexError::TableFull) => {},
+        Err(err) => panic!("FutexWait: {:?}", err),
+    }
+}
+
+/// Weckt bis zu `n` Prozesse an `word`.
+fn wake(word: &AtomicWord, n: u32) {
+    if let Err(err) = futex_wake(word, n) {
+        panic!("FutexWake: {:?}", err);
+    }
+}
+
+/// Mutex für den Usermodus
+pub struct UserMutex<T> {
+    state: AtomicWord,
+    data:  UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for UserMutex<T> {}
+unsafe impl<T: Send> Send for UserMutex<T> {}
+
+impl<T> UserMutex<T> {
+    /// Erzeugt einen freien Mutex für `data`
+    pub const fn new(data: T) -> UserMutex<T> {
+        UserMutex {
+            state: AtomicWord::new(UNLOCKED),
+            data:  UnsafeCell::new(data),
+        }
+    }
+
+    /// Belegt den Mutex; blockiert, solange er belegt ist.
+    pub fn lock(&self) -> UserMutexGuard<T> {
+        let mut c = self.state.compare_and_swap(UNLOCKED, LOCKED);
+        if c != UNLOCKED {
+            if c != CONTENDED {
+                c = self.state.swap(CONTENDED);
+            }
+            while c != UNLOCKED {
+                wait(&self.state, CONTENDED);
+                c = self.state.swap(CONTENDED);
+            }
+        }
+        UserMutexGuard { mutex: self }
+    }
+
+    /// Versucht, den Mutex ohne Warten zu belegen.
+    pub fn try_lock(&self) -> Option<UserMutexGuard<T>> {
+        if self.state.compare_and_swap(UNLOCKED, LOCKED) == UNLOCKED {
+            Some(UserMutexGuard { mutex: self })
+        } else {
+            None
+        }
+    }
+
+    /// Belegt den Mutex nach dem Warten in `UserCondvar::wait`.
+    ///
+    /// Der Zustand wird immer auf `CONTENDED` gesetzt, da weitere Prozesse aus derselben
+    /// Bedingungsvariable geweckt worden sein können.
+    fn lock_contended(&self) -> UserMutexGuard<T> {
+        while self.state.swap(CONTENDED) != UNLOCKED {
+            wait(&self.state, CONTENDED);
+        }
+        UserMutexGuard { mutex: self }
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED) == CONTENDED {
+            wake(&self.state, 1);
+        }
+    }
+}
+
+/// Belegter `UserMutex`; gibt ihn frei, wenn er den Gültigkeitsbereich verlässt.
+pub struct UserMutexGuard<'a, T: 'a> {
+    mutex: &'a UserMutex<T>,
+}
+
+impl<'a, T> Deref for UserMutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe{ &*self.mutex.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for UserMutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe{ &mut *self.mutex.data.get() }
+    }
+}
+
+impl<'a, T> Drop for UserMutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// Bedingungsvariable für die Nutzung mit `UserMutex`.
+///
+/// Das Wort ist ein Zähler, der bei jeder Benachrichtigung erhöht wird. Ein Wartender merkt
+/// sich den Zähler vor dem Freigeben des Mutex; wurde seitdem benachrichtigt, kehrt
+/// `FutexWait` sofort zurück. Wie beim Kernel-`Condvar` muss die Bedingung in einer Schleife
+/// geprüft werden.
+pub struct UserCondvar {
+    seq: AtomicWord,
+}
+
+impl UserCondvar {
+    /// Neue Bedingungsvariable
+    pub const fn new() -> UserCondvar {
+        UserCondvar {
+            seq: AtomicWord::new(0),
+        }
+    }
+
+    /// Gibt den Mutex frei, wartet auf eine Benachrichtigung und belegt den Mutex wieder.
+    pub fn wait<'a, T>(&self, guard: UserMutexGuard<'a, T>) -> UserMutexGuard<'a, T> {
+        let mutex = guard.mutex;
+        let seq = self.seq.load();
+        drop(guard);
+        wait(&self.seq, seq);
+        mutex.lock_contended()
+    }
+
+    /// Weckt einen Wartenden.
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1);
+        wake(&self.seq, 1);
+    }
+
+    /// Weckt alle Wartenden.
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1);
+        wake(&self.seq, ::core::u32::MAX);
+    }
+}